@@ -0,0 +1,85 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Metallic-roughness material, laid out like glTF 2.0's: the metallic-roughness texture holds roughness in G and
+// metallic in B, and the occlusion texture holds occlusion in R.
+layout(set = 1, binding = 0) uniform material_uniform
+{
+	vec4 base_color_factor;
+	vec4 emissive_factor;
+	float metallic_factor;
+	float roughness_factor;
+	float normal_scale;
+	float occlusion_strength;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D base_color_texture;
+layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform sampler2D normal_texture;
+layout(set = 1, binding = 4) uniform sampler2D occlusion_texture;
+layout(set = 1, binding = 5) uniform sampler2D emissive_texture;
+
+layout(location = 0) in struct dto
+{
+	vec2 tex_coord;
+	vec4 color;
+	vec3 world_position;
+	float view_depth;
+	vec3 world_normal;
+} in_dto;
+
+// Emission needs no lighting, so it goes straight into the lit output.
+layout(location = 0) out vec4 out_albedo;
+layout(location = 1) out vec4 out_normal;
+layout(location = 2) out vec4 out_material;
+layout(location = 3) out vec4 out_emissive;
+
+// Perturbs the geometric normal with the normal map, using a tangent frame built from screen-space derivatives so
+// meshes need no tangents.
+vec3 shading_normal()
+{
+	vec3 normal = normalize(in_dto.world_normal);
+
+	if (!gl_FrontFacing)
+	{
+		normal = -normal;
+	}
+
+	vec3 dp1 = dFdx(in_dto.world_position);
+	vec3 dp2 = dFdy(in_dto.world_position);
+	vec2 duv1 = dFdx(in_dto.tex_coord);
+	vec2 duv2 = dFdy(in_dto.tex_coord);
+
+	vec3 dp2_perp = cross(dp2, normal);
+	vec3 dp1_perp = cross(normal, dp1);
+	vec3 tangent = dp2_perp * duv1.x + dp1_perp * duv2.x;
+	vec3 bitangent = dp2_perp * duv1.y + dp1_perp * duv2.y;
+
+	float inverse_max = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+
+	if (isinf(inverse_max) || isnan(inverse_max))
+	{
+		return normal;
+	}
+
+	vec3 mapped = texture(normal_texture, in_dto.tex_coord).xyz * 2.0 - 1.0;
+	mapped.xy *= material.normal_scale;
+
+	return normalize(mat3(tangent * inverse_max, bitangent * inverse_max, normal) * mapped);
+}
+
+void main()
+{
+	vec4 base_color = in_dto.color * material.base_color_factor * texture(base_color_texture, in_dto.tex_coord);
+	vec4 metallic_roughness = texture(metallic_roughness_texture, in_dto.tex_coord);
+
+	float metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+	float roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+	float occlusion = mix(1.0, texture(occlusion_texture, in_dto.tex_coord).r, material.occlusion_strength);
+	vec3 emissive = material.emissive_factor.rgb * texture(emissive_texture, in_dto.tex_coord).rgb;
+
+	out_albedo = vec4(base_color.rgb, occlusion);
+	out_normal = vec4(shading_normal(), 0.0);
+	out_material = vec4(metallic, roughness, 0.0, 0.0);
+	out_emissive = vec4(emissive, 1.0);
+}
@@ -0,0 +1,161 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform deferred_uniform
+{
+	mat4 view_projection;
+	mat4 inverse_view_projection;
+	vec4 inverse_screen_size;
+} deferred;
+
+layout(input_attachment_index = 0, set = 1, binding = 0) uniform subpassInput gbuffer_albedo;
+layout(input_attachment_index = 1, set = 1, binding = 1) uniform subpassInput gbuffer_normal;
+layout(input_attachment_index = 2, set = 1, binding = 2) uniform subpassInput gbuffer_material;
+layout(input_attachment_index = 3, set = 1, binding = 3) uniform subpassInput gbuffer_depth;
+
+layout(set = 2, binding = 0) uniform lighting_uniform
+{
+	vec4 camera_position;
+	vec4 light_direction;
+	vec4 light_color;
+	float environment_intensity;
+	float prefiltered_mip_count;
+} lighting;
+
+layout(set = 2, binding = 1) uniform samplerCube irradiance_map;
+layout(set = 2, binding = 2) uniform samplerCube prefiltered_map;
+layout(set = 2, binding = 3) uniform sampler2D brdf_lut;
+
+// Laid out like LightVolumeConstants in deferred.rs.
+layout(push_constant) uniform light_constants
+{
+	mat4 model;
+	vec4 position_range;
+	vec4 color;
+	vec4 direction_cos_outer;
+	vec4 cos_inner;
+} light;
+
+layout(location = 0) out vec4 out_colour;
+
+const float PI = 3.14159265359;
+
+float distribution_ggx(float n_dot_h, float roughness)
+{
+	float a2 = roughness * roughness * roughness * roughness;
+	float denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+	return a2 / (PI * denominator * denominator);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+	float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+
+	return (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+	return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+	return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+struct surface
+{
+	vec3 position;
+	vec3 normal;
+	vec3 base_color;
+	float occlusion;
+	float metallic;
+	float roughness;
+};
+
+// Reads the G-buffer under this fragment. Returns false where no geometry was drawn.
+bool load_surface(out surface s)
+{
+	float depth = subpassLoad(gbuffer_depth).r;
+
+	if (depth >= 1.0)
+	{
+		return false;
+	}
+
+	vec2 ndc = gl_FragCoord.xy * deferred.inverse_screen_size.xy * 2.0 - 1.0;
+	vec4 position = deferred.inverse_view_projection * vec4(ndc, depth, 1.0);
+
+	vec4 albedo = subpassLoad(gbuffer_albedo);
+	vec4 material = subpassLoad(gbuffer_material);
+
+	s.position = position.xyz / position.w;
+	s.normal = normalize(subpassLoad(gbuffer_normal).xyz);
+	s.base_color = albedo.rgb;
+	s.occlusion = albedo.a;
+	s.metallic = material.r;
+	s.roughness = material.g;
+
+	return true;
+}
+
+// Cook-Torrance specular and Lambertian diffuse for light arriving from l.
+vec3 direct_lighting(surface s, vec3 v, vec3 l, vec3 radiance)
+{
+	vec3 h = normalize(v + l);
+
+	float n_dot_v = max(dot(s.normal, v), 0.0001);
+	float n_dot_l = max(dot(s.normal, l), 0.0);
+	float n_dot_h = max(dot(s.normal, h), 0.0);
+
+	vec3 f0 = mix(vec3(0.04), s.base_color, s.metallic);
+	vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+	vec3 specular = distribution_ggx(n_dot_h, s.roughness) * geometry_smith(n_dot_v, n_dot_l, s.roughness) * f
+		/ (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+	vec3 diffuse = (1.0 - f) * (1.0 - s.metallic) * s.base_color / PI;
+
+	return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// Inverse-square falloff, windowed so it reaches zero at the light's range.
+float distance_attenuation(float light_distance, float range)
+{
+	float ratio = light_distance / range;
+	float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+
+	return window * window / max(light_distance * light_distance, 0.0001);
+}
+
+// One point or spot light, for the pixels its volume covers.
+void main()
+{
+	surface s;
+
+	if (!load_surface(s))
+	{
+		discard;
+	}
+
+	vec3 to_light = light.position_range.xyz - s.position;
+	float light_distance = length(to_light);
+
+	if (light_distance >= light.position_range.w)
+	{
+		discard;
+	}
+
+	vec3 l = to_light / light_distance;
+	float attenuation = distance_attenuation(light_distance, light.position_range.w);
+
+	if (light.color.a > 0.0)
+	{
+		float cos_angle = dot(-l, light.direction_cos_outer.xyz);
+		attenuation *= smoothstep(light.direction_cos_outer.w, light.cos_inner.x, cos_angle);
+	}
+
+	vec3 v = normalize(lighting.camera_position.xyz - s.position);
+
+	out_colour = vec4(direct_lighting(s, v, l, light.color.rgb * attenuation), 1.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec2 in_tex_coord;
+layout(location = 2) in vec3 in_normal;
+
+layout(set = 0, binding = 0) uniform deferred_uniform
+{
+	mat4 view_projection;
+	mat4 inverse_view_projection;
+	vec4 inverse_screen_size;
+} deferred;
+
+// Laid out like LightVolumeConstants in deferred.rs.
+layout(push_constant) uniform light_constants
+{
+	mat4 model;
+	vec4 position_range;
+	vec4 color;
+	vec4 direction_cos_outer;
+	vec4 cos_inner;
+} light;
+
+void main()
+{
+	gl_Position = deferred.view_projection * light.model * vec4(in_position, 1.0);
+}
@@ -0,0 +1,142 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform deferred_uniform
+{
+	mat4 view_projection;
+	mat4 inverse_view_projection;
+	vec4 inverse_screen_size;
+} deferred;
+
+layout(input_attachment_index = 0, set = 1, binding = 0) uniform subpassInput gbuffer_albedo;
+layout(input_attachment_index = 1, set = 1, binding = 1) uniform subpassInput gbuffer_normal;
+layout(input_attachment_index = 2, set = 1, binding = 2) uniform subpassInput gbuffer_material;
+layout(input_attachment_index = 3, set = 1, binding = 3) uniform subpassInput gbuffer_depth;
+
+layout(set = 2, binding = 0) uniform lighting_uniform
+{
+	vec4 camera_position;
+	vec4 light_direction;
+	vec4 light_color;
+	float environment_intensity;
+	float prefiltered_mip_count;
+} lighting;
+
+layout(set = 2, binding = 1) uniform samplerCube irradiance_map;
+layout(set = 2, binding = 2) uniform samplerCube prefiltered_map;
+layout(set = 2, binding = 3) uniform sampler2D brdf_lut;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+const float PI = 3.14159265359;
+
+float distribution_ggx(float n_dot_h, float roughness)
+{
+	float a2 = roughness * roughness * roughness * roughness;
+	float denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+	return a2 / (PI * denominator * denominator);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+	float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+
+	return (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+	return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+	return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+struct surface
+{
+	vec3 position;
+	vec3 normal;
+	vec3 base_color;
+	float occlusion;
+	float metallic;
+	float roughness;
+};
+
+// Reads the G-buffer under this fragment. Returns false where no geometry was drawn.
+bool load_surface(out surface s)
+{
+	float depth = subpassLoad(gbuffer_depth).r;
+
+	if (depth >= 1.0)
+	{
+		return false;
+	}
+
+	vec2 ndc = gl_FragCoord.xy * deferred.inverse_screen_size.xy * 2.0 - 1.0;
+	vec4 position = deferred.inverse_view_projection * vec4(ndc, depth, 1.0);
+
+	vec4 albedo = subpassLoad(gbuffer_albedo);
+	vec4 material = subpassLoad(gbuffer_material);
+
+	s.position = position.xyz / position.w;
+	s.normal = normalize(subpassLoad(gbuffer_normal).xyz);
+	s.base_color = albedo.rgb;
+	s.occlusion = albedo.a;
+	s.metallic = material.r;
+	s.roughness = material.g;
+
+	return true;
+}
+
+// Cook-Torrance specular and Lambertian diffuse for light arriving from l.
+vec3 direct_lighting(surface s, vec3 v, vec3 l, vec3 radiance)
+{
+	vec3 h = normalize(v + l);
+
+	float n_dot_v = max(dot(s.normal, v), 0.0001);
+	float n_dot_l = max(dot(s.normal, l), 0.0);
+	float n_dot_h = max(dot(s.normal, h), 0.0);
+
+	vec3 f0 = mix(vec3(0.04), s.base_color, s.metallic);
+	vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+	vec3 specular = distribution_ggx(n_dot_h, s.roughness) * geometry_smith(n_dot_v, n_dot_l, s.roughness) * f
+		/ (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+	vec3 diffuse = (1.0 - f) * (1.0 - s.metallic) * s.base_color / PI;
+
+	return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// The directional light and the environment, for every pixel with geometry. Shadows are not applied.
+void main()
+{
+	surface s;
+
+	if (!load_surface(s))
+	{
+		discard;
+	}
+
+	vec3 v = normalize(lighting.camera_position.xyz - s.position);
+	vec3 direct = direct_lighting(s, v, normalize(-lighting.light_direction.xyz), lighting.light_color.rgb);
+
+	// Image-based lighting with the split-sum approximation.
+	float n_dot_v = max(dot(s.normal, v), 0.0001);
+	vec3 f0 = mix(vec3(0.04), s.base_color, s.metallic);
+	vec3 f_ambient = fresnel_schlick_roughness(n_dot_v, f0, s.roughness);
+	vec3 r = reflect(-v, s.normal);
+
+	vec3 irradiance = texture(irradiance_map, s.normal).rgb;
+	vec3 prefiltered = textureLod(prefiltered_map, r, s.roughness * (lighting.prefiltered_mip_count - 1.0)).rgb;
+	vec2 brdf = texture(brdf_lut, vec2(n_dot_v, s.roughness)).rg;
+
+	vec3 ambient_diffuse = (1.0 - f_ambient) * (1.0 - s.metallic) * irradiance * s.base_color;
+	vec3 ambient_specular = prefiltered * (f_ambient * brdf.x + brdf.y);
+	vec3 ambient = (ambient_diffuse + ambient_specular) * s.occlusion * lighting.environment_intensity;
+
+	out_colour = vec4(direct + ambient, 1.0);
+}
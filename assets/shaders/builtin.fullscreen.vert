@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec2 out_tex_coord;
+
+// A single triangle covering the screen, drawn with three vertices and no vertex buffer.
+void main()
+{
+	out_tex_coord = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+
+	gl_Position = vec4(out_tex_coord * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -8,14 +8,70 @@ layout(set = 1, binding = 0) uniform object_uniform_object
 
 layout(set = 1, binding = 1) uniform sampler2D diffuse_sampler;
 
+const int MAX_SHADOW_VIEWS = 8;
+
+// Cascades of the directional light come first, followed by one view per spot light.
+layout(set = 2, binding = 0) uniform shadow_uniform
+{
+	mat4 view_projections[MAX_SHADOW_VIEWS];
+	vec4 atlas_rects[MAX_SHADOW_VIEWS];
+	vec4 cascade_splits;
+	uint cascade_count;
+	uint spot_count;
+} shadow_ubo;
+
+layout(set = 2, binding = 1) uniform sampler2DShadow shadow_atlas;
+
 layout(location = 0) in struct dto
 {
 	vec2 tex_coord;
+	vec4 color;
+	vec3 world_position;
+	float view_depth;
 } in_dto;
 
 layout(location = 0) out vec4 out_colour;
 
+// 1 when lit, 0 when in shadow. Positions outside the view are lit.
+float sample_shadow(uint view_index)
+{
+	vec4 clip = shadow_ubo.view_projections[view_index] * vec4(in_dto.world_position, 1.0);
+	vec3 ndc = clip.xyz / clip.w;
+
+	if (clip.w <= 0.0 || any(greaterThan(abs(ndc.xy), vec2(1.0))) || ndc.z > 1.0)
+	{
+		return 1.0;
+	}
+
+	vec4 rect = shadow_ubo.atlas_rects[view_index];
+
+	return texture(shadow_atlas, vec3(rect.xy + (ndc.xy * 0.5 + 0.5) * rect.zw, ndc.z));
+}
+
+float shadow_factor()
+{
+	float lit = 1.0;
+
+	for (uint cascade = 0; cascade < shadow_ubo.cascade_count; cascade++)
+	{
+		if (in_dto.view_depth < shadow_ubo.cascade_splits[cascade])
+		{
+			lit = sample_shadow(cascade);
+			break;
+		}
+	}
+
+	for (uint spot = 0; spot < shadow_ubo.spot_count; spot++)
+	{
+		lit *= sample_shadow(shadow_ubo.cascade_count + spot);
+	}
+
+	return lit;
+}
+
 void main()
 {
-	out_colour = object_uniform.diffuse_color * texture(diffuse_sampler, in_dto.tex_coord);
+	vec4 colour = in_dto.color * object_uniform.diffuse_color * texture(diffuse_sampler, in_dto.tex_coord);
+
+	out_colour = vec4(colour.rgb * mix(0.4, 1.0, shadow_factor()), colour.a);
 }
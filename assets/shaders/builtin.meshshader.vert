@@ -4,6 +4,8 @@
 layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec2 in_tex_coord;
 layout(location = 2) in vec3 in_normal;
+layout(location = 3) in mat4 in_model;
+layout(location = 7) in vec4 in_color;
 
 layout(set = 0, binding = 0) uniform global_uniform
 {
@@ -11,19 +13,23 @@ layout(set = 0, binding = 0) uniform global_uniform
 	mat4 view;
 } global_ubo;
 
-layout(push_constant) uniform u_push_constants
-{
-	mat4 model;
-} push_constants;
-
 layout(location = 0) out struct dto
 {
 	vec2 tex_coord;
+	vec4 color;
+	vec3 world_position;
+	float view_depth;
 } out_dto;
 
 void main()
 {
-	gl_Position = global_ubo.projection * global_ubo.view * push_constants.model * vec4(in_position, 1.0);
+	vec4 world_position = in_model * vec4(in_position, 1.0);
+	vec4 view_position = global_ubo.view * world_position;
+
+	gl_Position = global_ubo.projection * view_position;
 
 	out_dto.tex_coord = in_tex_coord;
+	out_dto.color = in_color;
+	out_dto.world_position = world_position.xyz;
+	out_dto.view_depth = -view_position.z;
 }
@@ -0,0 +1,209 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Metallic-roughness material, laid out like glTF 2.0's: the metallic-roughness texture holds roughness in G and
+// metallic in B, and the occlusion texture holds occlusion in R.
+layout(set = 1, binding = 0) uniform material_uniform
+{
+	vec4 base_color_factor;
+	vec4 emissive_factor;
+	float metallic_factor;
+	float roughness_factor;
+	float normal_scale;
+	float occlusion_strength;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D base_color_texture;
+layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform sampler2D normal_texture;
+layout(set = 1, binding = 4) uniform sampler2D occlusion_texture;
+layout(set = 1, binding = 5) uniform sampler2D emissive_texture;
+
+const int MAX_SHADOW_VIEWS = 8;
+
+// Cascades of the directional light come first, followed by one view per spot light.
+layout(set = 2, binding = 0) uniform shadow_uniform
+{
+	mat4 view_projections[MAX_SHADOW_VIEWS];
+	vec4 atlas_rects[MAX_SHADOW_VIEWS];
+	vec4 cascade_splits;
+	uint cascade_count;
+	uint spot_count;
+} shadow_ubo;
+
+layout(set = 2, binding = 1) uniform sampler2DShadow shadow_atlas;
+
+layout(set = 3, binding = 0) uniform lighting_uniform
+{
+	vec4 camera_position;
+	vec4 light_direction;
+	vec4 light_color;
+	float environment_intensity;
+	float prefiltered_mip_count;
+} lighting;
+
+layout(set = 3, binding = 1) uniform samplerCube irradiance_map;
+layout(set = 3, binding = 2) uniform samplerCube prefiltered_map;
+layout(set = 3, binding = 3) uniform sampler2D brdf_lut;
+
+layout(location = 0) in struct dto
+{
+	vec2 tex_coord;
+	vec4 color;
+	vec3 world_position;
+	float view_depth;
+	vec3 world_normal;
+} in_dto;
+
+layout(location = 0) out vec4 out_colour;
+
+// Alpha-tested variants discard fragments whose base colour alpha is below the cutoff.
+layout(constant_id = 0) const bool ALPHA_TEST = false;
+layout(constant_id = 1) const float ALPHA_CUTOFF = 0.5;
+
+const float PI = 3.14159265359;
+
+// 1 when lit, 0 when in shadow. Positions outside the view are lit.
+float sample_shadow(uint view_index)
+{
+	vec4 clip = shadow_ubo.view_projections[view_index] * vec4(in_dto.world_position, 1.0);
+	vec3 ndc = clip.xyz / clip.w;
+
+	if (clip.w <= 0.0 || any(greaterThan(abs(ndc.xy), vec2(1.0))) || ndc.z > 1.0)
+	{
+		return 1.0;
+	}
+
+	vec4 rect = shadow_ubo.atlas_rects[view_index];
+
+	return texture(shadow_atlas, vec3(rect.xy + (ndc.xy * 0.5 + 0.5) * rect.zw, ndc.z));
+}
+
+float shadow_factor()
+{
+	float lit = 1.0;
+
+	for (uint cascade = 0; cascade < shadow_ubo.cascade_count; cascade++)
+	{
+		if (in_dto.view_depth < shadow_ubo.cascade_splits[cascade])
+		{
+			lit = sample_shadow(cascade);
+			break;
+		}
+	}
+
+	for (uint spot = 0; spot < shadow_ubo.spot_count; spot++)
+	{
+		lit *= sample_shadow(shadow_ubo.cascade_count + spot);
+	}
+
+	return lit;
+}
+
+// Perturbs the geometric normal with the normal map, using a tangent frame built from screen-space derivatives so
+// meshes need no tangents.
+vec3 shading_normal()
+{
+	vec3 normal = normalize(in_dto.world_normal);
+
+	if (!gl_FrontFacing)
+	{
+		normal = -normal;
+	}
+
+	vec3 dp1 = dFdx(in_dto.world_position);
+	vec3 dp2 = dFdy(in_dto.world_position);
+	vec2 duv1 = dFdx(in_dto.tex_coord);
+	vec2 duv2 = dFdy(in_dto.tex_coord);
+
+	vec3 dp2_perp = cross(dp2, normal);
+	vec3 dp1_perp = cross(normal, dp1);
+	vec3 tangent = dp2_perp * duv1.x + dp1_perp * duv2.x;
+	vec3 bitangent = dp2_perp * duv1.y + dp1_perp * duv2.y;
+
+	float inverse_max = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+
+	if (isinf(inverse_max) || isnan(inverse_max))
+	{
+		return normal;
+	}
+
+	vec3 mapped = texture(normal_texture, in_dto.tex_coord).xyz * 2.0 - 1.0;
+	mapped.xy *= material.normal_scale;
+
+	return normalize(mat3(tangent * inverse_max, bitangent * inverse_max, normal) * mapped);
+}
+
+float distribution_ggx(float n_dot_h, float roughness)
+{
+	float a2 = roughness * roughness * roughness * roughness;
+	float denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+	return a2 / (PI * denominator * denominator);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+	float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+
+	return (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+	return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+	return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+void main()
+{
+	vec4 base_color = in_dto.color * material.base_color_factor * texture(base_color_texture, in_dto.tex_coord);
+
+	if (ALPHA_TEST && base_color.a < ALPHA_CUTOFF)
+	{
+		discard;
+	}
+	vec4 metallic_roughness = texture(metallic_roughness_texture, in_dto.tex_coord);
+
+	float metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+	float roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+	float occlusion = mix(1.0, texture(occlusion_texture, in_dto.tex_coord).r, material.occlusion_strength);
+	vec3 emissive = material.emissive_factor.rgb * texture(emissive_texture, in_dto.tex_coord).rgb;
+
+	vec3 n = shading_normal();
+	vec3 v = normalize(lighting.camera_position.xyz - in_dto.world_position);
+	vec3 l = normalize(-lighting.light_direction.xyz);
+	vec3 h = normalize(v + l);
+
+	float n_dot_v = max(dot(n, v), 0.0001);
+	float n_dot_l = max(dot(n, l), 0.0);
+	float n_dot_h = max(dot(n, h), 0.0);
+
+	vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+
+	// Cook-Torrance specular and Lambertian diffuse from the directional light.
+	vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+	vec3 specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * f
+		/ (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+	vec3 diffuse = (1.0 - f) * (1.0 - metallic) * base_color.rgb / PI;
+
+	vec3 direct = (diffuse + specular) * lighting.light_color.rgb * n_dot_l * shadow_factor();
+
+	// Image-based lighting with the split-sum approximation.
+	vec3 f_ambient = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+	vec3 r = reflect(-v, n);
+
+	vec3 irradiance = texture(irradiance_map, n).rgb;
+	vec3 prefiltered = textureLod(prefiltered_map, r, roughness * (lighting.prefiltered_mip_count - 1.0)).rgb;
+	vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+
+	vec3 ambient_diffuse = (1.0 - f_ambient) * (1.0 - metallic) * irradiance * base_color.rgb;
+	vec3 ambient_specular = prefiltered * (f_ambient * brdf.x + brdf.y);
+	vec3 ambient = (ambient_diffuse + ambient_specular) * occlusion * lighting.environment_intensity;
+
+	out_colour = vec4(direct + ambient + emissive, base_color.a);
+}
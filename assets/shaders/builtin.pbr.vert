@@ -0,0 +1,37 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec2 in_tex_coord;
+layout(location = 2) in vec3 in_normal;
+layout(location = 3) in mat4 in_model;
+layout(location = 7) in vec4 in_color;
+
+layout(set = 0, binding = 0) uniform global_uniform
+{
+	mat4 projection;
+	mat4 view;
+} global_ubo;
+
+layout(location = 0) out struct dto
+{
+	vec2 tex_coord;
+	vec4 color;
+	vec3 world_position;
+	float view_depth;
+	vec3 world_normal;
+} out_dto;
+
+void main()
+{
+	vec4 world_position = in_model * vec4(in_position, 1.0);
+	vec4 view_position = global_ubo.view * world_position;
+
+	gl_Position = global_ubo.projection * view_position;
+
+	out_dto.tex_coord = in_tex_coord;
+	out_dto.color = in_color;
+	out_dto.world_position = world_position.xyz;
+	out_dto.view_depth = -view_position.z;
+	out_dto.world_normal = transpose(inverse(mat3(in_model))) * in_normal;
+}
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(push_constant) uniform u_push_constants
+{
+	float intensity;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+void main()
+{
+	vec4 colour = texture(scene, in_tex_coord);
+
+	out_colour = vec4(colour.rgb + texture(bloom, in_tex_coord).rgb * push_constants.intensity, colour.a);
+}
@@ -0,0 +1,61 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform u_push_constants
+{
+	vec2 source_texel_size;
+	float threshold;
+	float prefilter;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+// Keeps what is brighter than the threshold, with a soft knee so bloom fades in instead of popping.
+vec3 apply_threshold(vec3 colour)
+{
+	float brightness = max(colour.r, max(colour.g, colour.b));
+	float knee = push_constants.threshold * 0.5;
+	float soft = clamp(brightness - push_constants.threshold + knee, 0.0, 2.0 * knee);
+	soft = soft * soft / (4.0 * knee + 0.0001);
+
+	float contribution = max(soft, brightness - push_constants.threshold) / max(brightness, 0.0001);
+
+	return colour * contribution;
+}
+
+// 13-tap filter: a 4x4 box made of overlapping bilinear taps, weighted towards the centre.
+void main()
+{
+	vec2 t = push_constants.source_texel_size;
+	vec2 uv = in_tex_coord;
+
+	vec3 a = texture(source, uv + t * vec2(-2.0, -2.0)).rgb;
+	vec3 b = texture(source, uv + t * vec2(0.0, -2.0)).rgb;
+	vec3 c = texture(source, uv + t * vec2(2.0, -2.0)).rgb;
+	vec3 d = texture(source, uv + t * vec2(-2.0, 0.0)).rgb;
+	vec3 e = texture(source, uv).rgb;
+	vec3 f = texture(source, uv + t * vec2(2.0, 0.0)).rgb;
+	vec3 g = texture(source, uv + t * vec2(-2.0, 2.0)).rgb;
+	vec3 h = texture(source, uv + t * vec2(0.0, 2.0)).rgb;
+	vec3 i = texture(source, uv + t * vec2(2.0, 2.0)).rgb;
+	vec3 j = texture(source, uv + t * vec2(-1.0, -1.0)).rgb;
+	vec3 k = texture(source, uv + t * vec2(1.0, -1.0)).rgb;
+	vec3 l = texture(source, uv + t * vec2(-1.0, 1.0)).rgb;
+	vec3 m = texture(source, uv + t * vec2(1.0, 1.0)).rgb;
+
+	vec3 colour = e * 0.125
+		+ (a + c + g + i) * 0.03125
+		+ (b + d + f + h) * 0.0625
+		+ (j + k + l + m) * 0.125;
+
+	if (push_constants.prefilter > 0.5)
+	{
+		colour = apply_threshold(colour);
+	}
+
+	out_colour = vec4(colour, 1.0);
+}
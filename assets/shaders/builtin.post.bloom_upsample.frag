@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform u_push_constants
+{
+	vec2 source_texel_size;
+	float radius;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+// 3x3 tent filter. The result is blended additively onto the next larger level of the chain.
+void main()
+{
+	vec2 t = push_constants.source_texel_size * push_constants.radius;
+	vec2 uv = in_tex_coord;
+
+	vec3 colour = texture(source, uv).rgb * 4.0;
+
+	colour += (texture(source, uv + vec2(-t.x, 0.0)).rgb
+		+ texture(source, uv + vec2(t.x, 0.0)).rgb
+		+ texture(source, uv + vec2(0.0, -t.y)).rgb
+		+ texture(source, uv + vec2(0.0, t.y)).rgb) * 2.0;
+
+	colour += texture(source, uv + vec2(-t.x, -t.y)).rgb
+		+ texture(source, uv + vec2(t.x, -t.y)).rgb
+		+ texture(source, uv + vec2(-t.x, t.y)).rgb
+		+ texture(source, uv + vec2(t.x, t.y)).rgb;
+
+	out_colour = vec4(colour / 16.0, 1.0);
+}
@@ -0,0 +1,46 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+// A size^3 lookup table unwrapped into a (size * size) x size strip: blue selects the slice, red and green the
+// texel inside it.
+layout(set = 0, binding = 1) uniform sampler2D lut;
+
+layout(push_constant) uniform u_push_constants
+{
+	float lut_size;
+	float intensity;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+vec3 sample_slice(vec2 red_green, float slice)
+{
+	float size = push_constants.lut_size;
+
+	// Sample texel centres so neighbouring slices never bleed into each other.
+	vec2 texel = red_green * (size - 1.0) + 0.5;
+	vec2 uv = vec2((slice * size + texel.x) / (size * size), texel.y / size);
+
+	return texture(lut, uv).rgb;
+}
+
+void main()
+{
+	vec4 colour = texture(scene, in_tex_coord);
+	vec3 graded_input = clamp(colour.rgb, 0.0, 1.0);
+
+	float blue = graded_input.b * (push_constants.lut_size - 1.0);
+	float slice = floor(blue);
+
+	vec3 graded = mix(
+		sample_slice(graded_input.rg, slice),
+		sample_slice(graded_input.rg, min(slice + 1.0, push_constants.lut_size - 1.0)),
+		blue - slice
+	);
+
+	out_colour = vec4(mix(colour.rgb, graded, push_constants.intensity), colour.a);
+}
@@ -0,0 +1,27 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(push_constant) uniform u_push_constants
+{
+	float encode_srgb;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+vec3 linear_to_srgb(vec3 colour)
+{
+	return mix(colour * 12.92, 1.055 * pow(colour, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), colour));
+}
+
+// Copies the end of the post-process chain to the output, encoding sRGB when the output format does not.
+void main()
+{
+	vec4 colour = texture(scene, in_tex_coord);
+	vec3 rgb = clamp(colour.rgb, 0.0, 1.0);
+
+	out_colour = vec4(push_constants.encode_srgb > 0.5 ? linear_to_srgb(rgb) : rgb, 1.0);
+}
@@ -0,0 +1,56 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(push_constant) uniform u_push_constants
+{
+	vec2 texel_size;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+float luma(vec3 colour)
+{
+	return dot(sqrt(colour), vec3(0.299, 0.587, 0.114));
+}
+
+// FXAA on tonemapped colour: blurs along the edge direction found from the luma of the four diagonal neighbours.
+void main()
+{
+	vec2 t = push_constants.texel_size;
+	vec2 uv = in_tex_coord;
+
+	vec4 centre = texture(scene, uv);
+
+	float luma_nw = luma(texture(scene, uv + vec2(-1.0, -1.0) * t).rgb);
+	float luma_ne = luma(texture(scene, uv + vec2(1.0, -1.0) * t).rgb);
+	float luma_sw = luma(texture(scene, uv + vec2(-1.0, 1.0) * t).rgb);
+	float luma_se = luma(texture(scene, uv + vec2(1.0, 1.0) * t).rgb);
+	float luma_m = luma(centre.rgb);
+
+	float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+	float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+	vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+
+	float direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+	float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+
+	direction = clamp(direction * inverse_direction_min, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * t;
+
+	vec3 rgb_a = 0.5 * (texture(scene, uv + direction * (1.0 / 3.0 - 0.5)).rgb
+		+ texture(scene, uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+	vec3 rgb_b = rgb_a * 0.5 + 0.25 * (texture(scene, uv + direction * -0.5).rgb
+		+ texture(scene, uv + direction * 0.5).rgb);
+
+	float luma_b = luma(rgb_b);
+
+	out_colour = vec4((luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b, centre.a);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(push_constant) uniform u_push_constants
+{
+	float intensity;
+	float smoothness;
+} push_constants;
+
+layout(location = 0) in vec2 in_tex_coord;
+
+layout(location = 0) out vec4 out_colour;
+
+void main()
+{
+	vec4 colour = texture(scene, in_tex_coord);
+
+	float distance_from_centre = length(in_tex_coord - 0.5) * 1.41421356;
+	float falloff = smoothstep(1.0 - push_constants.smoothness, 1.0, distance_from_centre);
+
+	out_colour = vec4(colour.rgb * (1.0 - falloff * push_constants.intensity), colour.a);
+}
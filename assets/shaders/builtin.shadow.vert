@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 in_position;
+layout(location = 3) in mat4 in_model;
+
+layout(push_constant) uniform u_push_constants
+{
+	mat4 view_projection;
+} push_constants;
+
+void main()
+{
+	gl_Position = push_constants.view_projection * in_model * vec4(in_position, 1.0);
+}
@@ -2,3 +2,33 @@
 
 glslc builtin.meshshader.vert -o builtin.meshshader.vert.spv
 glslc builtin.meshshader.frag -o builtin.meshshader.frag.spv
+glslc builtin.shadow.vert -o builtin.shadow.vert.spv
+glslc builtin.cull.comp -o builtin.cull.comp.spv
+glslc builtin.pbr.vert -o builtin.pbr.vert.spv
+glslc builtin.pbr.frag -o builtin.pbr.frag.spv
+glslc builtin.ibl.equirect_to_cube.comp -o builtin.ibl.equirect_to_cube.comp.spv
+glslc builtin.ibl.irradiance.comp -o builtin.ibl.irradiance.comp.spv
+glslc builtin.ibl.prefilter.comp -o builtin.ibl.prefilter.comp.spv
+glslc builtin.ibl.brdf_lut.comp -o builtin.ibl.brdf_lut.comp.spv
+glslc builtin.fullscreen.vert -o builtin.fullscreen.vert.spv
+glslc builtin.post.bloom_downsample.frag -o builtin.post.bloom_downsample.frag.spv
+glslc builtin.post.bloom_upsample.frag -o builtin.post.bloom_upsample.frag.spv
+glslc builtin.post.bloom_composite.frag -o builtin.post.bloom_composite.frag.spv
+glslc builtin.post.tonemap.frag -o builtin.post.tonemap.frag.spv
+glslc builtin.post.fxaa.frag -o builtin.post.fxaa.frag.spv
+glslc builtin.post.color_grading.frag -o builtin.post.color_grading.frag.spv
+glslc builtin.post.vignette.frag -o builtin.post.vignette.frag.spv
+glslc builtin.post.composite.frag -o builtin.post.composite.frag.spv
+glslc builtin.fullscreen.vert -o builtin.fullscreen.vert.spv
+glslc builtin.post.bloom_downsample.frag -o builtin.post.bloom_downsample.frag.spv
+glslc builtin.post.bloom_upsample.frag -o builtin.post.bloom_upsample.frag.spv
+glslc builtin.post.bloom_composite.frag -o builtin.post.bloom_composite.frag.spv
+glslc builtin.post.tonemap.frag -o builtin.post.tonemap.frag.spv
+glslc builtin.post.fxaa.frag -o builtin.post.fxaa.frag.spv
+glslc builtin.post.color_grading.frag -o builtin.post.color_grading.frag.spv
+glslc builtin.post.vignette.frag -o builtin.post.vignette.frag.spv
+glslc builtin.post.composite.frag -o builtin.post.composite.frag.spv
+glslc builtin.deferred.gbuffer.frag -o builtin.deferred.gbuffer.frag.spv
+glslc builtin.deferred.lighting.frag -o builtin.deferred.lighting.frag.spv
+glslc builtin.deferred.light_volume.vert -o builtin.deferred.light_volume.vert.spv
+glslc builtin.deferred.light_volume.frag -o builtin.deferred.light_volume.frag.spv
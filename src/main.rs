@@ -1,106 +1,341 @@
-use std::mem::size_of;
+use std::cell::{Cell, RefCell};
 
-use ash::vk::{self, AttachmentDescription, SubpassDependency};
-use lise::{math::vec2::Vec2UI, node::Node, renderer::{self, frame_buffer::Framebuffer, render_pass::{RenderPass, RenderPassSubPassInfo}, shader::{Shader, ShaderDescriptorInfo, ShaderDescriptorSetInfo, ShaderDescriptorTypeInfo, ShaderPushConstantInfo, ShaderStageInfo, ShaderType, ShaderVertexAttributeInfo}, vkcontext::VkContext, Renderer}, utility::Clock};
+use ash::vk;
+use lise::{math::{mat4::Mat4, vec2::{Vec2F, Vec2UI}, vec3::Vec3F}, node::Node, renderer::{self, deferred::{DeferredRenderer, LightVolume, LocalLight, RenderPath}, gpu_driven::{GpuObject, GpuScene}, hdr::HdrImage, ibl::{ImageBasedLighting, LightingParameters, LIGHTING_DESCRIPTOR_SET}, material::{Material, MaterialValue}, mesh::{InstanceData, Mesh, MeshInstance, Vertex}, pipeline::{PipelineRenderTarget, PipelineStateInfo}, post_process::{ColorGradingLut, PostProcessEffect, PostProcessSettings, PostProcessStack, POST_PROCESS_FORMAT}, render_graph::{AttachmentLoad, BufferAccess, GraphBuffer, GraphImage, ImageAccess, ImportedImage, RenderGraph, RenderGraphPass, TransientImageDescription}, render_queue::{InstanceBuffer, RenderQueue}, shader::{watcher::ShaderWatcher, Shader, ShaderDescriptorInfo, ShaderDescriptorSetInfo, ShaderDescriptorTypeInfo, ShaderStageConfig, ShaderStageInfo, ShaderType, ShaderUniformFieldInfo, ShaderVertexAttributeInfo}, shadow::{DirectionalLight, ShadowCamera, ShadowDepthBias, ShadowMaps, SpotLight, SHADOW_DESCRIPTOR_SET, SHADOW_MAP_FORMAT}, texture::Texture, upload::{UploadManager, DEFAULT_STAGING_BUFFER_SIZE}, utility, vkcontext::VkContextBuilder, Renderer}, utility::Clock};
 use simple_logger::SimpleLogger;
-use simple_window::{Window, WindowEvent};
+use simple_window::{Keys, Window, WindowEvent, WindowInputEvent};
+
+/// The render graph and the resources imported into it, rebuilt whenever the sample count or render path changes.
+struct FrameGraph<'g> {
+    render_graph: RenderGraph<'g>,
+    swapchain_image: GraphImage,
+    shadow_atlas: GraphImage,
+    draw_commands: GraphBuffer,
+    draw_count: GraphBuffer,
+    /// The deferred renderer's output, imported as the scene colour when rendering deferred.
+    deferred_output: Option<GraphImage>,
+}
 
 fn main() {
     SimpleLogger::new().init().unwrap();
 
     let mut window = Window::new("LiSE Test", 200, 200, 400, 500);
 
-    let vkcontext = VkContext::new(&window);
+    let vkcontext = VkContextBuilder::new()
+        .application_name("LiSE Test")
+        .build(&window);
 
     let mut renderer = Renderer::new(&vkcontext);
 
-    let world_render_pass = RenderPass::new(
+    let swapchain_format = renderer.swapchain.swapchain_properties.format.format;
+
+    // M cycles through the supported sample counts.
+    let sample_counts = [
+        vk::SampleCountFlags::TYPE_1,
+        vk::SampleCountFlags::TYPE_2,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_8,
+    ]
+    .into_iter()
+    .filter(|&samples| vkcontext.supported_sample_counts().contains(samples))
+    .collect::<Vec<_>>();
+
+    let mut sample_count = vkcontext.clamp_sample_count(vk::SampleCountFlags::TYPE_4);
+
+    // D switches between forward and deferred rendering. The deferred path doesn't use MSAA.
+    let mut render_path = RenderPath::Forward;
+
+    let alpha_blend = vk::PipelineColorBlendAttachmentState {
+        blend_enable: vk::TRUE,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A,
+    };
+
+    let create_mesh_shader = |name: &str, vertex_bindings: &[vk::VertexInputBindingDescription], vertex_attributes: &[ShaderVertexAttributeInfo]| Shader::new(
         &vkcontext,
-        Vec2UI::default(),
-        renderer.get_render_area_size(),
+        name,
+        &PipelineRenderTarget::Dynamic {
+            color_attachment_formats: &[POST_PROCESS_FORMAT],
+            depth_attachment_format: vk::Format::UNDEFINED,
+            stencil_attachment_format: vk::Format::UNDEFINED,
+        },
+        &[alpha_blend],
+        vertex_bindings,
+        vertex_attributes,
+        &[],
         &[
-            AttachmentDescription::default()
-                .format(renderer.swapchain.swapchain_properties.format.format)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR),
+            ShaderDescriptorSetInfo {
+                max_set_allocations: 1 * renderer::MAX_FRAMES_IN_FLIGHT,
+                descriptors: &[
+                    ShaderDescriptorInfo {
+                        name: "camera",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { 
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "projection", field_type: ShaderType::Matrix4 },
+                                ShaderUniformFieldInfo { name: "view", field_type: ShaderType::Matrix4 },
+                            ],
+                        },
+                        stage_flags: vk::ShaderStageFlags::VERTEX,
+                    },
+                ]
+            },
+            ShaderDescriptorSetInfo {
+                max_set_allocations: 1000 * renderer::MAX_FRAMES_IN_FLIGHT,
+                descriptors: &[
+                    ShaderDescriptorInfo {
+                        name: "material",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { 
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "diffuse_color", field_type: ShaderType::Float32_4 },
+                            ],
+                        },
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "diffuse_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                ],
+            },
+            SHADOW_DESCRIPTOR_SET,
         ],
         &[
-            Some(vk::ClearValue { color: vk::ClearColorValue { float32: [0.4f32, 0.5f32, 0.6f32, 0f32] } }),
+            ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.meshshader.vert.spv"),
+            ShaderStageInfo::new(vk::ShaderStageFlags::FRAGMENT, "shaders/builtin.meshshader.frag.spv"),
         ],
+        &PipelineStateInfo::get_default_pipeline_state_info().depth_test(false).sample_count(sample_count),
+    );
+
+    let mesh_shader = create_mesh_shader(
+        "LiSE Test",
+        &[ Vertex::get_binding_description(0), InstanceData::get_binding_description(1) ],
+        &[ Vertex::get_attributes(0).as_slice(), InstanceData::get_attributes(1).as_slice() ].concat(),
+    );
+
+    // The GPU-driven path reads the same instance attributes from its object buffer, whose stride is larger.
+    let gpu_mesh_shader = vkcontext.is_draw_indirect_count_enabled().then(|| create_mesh_shader(
+        "LiSE Test GPU-driven",
+        &[ Vertex::get_binding_description(0), GpuObject::get_binding_description(1) ],
+        &[ Vertex::get_attributes(0).as_slice(), GpuObject::get_attributes(1).as_slice() ].concat(),
+    ));
+
+    // The PBR stages, with their entry points and specialization constants, come from the shader's JSON. Variants
+    // share the SPIR-V and differ only in their specialization constants.
+    let create_pbr_shader = |name: &str| Shader::new(
+        &vkcontext,
+        name,
+        &PipelineRenderTarget::Dynamic {
+            color_attachment_formats: &[POST_PROCESS_FORMAT],
+            depth_attachment_format: vk::Format::UNDEFINED,
+            stencil_attachment_format: vk::Format::UNDEFINED,
+        },
+        &[alpha_blend],
+        &[ Vertex::get_binding_description(0), InstanceData::get_binding_description(1) ],
+        &[ Vertex::get_attributes(0).as_slice(), InstanceData::get_attributes(1).as_slice() ].concat(),
+        &[],
         &[
-            RenderPassSubPassInfo {
-                bind_point: vk::PipelineBindPoint::GRAPHICS,
-                input_attachments: &[],
-                color_attachments: Some(&[
-                    vk::AttachmentReference {
-                        attachment: 0,
-                        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                    }
-                ]),
-                resolve_attachments: None,
-                depth_stencil_attachments: None,
-                preserve_attachments: None,
+            ShaderDescriptorSetInfo {
+                max_set_allocations: 1 * renderer::MAX_FRAMES_IN_FLIGHT,
+                descriptors: &[
+                    ShaderDescriptorInfo {
+                        name: "camera",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer {
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "projection", field_type: ShaderType::Matrix4 },
+                                ShaderUniformFieldInfo { name: "view", field_type: ShaderType::Matrix4 },
+                            ],
+                        },
+                        stage_flags: vk::ShaderStageFlags::VERTEX,
+                    },
+                ]
+            },
+            ShaderDescriptorSetInfo {
+                max_set_allocations: 1000 * renderer::MAX_FRAMES_IN_FLIGHT,
+                descriptors: &[
+                    ShaderDescriptorInfo {
+                        name: "material",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer {
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "base_color_factor", field_type: ShaderType::Float32_4 },
+                                ShaderUniformFieldInfo { name: "emissive_factor", field_type: ShaderType::Float32_4 },
+                                ShaderUniformFieldInfo { name: "metallic_factor", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "roughness_factor", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "normal_scale", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "occlusion_strength", field_type: ShaderType::Float32 },
+                            ],
+                        },
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "base_color_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "metallic_roughness_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "normal_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "occlusion_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "emissive_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                ],
             },
+            SHADOW_DESCRIPTOR_SET,
+            LIGHTING_DESCRIPTOR_SET,
         ],
-        &[
-            SubpassDependency {
-                src_subpass: vk::SUBPASS_EXTERNAL,
-                dst_subpass: 0,
-                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                src_access_mask: vk::AccessFlags::default(),
-                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                dependency_flags: vk::DependencyFlags::default(),
+        &ShaderStageConfig::load_stages(format!("shaders/{}.json", name)).iter()
+            .map(ShaderStageConfig::as_stage_info)
+            .collect::<Vec<_>>(),
+        &PipelineStateInfo::get_default_pipeline_state_info().depth_test(false).sample_count(sample_count),
+    );
+
+    let pbr_shader = create_pbr_shader("builtin.pbr");
+    let pbr_alpha_test_shader = create_pbr_shader("builtin.pbr.alpha_test");
+
+    // Scene.
+    let mut upload_manager = UploadManager::new(&vkcontext, DEFAULT_STAGING_BUFFER_SIZE);
+
+    let normal = Vec3F::new(0.0, 0.0, 1.0);
+
+    let quad_vertices = [
+        Vertex { position: Vec3F::new(-0.5, -0.5, 0.0), texture_coordinate: Vec2F { x: 0.0, y: 0.0 }, normal },
+        Vertex { position: Vec3F::new(0.5, -0.5, 0.0), texture_coordinate: Vec2F { x: 1.0, y: 0.0 }, normal },
+        Vertex { position: Vec3F::new(0.5, 0.5, 0.0), texture_coordinate: Vec2F { x: 1.0, y: 1.0 }, normal },
+        Vertex { position: Vec3F::new(-0.5, 0.5, 0.0), texture_coordinate: Vec2F { x: 0.0, y: 1.0 }, normal },
+    ];
+    let quad_indices = [0, 1, 2, 2, 3, 0];
+
+    let (quad, _) = Mesh::new(&vkcontext, &mut upload_manager, "Quad".to_string(), &quad_vertices, &quad_indices);
+
+    let (white_texture, _) = Texture::new(
+        &vkcontext,
+        &mut upload_manager,
+        "builtin.white",
+        Vec2UI { x: 1, y: 1 },
+        vk::Format::R8G8B8A8_UNORM,
+        &[255, 255, 255, 255],
+    );
+
+    let (flat_normal_texture, _) = Texture::new(
+        &vkcontext,
+        &mut upload_manager,
+        "builtin.flat_normal",
+        Vec2UI { x: 1, y: 1 },
+        vk::Format::R8G8B8A8_UNORM,
+        &[128, 128, 255, 255],
+    );
+
+    // A checkerboard of opaque and fully transparent texels for the alpha-tested quad.
+    let (cutout_texture, _) = Texture::new(
+        &vkcontext,
+        &mut upload_manager,
+        "builtin.cutout",
+        Vec2UI { x: 8, y: 8 },
+        vk::Format::R8G8B8A8_UNORM,
+        &(0..64).flat_map(|i| [255, 255, 255, if (i % 8 + i / 8) % 2 == 0 { 255 } else { 0 }]).collect::<Vec<u8>>(),
+    );
+
+    // A procedural sky stands in for an environment loaded with HdrImage::load: a bright zenith fading to the
+    // horizon, a dark ground and a small, very bright sun.
+    let sky = {
+        let size = Vec2UI { x: 128, y: 64 };
+        let sun_direction = Vec3F::new(-0.3, 0.4, 1.0);
+        let sun_length = (sun_direction.x * sun_direction.x + sun_direction.y * sun_direction.y + sun_direction.z * sun_direction.z).sqrt();
+
+        let pixels = (0..size.y).flat_map(|y| (0..size.x).map(move |x| (x, y))).map(|(x, y)| {
+            let phi = (x as f32 + 0.5) / size.x as f32 * std::f32::consts::TAU - std::f32::consts::PI;
+            let theta = (y as f32 + 0.5) / size.y as f32 * std::f32::consts::PI;
+            let direction = Vec3F::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+
+            let sun = (direction.x * sun_direction.x + direction.y * sun_direction.y + direction.z * sun_direction.z) / sun_length;
+
+            if sun > 0.995 {
+                [40.0, 38.0, 34.0, 1.0]
+            } else if direction.y >= 0.0 {
+                let t = direction.y.powf(0.5);
+                [0.8 - 0.5 * t, 0.9 - 0.4 * t, 1.0, 1.0]
+            } else {
+                [0.15, 0.13, 0.1, 1.0]
             }
-        ]
+        })
+        .collect();
+
+        HdrImage::new(size, pixels)
+    };
+
+    let (environment_lighting, _) = ImageBasedLighting::new(&vkcontext, &mut upload_manager, &sky, 256);
+    let environment_lighting = RefCell::new(environment_lighting);
+
+    let camera_position = Vec3F::new(0.0, 0.0, 2.0);
+    let view = Mat4::look_at(camera_position, Vec3F::new(0.0, 0.0, 0.0), Vec3F::new(0.0, 1.0, 0.0));
+    let render_area_size = renderer.get_render_area_size();
+    let projection = Mat4::perspective(
+        60f32.to_radians(),
+        render_area_size.x as f32 / render_area_size.y as f32,
+        0.1,
+        100.0,
     );
 
-    let framebuffers = (0..renderer.swapchain.image_views.len()).map(|i| {
-        let attachments = [renderer.swapchain.image_views[i]];
+    let camera = RefCell::new(Material::new(&vkcontext, "Camera", &mesh_shader, 0));
+    camera.borrow_mut().set_parameter("projection", MaterialValue::Matrix4(projection.as_array()));
+    camera.borrow_mut().set_parameter("view", MaterialValue::Matrix4(view.as_array()));
 
-        Framebuffer::new(&vkcontext, world_render_pass.handle, &attachments, renderer.get_render_area_size())
-    })
-    .collect::<Vec<_>>();
+    let material = RefCell::new(Material::new(&vkcontext, "Default", &mesh_shader, 1));
+    material.borrow_mut().set_parameter("diffuse_color", MaterialValue::Float32_4([1.0, 0.5, 0.2, 1.0]));
+    material.borrow_mut().set_texture("diffuse_texture", &white_texture);
+
+    let pbr_material = RefCell::new(Material::load(&vkcontext, "materials/builtin.pbr.material.json", &pbr_shader, |name| {
+        [&white_texture, &flat_normal_texture].into_iter().find(|texture| texture.name == name)
+    }));
+    pbr_material.borrow_mut().set_parameter("metallic_factor", MaterialValue::Float32(0.8));
+    pbr_material.borrow_mut().set_parameter("roughness_factor", MaterialValue::Float32(0.35));
 
-    let mesh_shader = Shader::new(
+    let pbr_alpha_test_material = RefCell::new(Material::load(&vkcontext, "materials/builtin.pbr.alpha_test.material.json", &pbr_alpha_test_shader, |name| {
+        [&white_texture, &flat_normal_texture, &cutout_texture].into_iter().find(|texture| texture.name == name)
+    }));
+
+    // The deferred path draws the same quads into a G-buffer and lights them with a few local lights as well.
+    let (deferred_renderer, _) = DeferredRenderer::new(&vkcontext, &mut upload_manager, render_area_size, [0.4, 0.5, 0.6, 0.0]);
+
+    let gbuffer_shader = Shader::new(
         &vkcontext,
-        "LiSE Test",
-        world_render_pass.handle,
-        0,
-        &[
-            vk::PipelineColorBlendAttachmentState {
-                blend_enable: vk::TRUE,
-                src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
-                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                color_blend_op: vk::BlendOp::ADD,
-                src_alpha_blend_factor: vk::BlendFactor::SRC_ALPHA,
-                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                alpha_blend_op: vk::BlendOp::ADD,
-                color_write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            },
-        ],
-        &[ Vertex::get_binding_description(0) ],
-        &[
-            ShaderVertexAttributeInfo { attribute_type: ShaderType::Float32_3, binding: 0 },
-            ShaderVertexAttributeInfo { attribute_type: ShaderType::Float32_3, binding: 0 },
-            ShaderVertexAttributeInfo { attribute_type: ShaderType::Float32_2, binding: 0 },
-        ],
-        &[
-            ShaderPushConstantInfo { push_constant_type: ShaderType::Matrix4, stage_flags: vk::ShaderStageFlags::VERTEX },
-        ],
+        "builtin.deferred.gbuffer",
+        &deferred_renderer.geometry_render_target(),
+        &DeferredRenderer::GEOMETRY_COLOR_BLEND_ATTACHMENT_STATES,
+        &[ Vertex::get_binding_description(0), InstanceData::get_binding_description(1) ],
+        &[ Vertex::get_attributes(0).as_slice(), InstanceData::get_attributes(1).as_slice() ].concat(),
+        &[],
         &[
             ShaderDescriptorSetInfo {
                 max_set_allocations: 1 * renderer::MAX_FRAMES_IN_FLIGHT,
                 descriptors: &[
                     ShaderDescriptorInfo {
-                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { 
-                            fields: &[ ShaderType::Matrix4, ShaderType::Matrix4 ],
+                        name: "camera",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer {
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "projection", field_type: ShaderType::Matrix4 },
+                                ShaderUniformFieldInfo { name: "view", field_type: ShaderType::Matrix4 },
+                            ],
                         },
                         stage_flags: vk::ShaderStageFlags::VERTEX,
                     },
@@ -110,12 +345,41 @@ fn main() {
                 max_set_allocations: 1000 * renderer::MAX_FRAMES_IN_FLIGHT,
                 descriptors: &[
                     ShaderDescriptorInfo {
-                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { 
-                            fields: &[ ShaderType::Float32_4 ],
+                        name: "material",
+                        descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer {
+                            fields: &[
+                                ShaderUniformFieldInfo { name: "base_color_factor", field_type: ShaderType::Float32_4 },
+                                ShaderUniformFieldInfo { name: "emissive_factor", field_type: ShaderType::Float32_4 },
+                                ShaderUniformFieldInfo { name: "metallic_factor", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "roughness_factor", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "normal_scale", field_type: ShaderType::Float32 },
+                                ShaderUniformFieldInfo { name: "occlusion_strength", field_type: ShaderType::Float32 },
+                            ],
                         },
                         stage_flags: vk::ShaderStageFlags::FRAGMENT,
                     },
                     ShaderDescriptorInfo {
+                        name: "base_color_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "metallic_roughness_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "normal_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "occlusion_texture",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "emissive_texture",
                         descriptor_type: ShaderDescriptorTypeInfo::Sampler,
                         stage_flags: vk::ShaderStageFlags::FRAGMENT,
                     },
@@ -123,18 +387,290 @@ fn main() {
             },
         ],
         &[
-            ShaderStageInfo {
-                stage_type: vk::ShaderStageFlags::VERTEX,
-                stage_file: "shaders/builtin.meshshader.vert.spv",
-            },
-            ShaderStageInfo {
-                stage_type: vk::ShaderStageFlags::FRAGMENT,
-                stage_file: "shaders/builtin.meshshader.frag.spv",
-            },
+            ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.pbr.vert.spv"),
+            ShaderStageInfo::new(vk::ShaderStageFlags::FRAGMENT, "shaders/builtin.deferred.gbuffer.frag.spv"),
         ],
-        false,
+        &PipelineStateInfo::get_default_pipeline_state_info().depth_test(true),
+    );
+
+    let gbuffer_material = RefCell::new(Material::load(&vkcontext, "materials/builtin.deferred.gbuffer.material.json", &gbuffer_shader, |name| {
+        [&white_texture, &flat_normal_texture].into_iter().find(|texture| texture.name == name)
+    }));
+    gbuffer_material.borrow_mut().set_parameter("metallic_factor", MaterialValue::Float32(0.8));
+    gbuffer_material.borrow_mut().set_parameter("roughness_factor", MaterialValue::Float32(0.35));
+
+    let deferred_renderer = RefCell::new(deferred_renderer);
+
+    let local_lights = [
+        LocalLight {
+            volume: LightVolume::Point { position: Vec3F::new(-1.2, 0.6, 0.4), range: 1.5 },
+            color: Vec3F::new(1.0, 0.6, 0.3),
+            intensity: 2.0,
+        },
+        LocalLight {
+            volume: LightVolume::Point { position: Vec3F::new(1.2, -0.6, 0.4), range: 1.5 },
+            color: Vec3F::new(0.3, 0.6, 1.0),
+            intensity: 2.0,
+        },
+        LocalLight {
+            volume: LightVolume::Spot {
+                cone: SpotLight {
+                    position: Vec3F::new(0.0, 1.2, 1.2),
+                    direction: Vec3F::new(0.0, -1.0, -1.0),
+                    outer_angle: 30f32.to_radians(),
+                    range: 4.0,
+                },
+                inner_angle: 20f32.to_radians(),
+            },
+            color: Vec3F::new(1.0, 1.0, 1.0),
+            intensity: 6.0,
+        },
+    ];
+
+    let instance_buffer = RefCell::new(InstanceBuffer::new(&vkcontext));
+
+    let quad_instances = [(-1.2, [1.0, 0.3, 0.3, 1.0]), (0.0, [1.0; 4]), (1.2, [0.3, 0.3, 1.0, 1.0])].map(|(x, color)| {
+        InstanceData { model: Mat4::from_translation(Vec3F::new(x, 0.0, 0.0)), color }
+    });
+
+    // The quads cast shadows onto the grid behind them.
+    let shadow_maps = RefCell::new(ShadowMaps::new(&vkcontext, 2048, 1024, ShadowDepthBias::default()));
+    let shadow_instance_buffer = RefCell::new(InstanceBuffer::new(&vkcontext));
+
+    let sun = DirectionalLight {
+        direction: Vec3F::new(0.3, -0.4, -1.0),
+        cascade_count: 2,
+        max_distance: 10.0,
+    };
+
+    let shadow_camera = ShadowCamera {
+        view,
+        fov_y: 60f32.to_radians(),
+        aspect_ratio: render_area_size.x as f32 / render_area_size.y as f32,
+        near: 0.1,
+    };
+
+    // A grid of small quads behind the instanced ones, culled and drawn entirely on the GPU.
+    let gpu_scene = gpu_mesh_shader.as_ref().map(|_| {
+        let mut gpu_scene = GpuScene::new(&vkcontext, 1024, 1024, 1024);
+        let (gpu_quad, _) = gpu_scene.add_mesh(&mut upload_manager, &quad_vertices, &quad_indices);
+
+        for y in -8..8 {
+            for x in -8..8 {
+                let position = Vec3F::new(x as f32 * 0.4, y as f32 * 0.4, -2.0);
+
+                gpu_scene.add_object(gpu_quad, InstanceData {
+                    model: Mat4::from_translation(position) * Mat4::from_scale(Vec3F::new(0.3, 0.3, 0.3)),
+                    color: [0.5 + x as f32 / 16.0, 0.5 + y as f32 / 16.0, 0.5, 1.0],
+                });
+            }
+        }
+
+        RefCell::new(gpu_scene)
+    });
+
+    // The world renders into an HDR image that the post-process stack tonemaps into the swapchain image.
+    let (post_process, _) = PostProcessStack::new(
+        &vkcontext,
+        &mut upload_manager,
+        swapchain_format,
+        PostProcessSettings {
+            effects: vec![
+                PostProcessEffect::Bloom { threshold: 1.0, intensity: 0.08, radius: 1.0, mip_count: 5 },
+                PostProcessEffect::Tonemap { exposure: 1.2 },
+                PostProcessEffect::ColorGrading { intensity: 1.0 },
+                PostProcessEffect::Fxaa,
+                PostProcessEffect::Vignette { intensity: 0.4, smoothness: 0.6 },
+            ],
+        },
+        &ColorGradingLut::from_fn(16, |[red, green, blue]| [red * 1.05, green, blue * 0.92]),
     );
 
+    let current_frame = Cell::new(0u32);
+
+    // Changing the sample count or the render path rebuilds the whole graph.
+    let build_render_graph = |sample_count: vk::SampleCountFlags, render_path: RenderPath| {
+        let mut render_graph = RenderGraph::new(&vkcontext);
+
+        let swapchain_image = render_graph.import_image(
+            "Swapchain",
+            swapchain_format,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        );
+
+        let shadow_atlas = render_graph.import_image(
+            "ShadowAtlas",
+            SHADOW_MAP_FORMAT,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        // The deferred renderer owns its output, since its render pass and framebuffer are built around it.
+        let deferred_output = (render_path == RenderPath::Deferred).then(|| render_graph.import_image(
+            "SceneColor",
+            POST_PROCESS_FORMAT,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        ));
+
+        let scene_color = deferred_output.unwrap_or_else(|| render_graph.create_image(
+            "SceneColor",
+            TransientImageDescription { size: render_area_size, format: POST_PROCESS_FORMAT, samples: vk::SampleCountFlags::TYPE_1 },
+        ));
+
+        // With MSAA the world renders into a multisampled image that is resolved into the scene colour.
+        let multisampled_scene_color = (render_path == RenderPath::Forward && sample_count != vk::SampleCountFlags::TYPE_1).then(|| render_graph.create_image(
+            "MultisampledSceneColor",
+            TransientImageDescription { size: render_area_size, format: POST_PROCESS_FORMAT, samples: sample_count },
+        ));
+
+        let draw_commands = render_graph.import_buffer("DrawCommands");
+        let draw_count = render_graph.import_buffer("DrawCount");
+
+        // Builds the environment maps on the first frame; nothing is recorded afterwards.
+        render_graph.add_pass(
+            RenderGraphPass::new("EnvironmentLighting")
+                .side_effects()
+                .execute(|context| environment_lighting.borrow_mut().record_generate(context.command_buffer))
+        );
+
+        render_graph.add_pass(
+            RenderGraphPass::new("Shadows")
+                .depth_attachment(
+                    shadow_atlas,
+                    AttachmentLoad::Clear(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }),
+                )
+                .execute(|context| {
+                    let frame_index = current_frame.get();
+
+                    shadow_maps.borrow().record(context.command_buffer, |command_buffer, _| {
+                        quad.bind(command_buffer);
+                        shadow_instance_buffer.borrow_mut().write_and_bind(command_buffer, frame_index, 1, &quad_instances);
+                        quad.draw_instanced(command_buffer, quad_instances.len() as u32, 0);
+                    });
+                })
+        );
+
+        if let Some(deferred_output) = deferred_output {
+            render_graph.add_pass(
+                RenderGraphPass::new("Deferred")
+                    .render_pass_attachment(deferred_output, ImageAccess::ColorAttachment)
+                    .execute(|context| {
+                        let frame_index = current_frame.get();
+                        let camera = camera.borrow();
+                        let gbuffer_material = gbuffer_material.borrow();
+
+                        let mut render_queue = RenderQueue::new();
+
+                        for instance in quad_instances {
+                            render_queue.push_instance(MeshInstance { mesh: &quad, material: &gbuffer_material, instance });
+                        }
+
+                        render_queue.prepare(&(projection * view), camera_position);
+
+                        deferred_renderer.borrow().record(
+                            context.command_buffer,
+                            frame_index,
+                            &environment_lighting.borrow(),
+                            &local_lights,
+                            |command_buffer| render_queue.record(
+                                command_buffer,
+                                frame_index,
+                                &mut instance_buffer.borrow_mut(),
+                                1,
+                                |command_buffer, _| camera.bind(command_buffer, frame_index),
+                            ),
+                        );
+                    })
+            );
+        } else {
+            let clear_color = AttachmentLoad::Clear(vk::ClearValue { color: vk::ClearColorValue { float32: [0.4f32, 0.5f32, 0.6f32, 0f32] } });
+
+            let mut world_pass = match multisampled_scene_color {
+                Some(multisampled_scene_color) => RenderGraphPass::new("World")
+                    .color_attachment(multisampled_scene_color, clear_color)
+                    .resolve_attachment(multisampled_scene_color, scene_color),
+                None => RenderGraphPass::new("World").color_attachment(scene_color, clear_color),
+            }
+            .image(shadow_atlas, ImageAccess::Sampled { stage: vk::PipelineStageFlags2::FRAGMENT_SHADER });
+
+            if let Some(gpu_scene) = &gpu_scene {
+                render_graph.add_pass(
+                    RenderGraphPass::new("Cull")
+                        .buffer(draw_commands, BufferAccess::StorageWrite { stage: vk::PipelineStageFlags2::COMPUTE_SHADER })
+                        .buffer(draw_count, BufferAccess::StorageWrite { stage: vk::PipelineStageFlags2::COMPUTE_SHADER })
+                        .execute(|context| gpu_scene.borrow().record_cull(context.command_buffer, current_frame.get()))
+                );
+
+                world_pass = world_pass
+                    .buffer(draw_commands, BufferAccess::IndirectRead)
+                    .buffer(draw_count, BufferAccess::IndirectRead);
+            }
+
+            render_graph.add_pass(
+                world_pass.execute(|context| {
+                    let frame_index = current_frame.get();
+                    let camera = camera.borrow();
+                    let material = material.borrow();
+                    let pbr_material = pbr_material.borrow();
+                    let pbr_alpha_test_material = pbr_alpha_test_material.borrow();
+                    let shadow_maps = shadow_maps.borrow();
+                    let environment_lighting = environment_lighting.borrow();
+
+                    utility::set_viewport_and_scissor(
+                        &vkcontext.device,
+                        context.command_buffer,
+                        Vec2UI::default(),
+                        context.render_area.unwrap(),
+                    );
+
+                    if let (Some(gpu_mesh_shader), Some(gpu_scene)) = (&gpu_mesh_shader, &gpu_scene) {
+                        gpu_mesh_shader.bind(context.command_buffer);
+                        camera.bind(context.command_buffer, frame_index);
+                        shadow_maps.bind(context.command_buffer, gpu_mesh_shader, 2, frame_index);
+                        material.bind(context.command_buffer, frame_index);
+                        gpu_scene.borrow().draw(context.command_buffer, frame_index, 1);
+                    }
+
+                    let mut render_queue = RenderQueue::new();
+
+                    // The last quad is cut out by the alpha-tested PBR variant.
+                    for (index, instance) in quad_instances.into_iter().enumerate() {
+                        let material = if index == quad_instances.len() - 1 { &pbr_alpha_test_material } else { &pbr_material };
+                        render_queue.push_instance(MeshInstance { mesh: &quad, material, instance });
+                    }
+
+                    render_queue.prepare(&(projection * view), camera_position);
+                    render_queue.record(
+                        context.command_buffer,
+                        frame_index,
+                        &mut instance_buffer.borrow_mut(),
+                        1,
+                        |command_buffer, shader| {
+                            camera.bind(command_buffer, frame_index);
+                            shadow_maps.bind(command_buffer, shader, 2, frame_index);
+
+                            if shader.name == pbr_shader.name || shader.name == pbr_alpha_test_shader.name {
+                                environment_lighting.bind(command_buffer, shader, 3, frame_index);
+                            }
+                        },
+                    );
+                })
+            );
+        }
+
+        post_process.add_passes(&mut render_graph, scene_color, swapchain_image, render_area_size);
+
+        render_graph.compile();
+
+        log::debug!("Render graph:\n{}", render_graph.to_dot());
+
+        FrameGraph { render_graph, swapchain_image, shadow_atlas, draw_commands, draw_count, deferred_output }
+    };
+
+    let mut frame_graph = build_render_graph(sample_count, render_path);
+
     // Node testing.
     let mut root = Node::new("Root", None);
     root.add_child(Node::new("C1", None));
@@ -151,18 +687,76 @@ fn main() {
     let mut sum_time = 0u32;
     let mut frame_sum = 0u32;
 
+    // Debug builds pick up shader changes without a restart.
+    let mut shader_watcher = cfg!(debug_assertions).then(|| ShaderWatcher::new(std::time::Duration::from_millis(500)));
+
     log::debug!("Entering game loop.");
     let mut is_running = true;
 
     while is_running {
         clock.reset();
 
+        let mut is_sample_count_switch_requested = false;
+        let mut is_render_path_switch_requested = false;
+
         window.poll_messages(|event| {
-            if let WindowEvent::Close = event {
-                is_running = false;
+            match event {
+                WindowEvent::Close => is_running = false,
+                WindowEvent::Input(WindowInputEvent::KeyDown(Keys::M)) => is_sample_count_switch_requested = true,
+                WindowEvent::Input(WindowInputEvent::KeyDown(Keys::D)) => is_render_path_switch_requested = true,
+                _ => (),
             }
         });
 
+        if is_sample_count_switch_requested {
+            let index = sample_counts.iter().position(|&samples| samples == sample_count).unwrap();
+            sample_count = sample_counts[(index + 1) % sample_counts.len()];
+
+            log::info!("Switching to {}x MSAA.", sample_count.as_raw());
+
+            // Pipelines and transient images of in-flight frames are about to be destroyed.
+            vkcontext.wait_gpu_idle();
+
+            for shader in [&mesh_shader, &pbr_shader, &pbr_alpha_test_shader].into_iter().chain(gpu_mesh_shader.as_ref()) {
+                shader.set_sample_count(sample_count);
+            }
+
+            frame_graph = build_render_graph(sample_count, render_path);
+        }
+
+        if is_render_path_switch_requested {
+            render_path = match render_path {
+                RenderPath::Forward => RenderPath::Deferred,
+                RenderPath::Deferred => RenderPath::Forward,
+            };
+
+            log::info!("Switching to the {:?} render path.", render_path);
+
+            vkcontext.wait_gpu_idle();
+
+            frame_graph = build_render_graph(sample_count, render_path);
+        }
+
+        let changed_shader_files = shader_watcher.as_mut().map(ShaderWatcher::poll).unwrap_or_default();
+
+        if !changed_shader_files.is_empty() {
+            // Pipelines of in-flight frames are about to be replaced.
+            vkcontext.wait_gpu_idle();
+
+            for shader in [&mesh_shader, &pbr_shader, &pbr_alpha_test_shader, &gbuffer_shader].into_iter().chain(gpu_mesh_shader.as_ref()) {
+                shader.reload(&changed_shader_files);
+            }
+
+            shadow_maps.borrow().caster_shader.reload(&changed_shader_files);
+            deferred_renderer.borrow().reload_shaders(&changed_shader_files);
+            post_process.reload_shaders(&changed_shader_files);
+            environment_lighting.borrow_mut().reload_shaders(&changed_shader_files);
+
+            if let Some(gpu_scene) = &gpu_scene {
+                gpu_scene.borrow().reload_shaders(&changed_shader_files);
+            }
+        }
+
         if sum_time >= 1000000 {
             log::debug!("It's been {} microseconds. {} frames have elapsed. FPS: {}", sum_time, frame_sum, frame_sum as f32 / (sum_time as f32 / 1000000f32));
             sum_time = 0;
@@ -170,12 +764,63 @@ fn main() {
         }
         
         renderer.prepare_frame();
+        renderer.acquire_uploads(&mut upload_manager);
 
-        world_render_pass.begin(renderer.get_current_command_buffer_handle(), framebuffers[renderer.current_image_index as usize].handle);
+        current_frame.set(renderer.current_frame);
+        post_process.prepare(renderer.current_frame);
+        instance_buffer.borrow_mut().prepare(renderer.current_frame);
+        shadow_instance_buffer.borrow_mut().prepare(renderer.current_frame);
+        camera.borrow_mut().update(renderer.current_frame);
+        material.borrow_mut().update(renderer.current_frame);
+        pbr_material.borrow_mut().update(renderer.current_frame);
+        pbr_alpha_test_material.borrow_mut().update(renderer.current_frame);
+        gbuffer_material.borrow_mut().update(renderer.current_frame);
+        deferred_renderer.borrow_mut().prepare(renderer.current_frame, &(projection * view));
+        environment_lighting.borrow_mut().prepare(renderer.current_frame, &LightingParameters {
+            camera_position,
+            light_direction: sun.direction,
+            light_color: Vec3F::new(1.0, 0.95, 0.9),
+            light_intensity: 3.0,
+            environment_intensity: 1.0,
+        });
+        shadow_maps.borrow_mut().prepare(renderer.current_frame, &shadow_camera, Some(&sun), &[]);
 
-        mesh_shader.bind(renderer.get_current_command_buffer_handle());
+        let mut imported_buffers = Vec::new();
 
-        world_render_pass.end(renderer.get_current_command_buffer_handle());
+        if let Some(gpu_scene) = &gpu_scene {
+            gpu_scene.borrow_mut().prepare(renderer.current_frame, &(projection * view));
+
+            imported_buffers.push((frame_graph.draw_commands, gpu_scene.borrow().draw_command_buffer(renderer.current_frame)));
+            imported_buffers.push((frame_graph.draw_count, gpu_scene.borrow().draw_count_buffer(renderer.current_frame)));
+        }
+
+        let image_index = renderer.current_image_index as usize;
+
+        let mut imported_images = vec![(frame_graph.swapchain_image, ImportedImage {
+            handle: renderer.swapchain.images[image_index],
+            view: renderer.swapchain.image_views[image_index],
+            size: renderer.get_render_area_size(),
+        }), (frame_graph.shadow_atlas, ImportedImage {
+            handle: shadow_maps.borrow().atlas.handle,
+            view: shadow_maps.borrow().atlas.image_view.unwrap(),
+            size: shadow_maps.borrow().atlas.size,
+        })];
+
+        if let Some(deferred_output) = frame_graph.deferred_output {
+            let deferred_renderer = deferred_renderer.borrow();
+
+            imported_images.push((deferred_output, ImportedImage {
+                handle: deferred_renderer.output.handle,
+                view: deferred_renderer.output.image_view.unwrap(),
+                size: deferred_renderer.output.size,
+            }));
+        }
+
+        frame_graph.render_graph.execute(
+            &renderer.command_buffers[renderer.current_frame as usize],
+            &imported_images,
+            &imported_buffers,
+        );
 
         renderer.submit_frame();
         sum_time += clock.elapsed() as u32;
@@ -186,20 +831,3 @@ fn main() {
         vkcontext.device.device_wait_idle().unwrap();
     }
 }
-
-#[derive(Clone, Copy)]
-#[repr(C)]
-struct Vertex {
-    pos: [f32; 3],
-    color: [f32; 3],
-    uv: [f32; 2],
-}
-
-impl Vertex {
-    fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::default()
-            .binding(binding)
-            .stride(size_of::<Vertex>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
-    }
-}
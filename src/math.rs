@@ -1,3 +1,5 @@
+pub mod frustum;
+pub mod mat4;
 pub mod vec2;
 pub mod vec3;
 pub mod vec4;
@@ -0,0 +1,85 @@
+use super::{mat4::{dot, sub, Mat4}, vec3::Vec3F};
+
+#[derive(Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Vec3F,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere around the axis-aligned bounds of `points`. Not minimal, but cheap and never too small.
+    pub fn from_points<I: IntoIterator<Item = Vec3F>>(points: I) -> Self {
+        let points = points.into_iter().collect::<Vec<_>>();
+
+        if points.is_empty() {
+            return Self { center: Vec3F::new(0.0, 0.0, 0.0), radius: 0.0 };
+        }
+
+        let (min, max) = points.iter().fold(
+            (points[0], points[0]),
+            |(min, max), p| {
+                (
+                    Vec3F::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    Vec3F::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                )
+            },
+        );
+
+        let center = Vec3F::new((min.x + max.x) * 0.5, (min.y + max.y) * 0.5, (min.z + max.z) * 0.5);
+
+        let radius = points.iter()
+            .map(|p| { let d = sub(*p, center); dot(d, d) })
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        Self { center, radius }
+    }
+
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let c = &transform.columns;
+        let center = Vec3F::new(
+            c[0][0] * self.center.x + c[1][0] * self.center.y + c[2][0] * self.center.z + c[3][0],
+            c[0][1] * self.center.x + c[1][1] * self.center.y + c[2][1] * self.center.z + c[3][1],
+            c[0][2] * self.center.x + c[1][2] * self.center.y + c[2][2] * self.center.z + c[3][2],
+        );
+
+        Self { center, radius: self.radius * transform.max_scale() }
+    }
+}
+
+/// The six planes of a view frustum, each as `(a, b, c, d)` with the normal pointing inwards.
+#[derive(Clone, Copy)]
+pub struct Frustum {
+    pub planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a Vulkan style projection * view matrix (depth in [0, 1]).
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let r = [view_projection.row(0), view_projection.row(1), view_projection.row(2), view_projection.row(3)];
+
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+        let planes = [
+            add(r[3], r[0]),
+            sub(r[3], r[0]),
+            add(r[3], r[1]),
+            sub(r[3], r[1]),
+            r[2],
+            sub(r[3], r[2]),
+        ]
+        .map(|p| {
+            let length = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+            [p[0] / length, p[1] / length, p[2] / length, p[3] / length]
+        });
+
+        Self { planes }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|p| {
+            p[0] * sphere.center.x + p[1] * sphere.center.y + p[2] * sphere.center.z + p[3] >= -sphere.radius
+        })
+    }
+}
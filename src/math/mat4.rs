@@ -0,0 +1,260 @@
+use std::ops::Mul;
+
+use super::vec3::Vec3F;
+
+/// A column-major 4x4 matrix, laid out the way GLSL expects a `mat4`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct Mat4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_translation(translation: Vec3F) -> Self {
+        let mut m = Self::IDENTITY;
+        m.columns[3] = [translation.x, translation.y, translation.z, 1.0];
+        m
+    }
+
+    pub fn from_scale(scale: Vec3F) -> Self {
+        let mut m = Self::IDENTITY;
+        m.columns[0][0] = scale.x;
+        m.columns[1][1] = scale.y;
+        m.columns[2][2] = scale.z;
+        m
+    }
+
+    /// A rotation of `angle` radians around the normalized `axis`.
+    pub fn from_axis_angle(axis: Vec3F, angle: f32) -> Self {
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        let (x, y, z) = (axis.x, axis.y, axis.z);
+
+        Self {
+            columns: [
+                [t * x * x + c, t * x * y + s * z, t * x * z - s * y, 0.0],
+                [t * x * y - s * z, t * y * y + c, t * y * z + s * x, 0.0],
+                [t * x * z + s * y, t * y * z - s * x, t * z * z + c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// A right-handed perspective projection with Vulkan's clip space: depth in [0, 1] and Y pointing down.
+    pub fn perspective(fov_y: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y * 0.5).tan();
+
+        Self {
+            columns: [
+                [f / aspect_ratio, 0.0, 0.0, 0.0],
+                [0.0, -f, 0.0, 0.0],
+                [0.0, 0.0, far / (near - far), -1.0],
+                [0.0, 0.0, near * far / (near - far), 0.0],
+            ],
+        }
+    }
+
+    /// An orthographic projection with Vulkan's clip space.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        Self {
+            columns: [
+                [2.0 / (right - left), 0.0, 0.0, 0.0],
+                [0.0, -2.0 / (top - bottom), 0.0, 0.0],
+                [0.0, 0.0, 1.0 / (near - far), 0.0],
+                [
+                    -(right + left) / (right - left),
+                    (top + bottom) / (top - bottom),
+                    near / (near - far),
+                    1.0,
+                ],
+            ],
+        }
+    }
+
+    /// A right-handed view matrix looking from `eye` towards `target`.
+    pub fn look_at(eye: Vec3F, target: Vec3F, up: Vec3F) -> Self {
+        let forward = normalize(sub(target, eye));
+        let side = normalize(cross(forward, up));
+        let up = cross(side, forward);
+
+        Self {
+            columns: [
+                [side.x, up.x, -forward.x, 0.0],
+                [side.y, up.y, -forward.y, 0.0],
+                [side.z, up.z, -forward.z, 0.0],
+                [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+            ],
+        }
+    }
+
+    pub fn row(&self, index: usize) -> [f32; 4] {
+        [self.columns[0][index], self.columns[1][index], self.columns[2][index], self.columns[3][index]]
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self { columns: [self.row(0), self.row(1), self.row(2), self.row(3)] }
+    }
+
+    pub fn transform_point(&self, point: Vec3F) -> Vec3F {
+        let c = &self.columns;
+
+        let x = c[0][0] * point.x + c[1][0] * point.y + c[2][0] * point.z + c[3][0];
+        let y = c[0][1] * point.x + c[1][1] * point.y + c[2][1] * point.z + c[3][1];
+        let z = c[0][2] * point.x + c[1][2] * point.y + c[2][2] * point.z + c[3][2];
+        let w = c[0][3] * point.x + c[1][3] * point.y + c[2][3] * point.z + c[3][3];
+
+        Vec3F::new(x / w, y / w, z / w)
+    }
+
+    pub fn translation(&self) -> Vec3F {
+        Vec3F::new(self.columns[3][0], self.columns[3][1], self.columns[3][2])
+    }
+
+    /// The largest scale factor along any of the matrix's axes, used to scale bounding volumes.
+    pub fn max_scale(&self) -> f32 {
+        (0..3).map(|i| {
+            let c = self.columns[i];
+            (c[0] * c[0] + c[1] * c[1] + c[2] * c[2]).sqrt()
+        })
+        .fold(0.0, f32::max)
+    }
+
+    /// The inverse of an affine transform (rotation, scale and translation, no projection).
+    pub fn inverse_affine(&self) -> Self {
+        let c = &self.columns;
+
+        let (a, b, cc) = ([c[0][0], c[0][1], c[0][2]], [c[1][0], c[1][1], c[1][2]], [c[2][0], c[2][1], c[2][2]]);
+
+        let det = a[0] * (b[1] * cc[2] - cc[1] * b[2]) - b[0] * (a[1] * cc[2] - cc[1] * a[2]) + cc[0] * (a[1] * b[2] - b[1] * a[2]);
+        let inv_det = 1.0 / det;
+
+        let r = [
+            [
+                (b[1] * cc[2] - cc[1] * b[2]) * inv_det,
+                (cc[1] * a[2] - a[1] * cc[2]) * inv_det,
+                (a[1] * b[2] - b[1] * a[2]) * inv_det,
+            ],
+            [
+                (cc[0] * b[2] - b[0] * cc[2]) * inv_det,
+                (a[0] * cc[2] - cc[0] * a[2]) * inv_det,
+                (b[0] * a[2] - a[0] * b[2]) * inv_det,
+            ],
+            [
+                (b[0] * cc[1] - cc[0] * b[1]) * inv_det,
+                (cc[0] * a[1] - a[0] * cc[1]) * inv_det,
+                (a[0] * b[1] - b[0] * a[1]) * inv_det,
+            ],
+        ];
+
+        let t = [c[3][0], c[3][1], c[3][2]];
+
+        let translation = [
+            -(r[0][0] * t[0] + r[1][0] * t[1] + r[2][0] * t[2]),
+            -(r[0][1] * t[0] + r[1][1] * t[1] + r[2][1] * t[2]),
+            -(r[0][2] * t[0] + r[1][2] * t[1] + r[2][2] * t[2]),
+        ];
+
+        Self {
+            columns: [
+                [r[0][0], r[0][1], r[0][2], 0.0],
+                [r[1][0], r[1][1], r[1][2], 0.0],
+                [r[2][0], r[2][1], r[2][2], 0.0],
+                [translation[0], translation[1], translation[2], 1.0],
+            ],
+        }
+    }
+
+    /// The general inverse, for matrices such as projections that `inverse_affine` can't handle. Singular matrices
+    /// produce non-finite values.
+    pub fn inverse(&self) -> Self {
+        let m = self.as_array();
+        let mut inv = [0.0f32; 16];
+
+        inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15] + m[9] * m[7] * m[14] + m[13] * m[6] * m[11] - m[13] * m[7] * m[10];
+        inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15] - m[8] * m[7] * m[14] - m[12] * m[6] * m[11] + m[12] * m[7] * m[10];
+        inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15] + m[8] * m[7] * m[13] + m[12] * m[5] * m[11] - m[12] * m[7] * m[9];
+        inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14] - m[8] * m[6] * m[13] - m[12] * m[5] * m[10] + m[12] * m[6] * m[9];
+        inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15] - m[9] * m[3] * m[14] - m[13] * m[2] * m[11] + m[13] * m[3] * m[10];
+        inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15] + m[8] * m[3] * m[14] + m[12] * m[2] * m[11] - m[12] * m[3] * m[10];
+        inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15] - m[8] * m[3] * m[13] - m[12] * m[1] * m[11] + m[12] * m[3] * m[9];
+        inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14] + m[8] * m[2] * m[13] + m[12] * m[1] * m[10] - m[12] * m[2] * m[9];
+        inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15] + m[5] * m[3] * m[14] + m[13] * m[2] * m[7] - m[13] * m[3] * m[6];
+        inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15] - m[4] * m[3] * m[14] - m[12] * m[2] * m[7] + m[12] * m[3] * m[6];
+        inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15] + m[4] * m[3] * m[13] + m[12] * m[1] * m[7] - m[12] * m[3] * m[5];
+        inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14] - m[4] * m[2] * m[13] - m[12] * m[1] * m[6] + m[12] * m[2] * m[5];
+        inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11] - m[5] * m[3] * m[10] - m[9] * m[2] * m[7] + m[9] * m[3] * m[6];
+        inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11] + m[4] * m[3] * m[10] + m[8] * m[2] * m[7] - m[8] * m[3] * m[6];
+        inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11] - m[4] * m[3] * m[9] - m[8] * m[1] * m[7] + m[8] * m[3] * m[5];
+        inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10] + m[4] * m[2] * m[9] + m[8] * m[1] * m[6] - m[8] * m[2] * m[5];
+
+        let inv_det = 1.0 / (m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12]);
+
+        let mut columns = [[0.0f32; 4]; 4];
+
+        for (i, column) in columns.iter_mut().enumerate() {
+            for (j, value) in column.iter_mut().enumerate() {
+                *value = inv[i * 4 + j] * inv_det;
+            }
+        }
+
+        Self { columns }
+    }
+
+    pub fn as_array(&self) -> [f32; 16] {
+        let mut array = [0.0; 16];
+
+        for (i, column) in self.columns.iter().enumerate() {
+            array[i * 4..i * 4 + 4].copy_from_slice(column);
+        }
+
+        array
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut result = [[0.0f32; 4]; 4];
+
+        for (column, result_column) in result.iter_mut().enumerate() {
+            for (row, value) in result_column.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.columns[k][row] * rhs.columns[column][k]).sum();
+            }
+        }
+
+        Self { columns: result }
+    }
+}
+
+pub(crate) fn sub(a: Vec3F, b: Vec3F) -> Vec3F {
+    Vec3F::new(a.x - b.x, a.y - b.y, a.z - b.z)
+}
+
+pub(crate) fn dot(a: Vec3F, b: Vec3F) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+pub(crate) fn cross(a: Vec3F, b: Vec3F) -> Vec3F {
+    Vec3F::new(a.y * b.z - a.z * b.y, a.z * b.x - a.x * b.z, a.x * b.y - a.y * b.x)
+}
+
+pub(crate) fn normalize(v: Vec3F) -> Vec3F {
+    let length = dot(v, v).sqrt();
+    Vec3F::new(v.x / length, v.y / length, v.z / length)
+}
@@ -7,6 +7,7 @@ pub type Vec2I = Vec2<i32>;
 pub type Vec2UI = Vec2<u32>;
 
 #[derive(Clone, Copy, Default)]
+#[repr(C)]
 pub struct Vec2<T: PartialEq + PartialOrd + Add + Sub + Mul> {
     pub x: T,
     pub y: T,
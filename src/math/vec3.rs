@@ -7,6 +7,7 @@ pub type Vec3I = Vec3<i32>;
 pub type Vec3UI = Vec3<u32>;
 
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct Vec3<T: PartialEq + PartialOrd + Add + Sub + Mul + Copy + Clone> {
     pub x: T,
     pub y: T,
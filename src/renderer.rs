@@ -1,14 +1,31 @@
+pub mod block_compression;
 pub mod buffer;
 pub mod command_buffer;
 pub mod debug;
+pub mod deferred;
+pub mod device_features;
+pub mod dynamic_rendering;
 pub mod frame_buffer;
+pub mod gpu_driven;
+pub mod hdr;
+pub mod ibl;
 pub mod image;
+pub mod material;
 pub mod mesh;
+pub mod physical_device;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod post_process;
+pub mod render_graph;
 pub mod render_pass;
+pub mod render_queue;
 pub mod shader;
+pub mod shadow;
 pub mod swapchain;
+pub mod sync;
 pub mod texture;
+pub mod texture_container;
+pub mod upload;
 pub mod utility;
 pub mod vkcontext;
 
@@ -18,6 +35,8 @@ use ash::vk;
 use swapchain::Swapchain;
 use vkcontext::VkContext;
 use command_buffer::CommandBuffer;
+use sync::{DeletionQueue, FrameTimeline};
+use upload::{UploadManager, UploadWait};
 
 use crate::math::vec2::Vec2UI;
 
@@ -30,9 +49,12 @@ pub struct Renderer<'ctx> {
     pub current_frame: u32,
 
     pub image_available_semaphores: Vec<vk::Semaphore>,
-    pub queue_complete_semaphores: Vec<vk::Semaphore>,
-    pub queue_complete_fences: Vec<vk::Fence>,
-    pub queue_complete_fences_image: Vec<Option<vk::Fence>>,
+    pub render_finished_semaphores: Vec<vk::Semaphore>,
+    pub frame_timeline: FrameTimeline<'ctx>,
+    pub frame_slot_values: Vec<u64>,
+    pub swapchain_image_values: Vec<u64>,
+    pub deletion_queue: DeletionQueue<'ctx>,
+    pub upload_waits: Vec<UploadWait>,
     
     pub command_pool: vk::CommandPool,
     pub swapchain: Swapchain<'ctx>,
@@ -41,7 +63,7 @@ pub struct Renderer<'ctx> {
 
 impl<'ctx> Renderer<'ctx> {
     pub fn new(vkcontext: &'ctx VkContext) -> Self {
-        let swapchain = Swapchain::new(&vkcontext, vkcontext.queue_family_indices, true);
+        let swapchain = Swapchain::new(vkcontext, vkcontext.queue_family_indices, true);
 
         // Command pool.
         let command_pool = {
@@ -52,25 +74,18 @@ impl<'ctx> Renderer<'ctx> {
             unsafe { vkcontext.device.create_command_pool(&create_info, None).unwrap() }
         };
 
-        // Sync objects.
+        // Sync objects. Acquisition is tracked per frame in flight, presentation per swapchain image, since an
+        // image's render-finished semaphore may only be reused once that image has been acquired again.
         let image_available_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
-            let create_info = vk::SemaphoreCreateInfo::default();
-            unsafe { vkcontext.device.create_semaphore(&create_info, None).unwrap() }
+            sync::create_binary_semaphore(vkcontext)
         }).collect::<Vec<_>>();
 
-        let queue_complete_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
-            let create_info = vk::SemaphoreCreateInfo::default();
-            unsafe { vkcontext.device.create_semaphore(&create_info, None).unwrap() }
+        let render_finished_semaphores = swapchain.images.iter().map(|_| {
+            sync::create_binary_semaphore(vkcontext)
         }).collect::<Vec<_>>();
 
-        let queue_complete_fences = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
-            let create_info = vk::FenceCreateInfo::default()
-                .flags(vk::FenceCreateFlags::SIGNALED);
-            unsafe { vkcontext.device.create_fence(&create_info, None).unwrap() }
-        }).collect::<Vec<_>>();
-
-        let command_buffers = swapchain.images.iter().map(|_| {
-            CommandBuffer::new(&vkcontext, command_pool, true)
+        let command_buffers = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            CommandBuffer::new(vkcontext, command_pool, true)
         }).collect::<Vec<_>>();
 
         Self {
@@ -78,9 +93,12 @@ impl<'ctx> Renderer<'ctx> {
             current_image_index: 0,
             current_frame: 0,
             image_available_semaphores,
-            queue_complete_semaphores,
-            queue_complete_fences,
-            queue_complete_fences_image: vec![None; swapchain.images.len()],
+            render_finished_semaphores,
+            frame_timeline: FrameTimeline::new(vkcontext),
+            frame_slot_values: vec![0; MAX_FRAMES_IN_FLIGHT as usize],
+            swapchain_image_values: vec![0; swapchain.images.len()],
+            deletion_queue: DeletionQueue::new(),
+            upload_waits: Vec::new(),
             command_pool,
             swapchain,
             vkcontext,
@@ -94,14 +112,10 @@ impl<'ctx> Renderer<'ctx> {
             self.recreate_swapchain()
         }
 
-        // Wait for current frame to finish rendering.
-        unsafe {
-            self.vkcontext.device.wait_for_fences(
-                slice::from_ref(&self.queue_complete_fences[self.current_frame as usize]),
-                true,
-                u64::MAX
-            ).unwrap();
-        }
+        // Wait for the frame that last used this slot to finish rendering.
+        self.frame_timeline.wait_for_frame(self.frame_slot_values[self.current_frame as usize]);
+
+        self.deletion_queue.collect(self.vkcontext, self.frame_timeline.completed_value());
 
         // Get next swapchain image index.
         self.current_image_index = match self.swapchain.acquire_next_image_index(self.image_available_semaphores[self.current_frame as usize]) {
@@ -109,6 +123,9 @@ impl<'ctx> Renderer<'ctx> {
             None => return true,
         };
 
+        // Wait if a previous frame is still using this image.
+        self.frame_timeline.wait_for_frame(self.swapchain_image_values[self.current_image_index as usize]);
+
         // Begin command buffer.
         let command_buffer = &self.command_buffers[self.current_frame as usize];
         command_buffer.begin(false, false, false);
@@ -141,55 +158,89 @@ impl<'ctx> Renderer<'ctx> {
     pub fn submit_frame(&mut self) -> bool {
         let command_buffer = &self.command_buffers[self.current_frame as usize];
 
-        command_buffer.end(&self.vkcontext);
+        command_buffer.end(self.vkcontext);
 
-        // Wait if a previous frame is still using this image.
-        match self.queue_complete_fences_image[self.current_image_index as usize] {
-            Some(fence) => unsafe {
-                self.vkcontext.device.wait_for_fences(
-                    slice::from_ref(&fence),
-                    true,
-                    u64::MAX
-                ).unwrap();
-            },
-            None => (),
-        }
-
-        // Mark fence as  being in use by this image.
-        self.queue_complete_fences_image[self.current_image_index as usize] =
-            Some(self.queue_complete_fences[self.current_frame as usize]);
-
-        // Reset fence.
-        unsafe {
-            self.vkcontext.device.reset_fences(slice::from_ref(&self.queue_complete_fences_image[self.current_image_index as usize].unwrap())).unwrap();
-        }
+        let frame_value = self.frame_timeline.current_value();
 
         // Submit queue.
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(slice::from_ref(&self.image_available_semaphores[self.current_frame as usize]))
-            .wait_dst_stage_mask(slice::from_ref(&vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT))
-            .command_buffers(slice::from_ref(&command_buffer.handle))
-            .signal_semaphores(slice::from_ref(&self.queue_complete_semaphores[self.current_frame as usize]));
+        let mut wait_infos = vec![
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.image_available_semaphores[self.current_frame as usize])
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
+        ];
+
+        wait_infos.extend(self.upload_waits.drain(..).map(|wait| {
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(wait.semaphore)
+                .value(wait.value)
+                .stage_mask(wait.stage_mask)
+        }));
+
+        let signal_infos = [
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.render_finished_semaphores[self.current_image_index as usize])
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.frame_timeline.semaphore)
+                .value(frame_value)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+        ];
+
+        let command_buffer_info = vk::CommandBufferSubmitInfo::default()
+            .command_buffer(command_buffer.handle);
+
+        let submit_info = vk::SubmitInfo2::default()
+            .wait_semaphore_infos(&wait_infos)
+            .command_buffer_infos(slice::from_ref(&command_buffer_info))
+            .signal_semaphore_infos(&signal_infos);
 
         unsafe {
-            self.vkcontext.device.queue_submit(
+            self.vkcontext.device.queue_submit2(
                 self.vkcontext.graphics_queue,
                 slice::from_ref(&submit_info),
-                self.queue_complete_fences[self.current_frame as usize]
+                vk::Fence::null()
             ).unwrap();
         }
 
+        self.frame_slot_values[self.current_frame as usize] = frame_value;
+        self.swapchain_image_values[self.current_image_index as usize] = frame_value;
+        self.frame_timeline.advance();
+
         // Present.
-        if self.swapchain.present(
-            self.queue_complete_semaphores[self.current_frame as usize],
+        let present_out_of_date = self.swapchain.present(
+            self.render_finished_semaphores[self.current_image_index as usize],
             self.current_image_index
-        ) {
-            return true;
-        }
+        );
 
         self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
 
-        false
+        present_out_of_date
+    }
+
+    /// Submits pending uploads and makes them visible to the current frame. Must be called between `prepare_frame`
+    /// and the first command that reads uploaded resources.
+    pub fn acquire_uploads(&mut self, upload_manager: &mut UploadManager) {
+        upload_manager.flush();
+
+        let command_buffer = &self.command_buffers[self.current_frame as usize];
+
+        if let Some(wait) = upload_manager.record_pending_acquires(command_buffer) {
+            self.upload_waits.push(wait);
+        }
+    }
+
+    /// Destroys a resource once every frame submitted so far, including the one being recorded, has completed.
+    pub fn defer_destroy<F: FnOnce(&VkContext) + 'ctx>(&mut self, deleter: F) {
+        self.deletion_queue.push(self.frame_timeline.current_value(), deleter);
+    }
+
+    /// The value the frame currently being recorded will signal on `frame_timeline`.
+    pub fn current_frame_value(&self) -> u64 {
+        self.frame_timeline.current_value()
+    }
+
+    pub fn is_frame_complete(&self, frame_value: u64) -> bool {
+        self.frame_timeline.is_frame_complete(frame_value)
     }
 
     pub fn get_current_command_buffer_handle(&self) -> vk::CommandBuffer {
@@ -207,8 +258,19 @@ impl<'ctx> Renderer<'ctx> {
 
         self.vkcontext.wait_gpu_idle();
 
-        let swapchain = Swapchain::new(&self.vkcontext, self.vkcontext.queue_family_indices, true);
+        let swapchain = Swapchain::new(self.vkcontext, self.vkcontext.queue_family_indices, true);
+
+        if swapchain.images.len() != self.render_finished_semaphores.len() {
+            for semaphore in self.render_finished_semaphores.drain(..) {
+                unsafe { self.vkcontext.device.destroy_semaphore(semaphore, None); }
+            }
+
+            self.render_finished_semaphores = swapchain.images.iter().map(|_| {
+                sync::create_binary_semaphore(self.vkcontext)
+            }).collect::<Vec<_>>();
+        }
 
+        self.swapchain_image_values = vec![0; swapchain.images.len()];
         self.swapchain = swapchain;
     }
 }
@@ -217,6 +279,9 @@ impl<'ctx> Drop for Renderer<'ctx> {
     fn drop(&mut self) {
         log::debug!("Dropping renderer.");
 
+        self.vkcontext.wait_gpu_idle();
+        self.deletion_queue.flush(self.vkcontext);
+
         let device = &self.vkcontext.device;
 
         unsafe {
@@ -224,14 +289,10 @@ impl<'ctx> Drop for Renderer<'ctx> {
                 device.destroy_semaphore(*sem, None);
             }
 
-            for sem in self.queue_complete_semaphores.iter() {
+            for sem in self.render_finished_semaphores.iter() {
                 device.destroy_semaphore(*sem, None);
             }
 
-            for fence in self.queue_complete_fences.iter() {
-                device.destroy_fence(*fence, None);
-            }
-
             ManuallyDrop::drop(&mut self.command_buffers);
 
             device.destroy_command_pool(self.command_pool, None);
@@ -0,0 +1,1240 @@
+use ash::vk;
+
+use super::image::format_texel_size;
+
+/// Width, height and byte size of one block of a block-compressed `format`.
+pub fn block_extent(format: vk::Format) -> Option<(u32, u32, u32)> {
+    let extent = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK
+            | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC4_UNORM_BLOCK | vk::Format::BC4_SNORM_BLOCK
+            | vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+            | vk::Format::EAC_R11_UNORM_BLOCK | vk::Format::EAC_R11_SNORM_BLOCK => (4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK | vk::Format::BC3_UNORM_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK | vk::Format::BC5_UNORM_BLOCK | vk::Format::BC5_SNORM_BLOCK
+            | vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK | vk::Format::BC7_UNORM_BLOCK
+            | vk::Format::BC7_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+            | vk::Format::EAC_R11G11_UNORM_BLOCK | vk::Format::EAC_R11G11_SNORM_BLOCK
+            | vk::Format::ASTC_4X4_UNORM_BLOCK | vk::Format::ASTC_4X4_SRGB_BLOCK => (4, 4, 16),
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => (5, 4, 16),
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => (5, 5, 16),
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => (6, 5, 16),
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => (6, 6, 16),
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => (8, 5, 16),
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => (8, 6, 16),
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => (8, 8, 16),
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => (10, 5, 16),
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => (10, 6, 16),
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => (10, 8, 16),
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => (10, 10, 16),
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => (12, 10, 16),
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => (12, 12, 16),
+        _ => return None,
+    };
+
+    Some(extent)
+}
+
+/// Byte size of one tightly packed slice of `extent`, whether `format` is block-compressed or not. Fails for unknown
+/// formats and for sizes that don't fit in a `usize`.
+pub fn image_size(format: vk::Format, extent: vk::Extent3D) -> Result<usize, String> {
+    let (block_width, block_height, block_size) = block_extent(format)
+        .or_else(|| format_texel_size(format).map(|size| (1, 1, size)))
+        .ok_or_else(|| format!("unsupported format {:?}", format))?;
+
+    (extent.width.div_ceil(block_width) as usize)
+        .checked_mul(extent.height.div_ceil(block_height) as usize)
+        .and_then(|blocks| blocks.checked_mul(extent.depth as usize))
+        .and_then(|blocks| blocks.checked_mul(block_size as usize))
+        .ok_or_else(|| format!("{}x{}x{} {:?} image is too large", extent.width, extent.height, extent.depth, format))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompressionFamily {
+    Bc,
+    Etc2,
+    Astc,
+}
+
+/// Which family of block-compressed formats `format` belongs to. Each family is gated behind its own device feature.
+pub fn compression_family(format: vk::Format) -> Option<CompressionFamily> {
+    let raw = format.as_raw();
+
+    if (vk::Format::BC1_RGB_UNORM_BLOCK.as_raw()..=vk::Format::BC7_SRGB_BLOCK.as_raw()).contains(&raw) {
+        Some(CompressionFamily::Bc)
+    } else if (vk::Format::ETC2_R8G8B8_UNORM_BLOCK.as_raw()..=vk::Format::EAC_R11G11_SNORM_BLOCK.as_raw()).contains(&raw) {
+        Some(CompressionFamily::Etc2)
+    } else if (vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw()..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw()).contains(&raw) {
+        Some(CompressionFamily::Astc)
+    } else {
+        None
+    }
+}
+
+/// The uncompressed format `decode_block` produces for `format`, if it can decode it.
+pub fn decoded_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC2_UNORM_BLOCK
+            | vk::Format::BC3_UNORM_BLOCK | vk::Format::BC4_UNORM_BLOCK | vk::Format::BC5_UNORM_BLOCK
+            | vk::Format::BC7_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+            | vk::Format::EAC_R11_UNORM_BLOCK | vk::Format::EAC_R11G11_UNORM_BLOCK => Some(vk::Format::R8G8B8A8_UNORM),
+        vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC2_SRGB_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK | vk::Format::BC7_SRGB_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::BC4_SNORM_BLOCK | vk::Format::BC5_SNORM_BLOCK => Some(vk::Format::R8G8B8A8_SNORM),
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => Some(vk::Format::R16G16B16A16_SFLOAT),
+        _ => None,
+    }
+}
+
+/// Decodes one 4x4 block of `format` into RGBA8 texels in row-major order, as two's complement bytes for the signed
+/// formats. Only formats with an 8-bit `decoded_format` are supported; see `decode_bc6h_block` for BC6H.
+pub fn decode_block(format: vk::Format, block: &[u8]) -> Option<[[u8; 4]; 16]> {
+    let mut texels = [[0, 0, 0, 255]; 16];
+
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK => {
+            decode_bc1(&block[0..8], false, &mut texels);
+
+            for texel in texels.iter_mut() {
+                texel[3] = 255;
+            }
+        },
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => decode_bc1(&block[0..8], false, &mut texels),
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => {
+            decode_bc1(&block[8..16], true, &mut texels);
+
+            let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (4 * i)) & 0xf) as u8 * 17;
+            }
+        },
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => {
+            decode_bc1(&block[8..16], true, &mut texels);
+            decode_bc4(&block[0..8], 3, &mut texels);
+        },
+        vk::Format::BC4_UNORM_BLOCK => decode_bc4(&block[0..8], 0, &mut texels),
+        vk::Format::BC5_UNORM_BLOCK => {
+            decode_bc4(&block[0..8], 0, &mut texels);
+            decode_bc4(&block[8..16], 1, &mut texels);
+        },
+        vk::Format::BC4_SNORM_BLOCK => {
+            texels = [[0, 0, 0, 127]; 16];
+            decode_bc4_signed(&block[0..8], 0, &mut texels);
+        },
+        vk::Format::BC5_SNORM_BLOCK => {
+            texels = [[0, 0, 0, 127]; 16];
+            decode_bc4_signed(&block[0..8], 0, &mut texels);
+            decode_bc4_signed(&block[8..16], 1, &mut texels);
+        },
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => decode_bc7(block, &mut texels),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => decode_etc2(&block[0..8], false, &mut texels),
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => decode_etc2(&block[0..8], true, &mut texels),
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            decode_etc2(&block[8..16], false, &mut texels);
+            decode_eac(&block[0..8], 3, false, &mut texels);
+        },
+        vk::Format::EAC_R11_UNORM_BLOCK => decode_eac(&block[0..8], 0, true, &mut texels),
+        vk::Format::EAC_R11G11_UNORM_BLOCK => {
+            decode_eac(&block[0..8], 0, true, &mut texels);
+            decode_eac(&block[8..16], 1, true, &mut texels);
+        },
+        _ => return None,
+    }
+
+    Some(texels)
+}
+
+/// Decodes one 4x4 BC6H block into RGBA16F texels in row-major order, with alpha 1.
+pub fn decode_bc6h_block(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    let mut texels = [[0, 0, 0, HALF_ONE]; 16];
+    decode_bc6h(block, signed, &mut texels);
+    texels
+}
+
+/// The texels of one block as bytes of its `decoded_format`.
+fn decode_block_bytes(format: vk::Format, block: &[u8]) -> Option<Vec<u8>> {
+    match format {
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => {
+            let texels = decode_bc6h_block(block, format == vk::Format::BC6H_SFLOAT_BLOCK);
+            Some(texels.iter().flatten().flat_map(|half| half.to_le_bytes()).collect())
+        },
+        _ => decode_block(format, block).map(|texels| texels.concat()),
+    }
+}
+
+/// Decodes one tightly packed slice of `extent` texels in a compressed `format` to its `decoded_format`, cropping
+/// partial blocks.
+pub fn decode_image(format: vk::Format, extent: vk::Extent3D, data: &[u8]) -> Result<Vec<u8>, String> {
+    let (block_width, block_height, block_size) = block_extent(format).ok_or_else(|| format!("{:?} is not block-compressed", format))?;
+    let (blocks_x, blocks_y) = (extent.width.div_ceil(block_width), extent.height.div_ceil(block_height));
+
+    let expected_size = image_size(format, extent)?;
+    if data.len() < expected_size {
+        return Err(format!("expected {} bytes of {:?} data but found {}", expected_size, format, data.len()));
+    }
+
+    let texel_size = decoded_format(format)
+        .and_then(format_texel_size)
+        .ok_or_else(|| format!("no CPU decoder for {:?}", format))? as usize;
+
+    let row_length = extent.width as usize * texel_size;
+    let slice_size = row_length * extent.height as usize;
+    let mut pixels = vec![0u8; slice_size * extent.depth as usize];
+
+    for (block_index, block) in data[..expected_size].chunks_exact(block_size as usize).enumerate() {
+        let block_index = block_index as u32;
+        let z = block_index / (blocks_x * blocks_y);
+        let block_y = block_index / blocks_x % blocks_y;
+        let block_x = block_index % blocks_x;
+
+        let texels = decode_block_bytes(format, block).ok_or_else(|| format!("no CPU decoder for {:?}", format))?;
+
+        for (i, texel) in texels.chunks_exact(texel_size).enumerate() {
+            let (x, y) = (block_x * 4 + i as u32 % 4, block_y * 4 + i as u32 / 4);
+
+            if x < extent.width && y < extent.height {
+                let offset = z as usize * slice_size + y as usize * row_length + x as usize * texel_size;
+                pixels[offset..offset + texel_size].copy_from_slice(texel);
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn expand_565(color: u16) -> [u8; 3] {
+    let (r, g, b) = ((color >> 11) & 0x1f, (color >> 5) & 0x3f, color & 0x1f);
+
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8]
+}
+
+/// A BC1 block, which is also the colour half of BC2 and BC3. Those always use the four-colour palette, whereas BC1
+/// switches to three colours and transparent black when the first endpoint is not greater than the second.
+fn decode_bc1(block: &[u8], four_colors_only: bool, texels: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+    let (e0, e1) = (expand_565(c0), expand_565(c1));
+    let mix = |w0: u32, w1: u32| -> [u8; 4] {
+        let channel = |i: usize| ((e0[i] as u32 * w0 + e1[i] as u32 * w1) / (w0 + w1)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+
+    let palette = if c0 > c1 || four_colors_only {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (2 * i)) & 0x3) as usize];
+    }
+}
+
+/// A BC4 block, which is also the alpha half of BC3, written into `channel`.
+fn decode_bc4(block: &[u8], channel: usize, texels: &mut [[u8; 4]; 16]) {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let indices = u64::from_le_bytes(block.try_into().unwrap()) >> 16;
+
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+    }
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[channel] = palette[((indices >> (3 * i)) & 0x7) as usize] as u8;
+    }
+}
+
+/// A signed BC4 block, the signed counterpart of `decode_bc4`. -128 decodes like -127.
+fn decode_bc4_signed(block: &[u8], channel: usize, texels: &mut [[u8; 4]; 16]) {
+    let (a0, a1) = ((block[0] as i8).max(-127) as i32, (block[1] as i8).max(-127) as i32);
+    let indices = u64::from_le_bytes(block.try_into().unwrap()) >> 16;
+
+    let mut palette = [a0, a1, 0, 0, 0, 0, -127, 127];
+
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a0 + i as i32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * a0 + i as i32 * a1) / 5;
+        }
+    }
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[channel] = palette[((indices >> (3 * i)) & 0x7) as usize] as i8 as u8;
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 4, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false,
+        index_bits: 3, secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 6, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: true,
+        index_bits: 3, secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 5, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: false,
+        index_bits: 2, secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 7, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false,
+        index_bits: 2, secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1,
+        color_bits: 5, alpha_bits: 6, endpoint_p_bits: false, shared_p_bits: false,
+        index_bits: 2, secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0,
+        color_bits: 7, alpha_bits: 8, endpoint_p_bits: false, shared_p_bits: false,
+        index_bits: 2, secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 7, alpha_bits: 7, endpoint_p_bits: true, shared_p_bits: false,
+        index_bits: 4, secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0,
+        color_bits: 5, alpha_bits: 5, endpoint_p_bits: true, shared_p_bits: false,
+        index_bits: 2, secondary_index_bits: 0,
+    },
+];
+
+/// Texels in the second subset of each two-subset partition, one bit per texel.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// The anchor texel of the second subset in two-subset partitions.
+const BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor texels of the second and third subsets in three-subset partitions.
+const BC7_ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+fn bc7_interpolate(e0: u32, e1: u32, index: u32, index_bits: u32) -> u8 {
+    let weight = match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    };
+
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+fn bc7_expand(value: u32, precision: u32) -> u32 {
+    let value = value << (8 - precision);
+    value | (value >> precision)
+}
+
+fn decode_bc7(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    let mut reader = BitReader { bits: u128::from_le_bytes(block[0..16].try_into().unwrap()), position: 0 };
+
+    let mode_index = block[0].trailing_zeros();
+
+    // Mode 8 is reserved and decodes to transparent black.
+    if mode_index >= 8 {
+        *texels = [[0; 4]; 16];
+        return;
+    }
+
+    let mode = &BC7_MODES[mode_index as usize];
+    reader.read(mode_index + 1);
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // Endpoints as [subset][endpoint][channel].
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+
+    for channel in 0..3 {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = reader.read(mode.color_bits);
+            }
+        }
+    }
+
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+        for endpoint in subset.iter_mut() {
+            endpoint[3] = reader.read(mode.alpha_bits);
+        }
+    }
+
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            let shared = if mode.shared_p_bits { Some(reader.read(1)) } else { None };
+
+            for endpoint in subset.iter_mut() {
+                let p_bit = shared.unwrap_or_else(|| reader.read(1));
+
+                for (channel, value) in endpoint.iter_mut().enumerate() {
+                    if channel < 3 || mode.alpha_bits > 0 {
+                        *value = (*value << 1) | p_bit;
+                    }
+                }
+            }
+        }
+
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+        for endpoint in subset.iter_mut() {
+            for value in endpoint.iter_mut().take(3) {
+                *value = bc7_expand(*value, color_bits);
+            }
+
+            endpoint[3] = if alpha_bits > 0 { bc7_expand(endpoint[3], alpha_bits) } else { 255 };
+        }
+    }
+
+    let subset_of = |texel: usize| -> usize {
+        match mode.subsets {
+            2 => ((BC7_PARTITIONS_2[partition] >> texel) & 1) as usize,
+            3 => BC7_PARTITIONS_3[partition][texel] as usize,
+            _ => 0,
+        }
+    };
+
+    let is_anchor = |texel: usize| -> bool {
+        match mode.subsets {
+            2 => texel == 0 || texel == BC7_ANCHORS_2[partition] as usize,
+            3 => texel == 0 || texel == BC7_ANCHORS_3[0][partition] as usize || texel == BC7_ANCHORS_3[1][partition] as usize,
+            _ => texel == 0,
+        }
+    };
+
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        *index = reader.read(if is_anchor(texel) { mode.index_bits - 1 } else { mode.index_bits });
+    }
+
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0 {
+        for (texel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = reader.read(if texel == 0 { mode.secondary_index_bits - 1 } else { mode.secondary_index_bits });
+        }
+    }
+
+    for (texel, output) in texels.iter_mut().enumerate() {
+        let [e0, e1] = endpoints[subset_of(texel)];
+
+        let (color_index, color_index_bits, alpha_index, alpha_index_bits) = if mode.secondary_index_bits == 0 {
+            (indices[texel], mode.index_bits, indices[texel], mode.index_bits)
+        } else if index_selection == 0 {
+            (indices[texel], mode.index_bits, secondary_indices[texel], mode.secondary_index_bits)
+        } else {
+            (secondary_indices[texel], mode.secondary_index_bits, indices[texel], mode.index_bits)
+        };
+
+        for channel in 0..3 {
+            output[channel] = bc7_interpolate(e0[channel], e1[channel], color_index, color_index_bits);
+        }
+        output[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_index_bits);
+
+        match rotation {
+            1 => output.swap(0, 3),
+            2 => output.swap(1, 3),
+            3 => output.swap(2, 3),
+            _ => (),
+        }
+    }
+}
+
+const HALF_ONE: u16 = 0x3c00;
+
+// Endpoint fields of a BC6H block: w and x are the endpoints of the first region, y and z those of the second. D is
+// the partition.
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+const D: usize = 12;
+
+struct Bc6hMode {
+    /// The mode bits: two for the first two modes, five for the rest.
+    value: u32,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    regions: usize,
+    /// Runs of header bits in the order they are stored, written like the specification's `field[a:b]`: the first
+    /// bit read goes to bit `b` of the field, the next ones step towards bit `a`.
+    layout: &'static [(usize, u32, u32)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        value: 0x00, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], regions: 2,
+        layout: &[
+            (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0),
+            (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0),
+            (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x01, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], regions: 2,
+        layout: &[
+            (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 6, 0), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 6, 0), (BY, 5, 5),
+            (BZ, 2, 2), (GY, 4, 4), (BW, 6, 0), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0),
+            (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x02, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], regions: 2,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (RW, 10, 10), (GY, 3, 0), (GX, 3, 0), (GW, 10, 10),
+            (BZ, 0, 0), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2),
+            (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x06, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], regions: 2,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0),
+            (GW, 10, 10), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0), (RY, 3, 0), (BZ, 0, 0),
+            (BZ, 2, 2), (RZ, 3, 0), (GY, 4, 4), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0a, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], regions: 2,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (BY, 4, 4), (GY, 3, 0), (GX, 3, 0),
+            (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BW, 10, 10), (BY, 3, 0), (RY, 3, 0), (BZ, 1, 1),
+            (BZ, 2, 2), (RZ, 3, 0), (BZ, 4, 4), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0e, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], regions: 2,
+        layout: &[
+            (RW, 8, 0), (BY, 4, 4), (GW, 8, 0), (GY, 4, 4), (BW, 8, 0), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0),
+            (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0),
+            (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x12, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], regions: 2,
+        layout: &[
+            (RW, 7, 0), (GZ, 4, 4), (BY, 4, 4), (GW, 7, 0), (BZ, 2, 2), (GY, 4, 4), (BW, 7, 0), (BZ, 3, 3), (BZ, 4, 4),
+            (RX, 5, 0), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 5, 0),
+            (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x16, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], regions: 2,
+        layout: &[
+            (RW, 7, 0), (BZ, 0, 0), (BY, 4, 4), (GW, 7, 0), (GY, 5, 5), (GY, 4, 4), (BW, 7, 0), (GZ, 5, 5), (BZ, 4, 4),
+            (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0),
+            (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x1a, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], regions: 2,
+        layout: &[
+            (RW, 7, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 7, 0), (BY, 5, 5), (GY, 4, 4), (BW, 7, 0), (BZ, 5, 5), (BZ, 4, 4),
+            (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 4, 0),
+            (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x1e, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], regions: 2,
+        layout: &[
+            (RW, 5, 0), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 5, 0), (GY, 5, 5), (BY, 5, 5), (BZ, 2, 2),
+            (GY, 4, 4), (BW, 5, 0), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0),
+            (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x03, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], regions: 1,
+        layout: &[(RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 9, 0), (GX, 9, 0), (BX, 9, 0)],
+    },
+    Bc6hMode {
+        value: 0x07, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], regions: 1,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 8, 0), (RW, 10, 10), (GX, 8, 0), (GW, 10, 10), (BX, 8, 0),
+            (BW, 10, 10),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0b, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], regions: 1,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 7, 0), (RW, 10, 11), (GX, 7, 0), (GW, 10, 11), (BX, 7, 0),
+            (BW, 10, 11),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0f, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], regions: 1,
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 15), (GX, 3, 0), (GW, 10, 15), (BX, 3, 0),
+            (BW, 10, 15),
+        ],
+    },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    (value << (32 - bits)) >> (32 - bits)
+}
+
+/// Scales a quantized endpoint to 16 bits, or to 15 bits and a sign for signed blocks.
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else {
+        if bits >= 16 {
+            return value;
+        }
+
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+/// Scales an interpolated value to the bit pattern of a half float.
+fn bc6h_finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn decode_bc6h(block: &[u8], signed: bool, texels: &mut [[u16; 4]; 16]) {
+    let mut reader = BitReader { bits: u128::from_le_bytes(block[0..16].try_into().unwrap()), position: 0 };
+
+    let mut mode_value = reader.read(2);
+    if mode_value > 1 {
+        mode_value |= reader.read(3) << 2;
+    }
+
+    // The remaining mode values are reserved and decode to black.
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.value == mode_value) else {
+        *texels = [[0; 4]; 16];
+        return;
+    };
+
+    let mut fields = [0i32; 13];
+
+    for &(field, a, b) in mode.layout {
+        for i in 0..a.abs_diff(b) + 1 {
+            let bit = if a >= b { b + i } else { b - i };
+            fields[field] |= (reader.read(1) << bit) as i32;
+        }
+    }
+
+    debug_assert_eq!(reader.position, if mode.regions == 2 { 82 } else { 65 });
+
+    // Endpoints as [endpoint][channel], two per region.
+    let mut endpoints = [[0i32; 3]; 4];
+    for (endpoint, channels) in endpoints.iter_mut().enumerate() {
+        channels.copy_from_slice(&fields[endpoint * 3..endpoint * 3 + 3]);
+    }
+
+    if signed {
+        for value in endpoints[0].iter_mut() {
+            *value = sign_extend(*value, mode.endpoint_bits);
+        }
+    }
+
+    // Transformed modes store the other endpoints as deltas from the first.
+    let mask = (1 << mode.endpoint_bits) - 1;
+    let base = endpoints[0];
+
+    for endpoint in endpoints.iter_mut().take(mode.regions * 2).skip(1) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            if mode.transformed {
+                let delta = sign_extend(*value, mode.delta_bits[channel]);
+                *value = (base[channel] + delta) & mask;
+            }
+
+            if signed {
+                *value = sign_extend(*value, mode.endpoint_bits);
+            }
+        }
+    }
+
+    for endpoint in endpoints.iter_mut() {
+        for value in endpoint.iter_mut() {
+            *value = bc6h_unquantize(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let partition = fields[D] as usize;
+    let index_bits = if mode.regions == 2 { 3 } else { 4 };
+
+    for (texel, output) in texels.iter_mut().enumerate() {
+        let (region, is_anchor) = if mode.regions == 2 {
+            let region = ((BC7_PARTITIONS_2[partition] >> texel) & 1) as usize;
+            (region, texel == 0 || texel == BC7_ANCHORS_2[partition] as usize)
+        } else {
+            (0, texel == 0)
+        };
+
+        let index = reader.read(if is_anchor { index_bits - 1 } else { index_bits });
+        let weight = if index_bits == 3 { BC7_WEIGHTS_3[index as usize] } else { BC7_WEIGHTS_4[index as usize] } as i32;
+
+        let [e0, e1] = [endpoints[region * 2], endpoints[region * 2 + 1]];
+
+        for channel in 0..3 {
+            let value = (e0[channel] * (64 - weight) + e1[channel] * weight + 32) >> 6;
+            output[channel] = bc6h_finish_unquantize(value, signed);
+        }
+        output[3] = HALF_ONE;
+    }
+}
+
+const ETC1_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+fn extend_4(value: u64) -> i32 {
+    let value = (value & 0xf) as i32;
+    (value << 4) | value
+}
+
+fn extend_5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+fn offset_color(color: [i32; 3], offset: i32) -> [u8; 4] {
+    [
+        (color[0] + offset).clamp(0, 255) as u8,
+        (color[1] + offset).clamp(0, 255) as u8,
+        (color[2] + offset).clamp(0, 255) as u8,
+        255,
+    ]
+}
+
+/// An ETC1/ETC2 colour block. With `punch_through` the differential bit is the opaque bit instead and the individual
+/// mode is unavailable.
+fn decode_etc2(block: &[u8], punch_through: bool, texels: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+
+    let differential = punch_through || (bits >> 33) & 1 == 1;
+    let opaque = !punch_through || (bits >> 33) & 1 == 1;
+    let flip = (bits >> 32) & 1 == 1;
+
+    // Texel indices are stored column-major, most significant bits in the upper half.
+    let index_of = |i: usize| -> usize {
+        let (x, y) = (i % 4, i / 4);
+        let bit = x * 4 + y;
+        ((((bits >> (16 + bit)) & 1) << 1) | ((bits >> bit) & 1)) as usize
+    };
+
+    let signed_3 = |value: u64| -> i32 { ((value & 0x7) as i32) << 29 >> 29 };
+
+    let r = ((bits >> 59) & 0x1f) as i32;
+    let g = ((bits >> 51) & 0x1f) as i32;
+    let b = ((bits >> 43) & 0x1f) as i32;
+    let (r2, g2, b2) = (r + signed_3(bits >> 56), g + signed_3(bits >> 48), b + signed_3(bits >> 40));
+
+    if differential && !(0..32).contains(&r2) {
+        // T mode.
+        let c0 = [
+            extend_4(((bits >> 57) & 0xc) | ((bits >> 56) & 0x3)),
+            extend_4(bits >> 52),
+            extend_4(bits >> 48),
+        ];
+        let c1 = [extend_4(bits >> 44), extend_4(bits >> 40), extend_4(bits >> 36)];
+        let distance = ETC2_DISTANCES[((((bits >> 34) & 0x3) << 1) | ((bits >> 32) & 1)) as usize];
+
+        let paints = [offset_color(c0, 0), offset_color(c1, distance), offset_color(c1, 0), offset_color(c1, -distance)];
+        write_etc2_paints(&paints, opaque, index_of, texels);
+    } else if differential && !(0..32).contains(&g2) {
+        // H mode.
+        let c0_4 = [
+            (bits >> 59) & 0xf,
+            (((bits >> 56) & 0x7) << 1) | ((bits >> 52) & 1),
+            (((bits >> 51) & 1) << 3) | ((bits >> 47) & 0x7),
+        ];
+        let c1_4 = [(bits >> 43) & 0xf, (bits >> 39) & 0xf, (bits >> 35) & 0xf];
+
+        let packed = |c: [u64; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+        let mut distance_index = (((bits >> 34) & 1) << 2) | (((bits >> 32) & 1) << 1);
+        if packed(c0_4) >= packed(c1_4) {
+            distance_index |= 1;
+        }
+        let distance = ETC2_DISTANCES[distance_index as usize];
+
+        let c0 = c0_4.map(extend_4);
+        let c1 = c1_4.map(extend_4);
+
+        let paints = [offset_color(c0, distance), offset_color(c0, -distance), offset_color(c1, distance), offset_color(c1, -distance)];
+        write_etc2_paints(&paints, opaque, index_of, texels);
+    } else if differential && !(0..32).contains(&b2) {
+        // Planar mode, which is always opaque.
+        let extend_6 = |value: u64| -> i32 { let value = (value & 0x3f) as i32; (value << 2) | (value >> 4) };
+        let extend_7 = |value: u64| -> i32 { let value = (value & 0x7f) as i32; (value << 1) | (value >> 6) };
+
+        let origin = [
+            extend_6(bits >> 57),
+            extend_7((((bits >> 56) & 1) << 6) | ((bits >> 49) & 0x3f)),
+            extend_6((((bits >> 48) & 1) << 5) | (((bits >> 43) & 0x3) << 3) | ((bits >> 39) & 0x7)),
+        ];
+        let horizontal = [
+            extend_6((((bits >> 34) & 0x1f) << 1) | ((bits >> 32) & 1)),
+            extend_7(bits >> 25),
+            extend_6(bits >> 19),
+        ];
+        let vertical = [extend_6(bits >> 13), extend_7(bits >> 6), extend_6(bits)];
+
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+
+            for channel in 0..3 {
+                let value = (x * (horizontal[channel] - origin[channel]) + y * (vertical[channel] - origin[channel])
+                    + 4 * origin[channel] + 2) >> 2;
+                texel[channel] = value.clamp(0, 255) as u8;
+            }
+            texel[3] = 255;
+        }
+    } else {
+        let (base0, base1) = if differential {
+            ([extend_5(r), extend_5(g), extend_5(b)], [extend_5(r2), extend_5(g2), extend_5(b2)])
+        } else {
+            (
+                [extend_4(bits >> 60), extend_4(bits >> 52), extend_4(bits >> 44)],
+                [extend_4(bits >> 56), extend_4(bits >> 48), extend_4(bits >> 40)],
+            )
+        };
+
+        let tables = [((bits >> 37) & 0x7) as usize, ((bits >> 34) & 0x7) as usize];
+
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let second = if flip { y >= 2 } else { x >= 2 };
+
+            let (base, table) = if second { (base1, tables[1]) } else { (base0, tables[0]) };
+            let index = index_of(i);
+
+            *texel = if !opaque && index == 2 {
+                [0, 0, 0, 0]
+            } else if !opaque && index == 0 {
+                offset_color(base, 0)
+            } else {
+                offset_color(base, ETC1_MODIFIERS[table][index])
+            };
+        }
+    }
+}
+
+fn write_etc2_paints(paints: &[[u8; 4]; 4], opaque: bool, index_of: impl Fn(usize) -> usize, texels: &mut [[u8; 4]; 16]) {
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let index = index_of(i);
+        *texel = if !opaque && index == 2 { [0, 0, 0, 0] } else { paints[index] };
+    }
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// An EAC block into `channel`: the alpha half of ETC2 RGBA8, or an 11-bit R11/RG11 channel rounded to 8 bits.
+fn decode_eac(block: &[u8], channel: usize, eleven_bit: bool, texels: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+
+    let base = (bits >> 56) as i32;
+    let multiplier = ((bits >> 52) & 0xf) as i32;
+    let modifiers = EAC_MODIFIERS[((bits >> 48) & 0xf) as usize];
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let index = ((bits >> (45 - 3 * (x * 4 + y))) & 0x7) as usize;
+        let modifier = modifiers[index];
+
+        texel[channel] = if eleven_bit {
+            let value = if multiplier == 0 {
+                base * 8 + 4 + modifier
+            } else {
+                base * 8 + 4 + modifier * multiplier * 8
+            };
+
+            ((value.clamp(0, 2047) * 255 + 1023) / 2047) as u8
+        } else {
+            (base + modifier * multiplier).clamp(0, 255) as u8
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn decode(format: vk::Format, block: &[u8]) -> [[u8; 4]; 16] {
+        decode_block(format, block).unwrap()
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red then blue, so four colours; each row has indices 0, 1, 2, 3.
+        let texels = decode(vk::Format::BC1_RGBA_UNORM_BLOCK, &[0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4]);
+
+        for row in texels.chunks_exact(4) {
+            assert_eq!(row, [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]]);
+        }
+    }
+
+    #[test]
+    fn bc1_three_colors() {
+        // Blue then red is not descending, so index 2 is the midpoint and index 3 transparent black.
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0xe4, 0xe4, 0xe4, 0xe4];
+
+        let texels = decode(vk::Format::BC1_RGBA_UNORM_BLOCK, &block);
+        assert_eq!(texels[..4], [BLUE, RED, [127, 0, 127, 255], [0, 0, 0, 0]]);
+
+        let texels = decode(vk::Format::BC1_RGB_UNORM_BLOCK, &block);
+        assert_eq!(texels[3], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc2() {
+        // Texel i has 4-bit alpha i. The colour half always uses four colours, even with ascending endpoints.
+        let block = [0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe, 0x1f, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff, 0xff];
+
+        for (i, texel) in decode(vk::Format::BC2_UNORM_BLOCK, &block).iter().enumerate() {
+            assert_eq!(*texel, [170, 0, 85, i as u8 * 17]);
+        }
+    }
+
+    #[test]
+    fn bc3() {
+        // Alpha endpoints 255 and 0 give eight levels; every texel uses index 2.
+        let block = [0xff, 0x00, 0x92, 0x24, 0x49, 0x92, 0x24, 0x49, 0x00, 0xf8, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(decode(vk::Format::BC3_UNORM_BLOCK, &block), [[255, 0, 0, 218]; 16]);
+    }
+
+    #[test]
+    fn bc4() {
+        // Ascending endpoints give six levels plus 0 and 255; texel i uses index i % 8.
+        let block = [0x00, 0xff, 0x88, 0xc6, 0xfa, 0x88, 0xc6, 0xfa];
+        let expected = [0, 255, 51, 102, 153, 204, 0, 255];
+
+        for (i, texel) in decode(vk::Format::BC4_UNORM_BLOCK, &block).iter().enumerate() {
+            assert_eq!(*texel, [expected[i % 8], 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn bc5() {
+        let block = [0xff, 0x00, 0, 0, 0, 0, 0, 0, 0x00, 0xff, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24];
+
+        assert_eq!(decode(vk::Format::BC5_UNORM_BLOCK, &block), [[255, 255, 0, 255]; 16]);
+    }
+
+    #[test]
+    fn bc4_signed() {
+        // Endpoints 70 and -70 give eight levels; texel i uses index i % 8.
+        let block = [70, (-70i8) as u8, 0x88, 0xc6, 0xfa, 0x88, 0xc6, 0xfa];
+        let expected = [70, -70, 50, 30, 10, -10, -30, -50];
+
+        for (i, texel) in decode(vk::Format::BC4_SNORM_BLOCK, &block).iter().enumerate() {
+            assert_eq!(*texel, [expected[i % 8] as u8, 0, 0, 127]);
+        }
+    }
+
+    #[test]
+    fn bc5_signed() {
+        // -128 decodes like -127.
+        let block = [0x80, 0x80, 0, 0, 0, 0, 0, 0, 0x7f, 0x00, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(decode(vk::Format::BC5_SNORM_BLOCK, &block), [[0x81, 0x7f, 0, 127]; 16]);
+    }
+
+    const HALF_MAX: u16 = 0x7bff;
+
+    #[test]
+    fn bc6h_one_region() {
+        // Mode 11 with endpoints 0 and 1023. Texel 0 is the anchor at index 0, the rest use index 15.
+        let block = [0x03, 0x00, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff, 0xf1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let texels = decode_bc6h_block(&block, false);
+
+        assert_eq!(texels[0], [0, 0, 0, HALF_ONE]);
+        assert_eq!(texels[1..], [[HALF_MAX, HALF_MAX, HALF_MAX, HALF_ONE]; 15]);
+    }
+
+    #[test]
+    fn bc6h_signed() {
+        // Mode 11 with endpoints -1 and 511, the largest positive 10-bit value.
+        let block = [0xe3, 0xff, 0xff, 0xff, 0xff, 0xef, 0xbf, 0xff, 0xf0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let texels = decode_bc6h_block(&block, true);
+
+        assert_eq!(texels[0], [0x805d, 0x805d, 0x805d, HALF_ONE]);
+        assert_eq!(texels[1..], [[HALF_MAX, HALF_MAX, HALF_MAX, HALF_ONE]; 15]);
+    }
+
+    #[test]
+    fn bc6h_two_regions() {
+        // Mode 1, partition 0, base 100 with deltas -1, +2 and -2. Texels 1 and 2 use index 7, the rest index 0, and
+        // the right half of the block is the second region.
+        let block = [0x90, 0x0c, 0x32, 0xc8, 0xf8, 0xe5, 0xf3, 0x5f, 0x44, 0x1f, 0xf0, 0x03, 0x00, 0x00, 0x00, 0x00];
+        let texels = decode_bc6h_block(&block, false);
+
+        assert_eq!(texels[0], [0x0c2b, 0x0c2b, 0x0c2b, HALF_ONE]);
+        assert_eq!(texels[1], [0x0c0c, 0x0c0c, 0x0c0c, HALF_ONE]);
+        assert_eq!(texels[2], [0x0bed, 0x0bed, 0x0bed, HALF_ONE]);
+        assert_eq!(texels[3], [0x0c69, 0x0c69, 0x0c69, HALF_ONE]);
+    }
+
+    #[test]
+    fn bc6h_mode_layouts_fill_the_header() {
+        // Decoding checks in debug builds that each layout ends where the indices start.
+        for mode in BC6H_MODES.iter() {
+            let mode_bits = if mode.value < 2 { 2 } else { 5 };
+            let bits = (u128::MAX << mode_bits) | mode.value as u128;
+
+            for signed in [false, true] {
+                decode_bc6h_block(&bits.to_le_bytes(), signed);
+            }
+        }
+    }
+
+    #[test]
+    fn bc6h_image() {
+        let block = [0x03, 0x00, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff, 0xf1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let extent = vk::Extent3D { width: 2, height: 1, depth: 1 };
+
+        let pixels = decode_image(vk::Format::BC6H_UFLOAT_BLOCK, extent, &block).unwrap();
+        let max = HALF_MAX.to_le_bytes();
+        let one = HALF_ONE.to_le_bytes();
+
+        assert_eq!(pixels, [[0, 0, 0, 0, 0, 0, one[0], one[1]], [max[0], max[1], max[0], max[1], max[0], max[1], one[0], one[1]]].concat());
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        // Endpoints black and white with opposite p-bits. Texel 0 is the anchor at index 7, the rest use index 15.
+        let block = [0x40, 0xc0, 0x1f, 0xf0, 0x07, 0xfc, 0x01, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let texels = decode(vk::Format::BC7_UNORM_BLOCK, &block);
+
+        assert_eq!(texels[0], [120; 4]);
+        assert_eq!(texels[1..], [[255; 4]; 15]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        assert_eq!(decode(vk::Format::BC7_UNORM_BLOCK, &[0; 16]), [[0; 4]; 16]);
+    }
+
+    /// An individual-mode ETC1 block with base colour 136 and modifier table 0, where only texel (1, 0) uses +8.
+    const ETC2_BLOCK: [u8; 8] = [0x88, 0x88, 0x88, 0x00, 0x00, 0x00, 0x00, 0x10];
+
+    #[test]
+    fn etc2_individual() {
+        let texels = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &ETC2_BLOCK);
+
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, if i == 1 { [144, 144, 144, 255] } else { [138, 138, 138, 255] });
+        }
+    }
+
+    #[test]
+    fn etc2_punch_through() {
+        // Differential base colour 132 with the opaque bit clear; texel (0, 1) uses index 2, which is transparent.
+        let block = [0x80, 0x80, 0x80, 0x00, 0x00, 0x02, 0x00, 0x00];
+        let texels = decode(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, &block);
+
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, if i == 4 { [0, 0, 0, 0] } else { [132, 132, 132, 255] });
+        }
+    }
+
+    #[test]
+    fn etc2_rgba() {
+        // Alpha base 128 with multiplier 1, texel (1, 0) at +14 and the rest at +2.
+        let mut block = [0x80, 0x10, 0x92, 0x4f, 0x24, 0x92, 0x49, 0x24, 0, 0, 0, 0, 0, 0, 0, 0];
+        block[8..].copy_from_slice(&ETC2_BLOCK);
+
+        let texels = decode(vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK, &block);
+
+        assert_eq!(texels[0], [138, 138, 138, 130]);
+        assert_eq!(texels[1], [144, 144, 144, 142]);
+    }
+
+    #[test]
+    fn eac_r11_and_rg11() {
+        // Red: base 128 with multiplier 0, so 1028 + 2. Green: base 255 at +14 * 8, clamped to 2047.
+        let red = [0x80, 0x00, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24];
+        let green = [0xff, 0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+
+        assert_eq!(decode(vk::Format::EAC_R11_UNORM_BLOCK, &red), [[128, 0, 0, 255]; 16]);
+
+        let block = [red, green].concat();
+        assert_eq!(decode(vk::Format::EAC_R11G11_UNORM_BLOCK, &block), [[128, 255, 0, 255]; 16]);
+    }
+
+    #[test]
+    fn decode_image_crops_partial_blocks() {
+        let extent = vk::Extent3D { width: 2, height: 3, depth: 1 };
+        let pixels = decode_image(vk::Format::BC1_RGBA_UNORM_BLOCK, extent, &[0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4]).unwrap();
+
+        assert_eq!(pixels, [RED, BLUE, RED, BLUE, RED, BLUE].concat());
+    }
+}
@@ -120,8 +120,6 @@ impl<'ctx> Buffer<'ctx> {
         dest_offset: vk::DeviceSize,
         size: vk::DeviceSize,
     ) {
-        unsafe { self.vkcontext.device.queue_wait_idle(queue).unwrap(); }
-
         let cb = CommandBuffer::new(self.vkcontext, pool, true);
 
         cb.begin(true, false, false);
@@ -46,6 +46,7 @@ impl<'ctx> CommandBuffer<'ctx> {
         unsafe { vkcontext.device.end_command_buffer(self.handle).unwrap() }
     }
 
+    /// Ends the command buffer, submits it to `queue` and blocks until the GPU has finished executing it.
     pub fn end_and_submit_single_use(&self, queue: vk::Queue) {
         let buffers = [self.handle];
 
@@ -53,20 +54,30 @@ impl<'ctx> CommandBuffer<'ctx> {
             .command_buffers(&buffers);
 
         unsafe {
+            self.vkcontext.device.end_command_buffer(self.handle).unwrap();
+
+            let fence = self.vkcontext.device.create_fence(&vk::FenceCreateInfo::default(), None).unwrap();
+
             self.vkcontext.device.queue_submit(
                 queue,
                 std::slice::from_ref(&submit_info),
-                vk::Fence::null()
+                fence
             )
-            .unwrap()
+            .unwrap();
+
+            self.vkcontext.device.wait_for_fences(slice::from_ref(&fence), true, u64::MAX).unwrap();
+            self.vkcontext.device.destroy_fence(fence, None);
         }
     }
 }
 
 impl<'ctx> CommandBuffer<'ctx> {
+    /// Transitions `subresource_range` of `image`. Depth/stencil images must include both aspects unless only one of
+    /// them is being transitioned on purpose.
     pub fn transition_image(
         &self,
         image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
         src_stage: vk::PipelineStageFlags,
         dst_stage: vk::PipelineStageFlags,
         src_access: vk::AccessFlags,
@@ -74,13 +85,6 @@ impl<'ctx> CommandBuffer<'ctx> {
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
     ) {
-        let resource_range = vk::ImageSubresourceRange::default()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .base_mip_level(0)
-            .level_count(1)
-            .base_array_layer(0)
-            .layer_count(1);
-
         let barrier = vk::ImageMemoryBarrier::default()
             .src_access_mask(src_access)
             .dst_access_mask(dst_access)
@@ -89,7 +93,7 @@ impl<'ctx> CommandBuffer<'ctx> {
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(image)
-            .subresource_range(resource_range);
+            .subresource_range(subresource_range);
 
         unsafe {
             self.vkcontext.device.cmd_pipeline_barrier(
@@ -105,8 +109,127 @@ impl<'ctx> CommandBuffer<'ctx> {
     }
 }
 
+impl<'ctx> CommandBuffer<'ctx> {
+    /// Records the release half of a queue family ownership transfer. Must be submitted on a queue of
+    /// `transfer.src_queue_family_index`, after the commands that last accessed the resource.
+    pub fn release_ownership(
+        &self,
+        transfer: &QueueOwnershipTransfer,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+    ) {
+        if !transfer.is_required() { return; }
+
+        self.record_ownership_barrier(
+            transfer,
+            src_stage,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            src_access,
+            vk::AccessFlags::empty(),
+        );
+    }
+
+    /// Records the acquire half of a queue family ownership transfer. Must be submitted on a queue of
+    /// `transfer.dst_queue_family_index`, ordered after the matching release with a semaphore.
+    pub fn acquire_ownership(
+        &self,
+        transfer: &QueueOwnershipTransfer,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        if !transfer.is_required() { return; }
+
+        self.record_ownership_barrier(
+            transfer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            dst_stage,
+            vk::AccessFlags::empty(),
+            dst_access,
+        );
+    }
+
+    fn record_ownership_barrier(
+        &self,
+        transfer: &QueueOwnershipTransfer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let mut buffer_barriers = Vec::new();
+        let mut image_barriers = Vec::new();
+
+        match transfer.resource {
+            OwnershipTransferResource::Buffer { buffer, offset, size } => {
+                buffer_barriers.push(vk::BufferMemoryBarrier::default()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(transfer.src_queue_family_index)
+                    .dst_queue_family_index(transfer.dst_queue_family_index)
+                    .buffer(buffer)
+                    .offset(offset)
+                    .size(size)
+                );
+            },
+            OwnershipTransferResource::Image { image, subresource_range, old_layout, new_layout } => {
+                image_barriers.push(vk::ImageMemoryBarrier::default()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .old_layout(old_layout)
+                    .new_layout(new_layout)
+                    .src_queue_family_index(transfer.src_queue_family_index)
+                    .dst_queue_family_index(transfer.dst_queue_family_index)
+                    .image(image)
+                    .subresource_range(subresource_range)
+                );
+            },
+        }
+
+        unsafe {
+            self.vkcontext.device.cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::default(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+        }
+    }
+}
+
 impl<'ctx> Drop for CommandBuffer<'ctx> {
     fn drop(&mut self) {
         unsafe { self.vkcontext.device.free_command_buffers(self.command_pool, &[self.handle]) }
     }
 }
+
+#[derive(Clone, Copy)]
+pub enum OwnershipTransferResource {
+    Buffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    },
+    /// The layout transition happens as part of the transfer; both halves must specify the same layouts.
+    Image {
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub struct QueueOwnershipTransfer {
+    pub resource: OwnershipTransferResource,
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
+}
+
+impl QueueOwnershipTransfer {
+    pub fn is_required(&self) -> bool {
+        self.src_queue_family_index != self.dst_queue_family_index
+    }
+}
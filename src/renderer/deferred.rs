@@ -0,0 +1,672 @@
+use std::{f32::consts::PI, slice};
+
+use ash::vk;
+
+use crate::math::{
+    mat4::{cross, dot, normalize, sub, Mat4},
+    vec2::{Vec2F, Vec2UI},
+    vec3::Vec3F,
+};
+
+use super::{
+    buffer::Buffer,
+    frame_buffer::Framebuffer,
+    ibl::{ImageBasedLighting, LIGHTING_DESCRIPTOR_SET},
+    image::{Image, ImageUsage},
+    mesh::{Mesh, Vertex},
+    pipeline::{PipelineRenderTarget, PipelineStateInfo},
+    post_process::POST_PROCESS_FORMAT,
+    render_pass::{RenderPass, RenderPassSubPassInfo},
+    shader::{
+        Shader,
+        ShaderDescriptorInfo,
+        ShaderDescriptorSetInfo,
+        ShaderDescriptorTypeInfo,
+        ShaderPushConstantInfo,
+        ShaderStageInfo,
+        ShaderType,
+    },
+    shadow::SpotLight,
+    upload::{UploadManager, UploadTicket},
+    utility,
+    vkcontext::VkContext,
+    MAX_FRAMES_IN_FLIGHT,
+};
+
+/// Base colour in RGB and ambient occlusion in A.
+pub const GBUFFER_ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// World-space normal in RGB.
+pub const GBUFFER_NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Metallic in R and roughness in G.
+pub const GBUFFER_MATERIAL_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+pub const GBUFFER_DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+pub const DEFERRED_OUTPUT_FORMAT: vk::Format = POST_PROCESS_FORMAT;
+
+const OUTPUT_ATTACHMENT: u32 = 0;
+const ALBEDO_ATTACHMENT: u32 = 1;
+const NORMAL_ATTACHMENT: u32 = 2;
+const MATERIAL_ATTACHMENT: u32 = 3;
+const DEPTH_ATTACHMENT: u32 = 4;
+
+const OPAQUE: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::FALSE,
+    src_color_blend_factor: vk::BlendFactor::ONE,
+    dst_color_blend_factor: vk::BlendFactor::ZERO,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+};
+
+const ADDITIVE: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::TRUE,
+    dst_color_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ONE,
+    ..OPAQUE
+};
+
+/// The camera the lighting subpass reconstructs positions with, laid out like `DeferredUniform`.
+const DEFERRED_FRAME_DESCRIPTOR_SET: ShaderDescriptorSetInfo<'static> = ShaderDescriptorSetInfo {
+    max_set_allocations: MAX_FRAMES_IN_FLIGHT,
+    descriptors: &[
+        ShaderDescriptorInfo {
+            name: "deferred",
+            descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { fields: &[] },
+            stage_flags: vk::ShaderStageFlags::from_raw(
+                vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw()
+            ),
+        },
+    ],
+};
+
+const GBUFFER_DESCRIPTOR_SET: ShaderDescriptorSetInfo<'static> = ShaderDescriptorSetInfo {
+    max_set_allocations: 1,
+    descriptors: &[
+        ShaderDescriptorInfo {
+            name: "gbuffer_albedo",
+            descriptor_type: ShaderDescriptorTypeInfo::InputAttachment,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "gbuffer_normal",
+            descriptor_type: ShaderDescriptorTypeInfo::InputAttachment,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "gbuffer_material",
+            descriptor_type: ShaderDescriptorTypeInfo::InputAttachment,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "gbuffer_depth",
+            descriptor_type: ShaderDescriptorTypeInfo::InputAttachment,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+    ],
+};
+
+/// How the world is lit: every light evaluated per object while drawing it, or the surfaces written to a G-buffer
+/// first and lit once per pixel afterwards.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderPath {
+    Forward,
+    Deferred,
+}
+
+#[derive(Clone, Copy)]
+pub enum LightVolume {
+    Point {
+        position: Vec3F,
+        range: f32,
+    },
+    /// The light fades out between `inner_angle` and the cone's outer angle, both half-angles in radians. The outer
+    /// angle must be less than a right angle.
+    Spot {
+        cone: SpotLight,
+        inner_angle: f32,
+    },
+}
+
+/// A point or spot light shaded by drawing its volume in the lighting subpass.
+#[derive(Clone, Copy)]
+pub struct LocalLight {
+    pub volume: LightVolume,
+    pub color: Vec3F,
+    pub intensity: f32,
+}
+
+impl LocalLight {
+    fn volume_constants(&self) -> LightVolumeConstants {
+        let radiance = [self.color.x * self.intensity, self.color.y * self.intensity, self.color.z * self.intensity];
+
+        match self.volume {
+            LightVolume::Point { position, range } => LightVolumeConstants {
+                model: Mat4::from_translation(position) * Mat4::from_scale(Vec3F::new(range, range, range)),
+                position_range: [position.x, position.y, position.z, range],
+                color: [radiance[0], radiance[1], radiance[2], 0.0],
+                direction_cos_outer: [0.0; 4],
+                cos_inner: [0.0; 4],
+            },
+            LightVolume::Spot { cone, inner_angle } => {
+                let direction = normalize(cone.direction);
+                let up = if direction.y.abs() > 0.99 { Vec3F::new(1.0, 0.0, 0.0) } else { Vec3F::new(0.0, 1.0, 0.0) };
+                let target = Vec3F::new(cone.position.x + direction.x, cone.position.y + direction.y, cone.position.z + direction.z);
+                let radius = cone.range * cone.outer_angle.tan();
+
+                // The unit cone points down -Z, like a camera looking from the light along its direction.
+                let orientation = Mat4::look_at(cone.position, target, up).inverse_affine();
+
+                LightVolumeConstants {
+                    model: orientation * Mat4::from_scale(Vec3F::new(radius, radius, cone.range)),
+                    position_range: [cone.position.x, cone.position.y, cone.position.z, cone.range],
+                    color: [radiance[0], radiance[1], radiance[2], 1.0],
+                    direction_cos_outer: [direction.x, direction.y, direction.z, cone.outer_angle.cos()],
+                    cos_inner: [inner_angle.cos(), 0.0, 0.0, 0.0],
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct DeferredUniform {
+    view_projection: Mat4,
+    inverse_view_projection: Mat4,
+    inverse_screen_size: [f32; 4],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct LightVolumeConstants {
+    model: Mat4,
+    position_range: [f32; 4],
+    /// Radiance in RGB, and 1 in A for spot lights.
+    color: [f32; 4],
+    direction_cos_outer: [f32; 4],
+    cos_inner: [f32; 4],
+}
+
+/// A deferred renderer built as one render pass of two subpasses, so the G-buffer can stay in tile memory on GPUs
+/// that render in tiles. The geometry subpass writes albedo, normals, material parameters and depth, plus emission
+/// straight into the output. The lighting subpass reads them back as input attachments, adds the sun and the
+/// environment with a fullscreen triangle, and every local light by drawing its volume.
+///
+/// Geometry is drawn by the caller with shaders targeting `geometry_render_target`, which write the four
+/// attachments described by `GEOMETRY_COLOR_BLEND_ATTACHMENT_STATES`. There is no blending into the G-buffer, so
+/// only opaque surfaces can be drawn, and shadows are not applied.
+pub struct DeferredRenderer<'ctx> {
+    pub render_pass: RenderPass<'ctx>,
+    /// The lit HDR image, kept in `COLOR_ATTACHMENT_OPTIMAL` outside the render pass.
+    pub output: Image<'ctx>,
+
+    /// Albedo, normal, material and depth, in attachment order.
+    gbuffer: [Image<'ctx>; 4],
+    framebuffer: Framebuffer<'ctx>,
+
+    lighting_shader: Shader<'ctx>,
+    light_volume_shader: Shader<'ctx>,
+    sphere: Mesh<'ctx>,
+    cone: Mesh<'ctx>,
+
+    uniform_buffers: Vec<Buffer<'ctx>>,
+    frame_descriptor_sets: Vec<vk::DescriptorSet>,
+    gbuffer_descriptor_set: vk::DescriptorSet,
+
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> DeferredRenderer<'ctx> {
+    pub const GEOMETRY_SUBPASS: u32 = 0;
+    pub const LIGHTING_SUBPASS: u32 = 1;
+
+    /// Albedo, normal, material and emission, in the order geometry fragment shaders write them.
+    pub const GEOMETRY_COLOR_BLEND_ATTACHMENT_STATES: [vk::PipelineColorBlendAttachmentState; 4] = [OPAQUE; 4];
+
+    pub fn new(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        size: Vec2UI,
+        clear_color: [f32; 4],
+    ) -> (Self, UploadTicket) {
+        let gbuffer_usage = vk::ImageUsageFlags::INPUT_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+
+        let output = Image::new_attachment(
+            vkcontext,
+            size,
+            DEFERRED_OUTPUT_FORMAT,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        );
+
+        let [albedo, normal, material] = [GBUFFER_ALBEDO_FORMAT, GBUFFER_NORMAL_FORMAT, GBUFFER_MATERIAL_FORMAT].map(|format| {
+            Image::new_attachment(
+                vkcontext,
+                size,
+                format,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | gbuffer_usage,
+            )
+        });
+
+        let depth = Image::new_attachment(
+            vkcontext,
+            size,
+            GBUFFER_DEPTH_FORMAT,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | gbuffer_usage,
+        );
+
+        let render_pass = create_render_pass(vkcontext, size, clear_color);
+
+        let framebuffer = Framebuffer::new(
+            vkcontext,
+            render_pass.handle,
+            &[&output, &albedo, &normal, &material, &depth].map(|image| image.image_view.unwrap()),
+            size,
+        );
+
+        let lighting_target = PipelineRenderTarget::RenderPass {
+            render_pass: render_pass.handle,
+            subpass_index: Self::LIGHTING_SUBPASS,
+        };
+
+        let lighting_shader = Shader::new(
+            vkcontext,
+            "builtin.deferred.lighting",
+            &lighting_target,
+            &[ADDITIVE],
+            &[],
+            &[],
+            &[],
+            &[DEFERRED_FRAME_DESCRIPTOR_SET, GBUFFER_DESCRIPTOR_SET, LIGHTING_DESCRIPTOR_SET],
+            &[
+                ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.fullscreen.vert.spv"),
+                ShaderStageInfo::new(vk::ShaderStageFlags::FRAGMENT, "shaders/builtin.deferred.lighting.frag.spv"),
+            ],
+            &PipelineStateInfo::get_default_pipeline_state_info()
+                .depth_test(false)
+                .cull_mode(vk::CullModeFlags::NONE),
+        );
+
+        // Back faces are drawn without a depth test, so a volume still shades when the camera is inside it.
+        let light_volume_stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+
+        let light_volume_shader = Shader::new(
+            vkcontext,
+            "builtin.deferred.light_volume",
+            &lighting_target,
+            &[ADDITIVE],
+            &[ Vertex::get_binding_description(0) ],
+            &Vertex::get_attributes(0),
+            &[
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Matrix4, stage_flags: light_volume_stages },
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Float32_4, stage_flags: light_volume_stages },
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Float32_4, stage_flags: light_volume_stages },
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Float32_4, stage_flags: light_volume_stages },
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Float32_4, stage_flags: light_volume_stages },
+            ],
+            &[DEFERRED_FRAME_DESCRIPTOR_SET, GBUFFER_DESCRIPTOR_SET, LIGHTING_DESCRIPTOR_SET],
+            &[
+                ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.deferred.light_volume.vert.spv"),
+                ShaderStageInfo::new(vk::ShaderStageFlags::FRAGMENT, "shaders/builtin.deferred.light_volume.frag.spv"),
+            ],
+            &PipelineStateInfo::get_default_pipeline_state_info()
+                .depth_test(false)
+                .cull_mode(vk::CullModeFlags::FRONT),
+        );
+
+        let (sphere_vertices, sphere_indices) = light_sphere();
+        let (cone_vertices, cone_indices) = light_cone();
+
+        let (sphere, _) = Mesh::new(vkcontext, upload_manager, "builtin.light_sphere".to_string(), &sphere_vertices, &sphere_indices);
+        let (cone, ticket) = Mesh::new(vkcontext, upload_manager, "builtin.light_cone".to_string(), &cone_vertices, &cone_indices);
+
+        let uniform_buffers = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            Buffer::new(
+                vkcontext,
+                std::mem::size_of::<DeferredUniform>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                true,
+            )
+        })
+        .collect::<Vec<_>>();
+
+        // Both lighting shaders have identical layouts, so sets allocated from one can be bound on the other.
+        let frame_descriptor_sets = lighting_shader.allocate_descriptor_sets(0, MAX_FRAMES_IN_FLIGHT);
+        let gbuffer_descriptor_set = lighting_shader.allocate_descriptor_sets(1, 1)[0];
+
+        for (descriptor_set, uniform_buffer) in frame_descriptor_sets.iter().zip(uniform_buffers.iter()) {
+            let buffer_info = vk::DescriptorBufferInfo::default()
+                .buffer(uniform_buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE);
+
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(*descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(slice::from_ref(&buffer_info));
+
+            unsafe { vkcontext.device.update_descriptor_sets(slice::from_ref(&write), &[]); }
+        }
+
+        let image_infos = [
+            (&albedo, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (&normal, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (&material, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (&depth, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+        ]
+        .map(|(image, layout)| {
+            vk::DescriptorImageInfo::default()
+                .image_view(image.image_view.unwrap())
+                .image_layout(layout)
+        });
+
+        let writes = image_infos.iter().enumerate().map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(gbuffer_descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .image_info(slice::from_ref(image_info))
+        })
+        .collect::<Vec<_>>();
+
+        unsafe { vkcontext.device.update_descriptor_sets(&writes, &[]); }
+
+        let renderer = Self {
+            render_pass,
+            output,
+            gbuffer: [albedo, normal, material, depth],
+            framebuffer,
+            lighting_shader,
+            light_volume_shader,
+            sphere,
+            cone,
+            uniform_buffers,
+            frame_descriptor_sets,
+            gbuffer_descriptor_set,
+            vkcontext,
+        };
+
+        (renderer, ticket)
+    }
+}
+
+impl<'ctx> DeferredRenderer<'ctx> {
+    /// What geometry shaders must be built for.
+    pub fn geometry_render_target(&self) -> PipelineRenderTarget<'static> {
+        PipelineRenderTarget::RenderPass {
+            render_pass: self.render_pass.handle,
+            subpass_index: Self::GEOMETRY_SUBPASS,
+        }
+    }
+
+    pub fn size(&self) -> Vec2UI {
+        self.output.size
+    }
+
+    /// Reloads the lighting shaders built from any of `changed_files`. See `Shader::reload`.
+    pub fn reload_shaders(&self, changed_files: &[String]) {
+        self.lighting_shader.reload(changed_files);
+        self.light_volume_shader.reload(changed_files);
+    }
+
+    /// Writes the camera of `frame_index`.
+    pub fn prepare(&mut self, frame_index: u32, view_projection: &Mat4) {
+        let size = self.size();
+
+        let uniform = DeferredUniform {
+            view_projection: *view_projection,
+            inverse_view_projection: view_projection.inverse(),
+            inverse_screen_size: [1.0 / size.x as f32, 1.0 / size.y as f32, 0.0, 0.0],
+        };
+
+        self.uniform_buffers[frame_index as usize].load_value(0, &uniform, vk::MemoryMapFlags::default());
+    }
+
+    /// Records the whole render pass. `draw_geometry` records the G-buffer draws, with the viewport already set.
+    /// The sun and the environment come from `environment_lighting`, whose maps must have been generated.
+    pub fn record<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: u32,
+        environment_lighting: &ImageBasedLighting,
+        lights: &[LocalLight],
+        draw_geometry: F,
+    ) {
+        let device = &self.vkcontext.device;
+
+        // The render graph has put the output in COLOR_ATTACHMENT_OPTIMAL, without the image knowing.
+        self.output.mark_usage(self.output.subresource_range(), ImageUsage::ColorAttachment);
+
+        let [albedo, normal, material, depth] = &self.gbuffer;
+        self.render_pass.begin_tracked(command_buffer, self.framebuffer.handle, &[&self.output, albedo, normal, material, depth]);
+        utility::set_viewport_and_scissor(device, command_buffer, Vec2UI::default(), self.size());
+
+        draw_geometry(command_buffer);
+
+        unsafe { device.cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE); }
+
+        let descriptor_sets = [self.frame_descriptor_sets[frame_index as usize], self.gbuffer_descriptor_set];
+
+        self.lighting_shader.bind(command_buffer);
+        self.lighting_shader.bind_descriptor_sets(command_buffer, 0, &descriptor_sets);
+        environment_lighting.bind(command_buffer, &self.lighting_shader, 2, frame_index);
+
+        unsafe { device.cmd_draw(command_buffer, 3, 1, 0, 0); }
+
+        if !lights.is_empty() {
+            self.light_volume_shader.bind(command_buffer);
+            self.light_volume_shader.bind_descriptor_sets(command_buffer, 0, &descriptor_sets);
+            environment_lighting.bind(command_buffer, &self.light_volume_shader, 2, frame_index);
+
+            for light in lights {
+                let mesh = match light.volume {
+                    LightVolume::Point { .. } => &self.sphere,
+                    LightVolume::Spot { .. } => &self.cone,
+                };
+
+                self.light_volume_shader.push_constants(command_buffer, 0, &light.volume_constants());
+                mesh.draw(command_buffer);
+            }
+        }
+
+        self.render_pass.end(command_buffer);
+    }
+}
+
+fn create_render_pass<'ctx>(vkcontext: &'ctx VkContext, size: Vec2UI, clear_color: [f32; 4]) -> RenderPass<'ctx> {
+    let gbuffer_attachment = |format: vk::Format, final_layout: vk::ImageLayout| {
+        vk::AttachmentDescription::default()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(final_layout)
+    };
+
+    // Only the output outlives the render pass.
+    let attachments = [
+        gbuffer_attachment(DEFERRED_OUTPUT_FORMAT, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        gbuffer_attachment(GBUFFER_ALBEDO_FORMAT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        gbuffer_attachment(GBUFFER_NORMAL_FORMAT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        gbuffer_attachment(GBUFFER_MATERIAL_FORMAT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        gbuffer_attachment(GBUFFER_DEPTH_FORMAT, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR),
+    ];
+
+    let clear_values = [
+        Some(vk::ClearValue { color: vk::ClearColorValue { float32: clear_color } }),
+        None,
+        None,
+        None,
+        Some(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }),
+    ];
+
+    let reference = |attachment: u32, layout: vk::ImageLayout| vk::AttachmentReference { attachment, layout };
+
+    let geometry_color_attachments = [ALBEDO_ATTACHMENT, NORMAL_ATTACHMENT, MATERIAL_ATTACHMENT, OUTPUT_ATTACHMENT]
+        .map(|attachment| reference(attachment, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL));
+    let geometry_depth_attachment = reference(DEPTH_ATTACHMENT, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let lighting_input_attachments = [
+        reference(ALBEDO_ATTACHMENT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(NORMAL_ATTACHMENT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(MATERIAL_ATTACHMENT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(DEPTH_ATTACHMENT, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+    ];
+    let lighting_color_attachments = [reference(OUTPUT_ATTACHMENT, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let subpasses = [
+        RenderPassSubPassInfo {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            input_attachments: &[],
+            color_attachments: Some(&geometry_color_attachments),
+            resolve_attachments: None,
+            depth_stencil_attachments: Some(&geometry_depth_attachment),
+            preserve_attachments: None,
+        },
+        RenderPassSubPassInfo {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            input_attachments: &lighting_input_attachments,
+            color_attachments: Some(&lighting_color_attachments),
+            resolve_attachments: None,
+            depth_stencil_attachments: None,
+            preserve_attachments: None,
+        },
+    ];
+
+    let dependencies = [
+        // The previous frame's lighting subpass must be done with the G-buffer before it is overwritten.
+        vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(DeferredRenderer::GEOMETRY_SUBPASS)
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            )
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            )
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            ),
+        // Each pixel only reads its own G-buffer texels, so the dependency can stay within a tile.
+        vk::SubpassDependency::default()
+            .src_subpass(DeferredRenderer::GEOMETRY_SUBPASS)
+            .dst_subpass(DeferredRenderer::LIGHTING_SUBPASS)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::INPUT_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            )
+            .dependency_flags(vk::DependencyFlags::BY_REGION),
+    ];
+
+    RenderPass::new(vkcontext, Vec2UI::default(), size, &attachments, &clear_values, &subpasses, &dependencies)
+}
+
+/// Adds the triangle wound so that it faces away from `interior`, which must be inside the convex mesh.
+fn push_outward_triangle(vertices: &[Vertex], indices: &mut Vec<u32>, [a, b, c]: [u32; 3], interior: Vec3F) {
+    let [pa, pb, pc] = [a, b, c].map(|index| vertices[index as usize].position);
+    let normal = cross(sub(pb, pa), sub(pc, pa));
+
+    if dot(normal, sub(pa, interior)) >= 0.0 {
+        indices.extend([a, b, c]);
+    } else {
+        indices.extend([a, c, b]);
+    }
+}
+
+fn volume_vertex(position: Vec3F) -> Vertex {
+    Vertex { position, texture_coordinate: Vec2F { x: 0.0, y: 0.0 }, normal: position }
+}
+
+/// A UV sphere enclosing the unit sphere: pushed out so that its flat faces never cut inside it.
+fn light_sphere() -> (Vec<Vertex>, Vec<u32>) {
+    const SEGMENTS: u32 = 16;
+    const RINGS: u32 = 8;
+
+    let scale = 1.0 / ((PI / SEGMENTS as f32).cos() * (PI / (2 * RINGS) as f32).cos());
+
+    let vertices = (0..=RINGS).flat_map(|ring| (0..SEGMENTS).map(move |segment| (ring, segment))).map(|(ring, segment)| {
+        let theta = ring as f32 / RINGS as f32 * PI;
+        let phi = segment as f32 / SEGMENTS as f32 * 2.0 * PI;
+
+        volume_vertex(Vec3F::new(theta.sin() * phi.cos() * scale, theta.cos() * scale, theta.sin() * phi.sin() * scale))
+    })
+    .collect::<Vec<_>>();
+
+    let mut indices = Vec::new();
+    let interior = Vec3F::new(0.0, 0.0, 0.0);
+
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let next = (segment + 1) % SEGMENTS;
+            let [a, b, c, d] = [
+                ring * SEGMENTS + segment,
+                ring * SEGMENTS + next,
+                (ring + 1) * SEGMENTS + segment,
+                (ring + 1) * SEGMENTS + next,
+            ];
+
+            // The triangles touching the poles are degenerate; one of each pair is enough there.
+            if ring != 0 {
+                push_outward_triangle(&vertices, &mut indices, [a, c, b], interior);
+            }
+
+            if ring != RINGS - 1 {
+                push_outward_triangle(&vertices, &mut indices, [b, c, d], interior);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// A cone with its apex at the origin, pointing down -Z, enclosing the circular cone of length and base radius 1.
+fn light_cone() -> (Vec<Vertex>, Vec<u32>) {
+    const SEGMENTS: u32 = 16;
+
+    let scale = 1.0 / (PI / SEGMENTS as f32).cos();
+
+    let mut vertices = vec![volume_vertex(Vec3F::new(0.0, 0.0, 0.0)), volume_vertex(Vec3F::new(0.0, 0.0, -1.0))];
+
+    vertices.extend((0..SEGMENTS).map(|segment| {
+        let phi = segment as f32 / SEGMENTS as f32 * 2.0 * PI;
+
+        volume_vertex(Vec3F::new(phi.cos() * scale, phi.sin() * scale, -1.0))
+    }));
+
+    let mut indices = Vec::new();
+    let interior = Vec3F::new(0.0, 0.0, -0.5);
+
+    for segment in 0..SEGMENTS {
+        let current = 2 + segment;
+        let next = 2 + (segment + 1) % SEGMENTS;
+
+        push_outward_triangle(&vertices, &mut indices, [0, current, next], interior);
+        push_outward_triangle(&vertices, &mut indices, [1, next, current], interior);
+    }
+
+    (vertices, indices)
+}
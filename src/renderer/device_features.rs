@@ -0,0 +1,282 @@
+use std::{mem::offset_of, ptr, slice};
+
+use ash::{vk, Instance};
+
+/// The set of device features a context requires, requests or has enabled. Each group mirrors the Vulkan feature
+/// struct of the same name; their `p_next` pointers are always null.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DeviceFeatures {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan11: vk::PhysicalDeviceVulkan11Features<'static>,
+    pub vulkan12: vk::PhysicalDeviceVulkan12Features<'static>,
+    pub vulkan13: vk::PhysicalDeviceVulkan13Features<'static>,
+}
+
+impl DeviceFeatures {
+    pub fn query(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut vulkan11 = vk::PhysicalDeviceVulkan11Features::default();
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan13 = vk::PhysicalDeviceVulkan13Features::default();
+
+        let core = {
+            let mut features2 = vk::PhysicalDeviceFeatures2::default()
+                .push_next(&mut vulkan11)
+                .push_next(&mut vulkan12)
+                .push_next(&mut vulkan13);
+
+            unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+            features2.features
+        };
+
+        vulkan11.p_next = ptr::null_mut();
+        vulkan12.p_next = ptr::null_mut();
+        vulkan13.p_next = ptr::null_mut();
+
+        Self {
+            core,
+            vulkan11,
+            vulkan12,
+            vulkan13,
+        }
+    }
+}
+
+impl DeviceFeatures {
+    /// Features enabled in either set.
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// Features enabled in both sets.
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a & b)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups().iter().all(|(_, bools, _)| bools.iter().all(|b| *b == vk::FALSE))
+    }
+
+    /// Names (e.g. `vulkan12.timeline_semaphore`) of the features enabled in `self` but not in `supported`.
+    pub fn missing_from(&self, supported: &Self) -> Vec<String> {
+        self.difference(supported).enabled_names()
+    }
+
+    pub fn enabled_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+
+        for (group, bools, group_names) in self.groups() {
+            for (enabled, name) in bools.iter().zip(group_names.iter()) {
+                if *enabled == vk::TRUE {
+                    names.push(format!("{}.{}", group, name));
+                }
+            }
+        }
+
+        names
+    }
+
+    pub fn enabled_count(&self) -> usize {
+        self.groups().iter().map(|(_, bools, _)| bools.iter().filter(|b| **b == vk::TRUE).count()).sum()
+    }
+
+    fn difference(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a & !b & 1)
+    }
+
+    fn combine(&self, other: &Self, op: impl Fn(vk::Bool32, vk::Bool32) -> vk::Bool32) -> Self {
+        let mut out = *self;
+        let others = other.groups();
+
+        for ((_, out_bools, _), (_, other_bools, _)) in out.groups_mut().into_iter().zip(others.iter()) {
+            for (out_bool, other_bool) in out_bools.iter_mut().zip(other_bools.iter()) {
+                *out_bool = op(*out_bool, *other_bool);
+            }
+        }
+
+        out
+    }
+
+    fn groups(&self) -> [(&'static str, &[vk::Bool32], &'static [&'static str]); 4] {
+        unsafe {
+            [
+                ("core", bools(&self.core, CORE_FIRST, CORE_FEATURE_NAMES.len()), &CORE_FEATURE_NAMES),
+                ("vulkan11", bools(&self.vulkan11, VULKAN11_FIRST, VULKAN11_FEATURE_NAMES.len()), &VULKAN11_FEATURE_NAMES),
+                ("vulkan12", bools(&self.vulkan12, VULKAN12_FIRST, VULKAN12_FEATURE_NAMES.len()), &VULKAN12_FEATURE_NAMES),
+                ("vulkan13", bools(&self.vulkan13, VULKAN13_FIRST, VULKAN13_FEATURE_NAMES.len()), &VULKAN13_FEATURE_NAMES),
+            ]
+        }
+    }
+
+    fn groups_mut(&mut self) -> [(&'static str, &mut [vk::Bool32], &'static [&'static str]); 4] {
+        unsafe {
+            [
+                ("core", bools_mut(&mut self.core, CORE_FIRST, CORE_FEATURE_NAMES.len()), &CORE_FEATURE_NAMES),
+                ("vulkan11", bools_mut(&mut self.vulkan11, VULKAN11_FIRST, VULKAN11_FEATURE_NAMES.len()), &VULKAN11_FEATURE_NAMES),
+                ("vulkan12", bools_mut(&mut self.vulkan12, VULKAN12_FIRST, VULKAN12_FEATURE_NAMES.len()), &VULKAN12_FEATURE_NAMES),
+                ("vulkan13", bools_mut(&mut self.vulkan13, VULKAN13_FIRST, VULKAN13_FEATURE_NAMES.len()), &VULKAN13_FEATURE_NAMES),
+            ]
+        }
+    }
+}
+
+// The feature structs are a header followed by a contiguous run of `Bool32`s, which lets them be treated as slices.
+// The assertions below guarantee the runs are contiguous and match the name tables.
+const CORE_FIRST: usize = offset_of!(vk::PhysicalDeviceFeatures, robust_buffer_access);
+const VULKAN11_FIRST: usize = offset_of!(vk::PhysicalDeviceVulkan11Features, storage_buffer16_bit_access);
+const VULKAN12_FIRST: usize = offset_of!(vk::PhysicalDeviceVulkan12Features, sampler_mirror_clamp_to_edge);
+const VULKAN13_FIRST: usize = offset_of!(vk::PhysicalDeviceVulkan13Features, robust_image_access);
+
+const _: () = assert!(offset_of!(vk::PhysicalDeviceFeatures, inherited_queries) == CORE_FIRST + 4 * (CORE_FEATURE_NAMES.len() - 1));
+const _: () = assert!(offset_of!(vk::PhysicalDeviceVulkan11Features, shader_draw_parameters) == VULKAN11_FIRST + 4 * (VULKAN11_FEATURE_NAMES.len() - 1));
+const _: () = assert!(offset_of!(vk::PhysicalDeviceVulkan12Features, subgroup_broadcast_dynamic_id) == VULKAN12_FIRST + 4 * (VULKAN12_FEATURE_NAMES.len() - 1));
+const _: () = assert!(offset_of!(vk::PhysicalDeviceVulkan13Features, maintenance4) == VULKAN13_FIRST + 4 * (VULKAN13_FEATURE_NAMES.len() - 1));
+
+unsafe fn bools<T>(features: &T, first: usize, count: usize) -> &[vk::Bool32] {
+    slice::from_raw_parts((features as *const T as *const u8).add(first) as *const vk::Bool32, count)
+}
+
+unsafe fn bools_mut<T>(features: &mut T, first: usize, count: usize) -> &mut [vk::Bool32] {
+    slice::from_raw_parts_mut((features as *mut T as *mut u8).add(first) as *mut vk::Bool32, count)
+}
+
+const CORE_FEATURE_NAMES: [&str; 55] = [
+    "robust_buffer_access",
+    "full_draw_index_uint32",
+    "image_cube_array",
+    "independent_blend",
+    "geometry_shader",
+    "tessellation_shader",
+    "sample_rate_shading",
+    "dual_src_blend",
+    "logic_op",
+    "multi_draw_indirect",
+    "draw_indirect_first_instance",
+    "depth_clamp",
+    "depth_bias_clamp",
+    "fill_mode_non_solid",
+    "depth_bounds",
+    "wide_lines",
+    "large_points",
+    "alpha_to_one",
+    "multi_viewport",
+    "sampler_anisotropy",
+    "texture_compression_etc2",
+    "texture_compression_astc_ldr",
+    "texture_compression_bc",
+    "occlusion_query_precise",
+    "pipeline_statistics_query",
+    "vertex_pipeline_stores_and_atomics",
+    "fragment_stores_and_atomics",
+    "shader_tessellation_and_geometry_point_size",
+    "shader_image_gather_extended",
+    "shader_storage_image_extended_formats",
+    "shader_storage_image_multisample",
+    "shader_storage_image_read_without_format",
+    "shader_storage_image_write_without_format",
+    "shader_uniform_buffer_array_dynamic_indexing",
+    "shader_sampled_image_array_dynamic_indexing",
+    "shader_storage_buffer_array_dynamic_indexing",
+    "shader_storage_image_array_dynamic_indexing",
+    "shader_clip_distance",
+    "shader_cull_distance",
+    "shader_float64",
+    "shader_int64",
+    "shader_int16",
+    "shader_resource_residency",
+    "shader_resource_min_lod",
+    "sparse_binding",
+    "sparse_residency_buffer",
+    "sparse_residency_image2_d",
+    "sparse_residency_image3_d",
+    "sparse_residency2_samples",
+    "sparse_residency4_samples",
+    "sparse_residency8_samples",
+    "sparse_residency16_samples",
+    "sparse_residency_aliased",
+    "variable_multisample_rate",
+    "inherited_queries",
+];
+
+const VULKAN11_FEATURE_NAMES: [&str; 12] = [
+    "storage_buffer16_bit_access",
+    "uniform_and_storage_buffer16_bit_access",
+    "storage_push_constant16",
+    "storage_input_output16",
+    "multiview",
+    "multiview_geometry_shader",
+    "multiview_tessellation_shader",
+    "variable_pointers_storage_buffer",
+    "variable_pointers",
+    "protected_memory",
+    "sampler_ycbcr_conversion",
+    "shader_draw_parameters",
+];
+
+const VULKAN12_FEATURE_NAMES: [&str; 47] = [
+    "sampler_mirror_clamp_to_edge",
+    "draw_indirect_count",
+    "storage_buffer8_bit_access",
+    "uniform_and_storage_buffer8_bit_access",
+    "storage_push_constant8",
+    "shader_buffer_int64_atomics",
+    "shader_shared_int64_atomics",
+    "shader_float16",
+    "shader_int8",
+    "descriptor_indexing",
+    "shader_input_attachment_array_dynamic_indexing",
+    "shader_uniform_texel_buffer_array_dynamic_indexing",
+    "shader_storage_texel_buffer_array_dynamic_indexing",
+    "shader_uniform_buffer_array_non_uniform_indexing",
+    "shader_sampled_image_array_non_uniform_indexing",
+    "shader_storage_buffer_array_non_uniform_indexing",
+    "shader_storage_image_array_non_uniform_indexing",
+    "shader_input_attachment_array_non_uniform_indexing",
+    "shader_uniform_texel_buffer_array_non_uniform_indexing",
+    "shader_storage_texel_buffer_array_non_uniform_indexing",
+    "descriptor_binding_uniform_buffer_update_after_bind",
+    "descriptor_binding_sampled_image_update_after_bind",
+    "descriptor_binding_storage_image_update_after_bind",
+    "descriptor_binding_storage_buffer_update_after_bind",
+    "descriptor_binding_uniform_texel_buffer_update_after_bind",
+    "descriptor_binding_storage_texel_buffer_update_after_bind",
+    "descriptor_binding_update_unused_while_pending",
+    "descriptor_binding_partially_bound",
+    "descriptor_binding_variable_descriptor_count",
+    "runtime_descriptor_array",
+    "sampler_filter_minmax",
+    "scalar_block_layout",
+    "imageless_framebuffer",
+    "uniform_buffer_standard_layout",
+    "shader_subgroup_extended_types",
+    "separate_depth_stencil_layouts",
+    "host_query_reset",
+    "timeline_semaphore",
+    "buffer_device_address",
+    "buffer_device_address_capture_replay",
+    "buffer_device_address_multi_device",
+    "vulkan_memory_model",
+    "vulkan_memory_model_device_scope",
+    "vulkan_memory_model_availability_visibility_chains",
+    "shader_output_viewport_index",
+    "shader_output_layer",
+    "subgroup_broadcast_dynamic_id",
+];
+
+const VULKAN13_FEATURE_NAMES: [&str; 15] = [
+    "robust_image_access",
+    "inline_uniform_block",
+    "descriptor_binding_inline_uniform_block_update_after_bind",
+    "pipeline_creation_cache_control",
+    "private_data",
+    "shader_demote_to_helper_invocation",
+    "shader_terminate_invocation",
+    "subgroup_size_control",
+    "compute_full_subgroups",
+    "synchronization2",
+    "texture_compression_astc_hdr",
+    "shader_zero_initialize_workgroup_memory",
+    "dynamic_rendering",
+    "shader_integer_dot_product",
+    "maintenance4",
+];
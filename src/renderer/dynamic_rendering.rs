@@ -0,0 +1,128 @@
+use ash::vk;
+
+use crate::math::vec2::Vec2UI;
+
+use super::vkcontext::VkContext;
+
+/// An image view rendered into with dynamic rendering. The view must already be in `image_layout` when rendering
+/// begins; no layout transitions are recorded on its behalf.
+#[derive(Clone, Copy)]
+pub struct RenderingAttachment {
+    pub image_view: vk::ImageView,
+    pub image_layout: vk::ImageLayout,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    pub clear_value: Option<vk::ClearValue>,
+    pub resolve: Option<RenderingResolveAttachment>,
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderingResolveAttachment {
+    pub image_view: vk::ImageView,
+    pub image_layout: vk::ImageLayout,
+    pub mode: vk::ResolveModeFlags,
+}
+
+impl RenderingAttachment {
+    pub fn color(image_view: vk::ImageView, clear_value: Option<vk::ClearColorValue>) -> Self {
+        Self {
+            image_view,
+            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            load_op: if clear_value.is_some() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::LOAD },
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: clear_value.map(|color| vk::ClearValue { color }),
+            resolve: None,
+        }
+    }
+
+    pub fn depth(image_view: vk::ImageView, clear_value: Option<vk::ClearDepthStencilValue>) -> Self {
+        Self {
+            image_view,
+            image_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            load_op: if clear_value.is_some() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::LOAD },
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: clear_value.map(|depth_stencil| vk::ClearValue { depth_stencil }),
+            resolve: None,
+        }
+    }
+
+    fn as_vk_rendering_attachment_info(&self) -> vk::RenderingAttachmentInfo<'static> {
+        let mut info = vk::RenderingAttachmentInfo::default()
+            .image_view(self.image_view)
+            .image_layout(self.image_layout)
+            .load_op(self.load_op)
+            .store_op(self.store_op)
+            .clear_value(self.clear_value.unwrap_or_default());
+
+        if let Some(resolve) = self.resolve {
+            info = info
+                .resolve_mode(resolve.mode)
+                .resolve_image_view(resolve.image_view)
+                .resolve_image_layout(resolve.image_layout);
+        }
+
+        info
+    }
+}
+
+/// The dynamic rendering counterpart of `RenderPass`. Nothing here depends on the swapchain images, so unlike a
+/// `RenderPass` and its `Framebuffer`s it only needs its render area updated when the swapchain is recreated.
+pub struct DynamicRendering<'c> {
+    pub render_area_start: Vec2UI,
+    pub render_area_size: Vec2UI,
+    vkcontext: &'c VkContext,
+}
+
+impl<'c> DynamicRendering<'c> {
+    pub fn new(vkcontext: &'c VkContext, render_area_start: Vec2UI, render_area_size: Vec2UI) -> Self {
+        assert!(
+            vkcontext.is_dynamic_rendering_enabled(),
+            "Dynamic rendering is not enabled on this device."
+        );
+
+        Self {
+            render_area_start,
+            render_area_size,
+            vkcontext,
+        }
+    }
+}
+
+impl<'c> DynamicRendering<'c> {
+    pub fn begin(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_attachments: &[RenderingAttachment],
+        depth_attachment: Option<&RenderingAttachment>,
+        stencil_attachment: Option<&RenderingAttachment>,
+    ) {
+        let color_attachments = color_attachments.iter()
+            .map(|attachment| attachment.as_vk_rendering_attachment_info())
+            .collect::<Vec<_>>();
+
+        let depth_attachment = depth_attachment.map(|attachment| attachment.as_vk_rendering_attachment_info());
+        let stencil_attachment = stencil_attachment.map(|attachment| attachment.as_vk_rendering_attachment_info());
+
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: self.render_area_start.x as i32, y: self.render_area_start.y as i32 },
+                extent: self.render_area_size.as_vk_extent_2d(),
+            })
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        if let Some(depth_attachment) = depth_attachment.as_ref() {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+
+        if let Some(stencil_attachment) = stencil_attachment.as_ref() {
+            rendering_info = rendering_info.stencil_attachment(stencil_attachment);
+        }
+
+        unsafe { self.vkcontext.device.cmd_begin_rendering(command_buffer, &rendering_info); }
+    }
+
+    pub fn end(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.vkcontext.device.cmd_end_rendering(command_buffer); }
+    }
+}
@@ -0,0 +1,411 @@
+use std::slice;
+
+use ash::vk;
+
+use crate::math::{frustum::{BoundingSphere, Frustum}, mat4::Mat4, vec3::Vec3UI};
+
+use super::{
+    buffer::Buffer,
+    mesh::{InstanceData, Vertex},
+    shader::{
+        ComputeShader,
+        ShaderDescriptorInfo,
+        ShaderDescriptorSetInfo,
+        ShaderDescriptorTypeInfo,
+        ShaderStageInfo,
+        ShaderType,
+        ShaderUniformFieldInfo,
+        ShaderVertexAttributeInfo,
+    },
+    upload::{UploadManager, UploadTicket},
+    vkcontext::VkContext,
+    MAX_FRAMES_IN_FLIGHT,
+};
+
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+/// Where a mesh lives inside a `GpuScene`'s shared vertex and index buffers.
+#[derive(Clone, Copy)]
+pub struct GpuMesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+    pub bounding_sphere: BoundingSphere,
+}
+
+/// An object as the cull shader sees it. It starts with an `InstanceData`, so the object buffer doubles as the
+/// instance-rate vertex buffer of the draws the cull shader emits.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct GpuObject {
+    pub instance: InstanceData,
+    pub bounds: [f32; 4],
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+    _padding: u32,
+}
+
+impl GpuObject {
+    pub fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(std::mem::size_of::<GpuObject>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+    }
+
+    pub fn get_attributes(binding: u32) -> [ShaderVertexAttributeInfo; 2] {
+        InstanceData::get_attributes(binding)
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CullUniform {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+    _padding: [u32; 3],
+}
+
+struct GpuSceneFrame<'ctx> {
+    object_buffer: Buffer<'ctx>,
+    cull_uniform_buffer: Buffer<'ctx>,
+    draw_command_buffer: Buffer<'ctx>,
+    draw_count_buffer: Buffer<'ctx>,
+    descriptor_set: vk::DescriptorSet,
+    object_count: u32,
+}
+
+/// Draws a whole scene with a single indirect draw. Every mesh lives in one shared vertex and index buffer, a
+/// compute pass frustum-culls the objects and writes a `vk::DrawIndexedIndirectCommand` per visible object along
+/// with a count, and `draw` submits them with `cmd_draw_indexed_indirect_count`.
+///
+/// Every object is drawn with the same shader and material, which the caller binds before `draw`. The shader's
+/// vertex input must be `Vertex` at binding 0 and `GpuObject` at binding 1.
+pub struct GpuScene<'ctx> {
+    pub vertex_buffer: Buffer<'ctx>,
+    pub index_buffer: Buffer<'ctx>,
+    pub max_objects: u32,
+
+    vertex_count: u32,
+    index_count: u32,
+    meshes: Vec<GpuMesh>,
+    objects: Vec<GpuObject>,
+    frames: Vec<GpuSceneFrame<'ctx>>,
+    cull_shader: ComputeShader<'ctx>,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> GpuScene<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext, max_vertices: u32, max_indices: u32, max_objects: u32) -> Self {
+        assert!(
+            vkcontext.is_draw_indirect_count_enabled(),
+            "GpuScene requires the draw_indirect_count and draw_indirect_first_instance features."
+        );
+
+        let vertex_buffer = Buffer::new(
+            vkcontext,
+            max_vertices as u64 * std::mem::size_of::<Vertex>() as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            true,
+        );
+
+        let index_buffer = Buffer::new(
+            vkcontext,
+            max_indices as u64 * std::mem::size_of::<u32>() as u64,
+            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            true,
+        );
+
+        let cull_shader = ComputeShader::new(
+            vkcontext,
+            "builtin.cull",
+            &[],
+            &[
+                ShaderDescriptorSetInfo {
+                    max_set_allocations: MAX_FRAMES_IN_FLIGHT,
+                    descriptors: &[
+                        ShaderDescriptorInfo {
+                            name: "cull",
+                            descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer {
+                                fields: &[
+                                    ShaderUniformFieldInfo { name: "planes[0]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "planes[1]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "planes[2]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "planes[3]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "planes[4]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "planes[5]", field_type: ShaderType::Float32_4 },
+                                    ShaderUniformFieldInfo { name: "object_count", field_type: ShaderType::UInt32 },
+                                ],
+                            },
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                        },
+                        ShaderDescriptorInfo {
+                            name: "objects",
+                            descriptor_type: ShaderDescriptorTypeInfo::StorageBuffer,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                        },
+                        ShaderDescriptorInfo {
+                            name: "draw_commands",
+                            descriptor_type: ShaderDescriptorTypeInfo::StorageBuffer,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                        },
+                        ShaderDescriptorInfo {
+                            name: "draw_count",
+                            descriptor_type: ShaderDescriptorTypeInfo::StorageBuffer,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                        },
+                    ],
+                },
+            ],
+            &ShaderStageInfo::new(vk::ShaderStageFlags::COMPUTE, "shaders/builtin.cull.comp.spv"),
+        );
+
+        let descriptor_sets = cull_shader.allocate_descriptor_sets(0, MAX_FRAMES_IN_FLIGHT);
+
+        let frames = descriptor_sets.into_iter()
+            .map(|descriptor_set| GpuSceneFrame::new(vkcontext, descriptor_set, max_objects))
+            .collect();
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            max_objects,
+            vertex_count: 0,
+            index_count: 0,
+            meshes: Vec::new(),
+            objects: Vec::new(),
+            frames,
+            cull_shader,
+            vkcontext,
+        }
+    }
+}
+
+impl<'ctx> GpuSceneFrame<'ctx> {
+    fn new(vkcontext: &'ctx VkContext, descriptor_set: vk::DescriptorSet, max_objects: u32) -> Self {
+        let host_visible = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        let object_buffer = Buffer::new(
+            vkcontext,
+            max_objects as u64 * std::mem::size_of::<GpuObject>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            host_visible,
+            true,
+        );
+
+        let cull_uniform_buffer = Buffer::new(
+            vkcontext,
+            std::mem::size_of::<CullUniform>() as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            host_visible,
+            true,
+        );
+
+        let draw_command_buffer = Buffer::new(
+            vkcontext,
+            max_objects as u64 * std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            true,
+        );
+
+        let draw_count_buffer = Buffer::new(
+            vkcontext,
+            std::mem::size_of::<u32>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            true,
+        );
+
+        let buffer_infos = [
+            &cull_uniform_buffer,
+            &object_buffer,
+            &draw_command_buffer,
+            &draw_count_buffer,
+        ]
+        .map(|buffer| vk::DescriptorBufferInfo::default().buffer(buffer.handle).offset(0).range(vk::WHOLE_SIZE));
+
+        let writes = buffer_infos.iter().enumerate().map(|(binding, buffer_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .dst_array_element(0)
+                .descriptor_type(if binding == 0 { vk::DescriptorType::UNIFORM_BUFFER } else { vk::DescriptorType::STORAGE_BUFFER })
+                .buffer_info(slice::from_ref(buffer_info))
+        })
+        .collect::<Vec<_>>();
+
+        unsafe { vkcontext.device.update_descriptor_sets(&writes, &[]); }
+
+        Self {
+            object_buffer,
+            cull_uniform_buffer,
+            draw_command_buffer,
+            draw_count_buffer,
+            descriptor_set,
+            object_count: 0,
+        }
+    }
+}
+
+impl<'ctx> GpuScene<'ctx> {
+    /// Appends a mesh to the shared buffers and queues its upload.
+    pub fn add_mesh(&mut self, upload_manager: &mut UploadManager, vertices: &[Vertex], indices: &[u32]) -> (usize, UploadTicket) {
+        let vertex_offset = self.vertex_count;
+        let first_index = self.index_count;
+
+        assert!(
+            (vertex_offset as u64 + vertices.len() as u64) * std::mem::size_of::<Vertex>() as u64 <= self.vertex_buffer.size,
+            "GpuScene vertex buffer is full."
+        );
+        assert!(
+            (first_index as u64 + indices.len() as u64) * std::mem::size_of::<u32>() as u64 <= self.index_buffer.size,
+            "GpuScene index buffer is full."
+        );
+
+        upload_manager.upload_buffer(&self.vertex_buffer, vertex_offset as u64 * std::mem::size_of::<Vertex>() as u64, vertices);
+        let ticket = upload_manager.upload_buffer(&self.index_buffer, first_index as u64 * std::mem::size_of::<u32>() as u64, indices);
+
+        self.vertex_count += vertices.len() as u32;
+        self.index_count += indices.len() as u32;
+
+        self.meshes.push(GpuMesh {
+            first_index,
+            index_count: indices.len() as u32,
+            vertex_offset: vertex_offset as i32,
+            bounding_sphere: BoundingSphere::from_points(vertices.iter().map(|vertex| vertex.position)),
+        });
+
+        (self.meshes.len() - 1, ticket)
+    }
+
+    pub fn mesh(&self, mesh: usize) -> &GpuMesh {
+        &self.meshes[mesh]
+    }
+
+    /// Adds an object drawing `mesh` and returns its index.
+    pub fn add_object(&mut self, mesh: usize, instance: InstanceData) -> usize {
+        assert!((self.objects.len() as u32) < self.max_objects, "GpuScene can hold at most {} objects.", self.max_objects);
+
+        let mesh = self.meshes[mesh];
+        let center = mesh.bounding_sphere.center;
+
+        self.objects.push(GpuObject {
+            instance,
+            bounds: [center.x, center.y, center.z, mesh.bounding_sphere.radius],
+            first_index: mesh.first_index,
+            index_count: mesh.index_count,
+            vertex_offset: mesh.vertex_offset,
+            _padding: 0,
+        });
+
+        self.objects.len() - 1
+    }
+
+    pub fn set_object_instance(&mut self, object: usize, instance: InstanceData) {
+        self.objects[object].instance = instance;
+    }
+
+    pub fn clear_objects(&mut self) {
+        self.objects.clear();
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn draw_command_buffer(&self, frame_index: u32) -> vk::Buffer {
+        self.frames[frame_index as usize].draw_command_buffer.handle
+    }
+
+    pub fn draw_count_buffer(&self, frame_index: u32) -> vk::Buffer {
+        self.frames[frame_index as usize].draw_count_buffer.handle
+    }
+}
+
+impl<'ctx> GpuScene<'ctx> {
+    /// Writes the objects and the frustum of `view_projection` into the buffers of `frame_index`. The renderer must
+    /// have waited for that frame's previous submission.
+    pub fn prepare(&mut self, frame_index: u32, view_projection: &Mat4) {
+        let frame = &mut self.frames[frame_index as usize];
+
+        frame.object_count = self.objects.len() as u32;
+
+        if !self.objects.is_empty() {
+            frame.object_buffer.load_slice(0, &self.objects, vk::MemoryMapFlags::default());
+        }
+
+        let cull_uniform = CullUniform {
+            planes: Frustum::from_view_projection(view_projection).planes,
+            object_count: frame.object_count,
+            _padding: [0; 3],
+        };
+
+        frame.cull_uniform_buffer.load_value(0, &cull_uniform, vk::MemoryMapFlags::default());
+    }
+
+    /// Reloads the cull shader if it was built from any of `changed_files`. See `ComputeShader::reload`.
+    pub fn reload_shaders(&self, changed_files: &[String]) {
+        self.cull_shader.reload(changed_files);
+    }
+
+    /// Records the cull dispatch for `frame_index`. It must run outside of rendering, and its writes to the draw
+    /// command and count buffers must be made visible to `DRAW_INDIRECT` before `draw`.
+    pub fn record_cull(&self, command_buffer: vk::CommandBuffer, frame_index: u32) {
+        let frame = &self.frames[frame_index as usize];
+        let device = &self.vkcontext.device;
+
+        let count_barrier = vk::BufferMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(frame.draw_count_buffer.handle)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+
+        unsafe {
+            device.cmd_fill_buffer(command_buffer, frame.draw_count_buffer.handle, 0, vk::WHOLE_SIZE, 0);
+
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().buffer_memory_barriers(slice::from_ref(&count_barrier)),
+            );
+        }
+
+        if frame.object_count == 0 {
+            return;
+        }
+
+        self.cull_shader.bind(command_buffer);
+        self.cull_shader.bind_descriptor_sets(command_buffer, 0, slice::from_ref(&frame.descriptor_set));
+        self.cull_shader.dispatch(command_buffer, Vec3UI::new(frame.object_count.div_ceil(CULL_WORKGROUP_SIZE), 1, 1), &[]);
+    }
+
+    /// Binds the shared buffers and submits the draws the cull pass of `frame_index` emitted.
+    pub fn draw(&self, command_buffer: vk::CommandBuffer, frame_index: u32, object_binding: u32) {
+        let frame = &self.frames[frame_index as usize];
+        let device = &self.vkcontext.device;
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.handle], &[0]);
+            device.cmd_bind_vertex_buffers(command_buffer, object_binding, &[frame.object_buffer.handle], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, self.index_buffer.handle, 0, vk::IndexType::UINT32);
+
+            device.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                frame.draw_command_buffer.handle,
+                0,
+                frame.draw_count_buffer.handle,
+                0,
+                self.max_objects,
+                std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+            );
+        }
+    }
+}
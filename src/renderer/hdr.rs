@@ -0,0 +1,255 @@
+use std::{io::Read, path::Path};
+
+use crate::{math::vec2::Vec2UI, utility};
+
+/// A Radiance `.hdr` (RGBE) image decoded to linear RGBA floats, top row first.
+pub struct HdrImage {
+    pub size: Vec2UI,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl HdrImage {
+    pub fn new(size: Vec2UI, pixels: Vec<[f32; 4]>) -> Self {
+        assert_eq!(pixels.len(), (size.x * size.y) as usize, "HDR image pixel count does not match its size.");
+
+        Self { size, pixels }
+    }
+
+    /// Loads and decodes a Radiance `.hdr` file relative to the assets directory. Supports flat, old-style and
+    /// adaptive run-length encoded scanlines in the `-Y height +X width` and `+Y height +X width` orientations.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let mut data = Vec::new();
+        utility::fs::load(&path).read_to_end(&mut data).unwrap();
+
+        Self::decode(&data).unwrap_or_else(|message| panic!("Failed to load {}: {}", path.as_ref().display(), message))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = HdrReader { data, position: 0 };
+
+        let signature = reader.read_line()?;
+
+        if signature != "#?RADIANCE" && signature != "#?RGBE" {
+            return Err(format!("unexpected signature \"{}\"", signature));
+        }
+
+        loop {
+            let line = reader.read_line()?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("unsupported pixel format \"{}\"", format));
+                }
+            }
+        }
+
+        let resolution = reader.read_line()?;
+        let tokens = resolution.split_whitespace().collect::<Vec<_>>();
+
+        let (flip_y, height, width) = match tokens.as_slice() {
+            ["-Y", height, "+X", width] => (false, height, width),
+            ["+Y", height, "+X", width] => (true, height, width),
+            _ => return Err(format!("unsupported resolution \"{}\"", resolution)),
+        };
+
+        let size = Vec2UI {
+            x: width.parse::<u32>().map_err(|_| format!("invalid width \"{}\"", width))?,
+            y: height.parse::<u32>().map_err(|_| format!("invalid height \"{}\"", height))?,
+        };
+
+        let pixel_count = size.x.checked_mul(size.y).ok_or_else(|| format!("image is too large ({}x{})", size.x, size.y))?;
+
+        let mut pixels = Vec::with_capacity(pixel_count as usize);
+        let mut scanline = vec![[0u8; 4]; size.x as usize];
+
+        for _ in 0..size.y {
+            reader.read_scanline(&mut scanline)?;
+            pixels.extend(scanline.iter().map(rgbe_to_rgba));
+        }
+
+        if flip_y {
+            let row_length = size.x as usize;
+            pixels = pixels.chunks(row_length).rev().flatten().copied().collect();
+        }
+
+        Ok(Self { size, pixels })
+    }
+
+    /// The pixels as tightly packed `R16G16B16A16_SFLOAT` texels, which unlike 32-bit floats can always be filtered.
+    pub fn to_half_float_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().flat_map(|channel| f32_to_f16(*channel).to_ne_bytes()).collect()
+    }
+}
+
+/// Rounds to the nearest half float, flushing values too small for a half to zero and clamping large ones to infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        // Subnormal: shift the mantissa, with its implicit leading bit, into place.
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let rounded = (mantissa + (1 << (shift - 1))) >> shift;
+
+        return sign | rounded as u16;
+    }
+
+    // Rounding may carry into the exponent, which correctly yields the next power of two or infinity.
+    let rounded = ((exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+
+    sign | rounded as u16
+}
+
+fn rgbe_to_rgba(rgbe: &[u8; 4]) -> [f32; 4] {
+    if rgbe[3] == 0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+
+    let scale = 2.0f32.powi(rgbe[3] as i32 - 136);
+
+    [rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale, 1.0]
+}
+
+struct HdrReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HdrReader<'a> {
+    fn read_byte(&mut self) -> Result<u8, String> {
+        let byte = *self.data.get(self.position).ok_or("unexpected end of file")?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn read_pixel(&mut self) -> Result<[u8; 4], String> {
+        Ok([self.read_byte()?, self.read_byte()?, self.read_byte()?, self.read_byte()?])
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        let start = self.position;
+
+        while self.read_byte()? != b'\n' {}
+
+        Ok(String::from_utf8_lossy(&self.data[start..self.position - 1]).trim_end().to_string())
+    }
+
+    fn read_scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+        let width = scanline.len();
+
+        // Adaptive run-length encoding is only used for widths in [8, 32767] and starts with 2, 2 and the width.
+        if (8..0x8000).contains(&width) {
+            let header = self.read_pixel()?;
+
+            if header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0 {
+                if ((header[2] as usize) << 8 | header[3] as usize) != width {
+                    return Err("scanline width mismatch".to_string());
+                }
+
+                return self.read_adaptive_scanline(scanline);
+            }
+
+            self.position -= 4;
+        }
+
+        self.read_flat_scanline(scanline)
+    }
+
+    /// Each channel is stored separately as runs (count above 128) and literal spans.
+    fn read_adaptive_scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+        for channel in 0..4 {
+            let mut x = 0;
+
+            while x < scanline.len() {
+                let count = self.read_byte()? as usize;
+
+                if count > 128 {
+                    let count = count - 128;
+                    let value = self.read_byte()?;
+
+                    if x + count > scanline.len() {
+                        return Err("run overflows scanline".to_string());
+                    }
+
+                    scanline[x..x + count].iter_mut().for_each(|pixel| pixel[channel] = value);
+                    x += count;
+                } else {
+                    if count == 0 || x + count > scanline.len() {
+                        return Err("invalid literal span".to_string());
+                    }
+
+                    for pixel in scanline[x..x + count].iter_mut() {
+                        pixel[channel] = self.read_byte()?;
+                    }
+
+                    x += count;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Plain RGBE pixels, where a (1, 1, 1, n) pixel repeats the previous one with consecutive repeat counts
+    /// forming higher-order bytes.
+    fn read_flat_scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+        let mut x = 0;
+        let mut shift = 0;
+
+        while x < scanline.len() {
+            let pixel = self.read_pixel()?;
+
+            if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+                if x == 0 {
+                    return Err("repeat at the start of a scanline".to_string());
+                }
+
+                // A u32 count takes at most four consecutive repeats.
+                if shift > 24 {
+                    return Err("too many consecutive repeats".to_string());
+                }
+
+                let count = (pixel[3] as usize) << shift;
+
+                if x + count > scanline.len() {
+                    return Err("repeat overflows scanline".to_string());
+                }
+
+                let previous = scanline[x - 1];
+                scanline[x..x + count].fill(previous);
+
+                x += count;
+                shift += 8;
+            } else {
+                scanline[x] = pixel;
+
+                x += 1;
+                shift = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,476 @@
+use std::slice;
+
+use ash::vk;
+
+use crate::math::{vec2::Vec2UI, vec3::{Vec3F, Vec3UI}};
+
+use super::{
+    buffer::Buffer,
+    hdr::HdrImage,
+    image::{Image, ImageDescription, ImageUsage},
+    shader::{
+        create_descriptor_set_layouts_and_pool,
+        ComputeShader,
+        Shader,
+        ShaderDescriptorInfo,
+        ShaderDescriptorSetInfo,
+        ShaderDescriptorTypeInfo,
+        ShaderPushConstantInfo,
+        ShaderStageInfo,
+        ShaderType,
+    },
+    texture::{create_sampler, Texture},
+    upload::{UploadManager, UploadTicket},
+    vkcontext::VkContext,
+    MAX_FRAMES_IN_FLIGHT,
+};
+
+pub const IBL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+pub const IRRADIANCE_MAP_SIZE: u32 = 32;
+pub const PREFILTERED_MAP_SIZE: u32 = 128;
+pub const PREFILTERED_MIP_COUNT: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 512;
+
+const IBL_WORKGROUP_SIZE: u32 = 8;
+
+/// The global descriptor set PBR shaders read lighting from: a uniform buffer laid out like `LightingUniform`
+/// followed by the irradiance cubemap, the prefiltered specular cubemap and the BRDF lookup table. Include it in a
+/// shader's descriptor sets and bind it with `bind`.
+pub const LIGHTING_DESCRIPTOR_SET: ShaderDescriptorSetInfo<'static> = ShaderDescriptorSetInfo {
+    max_set_allocations: MAX_FRAMES_IN_FLIGHT,
+    descriptors: &[
+        ShaderDescriptorInfo {
+            name: "lighting",
+            descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { fields: &[] },
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "irradiance_map",
+            descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "prefiltered_map",
+            descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "brdf_lut",
+            descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+    ],
+};
+
+/// The camera and the single directional light PBR shaders shade with, on top of the environment.
+#[derive(Clone, Copy)]
+pub struct LightingParameters {
+    pub camera_position: Vec3F,
+    /// The direction the light travels in.
+    pub light_direction: Vec3F,
+    pub light_color: Vec3F,
+    pub light_intensity: f32,
+    pub environment_intensity: f32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct LightingUniform {
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    environment_intensity: f32,
+    prefiltered_mip_count: f32,
+    _padding: [f32; 2],
+}
+
+/// Creates an image the IBL compute shaders write to one mip at a time, returning it with a storage view of each
+/// mip. Materials sample it through its default view of every mip.
+fn create_ibl_image<'ctx>(vkcontext: &'ctx VkContext, size: u32, mip_levels: u32, is_cube: bool) -> (Image<'ctx>, Vec<vk::ImageView>) {
+    let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED;
+
+    let description = if is_cube {
+        ImageDescription::new_cube(size, IBL_FORMAT, usage)
+    } else {
+        ImageDescription::new_2d(Vec2UI { x: size, y: size }, IBL_FORMAT, usage)
+    }
+    .mip_levels(mip_levels);
+
+    let mut image = Image::from_description(vkcontext, &description, vk::MemoryPropertyFlags::DEVICE_LOCAL, Some(vk::ImageAspectFlags::COLOR));
+
+    let storage_view_type = if is_cube { vk::ImageViewType::TYPE_2D_ARRAY } else { vk::ImageViewType::TYPE_2D };
+
+    let storage_views = (0..mip_levels).map(|mip| {
+        let subresource_range = image.subresource_range().base_mip_level(mip).level_count(1);
+        image.create_view(storage_view_type, subresource_range)
+    })
+    .collect();
+
+    (image, storage_views)
+}
+
+/// Work groups covering `mip` of an image written by the IBL shaders, one layer per group in z.
+fn group_count(image: &Image, mip: u32) -> Vec3UI {
+    let extent = image.mip_extent(mip);
+
+    Vec3UI::new(
+        extent.width.div_ceil(IBL_WORKGROUP_SIZE),
+        extent.height.div_ceil(IBL_WORKGROUP_SIZE),
+        image.description.array_layers,
+    )
+}
+
+/// Image-based lighting from an equirectangular HDR environment. Compute shaders convert it into a cubemap, then
+/// convolve that into a diffuse irradiance cubemap and a specular cubemap prefiltered for increasing roughness along
+/// its mips, and integrate the BRDF lookup table of the split-sum approximation.
+pub struct ImageBasedLighting<'ctx> {
+    /// The equirectangular environment as uploaded.
+    pub source: Texture<'ctx>,
+
+    environment: Image<'ctx>,
+    irradiance: Image<'ctx>,
+    prefiltered: Image<'ctx>,
+    brdf_lut: Image<'ctx>,
+    sampler: vk::Sampler,
+    is_generated: bool,
+
+    equirect_to_cube_shader: ComputeShader<'ctx>,
+    irradiance_shader: ComputeShader<'ctx>,
+    prefilter_shader: ComputeShader<'ctx>,
+    brdf_lut_shader: ComputeShader<'ctx>,
+    equirect_to_cube_set: vk::DescriptorSet,
+    irradiance_set: vk::DescriptorSet,
+    prefilter_sets: Vec<vk::DescriptorSet>,
+    brdf_lut_set: vk::DescriptorSet,
+
+    uniform_buffers: Vec<Buffer<'ctx>>,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> ImageBasedLighting<'ctx> {
+    /// Queues the upload of `environment` and creates every map. Nothing is computed until `record_generate`.
+    pub fn new(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        environment: &HdrImage,
+        environment_size: u32,
+    ) -> (Self, UploadTicket) {
+        let (source, ticket) = Texture::new(
+            vkcontext,
+            upload_manager,
+            "builtin.ibl.environment",
+            environment.size,
+            IBL_FORMAT,
+            &environment.to_half_float_bytes(),
+        );
+
+        let sampler = create_sampler(vkcontext, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        let (environment, environment_storage_views) = create_ibl_image(vkcontext, environment_size, 1, true);
+        let (irradiance, irradiance_storage_views) = create_ibl_image(vkcontext, IRRADIANCE_MAP_SIZE, 1, true);
+        let (prefiltered, prefiltered_storage_views) = create_ibl_image(vkcontext, PREFILTERED_MAP_SIZE, PREFILTERED_MIP_COUNT, true);
+        let (brdf_lut, brdf_lut_storage_views) = create_ibl_image(vkcontext, BRDF_LUT_SIZE, 1, false);
+        let environment_view = environment.image_view.unwrap();
+
+        let convolution_sets = |max_set_allocations| [
+            ShaderDescriptorSetInfo {
+                max_set_allocations,
+                descriptors: &[
+                    ShaderDescriptorInfo {
+                        name: "source",
+                        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+                        stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    },
+                    ShaderDescriptorInfo {
+                        name: "destination",
+                        descriptor_type: ShaderDescriptorTypeInfo::StorageImage,
+                        stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    },
+                ],
+            },
+        ];
+
+        let equirect_to_cube_shader = ComputeShader::new(
+            vkcontext,
+            "builtin.ibl.equirect_to_cube",
+            &[],
+            &convolution_sets(1),
+            &ShaderStageInfo::new(vk::ShaderStageFlags::COMPUTE, "shaders/builtin.ibl.equirect_to_cube.comp.spv"),
+        );
+
+        let irradiance_shader = ComputeShader::new(
+            vkcontext,
+            "builtin.ibl.irradiance",
+            &[],
+            &convolution_sets(1),
+            &ShaderStageInfo::new(vk::ShaderStageFlags::COMPUTE, "shaders/builtin.ibl.irradiance.comp.spv"),
+        );
+
+        let prefilter_shader = ComputeShader::new(
+            vkcontext,
+            "builtin.ibl.prefilter",
+            &[
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Float32, stage_flags: vk::ShaderStageFlags::COMPUTE },
+            ],
+            &convolution_sets(PREFILTERED_MIP_COUNT),
+            &ShaderStageInfo::new(vk::ShaderStageFlags::COMPUTE, "shaders/builtin.ibl.prefilter.comp.spv"),
+        );
+
+        let brdf_lut_shader = ComputeShader::new(
+            vkcontext,
+            "builtin.ibl.brdf_lut",
+            &[],
+            &[
+                ShaderDescriptorSetInfo {
+                    max_set_allocations: 1,
+                    descriptors: &[
+                        ShaderDescriptorInfo {
+                            name: "destination",
+                            descriptor_type: ShaderDescriptorTypeInfo::StorageImage,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                        },
+                    ],
+                },
+            ],
+            &ShaderStageInfo::new(vk::ShaderStageFlags::COMPUTE, "shaders/builtin.ibl.brdf_lut.comp.spv"),
+        );
+
+        let equirect_to_cube_set = equirect_to_cube_shader.allocate_descriptor_sets(0, 1)[0];
+        let irradiance_set = irradiance_shader.allocate_descriptor_sets(0, 1)[0];
+        let prefilter_sets = prefilter_shader.allocate_descriptor_sets(0, PREFILTERED_MIP_COUNT);
+        let brdf_lut_set = brdf_lut_shader.allocate_descriptor_sets(0, 1)[0];
+
+        write_convolution_set(vkcontext, equirect_to_cube_set, source.image_view(), sampler, environment_storage_views[0]);
+        write_convolution_set(vkcontext, irradiance_set, environment_view, sampler, irradiance_storage_views[0]);
+
+        for (descriptor_set, storage_view) in prefilter_sets.iter().zip(prefiltered_storage_views.iter()) {
+            write_convolution_set(vkcontext, *descriptor_set, environment_view, sampler, *storage_view);
+        }
+
+        let lut_info = vk::DescriptorImageInfo::default()
+            .image_view(brdf_lut_storage_views[0])
+            .image_layout(vk::ImageLayout::GENERAL);
+
+        let lut_write = vk::WriteDescriptorSet::default()
+            .dst_set(brdf_lut_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(slice::from_ref(&lut_info));
+
+        unsafe { vkcontext.device.update_descriptor_sets(slice::from_ref(&lut_write), &[]); }
+
+        let (descriptor_set_layouts, descriptor_pool) = create_descriptor_set_layouts_and_pool(vkcontext, &[LIGHTING_DESCRIPTOR_SET]);
+        let descriptor_set_layout = descriptor_set_layouts[0];
+
+        let descriptor_sets = {
+            let set_layouts = vec![descriptor_set_layout; MAX_FRAMES_IN_FLIGHT as usize];
+
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&set_layouts);
+
+            unsafe { vkcontext.device.allocate_descriptor_sets(&allocate_info).unwrap() }
+        };
+
+        let uniform_buffers = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            Buffer::new(
+                vkcontext,
+                std::mem::size_of::<LightingUniform>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                true,
+            )
+        })
+        .collect::<Vec<_>>();
+
+        for (descriptor_set, uniform_buffer) in descriptor_sets.iter().zip(uniform_buffers.iter()) {
+            let buffer_info = vk::DescriptorBufferInfo::default()
+                .buffer(uniform_buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE);
+
+            let image_infos = [&irradiance, &prefiltered, &brdf_lut].map(|image| {
+                vk::DescriptorImageInfo::default()
+                    .image_view(image.image_view.unwrap())
+                    .sampler(sampler)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            });
+
+            let mut writes = vec![
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(slice::from_ref(&buffer_info)),
+            ];
+
+            writes.extend(image_infos.iter().enumerate().map(|(index, image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(index as u32 + 1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(slice::from_ref(image_info))
+            }));
+
+            unsafe { vkcontext.device.update_descriptor_sets(&writes, &[]); }
+        }
+
+        let lighting = Self {
+            source,
+            environment,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            sampler,
+            is_generated: false,
+            equirect_to_cube_shader,
+            irradiance_shader,
+            prefilter_shader,
+            brdf_lut_shader,
+            equirect_to_cube_set,
+            irradiance_set,
+            prefilter_sets,
+            brdf_lut_set,
+            uniform_buffers,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            vkcontext,
+        };
+
+        (lighting, ticket)
+    }
+}
+
+fn write_convolution_set(
+    vkcontext: &VkContext,
+    descriptor_set: vk::DescriptorSet,
+    source_view: vk::ImageView,
+    sampler: vk::Sampler,
+    destination_view: vk::ImageView,
+) {
+    let source_info = vk::DescriptorImageInfo::default()
+        .image_view(source_view)
+        .sampler(sampler)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    let destination_info = vk::DescriptorImageInfo::default()
+        .image_view(destination_view)
+        .image_layout(vk::ImageLayout::GENERAL);
+
+    let writes = [
+        vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(slice::from_ref(&source_info)),
+        vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(slice::from_ref(&destination_info)),
+    ];
+
+    unsafe { vkcontext.device.update_descriptor_sets(&writes, &[]); }
+}
+
+impl<'ctx> ImageBasedLighting<'ctx> {
+    pub fn is_generated(&self) -> bool {
+        self.is_generated
+    }
+
+    /// Records the compute passes that build every map, the first time it is called and again after `reload_shaders`
+    /// replaced a shader; other calls record nothing. Must run outside of rendering, once the environment upload has
+    /// been acquired, and leaves every map ready to be sampled by fragment shaders.
+    pub fn record_generate(&mut self, command_buffer: vk::CommandBuffer) {
+        if self.is_generated {
+            return;
+        }
+
+        for image in [&self.environment, &self.irradiance, &self.prefiltered, &self.brdf_lut] {
+            image.transition_to(command_buffer, ImageUsage::Storage);
+        }
+
+        // Each map becomes readable by later compute and fragment shaders as soon as it is written.
+        self.equirect_to_cube_shader.bind(command_buffer);
+        self.equirect_to_cube_shader.bind_descriptor_sets(command_buffer, 0, slice::from_ref(&self.equirect_to_cube_set));
+        self.equirect_to_cube_shader.dispatch(command_buffer, group_count(&self.environment, 0), &[]);
+        self.environment.transition_to(command_buffer, ImageUsage::ShaderRead);
+
+        self.irradiance_shader.bind(command_buffer);
+        self.irradiance_shader.bind_descriptor_sets(command_buffer, 0, slice::from_ref(&self.irradiance_set));
+        self.irradiance_shader.dispatch(command_buffer, group_count(&self.irradiance, 0), &[]);
+        self.irradiance.transition_to(command_buffer, ImageUsage::ShaderRead);
+
+        // Each mip is filtered for an evenly spaced roughness.
+        self.prefilter_shader.bind(command_buffer);
+
+        for (mip, descriptor_set) in self.prefilter_sets.iter().enumerate() {
+            let roughness = mip as f32 / (PREFILTERED_MIP_COUNT - 1) as f32;
+
+            self.prefilter_shader.bind_descriptor_sets(command_buffer, 0, slice::from_ref(descriptor_set));
+            self.prefilter_shader.push_constants(command_buffer, 0, &roughness);
+            self.prefilter_shader.dispatch(command_buffer, group_count(&self.prefiltered, mip as u32), &[]);
+        }
+
+        self.prefiltered.transition_to(command_buffer, ImageUsage::ShaderRead);
+
+        self.brdf_lut_shader.bind(command_buffer);
+        self.brdf_lut_shader.bind_descriptor_sets(command_buffer, 0, slice::from_ref(&self.brdf_lut_set));
+        self.brdf_lut_shader.dispatch(command_buffer, group_count(&self.brdf_lut, 0), &[]);
+        self.brdf_lut.transition_to(command_buffer, ImageUsage::ShaderRead);
+
+        self.is_generated = true;
+    }
+
+    /// Reloads the compute shaders built from any of `changed_files` and regenerates the maps if one was replaced. See
+    /// `ComputeShader::reload`.
+    pub fn reload_shaders(&mut self, changed_files: &[String]) {
+        let shaders = [&self.equirect_to_cube_shader, &self.irradiance_shader, &self.prefilter_shader, &self.brdf_lut_shader];
+
+        if shaders.map(|shader| shader.reload(changed_files)).contains(&true) {
+            self.is_generated = false;
+        }
+    }
+
+    /// Writes `parameters` to the uniform buffer of `frame_index`.
+    pub fn prepare(&mut self, frame_index: u32, parameters: &LightingParameters) {
+        let direction = parameters.light_direction;
+        let color = parameters.light_color;
+
+        let uniform = LightingUniform {
+            camera_position: [parameters.camera_position.x, parameters.camera_position.y, parameters.camera_position.z, 1.0],
+            light_direction: [direction.x, direction.y, direction.z, 0.0],
+            light_color: [
+                color.x * parameters.light_intensity,
+                color.y * parameters.light_intensity,
+                color.z * parameters.light_intensity,
+                1.0,
+            ],
+            environment_intensity: parameters.environment_intensity,
+            prefiltered_mip_count: PREFILTERED_MIP_COUNT as f32,
+            _padding: [0.0; 2],
+        };
+
+        self.uniform_buffers[frame_index as usize].load_value(0, &uniform, vk::MemoryMapFlags::default());
+    }
+
+    /// Binds the lighting descriptor set of `frame_index` at `set_index` of `shader`, whose layout there must be
+    /// `LIGHTING_DESCRIPTOR_SET`.
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, shader: &Shader, set_index: u32, frame_index: u32) {
+        shader.bind_descriptor_sets(command_buffer, set_index, slice::from_ref(&self.descriptor_sets[frame_index as usize]));
+    }
+}
+
+impl<'ctx> Drop for ImageBasedLighting<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            self.vkcontext.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.vkcontext.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.vkcontext.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
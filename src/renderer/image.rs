@@ -1,22 +1,296 @@
+use std::{cell::RefCell, fmt, slice};
+
 use ash::vk;
 
 use crate::math::vec2::Vec2UI;
 
-use super::{utility, vkcontext::VkContext};
+use super::{
+    buffer::Buffer,
+    command_buffer::CommandBuffer,
+    render_graph::format_aspect_mask,
+    utility,
+    vkcontext::VkContext,
+};
+
+/// The shape and usage of an image. `new_2d`, `new_3d` and `new_cube` describe a single-sampled, optimally tiled image
+/// with one mip level, which the builder methods adjust.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDescription {
+    pub image_type: vk::ImageType,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+    pub flags: vk::ImageCreateFlags,
+    pub tiling: vk::ImageTiling,
+    pub usage: vk::ImageUsageFlags,
+}
+
+impl ImageDescription {
+    pub fn new_2d(size: Vec2UI, format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: size.as_vk_extent_3d(1),
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            flags: vk::ImageCreateFlags::empty(),
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+        }
+    }
+
+    /// A volume texture `depth` slices deep. 3D images can't have array layers.
+    pub fn new_3d(size: Vec2UI, depth: u32, format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            image_type: vk::ImageType::TYPE_3D,
+            extent: size.as_vk_extent_3d(depth),
+            ..Self::new_2d(size, format, usage)
+        }
+    }
+
+    /// Six square layers that can be viewed as a cube, in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn new_cube(size: u32, format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            array_layers: 6,
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            ..Self::new_2d(Vec2UI { x: size, y: size }, format, usage)
+        }
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    /// Every mip level down to 1x1.
+    pub fn full_mip_chain(mut self) -> Self {
+        let largest = self.extent.width.max(self.extent.height).max(self.extent.depth);
+        self.mip_levels = u32::BITS - largest.leading_zeros();
+        self
+    }
+
+    /// For cubes, the total number of faces: six per cube.
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = array_layers;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn tiling(mut self, tiling: vk::ImageTiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    pub fn flags(mut self, flags: vk::ImageCreateFlags) -> Self {
+        self.flags |= flags;
+        self
+    }
+
+    pub fn is_cube(&self) -> bool {
+        self.flags.contains(vk::ImageCreateFlags::CUBE_COMPATIBLE) && self.array_layers.is_multiple_of(6)
+    }
+
+    /// The view type that sees every layer: cubes for cube-compatible images, arrays for layered ones.
+    pub fn default_view_type(&self) -> vk::ImageViewType {
+        match self.image_type {
+            vk::ImageType::TYPE_1D if self.array_layers > 1 => vk::ImageViewType::TYPE_1D_ARRAY,
+            vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+            vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+            _ if self.is_cube() && self.array_layers > 6 => vk::ImageViewType::CUBE_ARRAY,
+            _ if self.is_cube() => vk::ImageViewType::CUBE,
+            _ if self.array_layers > 1 => vk::ImageViewType::TYPE_2D_ARRAY,
+            _ => vk::ImageViewType::TYPE_2D,
+        }
+    }
+
+    fn create_info(&self) -> vk::ImageCreateInfo<'static> {
+        vk::ImageCreateInfo::default()
+            .flags(self.flags)
+            .image_type(self.image_type)
+            .format(self.format)
+            .extent(self.extent)
+            .mip_levels(self.mip_levels)
+            .array_layers(self.array_layers)
+            .samples(self.samples)
+            .tiling(self.tiling)
+            .usage(self.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+    }
+}
+
+/// Why an image operation can't be recorded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageOperationError {
+    /// The format doesn't support `feature` with the image's tiling.
+    UnsupportedFormatFeature { format: vk::Format, feature: vk::FormatFeatureFlags },
+    /// The image wasn't created with `usage`.
+    MissingUsage(vk::ImageUsageFlags),
+    /// Copies and resolves need identical formats.
+    IncompatibleFormats { src: vk::Format, dst: vk::Format },
+    /// Copies and resolves need the same extent and layer count on both sides.
+    ExtentMismatch { src: vk::Extent3D, dst: vk::Extent3D },
+    /// Only resolves take multisampled images, and they must resolve into single-sampled ones.
+    InvalidSampleCount(vk::SampleCountFlags),
+    /// Colour operations on a depth/stencil format, or the other way around.
+    WrongAspect(vk::ImageAspectFlags),
+    /// The operation only works on uncompressed formats whose texel size is known, or needs a single mip level.
+    UnsupportedFormat(vk::Format),
+    NoSuchMipLevel(u32),
+}
+
+impl fmt::Display for ImageOperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormatFeature { format, feature } => write!(f, "{:?} doesn't support {:?}", format, feature),
+            Self::MissingUsage(usage) => write!(f, "the image wasn't created with {:?} usage", usage),
+            Self::IncompatibleFormats { src, dst } => write!(f, "can't copy {:?} into {:?}", src, dst),
+            Self::ExtentMismatch { src, dst } => write!(f, "extent {:?} doesn't match {:?}", src, dst),
+            Self::InvalidSampleCount(samples) => write!(f, "invalid sample count {:?} for this operation", samples),
+            Self::WrongAspect(aspect) => write!(f, "the format has the {:?} aspect", aspect),
+            Self::UnsupportedFormat(format) => write!(f, "{:?} is not supported by this operation", format),
+            Self::NoSuchMipLevel(mip_level) => write!(f, "the image has no mip level {}", mip_level),
+        }
+    }
+}
+
+/// Bytes per texel of uncompressed formats. Depth/stencil formats give the size of their depth aspect, which is what
+/// buffer copies transfer.
+pub fn format_texel_size(format: vk::Format) -> Option<u32> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT | vk::Format::R8_SRGB
+            | vk::Format::S8_UINT => Some(1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SNORM | vk::Format::R8G8_UINT | vk::Format::R8G8_SINT
+            | vk::Format::R16_UNORM | vk::Format::R16_UINT | vk::Format::R16_SINT | vk::Format::R16_SFLOAT
+            | vk::Format::D16_UNORM | vk::Format::D16_UNORM_S8_UINT => Some(2),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8B8A8_UINT | vk::Format::R8G8B8A8_SINT
+            | vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::B10G11R11_UFLOAT_PACK32
+            | vk::Format::R16G16_UNORM | vk::Format::R16G16_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT
+            | vk::Format::R32_SFLOAT | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => Some(4),
+        vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_UINT
+            | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT | vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// What an image is about to be used for, from which `Image::transition_to` derives the layout, access and stages.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageUsage {
+    TransferSrc,
+    TransferDst,
+    /// Sampled in any vertex, fragment or compute shader.
+    ShaderRead,
+    /// Read and written as a storage image, in fragment or compute shaders.
+    Storage,
+    ColorAttachment,
+    DepthStencilAttachment,
+    /// Depth testing without writes, and sampling at the same time.
+    DepthStencilRead,
+    Present,
+}
+
+impl ImageUsage {
+    fn state(self) -> SubresourceState {
+        let (layout, stage, access) = match self {
+            Self::TransferSrc => (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            Self::TransferDst => (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+            Self::ShaderRead => (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            Self::Storage => (
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            ),
+            Self::ColorAttachment => (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ),
+            Self::DepthStencilAttachment => (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+            Self::DepthStencilRead => (
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ,
+            ),
+            Self::Present => (
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+        };
+
+        SubresourceState { layout, stage, access }
+    }
+
+    fn is_read_only(self) -> bool {
+        matches!(self, Self::TransferSrc | Self::ShaderRead | Self::DepthStencilRead | Self::Present)
+    }
+}
+
+/// The last known layout of one mip level of one layer, and the accesses a later barrier has to wait for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SubresourceState {
+    layout: vk::ImageLayout,
+    stage: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+}
+
+impl SubresourceState {
+    const UNDEFINED: Self = Self {
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+        access: vk::AccessFlags::empty(),
+    };
+}
 
 pub struct Image<'ctx> {
     pub handle: vk::Image,
     pub format: vk::Format,
     pub size: Vec2UI,
+    pub samples: vk::SampleCountFlags,
+    pub description: ImageDescription,
 
     pub memory: vk::DeviceMemory,
 
+    /// Sees every mip level and layer, with the view type from `ImageDescription::default_view_type`.
     pub image_view: Option<vk::ImageView>,
+    /// Created with `create_view` and destroyed with the image.
+    views: Vec<vk::ImageView>,
+    /// Indexed by `layer * mip_levels + mip_level`. Depth and stencil aspects are tracked together.
+    subresource_states: RefCell<Vec<SubresourceState>>,
 
     vkcontext: &'ctx VkContext,
 }
 
 impl<'ctx> Image<'ctx> {
+    /// A single 2D image with one mip level and one layer.
     pub fn new(
         vkcontext: &'ctx VkContext,
         image_type: vk::ImageType,
@@ -27,22 +301,42 @@ impl<'ctx> Image<'ctx> {
         memory_flags: vk::MemoryPropertyFlags,
         view_aspect_flags: Option<vk::ImageAspectFlags>,
     ) -> Self {
-        let handle = {
-            let create_info = vk::ImageCreateInfo::default()
-                .image_type(image_type)
-                .format(format)
-                .extent(size.as_vk_extent_3d(1))
-                .mip_levels(4)
-                .array_layers(1)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .tiling(tiling)
-                .usage(use_flags)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .initial_layout(vk::ImageLayout::UNDEFINED);
-
-            unsafe { vkcontext.device.create_image(&create_info, None).unwrap() }
+        let description = ImageDescription {
+            image_type,
+            ..ImageDescription::new_2d(size, format, use_flags).tiling(tiling)
         };
 
+        Self::from_description(vkcontext, &description, memory_flags, view_aspect_flags)
+    }
+
+    /// A device-local colour or depth attachment with a single mip level, multisampled if `samples` is more than one.
+    /// Multisampled images can't be sampled as regular textures; resolve them into a single-sampled image first.
+    pub fn new_attachment(
+        vkcontext: &'ctx VkContext,
+        size: Vec2UI,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+        use_flags: vk::ImageUsageFlags,
+    ) -> Self {
+        if !vkcontext.supported_sample_counts().contains(samples) {
+            panic!("Sample count {:?} is not supported for framebuffer attachments.", samples);
+        }
+
+        let description = ImageDescription::new_2d(size, format, use_flags).samples(samples);
+
+        Self::from_description(vkcontext, &description, vk::MemoryPropertyFlags::DEVICE_LOCAL, Some(format_aspect_mask(format)))
+    }
+
+    /// Creates an image of any shape. The default view is created with `view_aspect_flags`, if given; sampled views of
+    /// combined depth/stencil formats must pick one of the two aspects.
+    pub fn from_description(
+        vkcontext: &'ctx VkContext,
+        description: &ImageDescription,
+        memory_flags: vk::MemoryPropertyFlags,
+        view_aspect_flags: Option<vk::ImageAspectFlags>,
+    ) -> Self {
+        let handle = unsafe { vkcontext.device.create_image(&description.create_info(), None).unwrap() };
+
         let memory_properties = vkcontext.physical_device_memory_properties;
         let memory_requirements = unsafe { vkcontext.device.get_image_memory_requirements(handle) };
 
@@ -58,37 +352,78 @@ impl<'ctx> Image<'ctx> {
 
         unsafe { vkcontext.device.bind_image_memory(handle, memory, 0).unwrap() }
 
-        let image_view = view_aspect_flags.map(|aspect_flags| {
-            let create_info = vk::ImageViewCreateInfo::default()
-                .image(handle)
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(format)
-                .subresource_range(vk::ImageSubresourceRange::default()
-                    .aspect_mask(aspect_flags)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1)
-                );
-
-            unsafe { vkcontext.device.create_image_view(&create_info, None).unwrap() }
-        });
-
-        Self {
+        let mut image = Self {
             handle,
-            format,
-            size,
+            format: description.format,
+            size: Vec2UI { x: description.extent.width, y: description.extent.height },
+            samples: description.samples,
+            description: *description,
             memory,
-            image_view,
+            image_view: None,
+            views: Vec::new(),
+            subresource_states: RefCell::new(vec![SubresourceState::UNDEFINED; (description.mip_levels * description.array_layers) as usize]),
             vkcontext,
+        };
+
+        image.image_view = view_aspect_flags.map(|aspect_flags| {
+            image.create_view_handle(
+                description.default_view_type(),
+                image.subresource_range().aspect_mask(aspect_flags),
+            )
+        });
+
+        image
+    }
+}
+
+impl<'ctx> Image<'ctx> {
+    /// Every mip level and layer, with every aspect of the format.
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(format_aspect_mask(self.format))
+            .base_mip_level(0)
+            .level_count(self.description.mip_levels)
+            .base_array_layer(0)
+            .layer_count(self.description.array_layers)
+    }
+
+    /// The size of `mip_level`, with depth for 3D images.
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent3D {
+        let extent = self.description.extent;
+
+        vk::Extent3D {
+            width: (extent.width >> mip_level).max(1),
+            height: (extent.height >> mip_level).max(1),
+            depth: (extent.depth >> mip_level).max(1),
         }
     }
+
+    /// An additional view over `subresource_range`, such as a single mip level for compute writes, one face of a cube
+    /// or one layer of an array to render into. It lives as long as the image.
+    pub fn create_view(&mut self, view_type: vk::ImageViewType, subresource_range: vk::ImageSubresourceRange) -> vk::ImageView {
+        let view = self.create_view_handle(view_type, subresource_range);
+
+        self.views.push(view);
+        view
+    }
+
+    fn create_view_handle(&self, view_type: vk::ImageViewType, subresource_range: vk::ImageSubresourceRange) -> vk::ImageView {
+        let create_info = vk::ImageViewCreateInfo::default()
+            .image(self.handle)
+            .view_type(view_type)
+            .format(self.format)
+            .subresource_range(subresource_range);
+
+        unsafe { self.vkcontext.device.create_image_view(&create_info, None).unwrap() }
+    }
 }
 
 impl<'ctx> Image<'ctx> {
+    /// Transitions `subresource_range` with explicit layouts and masks. Prefer `transition_to`, which derives them.
     pub fn transition_layout(
         &self,
         command_buffer: vk::CommandBuffer,
+        subresource_range: vk::ImageSubresourceRange,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
         src_access_mask: vk::AccessFlags,
@@ -104,13 +439,7 @@ impl<'ctx> Image<'ctx> {
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(self.handle)
-            .subresource_range(vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_mip_level(0)
-                .level_count(1)
-                .base_array_layer(0)
-                .layer_count(1)
-            );
+            .subresource_range(subresource_range);
 
         unsafe {
             self.vkcontext.device.cmd_pipeline_barrier(
@@ -124,45 +453,137 @@ impl<'ctx> Image<'ctx> {
             );
         }
 
+        self.mark_state(subresource_range, SubresourceState { layout: new_layout, stage: dst_stage, access: dst_access_mask });
     }
 
-    pub fn transition_undefined_to_transfer_dst_optimal(&self, command_buffer: vk::CommandBuffer) {
-        self.transition_layout(
-            command_buffer, 
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::AccessFlags::default(),
-            vk::AccessFlags::TRANSFER_WRITE,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::TRANSFER,
-        );
+    /// Transitions the whole image from wherever it was last left to `usage`.
+    pub fn transition_to(&self, command_buffer: vk::CommandBuffer, usage: ImageUsage) {
+        self.transition_range_to(command_buffer, self.subresource_range(), usage);
     }
 
-    pub fn transition_transfer_dst_optimal_to_shader_read_only_optimal(&self, command_buffer: vk::CommandBuffer) {
-        self.transition_layout(
-            command_buffer, 
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            vk::AccessFlags::TRANSFER_WRITE,
-            vk::AccessFlags::SHADER_READ,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::PipelineStageFlags::FRAGMENT_SHADER,
-        );
+    /// Transitions `subresource_range` to `usage`, waiting for the last recorded use of each subresource. Subresources
+    /// that are already read the same way are left alone.
+    pub fn transition_range_to(&self, command_buffer: vk::CommandBuffer, subresource_range: vk::ImageSubresourceRange, usage: ImageUsage) {
+        let target = usage.state();
+        let mut states = self.subresource_states.borrow_mut();
+
+        let mut transitions = Vec::new();
+
+        for (mip_level, layer) in self.subresources(&subresource_range) {
+            let state = &mut states[self.state_index(mip_level, layer)];
+
+            if *state == target && usage.is_read_only() {
+                continue;
+            }
+
+            transitions.push((mip_level, layer, *state));
+            *state = target;
+        }
+
+        if transitions.is_empty() {
+            return;
+        }
+
+        let barrier = |range: vk::ImageSubresourceRange, old: SubresourceState| {
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(old.access)
+                .dst_access_mask(target.access)
+                .old_layout(old.layout)
+                .new_layout(target.layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.handle)
+                .subresource_range(range)
+        };
+
+        let src_stage = transitions.iter().fold(vk::PipelineStageFlags::empty(), |stage, (_, _, old)| stage | old.stage);
+
+        // One barrier covers the range when every subresource comes from the same state, which is the usual case.
+        let barriers = if transitions.len() == self.subresources(&subresource_range).count()
+            && transitions.iter().all(|(_, _, old)| *old == transitions[0].2)
+        {
+            vec![barrier(subresource_range, transitions[0].2)]
+        } else {
+            transitions.iter().map(|&(mip_level, layer, old)| {
+                barrier(
+                    subresource_range.base_mip_level(mip_level).level_count(1).base_array_layer(layer).layer_count(1),
+                    old,
+                )
+            })
+            .collect()
+        };
+
+        unsafe {
+            self.vkcontext.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                target.stage,
+                vk::DependencyFlags::default(),
+                &[],
+                &[],
+                &barriers,
+            );
+        }
     }
 
-    pub fn copy_from_buffer(&self, command_buffer: vk::CommandBuffer, buffer: vk::Buffer) {
-        let copy_info = vk::BufferImageCopy::default()
-            .buffer_offset(0)
-            .buffer_row_length(0)
-            .buffer_image_height(0)
-            .image_subresource(vk::ImageSubresourceLayers::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .mip_level(0)
-                .base_array_layer(0)
-                .layer_count(1)
-            )
-            .image_offset(vk::Offset3D::default())
-            .image_extent(self.size.as_vk_extent_3d(1));
+    /// Records that `subresource_range` was moved to `usage` by a barrier recorded without this image, such as a queue
+    /// ownership transfer or the render graph.
+    pub fn mark_usage(&self, subresource_range: vk::ImageSubresourceRange, usage: ImageUsage) {
+        self.mark_state(subresource_range, usage.state());
+    }
+
+    /// Records that the whole image was left in `layout` by a render pass, which may have written to it in any
+    /// graphics stage.
+    pub(crate) fn mark_render_pass_layout(&self, layout: vk::ImageLayout) {
+        self.mark_state(self.subresource_range(), SubresourceState {
+            layout,
+            stage: vk::PipelineStageFlags::ALL_GRAPHICS,
+            access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        });
+    }
+
+    /// The layout every subresource was last left in, or `None` if they differ.
+    pub fn tracked_layout(&self) -> Option<vk::ImageLayout> {
+        let states = self.subresource_states.borrow();
+        let layout = states[0].layout;
+
+        states.iter().all(|state| state.layout == layout).then_some(layout)
+    }
+
+    fn mark_state(&self, subresource_range: vk::ImageSubresourceRange, state: SubresourceState) {
+        let mut states = self.subresource_states.borrow_mut();
+
+        for (mip_level, layer) in self.subresources(&subresource_range) {
+            states[self.state_index(mip_level, layer)] = state;
+        }
+    }
+
+    fn state_index(&self, mip_level: u32, layer: u32) -> usize {
+        (layer * self.description.mip_levels + mip_level) as usize
+    }
+
+    /// Every (mip level, layer) pair in `subresource_range`, resolving `REMAINING_*` counts.
+    fn subresources(&self, subresource_range: &vk::ImageSubresourceRange) -> impl Iterator<Item = (u32, u32)> {
+        let level_count = match subresource_range.level_count {
+            vk::REMAINING_MIP_LEVELS => self.description.mip_levels - subresource_range.base_mip_level,
+            level_count => level_count,
+        };
+
+        let layer_count = match subresource_range.layer_count {
+            vk::REMAINING_ARRAY_LAYERS => self.description.array_layers - subresource_range.base_array_layer,
+            layer_count => layer_count,
+        };
+
+        let mip_levels = subresource_range.base_mip_level..subresource_range.base_mip_level + level_count;
+        let layers = subresource_range.base_array_layer..subresource_range.base_array_layer + layer_count;
+
+        layers.flat_map(move |layer| mip_levels.clone().map(move |mip_level| (mip_level, layer)))
+    }
+
+    /// Copies every layer of `mip_level` from tightly packed texels, layer after layer. Depth/stencil images copy
+    /// their depth aspect.
+    pub fn copy_from_buffer(&self, command_buffer: vk::CommandBuffer, buffer: vk::Buffer, buffer_offset: vk::DeviceSize, mip_level: u32) {
+        let copy_info = self.buffer_copy(buffer_offset, mip_level);
 
             unsafe { 
                 self.vkcontext.device.cmd_copy_buffer_to_image(
@@ -176,6 +597,364 @@ impl<'ctx> Image<'ctx> {
     }
 }
 
+impl<'ctx> Image<'ctx> {
+    /// Copies every layer of `mip_level` into `buffer` as tightly packed texels, layer after layer. The image is left in
+    /// `TRANSFER_SRC_OPTIMAL`.
+    pub fn copy_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        buffer_offset: vk::DeviceSize,
+        mip_level: u32,
+    ) -> Result<(), ImageOperationError> {
+        self.check_transfer_source(mip_level)?;
+
+        self.transition_to(command_buffer, ImageUsage::TransferSrc);
+
+        let copy_info = self.buffer_copy(buffer_offset, mip_level);
+
+        unsafe {
+            self.vkcontext.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                slice::from_ref(&copy_info),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Reads every layer of `mip_level` back to the CPU, blocking until `queue` has finished. Meant for screenshots,
+    /// tests and debugging rather than per-frame use.
+    pub fn read_back(&self, command_pool: vk::CommandPool, queue: vk::Queue, mip_level: u32) -> Result<Vec<u8>, ImageOperationError> {
+        let texel_size = format_texel_size(self.format).ok_or(ImageOperationError::UnsupportedFormat(self.format))?;
+        self.check_transfer_source(mip_level)?;
+
+        let extent = self.mip_extent(mip_level);
+        let size = extent.width as u64 * extent.height as u64 * extent.depth as u64
+            * self.description.array_layers as u64
+            * texel_size as u64;
+
+        let mut staging = Buffer::new(
+            self.vkcontext,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            true,
+        );
+
+        let cb = CommandBuffer::new(self.vkcontext, command_pool, true);
+
+        cb.begin(true, false, false);
+
+        self.copy_to_buffer(cb.handle, staging.handle, 0, mip_level)?;
+
+        // Makes the copy visible to the host reads below.
+        let host_barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ);
+
+        unsafe {
+            self.vkcontext.device.cmd_pipeline_barrier2(
+                cb.handle,
+                &vk::DependencyInfo::default().memory_barriers(slice::from_ref(&host_barrier)),
+            );
+        }
+
+        cb.end_and_submit_single_use(queue);
+
+        let mut data = vec![0u8; size as usize];
+        let memory = staging.lock_memory(0, size, vk::MemoryMapFlags::default()) as *const u8;
+
+        unsafe { memory.copy_to_nonoverlapping(data.as_mut_ptr(), data.len()); }
+
+        staging.unlock_memory();
+
+        Ok(data)
+    }
+
+    /// Copies `mip_level` of every layer into the same mip level of `dst`, which must have the same format and shape.
+    /// The images are left in `TRANSFER_SRC_OPTIMAL` and `TRANSFER_DST_OPTIMAL`.
+    pub fn copy_to_image(&self, command_buffer: vk::CommandBuffer, dst: &Image, mip_level: u32) -> Result<(), ImageOperationError> {
+        self.check_transfer_source(mip_level)?;
+        dst.check_transfer_destination(mip_level)?;
+
+        if self.format != dst.format {
+            return Err(ImageOperationError::IncompatibleFormats { src: self.format, dst: dst.format });
+        }
+
+        if self.samples != dst.samples {
+            return Err(ImageOperationError::InvalidSampleCount(dst.samples));
+        }
+
+        check_same_shape(self, dst, mip_level, mip_level)?;
+
+        self.transition_to(command_buffer, ImageUsage::TransferSrc);
+        dst.transition_to(command_buffer, ImageUsage::TransferDst);
+
+        let layers = self.copy_subresource_layers(mip_level);
+
+        let region = vk::ImageCopy::default()
+            .src_subresource(layers)
+            .dst_subresource(layers)
+            .extent(self.mip_extent(mip_level));
+
+        unsafe {
+            self.vkcontext.device.cmd_copy_image(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(&region),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Scales `src_mip_level` of every layer into `dst_mip_level` of `dst`, converting between formats. `filter` must
+    /// be `NEAREST` for depth/stencil formats, and `LINEAR` needs filterable formats. The images are left in
+    /// `TRANSFER_SRC_OPTIMAL` and `TRANSFER_DST_OPTIMAL`.
+    pub fn blit_to(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        dst: &Image,
+        src_mip_level: u32,
+        dst_mip_level: u32,
+        filter: vk::Filter,
+    ) -> Result<(), ImageOperationError> {
+        self.check_transfer_source(src_mip_level)?;
+        dst.check_transfer_destination(dst_mip_level)?;
+        self.check_format_feature(vk::FormatFeatureFlags::BLIT_SRC)?;
+        dst.check_format_feature(vk::FormatFeatureFlags::BLIT_DST)?;
+
+        if filter == vk::Filter::LINEAR {
+            self.check_format_feature(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)?;
+        }
+
+        let (src_aspect, dst_aspect) = (format_aspect_mask(self.format), format_aspect_mask(dst.format));
+
+        if src_aspect != dst_aspect {
+            return Err(ImageOperationError::WrongAspect(dst_aspect));
+        }
+
+        if src_aspect != vk::ImageAspectFlags::COLOR && (self.format != dst.format || filter != vk::Filter::NEAREST) {
+            return Err(ImageOperationError::IncompatibleFormats { src: self.format, dst: dst.format });
+        }
+
+        for samples in [self.samples, dst.samples] {
+            if samples != vk::SampleCountFlags::TYPE_1 {
+                return Err(ImageOperationError::InvalidSampleCount(samples));
+            }
+        }
+
+        if self.description.array_layers != dst.description.array_layers {
+            return Err(ImageOperationError::ExtentMismatch { src: self.mip_extent(src_mip_level), dst: dst.mip_extent(dst_mip_level) });
+        }
+
+        self.transition_to(command_buffer, ImageUsage::TransferSrc);
+        dst.transition_to(command_buffer, ImageUsage::TransferDst);
+
+        let corner = |extent: vk::Extent3D| vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: extent.depth as i32 };
+
+        let region = vk::ImageBlit::default()
+            .src_subresource(self.subresource_layers(src_mip_level, src_aspect))
+            .src_offsets([vk::Offset3D::default(), corner(self.mip_extent(src_mip_level))])
+            .dst_subresource(dst.subresource_layers(dst_mip_level, dst_aspect))
+            .dst_offsets([vk::Offset3D::default(), corner(dst.mip_extent(dst_mip_level))]);
+
+        unsafe {
+            self.vkcontext.device.cmd_blit_image(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(&region),
+                filter,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Averages the samples of this multisampled colour image into the single-sampled `dst`, which must have the same
+    /// format and size. The images are left in `TRANSFER_SRC_OPTIMAL` and `TRANSFER_DST_OPTIMAL`.
+    pub fn resolve_to(&self, command_buffer: vk::CommandBuffer, dst: &Image) -> Result<(), ImageOperationError> {
+        self.check_transfer_source(0)?;
+        dst.check_transfer_destination(0)?;
+        dst.check_format_feature(vk::FormatFeatureFlags::COLOR_ATTACHMENT)?;
+
+        if self.samples == vk::SampleCountFlags::TYPE_1 {
+            return Err(ImageOperationError::InvalidSampleCount(self.samples));
+        }
+
+        if dst.samples != vk::SampleCountFlags::TYPE_1 {
+            return Err(ImageOperationError::InvalidSampleCount(dst.samples));
+        }
+
+        if format_aspect_mask(self.format) != vk::ImageAspectFlags::COLOR {
+            return Err(ImageOperationError::WrongAspect(format_aspect_mask(self.format)));
+        }
+
+        if self.format != dst.format {
+            return Err(ImageOperationError::IncompatibleFormats { src: self.format, dst: dst.format });
+        }
+
+        check_same_shape(self, dst, 0, 0)?;
+
+        self.transition_to(command_buffer, ImageUsage::TransferSrc);
+        dst.transition_to(command_buffer, ImageUsage::TransferDst);
+
+        let layers = self.copy_subresource_layers(0);
+
+        let region = vk::ImageResolve::default()
+            .src_subresource(layers)
+            .dst_subresource(layers)
+            .extent(self.mip_extent(0));
+
+        unsafe {
+            self.vkcontext.device.cmd_resolve_image(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(&region),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Clears every mip level and layer of a colour image, leaving it in `TRANSFER_DST_OPTIMAL`.
+    pub fn clear_color(&self, command_buffer: vk::CommandBuffer, color: vk::ClearColorValue) -> Result<(), ImageOperationError> {
+        self.check_transfer_destination(0)?;
+
+        let aspect_mask = format_aspect_mask(self.format);
+
+        if aspect_mask != vk::ImageAspectFlags::COLOR {
+            return Err(ImageOperationError::WrongAspect(aspect_mask));
+        }
+
+        self.transition_to(command_buffer, ImageUsage::TransferDst);
+
+        unsafe {
+            self.vkcontext.device.cmd_clear_color_image(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &color,
+                slice::from_ref(&self.subresource_range()),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Clears every mip level and layer of a depth/stencil image, leaving it in `TRANSFER_DST_OPTIMAL`.
+    pub fn clear_depth_stencil(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        value: vk::ClearDepthStencilValue,
+    ) -> Result<(), ImageOperationError> {
+        self.check_transfer_destination(0)?;
+
+        let aspect_mask = format_aspect_mask(self.format);
+
+        if aspect_mask == vk::ImageAspectFlags::COLOR {
+            return Err(ImageOperationError::WrongAspect(aspect_mask));
+        }
+
+        self.transition_to(command_buffer, ImageUsage::TransferDst);
+
+        unsafe {
+            self.vkcontext.device.cmd_clear_depth_stencil_image(
+                command_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &value,
+                slice::from_ref(&self.subresource_range()),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl<'ctx> Image<'ctx> {
+    fn check_format_feature(&self, feature: vk::FormatFeatureFlags) -> Result<(), ImageOperationError> {
+        if self.vkcontext.format_features(self.format, self.description.tiling).contains(feature) {
+            Ok(())
+        } else {
+            Err(ImageOperationError::UnsupportedFormatFeature { format: self.format, feature })
+        }
+    }
+
+    fn check_transfer_source(&self, mip_level: u32) -> Result<(), ImageOperationError> {
+        self.check_transfer(mip_level, vk::ImageUsageFlags::TRANSFER_SRC, vk::FormatFeatureFlags::TRANSFER_SRC)
+    }
+
+    fn check_transfer_destination(&self, mip_level: u32) -> Result<(), ImageOperationError> {
+        self.check_transfer(mip_level, vk::ImageUsageFlags::TRANSFER_DST, vk::FormatFeatureFlags::TRANSFER_DST)
+    }
+
+    fn check_transfer(&self, mip_level: u32, usage: vk::ImageUsageFlags, feature: vk::FormatFeatureFlags) -> Result<(), ImageOperationError> {
+        if mip_level >= self.description.mip_levels {
+            return Err(ImageOperationError::NoSuchMipLevel(mip_level));
+        }
+
+        if !self.description.usage.contains(usage) {
+            return Err(ImageOperationError::MissingUsage(usage));
+        }
+
+        self.check_format_feature(feature)
+    }
+
+    /// Buffer copies and image copies go through a single aspect: depth for depth/stencil formats.
+    fn copy_subresource_layers(&self, mip_level: u32) -> vk::ImageSubresourceLayers {
+        let aspect_mask = match format_aspect_mask(self.format) {
+            aspect_mask if aspect_mask.contains(vk::ImageAspectFlags::DEPTH) => vk::ImageAspectFlags::DEPTH,
+            aspect_mask => aspect_mask,
+        };
+
+        self.subresource_layers(mip_level, aspect_mask)
+    }
+
+    fn subresource_layers(&self, mip_level: u32, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceLayers {
+        vk::ImageSubresourceLayers::default()
+            .aspect_mask(aspect_mask)
+            .mip_level(mip_level)
+            .base_array_layer(0)
+            .layer_count(self.description.array_layers)
+    }
+
+    fn buffer_copy(&self, buffer_offset: vk::DeviceSize, mip_level: u32) -> vk::BufferImageCopy {
+        vk::BufferImageCopy::default()
+            .buffer_offset(buffer_offset)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(self.copy_subresource_layers(mip_level))
+            .image_offset(vk::Offset3D::default())
+            .image_extent(self.mip_extent(mip_level))
+    }
+}
+
+fn check_same_shape(src: &Image, dst: &Image, src_mip_level: u32, dst_mip_level: u32) -> Result<(), ImageOperationError> {
+    let (src_extent, dst_extent) = (src.mip_extent(src_mip_level), dst.mip_extent(dst_mip_level));
+
+    if src_extent != dst_extent || src.description.array_layers != dst.description.array_layers {
+        return Err(ImageOperationError::ExtentMismatch { src: src_extent, dst: dst_extent });
+    }
+
+    Ok(())
+}
+
 impl<'ctx> Drop for Image<'ctx> {
     fn drop(&mut self) {
         unsafe {
@@ -184,6 +963,10 @@ impl<'ctx> Drop for Image<'ctx> {
                 None => (),
             }
 
+            for &view in &self.views {
+                self.vkcontext.device.destroy_image_view(view, None);
+            }
+
             self.vkcontext.device.free_memory(self.memory, None);
 
             self.vkcontext.device.destroy_image(self.handle, None);
@@ -0,0 +1,317 @@
+use std::{collections::HashMap, sync::atomic::{AtomicU32, Ordering}};
+
+use ash::vk;
+use serde::Deserialize;
+
+use super::{buffer::Buffer, shader::{Shader, ShaderDescriptorSetLayoutInfo, ShaderType}, texture::Texture, vkcontext::VkContext, MAX_FRAMES_IN_FLIGHT};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MaterialValue {
+    Float32(f32),
+    Float32_2([f32; 2]),
+    Float32_3([f32; 3]),
+    Float32_4([f32; 4]),
+    Int32(i32),
+    UInt32(u32),
+    Matrix4([f32; 16]),
+}
+
+impl MaterialValue {
+    pub fn shader_type(&self) -> ShaderType {
+        match self {
+            Self::Float32(_) => ShaderType::Float32,
+            Self::Float32_2(_) => ShaderType::Float32_2,
+            Self::Float32_3(_) => ShaderType::Float32_3,
+            Self::Float32_4(_) => ShaderType::Float32_4,
+            Self::Int32(_) => ShaderType::Int32,
+            Self::UInt32(_) => ShaderType::UInt32,
+            Self::Matrix4(_) => ShaderType::Matrix4,
+        }
+    }
+
+    /// Reads a value of `shader_type` from JSON: a number for scalars, an array of numbers otherwise.
+    pub fn from_json(shader_type: ShaderType, value: &serde_json::Value) -> Option<Self> {
+        let floats = || -> Option<Vec<f32>> {
+            value.as_array()?.iter().map(|v| v.as_f64().map(|f| f as f32)).collect()
+        };
+
+        Some(match shader_type {
+            ShaderType::Float32 => Self::Float32(value.as_f64()? as f32),
+            ShaderType::Float32_2 => Self::Float32_2(floats()?.try_into().ok()?),
+            ShaderType::Float32_3 => Self::Float32_3(floats()?.try_into().ok()?),
+            ShaderType::Float32_4 => Self::Float32_4(floats()?.try_into().ok()?),
+            ShaderType::Int32 => Self::Int32(value.as_i64()?.try_into().ok()?),
+            ShaderType::UInt32 => Self::UInt32(value.as_u64()?.try_into().ok()?),
+            ShaderType::Matrix4 => Self::Matrix4(floats()?.try_into().ok()?),
+            _ => return None,
+        })
+    }
+
+    fn write_bytes(&self, dest: &mut [u8]) {
+        let mut write_f32s = |values: &[f32]| {
+            for (i, value) in values.iter().enumerate() {
+                dest[i * 4..i * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+            }
+        };
+
+        match self {
+            Self::Float32(v) => write_f32s(std::slice::from_ref(v)),
+            Self::Float32_2(v) => write_f32s(v),
+            Self::Float32_3(v) => write_f32s(v),
+            Self::Float32_4(v) => write_f32s(v),
+            Self::Matrix4(v) => write_f32s(v),
+            Self::Int32(v) => dest[..4].copy_from_slice(&v.to_ne_bytes()),
+            Self::UInt32(v) => dest[..4].copy_from_slice(&v.to_ne_bytes()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialConfig {
+    name: String,
+    shader: String,
+    descriptor_set: u32,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    parameters: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    textures: HashMap<String, String>,
+}
+
+static NEXT_MATERIAL_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Parameter values and textures for one descriptor set of a `Shader`. Every frame in flight gets its own descriptor
+/// set and uniform buffer, so changing a material never touches data an in-flight frame is reading.
+pub struct Material<'ctx, 's> {
+    pub id: u32,
+    pub name: String,
+    pub shader: &'s Shader<'ctx>,
+    pub set_index: u32,
+    pub is_transparent: bool,
+
+    uniform_data: Vec<u8>,
+    uniform_offsets: Vec<Option<vk::DeviceSize>>,
+    uniform_buffers: Vec<Buffer<'ctx>>,
+    textures: Vec<Option<&'s Texture<'ctx>>>,
+
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    dirty_frames: Vec<bool>,
+
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx, 's> Material<'ctx, 's> {
+    pub fn new(vkcontext: &'ctx VkContext, name: &str, shader: &'s Shader<'ctx>, set_index: u32) -> Self {
+        let layout = shader.descriptor_set_layout_infos.get(set_index as usize)
+            .unwrap_or_else(|| panic!("Shader \"{}\" has no descriptor set {}.", shader.name, set_index));
+
+        if let Some(binding) = layout.bindings.iter().find(|binding| {
+            !matches!(binding.descriptor_type, vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        }) {
+            panic!("Material descriptor sets may only hold uniform buffers and samplers, \"{}\" is a {:?}.", binding.name, binding.descriptor_type);
+        }
+
+        // Pack the set's uniform buffers into one buffer per frame, each at a properly aligned offset.
+        let mut uniform_size = 0u64;
+
+        let uniform_offsets = layout.bindings.iter().map(|binding| {
+            if binding.descriptor_type != vk::DescriptorType::UNIFORM_BUFFER {
+                return None;
+            }
+
+            let offset = uniform_size.next_multiple_of(shader.minimum_uniform_alignment);
+            uniform_size = offset + binding.uniform_size as u64;
+
+            Some(offset)
+        })
+        .collect::<Vec<_>>();
+
+        let uniform_buffers = if uniform_size > 0 {
+            (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+                Buffer::new(
+                    vkcontext,
+                    uniform_size,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    true,
+                )
+            })
+            .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            name: name.to_string(),
+            shader,
+            set_index,
+            is_transparent: false,
+            uniform_data: vec![0; uniform_size as usize],
+            uniform_offsets,
+            uniform_buffers,
+            textures: vec![None; layout.bindings.len()],
+            descriptor_sets: shader.allocate_descriptor_sets(set_index, MAX_FRAMES_IN_FLIGHT),
+            dirty_frames: vec![true; MAX_FRAMES_IN_FLIGHT as usize],
+            vkcontext,
+        }
+    }
+
+    /// Loads a `.material.json` asset for `shader`. Textures are referred to by name and looked up with
+    /// `find_texture`.
+    pub fn load<P, F>(vkcontext: &'ctx VkContext, path: P, shader: &'s Shader<'ctx>, find_texture: F) -> Self
+    where
+        P: AsRef<std::path::Path>,
+        F: Fn(&str) -> Option<&'s Texture<'ctx>>,
+    {
+        use crate::utility::fs;
+
+        log::debug!("Reading material file: {}", path.as_ref().to_str().unwrap());
+
+        let config: MaterialConfig = serde_json::from_reader(fs::load(&path))
+            .unwrap_or_else(|error| panic!("Failed to parse material {}: {}", path.as_ref().display(), error));
+
+        if config.shader != shader.name {
+            panic!("Material \"{}\" expects shader \"{}\", got \"{}\".", config.name, config.shader, shader.name);
+        }
+
+        let mut material = Self::new(vkcontext, &config.name, shader, config.descriptor_set);
+        material.is_transparent = config.transparent;
+
+        for (name, value) in config.parameters.iter() {
+            let field_type = material.layout().find_uniform_field(name)
+                .map(|(_, field)| field.field_type)
+                .unwrap_or_else(|| panic!("Material \"{}\" sets unknown parameter \"{}\".", config.name, name));
+
+            let value = MaterialValue::from_json(field_type, value)
+                .unwrap_or_else(|| panic!("Material \"{}\" parameter \"{}\" is not a valid {:?}.", config.name, name, field_type));
+
+            material.set_parameter(name, value);
+        }
+
+        for (name, texture_name) in config.textures.iter() {
+            let texture = find_texture(texture_name)
+                .unwrap_or_else(|| panic!("Material \"{}\" uses unknown texture \"{}\".", config.name, texture_name));
+
+            material.set_texture(name, texture);
+        }
+
+        material
+    }
+}
+
+impl<'ctx, 's> Material<'ctx, 's> {
+    pub fn layout(&self) -> &'s ShaderDescriptorSetLayoutInfo {
+        &self.shader.descriptor_set_layout_infos[self.set_index as usize]
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: MaterialValue) {
+        let (binding, field) = self.layout().find_uniform_field(name)
+            .unwrap_or_else(|| panic!("Shader \"{}\" has no parameter \"{}\".", self.shader.name, name));
+
+        if field.field_type != value.shader_type() {
+            panic!(
+                "Parameter \"{}\" of shader \"{}\" is a {:?}, not a {:?}.",
+                name,
+                self.shader.name,
+                field.field_type,
+                value.shader_type()
+            );
+        }
+
+        let offset = self.uniform_offsets[binding.binding as usize].unwrap() as usize + field.offset as usize;
+        value.write_bytes(&mut self.uniform_data[offset..offset + field.field_type.size() as usize]);
+
+        self.mark_dirty();
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: &'s Texture<'ctx>) {
+        let binding = self.layout().find_binding(name)
+            .unwrap_or_else(|| panic!("Shader \"{}\" has no binding \"{}\".", self.shader.name, name));
+
+        if binding.descriptor_type != vk::DescriptorType::COMBINED_IMAGE_SAMPLER {
+            panic!("Binding \"{}\" of shader \"{}\" is not a sampler.", name, self.shader.name);
+        }
+
+        self.textures[binding.binding as usize] = Some(texture);
+
+        self.mark_dirty();
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty_frames.iter_mut().for_each(|dirty| *dirty = true);
+    }
+
+    /// Writes pending changes into the descriptor set and uniform buffer of `frame_index`. Must be called once per
+    /// frame before the material is bound, while that frame's previous submission is known to be complete.
+    pub fn update(&mut self, frame_index: u32) {
+        let frame_index = frame_index as usize;
+
+        if !self.dirty_frames[frame_index] {
+            return;
+        }
+
+        if let Some(buffer) = self.uniform_buffers.get_mut(frame_index) {
+            buffer.load_slice(0, &self.uniform_data, vk::MemoryMapFlags::default());
+        }
+
+        let layout = self.layout();
+
+        let buffer_infos = layout.bindings.iter().map(|binding| {
+            self.uniform_offsets[binding.binding as usize].map(|offset| {
+                vk::DescriptorBufferInfo::default()
+                    .buffer(self.uniform_buffers[frame_index].handle)
+                    .offset(offset)
+                    .range(binding.uniform_size as vk::DeviceSize)
+            })
+        })
+        .collect::<Vec<_>>();
+
+        let image_infos = layout.bindings.iter().map(|binding| {
+            if binding.descriptor_type != vk::DescriptorType::COMBINED_IMAGE_SAMPLER {
+                return None;
+            }
+
+            let texture = self.textures[binding.binding as usize]
+                .unwrap_or_else(|| panic!("Material \"{}\" has no texture bound to \"{}\".", self.name, binding.name));
+
+            Some(vk::DescriptorImageInfo::default()
+                .image_view(texture.image_view())
+                .sampler(texture.sampler)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+        })
+        .collect::<Vec<_>>();
+
+        let writes = layout.bindings.iter().filter_map(|binding| {
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(self.descriptor_sets[frame_index])
+                .dst_binding(binding.binding)
+                .dst_array_element(0)
+                .descriptor_type(binding.descriptor_type);
+
+            if let Some(buffer_info) = &buffer_infos[binding.binding as usize] {
+                Some(write.buffer_info(std::slice::from_ref(buffer_info)))
+            } else {
+                image_infos[binding.binding as usize].as_ref().map(|image_info| write.image_info(std::slice::from_ref(image_info)))
+            }
+        })
+        .collect::<Vec<_>>();
+
+        unsafe { self.vkcontext.device.update_descriptor_sets(&writes, &[]); }
+
+        self.dirty_frames[frame_index] = false;
+    }
+
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, frame_index: u32) {
+        debug_assert!(!self.dirty_frames[frame_index as usize], "Material \"{}\" bound before update.", self.name);
+
+        self.shader.bind_descriptor_sets(command_buffer, self.set_index, &self.descriptor_sets[frame_index as usize..=frame_index as usize]);
+    }
+}
+
+impl<'ctx, 's> Drop for Material<'ctx, 's> {
+    fn drop(&mut self) {
+        self.shader.free_descriptor_sets(&self.descriptor_sets);
+    }
+}
@@ -1,28 +1,34 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
 use ash::vk;
 
-use crate::math::vec3::Vec3F;
+use crate::math::{frustum::BoundingSphere, mat4::Mat4, vec2::Vec2F, vec3::Vec3F};
+
+use super::{buffer::Buffer, material::Material, shader::{ShaderType, ShaderVertexAttributeInfo}, upload::{UploadManager, UploadTicket}, vkcontext::VkContext};
 
-use super::{buffer::Buffer, vkcontext::VkContext};
+static NEXT_MESH_ID: AtomicU32 = AtomicU32::new(0);
 
-struct Mesh<'ctx> {
-    name: String,
-    vertices: Vec<Vertex>,
-    indices: Vec<u32>,
+pub struct Mesh<'ctx> {
+    pub id: u32,
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
 
-    vertex_buffer: Buffer<'ctx>,
-    index_buffer: Buffer<'ctx>,
+    pub vertex_buffer: Buffer<'ctx>,
+    pub index_buffer: Buffer<'ctx>,
+    pub bounding_sphere: BoundingSphere,
+    vkcontext: &'ctx VkContext,
 }
 
 impl<'ctx> Mesh<'ctx> {
     pub fn new(
         vkcontext: &'ctx VkContext,
-        command_pool: vk::CommandPool,
-        queue: vk::Queue,
+        upload_manager: &mut UploadManager,
         name: String,
         vertices: &[Vertex],
         indices: &[u32],
-    ) -> Self {
-        let mut vertex_buffer = Buffer::new(
+    ) -> (Self, UploadTicket) {
+        let vertex_buffer = Buffer::new(
             vkcontext,
             (vertices.len() * std::mem::size_of::<Vertex>()) as u64,
             vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
@@ -30,7 +36,7 @@ impl<'ctx> Mesh<'ctx> {
             true,
         );
 
-        let mut index_buffer = Buffer::new(
+        let index_buffer = Buffer::new(
             vkcontext,
             (indices.len() * std::mem::size_of::<u32>()) as u64,
             vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
@@ -38,22 +44,110 @@ impl<'ctx> Mesh<'ctx> {
             true,
         );
 
-        vertex_buffer.upload_slice_staged(command_pool, queue, 0, vertices);
-        index_buffer.upload_slice_staged(command_pool, queue, 0, indices);
+        upload_manager.upload_buffer(&vertex_buffer, 0, vertices);
+        let ticket = upload_manager.upload_buffer(&index_buffer, 0, indices);
 
-        Self {
+        let mesh = Self {
+            id: NEXT_MESH_ID.fetch_add(1, Ordering::Relaxed),
+            bounding_sphere: BoundingSphere::from_points(vertices.iter().map(|vertex| vertex.position)),
             name,
             vertices: vertices.to_owned(),
             indices: indices.to_owned(),
             vertex_buffer,
             index_buffer,
+            vkcontext,
+        };
+
+        (mesh, ticket)
+    }
+}
+
+impl<'ctx> Mesh<'ctx> {
+    pub fn bind(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.vkcontext.device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.handle], &[0]);
+            self.vkcontext.device.cmd_bind_index_buffer(command_buffer, self.index_buffer.handle, 0, vk::IndexType::UINT32);
         }
     }
+
+    /// Draws the mesh with whatever vertex and index buffers are bound, which must be this mesh's.
+    pub fn draw_bound(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.vkcontext.device.cmd_draw_indexed(command_buffer, self.indices.len() as u32, 1, 0, 0, 0); }
+    }
+
+    /// Draws `instance_count` instances with the bound buffers, reading instance data from `first_instance` onwards.
+    pub fn draw_instanced(&self, command_buffer: vk::CommandBuffer, instance_count: u32, first_instance: u32) {
+        unsafe {
+            self.vkcontext.device.cmd_draw_indexed(
+                command_buffer,
+                self.indices.len() as u32,
+                instance_count,
+                0,
+                0,
+                first_instance,
+            );
+        }
+    }
+
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        self.bind(command_buffer);
+        self.draw_bound(command_buffer);
+    }
 }
 
+/// A mesh drawn with a particular material, transform and colour.
 #[derive(Clone, Copy)]
-struct Vertex {
-    position: Vec3F,
-    texture_coordinate: Vec3F,
-    normal: Vec3F,
+pub struct MeshInstance<'a, 'ctx> {
+    pub mesh: &'a Mesh<'ctx>,
+    pub material: &'a Material<'ctx, 'a>,
+    pub instance: InstanceData,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: Vec3F,
+    pub texture_coordinate: Vec2F,
+    pub normal: Vec3F,
+}
+
+impl Vertex {
+    pub fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(std::mem::size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    pub fn get_attributes(binding: u32) -> [ShaderVertexAttributeInfo; 3] {
+        [
+            ShaderVertexAttributeInfo::per_vertex(ShaderType::Float32_3, binding),
+            ShaderVertexAttributeInfo::per_vertex(ShaderType::Float32_2, binding),
+            ShaderVertexAttributeInfo::per_vertex(ShaderType::Float32_3, binding),
+        ]
+    }
+}
+
+/// Per-instance data read from an instance-rate vertex buffer.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct InstanceData {
+    pub model: Mat4,
+    pub color: [f32; 4],
+}
+
+impl InstanceData {
+    pub fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(std::mem::size_of::<InstanceData>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+    }
+
+    pub fn get_attributes(binding: u32) -> [ShaderVertexAttributeInfo; 2] {
+        [
+            ShaderVertexAttributeInfo::per_instance(ShaderType::Matrix4, binding),
+            ShaderVertexAttributeInfo::per_instance(ShaderType::Float32_4, binding),
+        ]
+    }
 }
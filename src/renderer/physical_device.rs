@@ -0,0 +1,165 @@
+use std::{ffi::CStr, fmt};
+
+use ash::{vk, Instance};
+
+use super::vkcontext::QueueFamilyIndices;
+
+pub const GPU_SELECTION_ENV_VAR: &str = "LISE_GPU";
+
+/// How `VkContext` chooses between the physical devices the instance enumerates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// The suitable device with the highest score.
+    #[default]
+    Auto,
+    /// The device at this position in `vkEnumeratePhysicalDevices` order.
+    Index(usize),
+    /// The highest scoring suitable device whose name contains this string, ignoring case.
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// Reads `LISE_GPU`. A value that parses as an integer selects a device by index, anything else by name.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(GPU_SELECTION_ENV_VAR).ok()?;
+        let value = value.trim();
+
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(match value.parse::<usize>() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_string()),
+        })
+    }
+
+    pub fn select<'a>(&self, reports: &'a [PhysicalDeviceReport]) -> Result<&'a PhysicalDeviceReport, String> {
+        let best_suitable = |candidates: &mut dyn Iterator<Item = &'a PhysicalDeviceReport>| {
+            candidates
+                .filter(|report| report.is_suitable())
+                .max_by_key(|report| report.score)
+        };
+
+        match self {
+            Self::Auto => best_suitable(&mut reports.iter())
+                .ok_or_else(|| "No suitable physical devices found.".to_string()),
+            Self::Index(index) => {
+                let report = reports
+                    .iter()
+                    .find(|report| report.index == *index)
+                    .ok_or_else(|| format!("No physical device with index {}.", index))?;
+
+                if report.is_suitable() {
+                    Ok(report)
+                } else {
+                    Err(format!("Physical device {} ({}) is unsuitable: {}", index, report.name, report.rejection_reasons.join("; ")))
+                }
+            },
+            Self::Name(name) => {
+                let needle = name.to_lowercase();
+
+                best_suitable(&mut reports.iter().filter(|report| report.name.to_lowercase().contains(&needle)))
+                    .ok_or_else(|| format!("No suitable physical device name contains \"{}\".", name))
+            },
+        }
+    }
+}
+
+/// Everything `VkContext` considered about a physical device when choosing one.
+#[derive(Clone, Debug)]
+pub struct PhysicalDeviceReport {
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub driver_version: u32,
+    pub device_local_memory: vk::DeviceSize,
+    pub queue_family_indices: Option<QueueFamilyIndices>,
+    pub max_image_dimension_2d: u32,
+    pub supported_optional_features: usize,
+    pub score: u64,
+    pub rejection_reasons: Vec<String>,
+}
+
+impl PhysicalDeviceReport {
+    pub fn new(instance: &Instance, index: usize, device: vk::PhysicalDevice) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(device) };
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(device) };
+
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+
+        let device_local_memory = memory_properties.memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        Self {
+            index,
+            name,
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            driver_version: properties.driver_version,
+            device_local_memory,
+            queue_family_indices: None,
+            max_image_dimension_2d: properties.limits.max_image_dimension2_d,
+            supported_optional_features: 0,
+            score: 0,
+            rejection_reasons: Vec::new(),
+        }
+    }
+
+    pub fn is_suitable(&self) -> bool {
+        self.rejection_reasons.is_empty()
+    }
+
+    /// Ranks devices by type first (discrete > integrated > virtual > CPU), then by device-local memory, then by
+    /// optional capabilities. The type weights are far enough apart that memory size never reorders device types.
+    pub fn compute_score(&mut self) {
+        let type_score: u64 = match self.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4_000_000,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3_000_000,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2_000_000,
+            vk::PhysicalDeviceType::CPU => 1_000_000,
+            _ => 0,
+        };
+
+        let memory_score = (self.device_local_memory / (1024 * 1024)).min(512 * 1024);
+
+        let mut feature_score = (self.max_image_dimension_2d / 1024) as u64;
+
+        feature_score += 100 * self.supported_optional_features as u64;
+
+        if let Some(indices) = self.queue_family_indices {
+            if indices.has_dedicated_compute_queue() { feature_score += 50; }
+            if indices.has_dedicated_transfer_queue() { feature_score += 50; }
+        }
+
+        self.score = type_score + memory_score + feature_score;
+    }
+}
+
+impl fmt::Display for PhysicalDeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, Vulkan {}.{}.{}, {} MiB device-local)",
+            self.index,
+            self.name,
+            self.device_type,
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+            self.device_local_memory / (1024 * 1024),
+        )?;
+
+        if self.is_suitable() {
+            write!(f, " score {}", self.score)
+        } else {
+            write!(f, " rejected: {}", self.rejection_reasons.join("; "))
+        }
+    }
+}
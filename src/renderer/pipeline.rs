@@ -8,10 +8,11 @@ pub struct Pipeline<'ctx> {
 }
 
 impl<'ctx> Pipeline<'ctx> {
+    /// Fails only if the driver rejects the pipeline, e.g. for shader modules that don't link. Everything else is a
+    /// programming error and panics.
     pub fn new_graphics(
         vkcontext: &'ctx VkContext,
-        render_pass: vk::RenderPass,
-        subpass_index: u32,
+        render_target: &PipelineRenderTarget,
         pipeline_state_info: &PipelineStateInfo,
         vertex_bindings: &[vk::VertexInputBindingDescription],
         vertex_attributes: &[vk::VertexInputAttributeDescription],
@@ -20,7 +21,7 @@ impl<'ctx> Pipeline<'ctx> {
         shader_stages: &[vk::PipelineShaderStageCreateInfo],
         color_blend_attachment_states: &[vk::PipelineColorBlendAttachmentState],
         depth_test_enabled: bool,
-    ) -> Self {
+    ) -> Result<Self, vk::Result> {
         for state in Self::REQUIRED_DYNAMIC_STATE {
             if !pipeline_state_info.dynamic_state.contains(&state) {
                 panic!("Dynamic state supplied to Pipeline is missing one or more required dynamic states.");
@@ -47,6 +48,8 @@ impl<'ctx> Pipeline<'ctx> {
             let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
                 .dynamic_states(pipeline_state_info.dynamic_state);
 
+            let mut rendering_create_info = vk::PipelineRenderingCreateInfo::default();
+
             // TODO: Allow tesselation state.
             let mut create_info = vk::GraphicsPipelineCreateInfo::default()
                 .stages(shader_stages)
@@ -58,42 +61,67 @@ impl<'ctx> Pipeline<'ctx> {
                 .color_blend_state(&color_blend)
                 .dynamic_state(&dynamic_state)
                 
-                .layout(layout)
+                .layout(layout);
+
+            match *render_target {
+                PipelineRenderTarget::RenderPass { render_pass, subpass_index } => {
+                    create_info = create_info
+                        .render_pass(render_pass)
+                        .subpass(subpass_index);
+                },
+                PipelineRenderTarget::Dynamic {
+                    color_attachment_formats,
+                    depth_attachment_format,
+                    stencil_attachment_format,
+                } => {
+                    rendering_create_info = rendering_create_info
+                        .color_attachment_formats(color_attachment_formats)
+                        .depth_attachment_format(depth_attachment_format)
+                        .stencil_attachment_format(stencil_attachment_format);
+
+                    create_info = create_info.push_next(&mut rendering_create_info);
+                },
+            }
 
-                .render_pass(render_pass)
-                .subpass(subpass_index);
-                
             if depth_test_enabled {
                 create_info = create_info.depth_stencil_state(&pipeline_state_info.depth_stencil_state);
             }
 
-            unsafe {
+            let result = unsafe {
                 vkcontext.device.create_graphics_pipelines(
-                    vk::PipelineCache::default(),
+                    vkcontext.pipeline_cache,
                     std::slice::from_ref(&create_info),
                     None
                 )
-                .unwrap()[0]
+            };
+
+            match result {
+                Ok(pipelines) => pipelines[0],
+                Err((_, error)) => {
+                    unsafe { vkcontext.device.destroy_pipeline_layout(layout, None); }
+                    return Err(error);
+                },
             }
         };
 
-        Self {
+        Ok(Self {
             handle,
             layout,
             vkcontext,
-        }
+        })
     }
 
+    /// Fails only if the driver rejects the pipeline, like `new_graphics`.
     pub fn new_compute(
         vkcontext: &'ctx VkContext,
+        push_constant_ranges: &[vk::PushConstantRange],
         descriptor_set_layouts: &[vk::DescriptorSetLayout],
         compute_stage_create_info: vk::PipelineShaderStageCreateInfo,
-    ) -> Self {
-
-
+    ) -> Result<Self, vk::Result> {
         let layout = { 
             let create_info = vk::PipelineLayoutCreateInfo::default()
-                .set_layouts(descriptor_set_layouts);
+                .set_layouts(descriptor_set_layouts)
+                .push_constant_ranges(push_constant_ranges);
 
             unsafe { vkcontext.device.create_pipeline_layout(&create_info, None).unwrap() }
         };
@@ -105,19 +133,25 @@ impl<'ctx> Pipeline<'ctx> {
                 .layout(layout);
 
             let create_infos = [create_info];
-            
-            unsafe {
-                vkcontext.device
-                .create_compute_pipelines(vk::PipelineCache::null(), &create_infos, None)
-                .unwrap()[0]
+
+            let result = unsafe {
+                vkcontext.device.create_compute_pipelines(vkcontext.pipeline_cache, &create_infos, None)
+            };
+
+            match result {
+                Ok(pipelines) => pipelines[0],
+                Err((_, error)) => {
+                    unsafe { vkcontext.device.destroy_pipeline_layout(layout, None); }
+                    return Err(error);
+                },
             }
         };
 
-        Self {
+        Ok(Self {
             handle,
             layout,
             vkcontext,
-        }
+        })
     }
 }
 
@@ -144,6 +178,22 @@ impl<'ctx> Drop for Pipeline<'ctx> {
     }
 }
 
+/// What a graphics pipeline renders into: a subpass of a `vk::RenderPass`, or attachments of the given formats
+/// bound with dynamic rendering. Unused formats are `vk::Format::UNDEFINED`.
+#[derive(Clone, Copy)]
+pub enum PipelineRenderTarget<'a> {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        subpass_index: u32,
+    },
+    Dynamic {
+        color_attachment_formats: &'a [vk::Format],
+        depth_attachment_format: vk::Format,
+        stencil_attachment_format: vk::Format,
+    },
+}
+
+#[derive(Clone, Copy)]
 pub struct PipelineStateInfo<'a> {
     viewport_state: vk::PipelineViewportStateCreateInfo<'a>,
     input_assembly_state: vk::PipelineInputAssemblyStateCreateInfo<'a>,
@@ -196,3 +246,46 @@ impl<'a> PipelineStateInfo<'a> {
         }
     }
 }
+
+impl<'a> PipelineStateInfo<'a> {
+    pub fn depth_test(mut self, enabled: bool) -> Self {
+        self.depth_stencil_state = self.depth_stencil_state
+            .depth_test_enable(enabled)
+            .depth_write_enable(enabled);
+        self
+    }
+
+    pub fn depth_compare_op(mut self, compare_op: vk::CompareOp) -> Self {
+        self.depth_stencil_state = self.depth_stencil_state.depth_compare_op(compare_op);
+        self
+    }
+
+    /// Offsets the depth of every fragment by `constant_factor` units plus `slope_factor` times the polygon's depth
+    /// slope, clamped to `clamp` unless it is zero. Used by shadow casters to avoid self-shadowing.
+    pub fn depth_bias(mut self, constant_factor: f32, slope_factor: f32, clamp: f32) -> Self {
+        self.rasterizer_state = self.rasterizer_state
+            .depth_bias_enable(true)
+            .depth_bias_constant_factor(constant_factor)
+            .depth_bias_slope_factor(slope_factor)
+            .depth_bias_clamp(clamp);
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.rasterizer_state = self.rasterizer_state.cull_mode(cull_mode);
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: vk::SampleCountFlags) -> Self {
+        self.multisampler_state = self.multisampler_state.rasterization_samples(sample_count);
+        self
+    }
+
+    pub fn rasterization_samples(&self) -> vk::SampleCountFlags {
+        self.multisampler_state.rasterization_samples
+    }
+
+    pub fn is_depth_test_enabled(&self) -> bool {
+        self.depth_stencil_state.depth_test_enable == vk::TRUE
+    }
+}
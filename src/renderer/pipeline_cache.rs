@@ -0,0 +1,79 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use ash::{vk, Device};
+
+pub const PIPELINE_CACHE_FILE_NAME: &str = "pipeline_cache.bin";
+
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Where the pipeline cache is kept: next to the executable, so it doesn't depend on the working directory, or in the
+/// working directory if the executable's location is unknown.
+pub fn pipeline_cache_path() -> PathBuf {
+    std::env::current_exe().ok()
+        .and_then(|executable| executable.parent().map(|directory| directory.join(PIPELINE_CACHE_FILE_NAME)))
+        .unwrap_or_else(|| PathBuf::from(PIPELINE_CACHE_FILE_NAME))
+}
+
+pub fn load_pipeline_cache<P: AsRef<Path>>(
+    device: &Device,
+    physical_device_properties: &vk::PhysicalDeviceProperties,
+    path: P,
+) -> vk::PipelineCache {
+    let initial_data = match fs::read(&path) {
+        Ok(data) if is_pipeline_cache_data_valid(&data, physical_device_properties) => {
+            log::debug!("Loaded pipeline cache ({} bytes).", data.len());
+            data
+        },
+        Ok(_) => {
+            log::warn!("Discarding stale pipeline cache: {}", path.as_ref().display());
+            Vec::new()
+        },
+        Err(_) => {
+            log::debug!("No pipeline cache found at {}.", path.as_ref().display());
+            Vec::new()
+        },
+    };
+
+    let create_info = vk::PipelineCacheCreateInfo::default()
+        .initial_data(&initial_data);
+
+    unsafe { device.create_pipeline_cache(&create_info, None).unwrap() }
+}
+
+pub fn save_pipeline_cache<P: AsRef<Path>>(device: &Device, pipeline_cache: vk::PipelineCache, path: P) {
+    let data = match unsafe { device.get_pipeline_cache_data(pipeline_cache) } {
+        Ok(data) => data,
+        Err(error) => {
+            log::error!("Failed to retrieve pipeline cache data: {}", error);
+            return;
+        },
+    };
+
+    match fs::write(&path, &data) {
+        Ok(()) => log::debug!("Saved pipeline cache ({} bytes).", data.len()),
+        Err(error) => log::error!("Failed to write pipeline cache to {}: {}", path.as_ref().display(), error),
+    }
+}
+
+/// Checks the `VkPipelineCacheHeaderVersionOne` at the start of `data` against the physical device, so that caches
+/// written by a different driver or GPU are never handed back to Vulkan.
+fn is_pipeline_cache_data_valid(data: &[u8], physical_device_properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let header_size = read_u32(0) as usize;
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..PIPELINE_CACHE_HEADER_SIZE];
+
+    header_size >= PIPELINE_CACHE_HEADER_SIZE
+        && header_size <= data.len()
+        && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && vendor_id == physical_device_properties.vendor_id
+        && device_id == physical_device_properties.device_id
+        && uuid == physical_device_properties.pipeline_cache_uuid
+}
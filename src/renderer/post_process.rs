@@ -0,0 +1,723 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    slice,
+};
+
+use ash::vk;
+
+use crate::math::vec2::Vec2UI;
+
+use super::{
+    pipeline::{PipelineRenderTarget, PipelineStateInfo},
+    render_graph::{AttachmentLoad, GraphImage, ImageAccess, RenderGraph, RenderGraphPass, TransientImageDescription},
+    shader::{Shader, ShaderDescriptorInfo, ShaderDescriptorSetInfo, ShaderDescriptorTypeInfo, ShaderPushConstantInfo, ShaderStageInfo, ShaderType},
+    texture::{create_sampler, Texture},
+    upload::{UploadManager, UploadTicket},
+    utility,
+    vkcontext::VkContext,
+    MAX_FRAMES_IN_FLIGHT,
+};
+
+/// Format of the scene colour the stack reads and of every intermediate image in the chain.
+pub const POST_PROCESS_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+pub const MAX_BLOOM_MIPS: u32 = 8;
+
+const SINGLE_INPUT: &[ShaderDescriptorInfo] = &[
+    ShaderDescriptorInfo {
+        name: "scene",
+        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+    },
+];
+
+const TWO_INPUTS: &[ShaderDescriptorInfo] = &[
+    ShaderDescriptorInfo {
+        name: "scene",
+        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+    },
+    ShaderDescriptorInfo {
+        name: "overlay",
+        descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+    },
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PostProcessEffect {
+    /// Adds a blurred copy of everything brighter than `threshold`, built from a chain of `mip_count` downsampled
+    /// images. Works on HDR colour, so it belongs before `Tonemap`.
+    Bloom { threshold: f32, intensity: f32, radius: f32, mip_count: u32 },
+    /// Scales by `exposure` and maps HDR colour into [0, 1] with the ACES filmic curve.
+    Tonemap { exposure: f32 },
+    /// Fast approximate anti-aliasing. Expects tonemapped colour.
+    Fxaa,
+    /// Looks colours up in the stack's colour grading LUT and blends the result in by `intensity`. Expects tonemapped
+    /// colour.
+    ColorGrading { intensity: f32 },
+    /// Darkens towards the corners, starting `smoothness` away from them.
+    Vignette { intensity: f32, smoothness: f32 },
+}
+
+/// The effects of the stack, applied in order.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PostProcessSettings {
+    pub effects: Vec<PostProcessEffect>,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            effects: vec![
+                PostProcessEffect::Bloom { threshold: 1.0, intensity: 0.05, radius: 1.0, mip_count: 5 },
+                PostProcessEffect::Tonemap { exposure: 1.0 },
+                PostProcessEffect::Fxaa,
+            ],
+        }
+    }
+}
+
+/// A `size`³ colour lookup table unwrapped into a `size * size` by `size` strip of RGBA8 texels: blue picks the
+/// `size`-wide slice, red and green the texel inside it. Most grading tools export this layout.
+pub struct ColorGradingLut {
+    pub size: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl ColorGradingLut {
+    pub fn identity(size: u32) -> Self {
+        Self::from_fn(size, |colour| colour)
+    }
+
+    /// Builds a LUT by evaluating `grade` on every entry, with colours in [0, 1].
+    pub fn from_fn<F: Fn([f32; 3]) -> [f32; 3]>(size: u32, grade: F) -> Self {
+        assert!(size >= 2, "A colour grading LUT needs at least 2 entries per channel.");
+
+        let scale = 1.0 / (size - 1) as f32;
+        let mut pixels = Vec::with_capacity((size * size * size * 4) as usize);
+
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    let graded = grade([red as f32 * scale, green as f32 * scale, blue as f32 * scale]);
+
+                    pixels.extend(graded.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8));
+                    pixels.push(255);
+                }
+            }
+        }
+
+        Self { size, pixels }
+    }
+}
+
+/// How many passes of each shader a graph can hold for a list of effects, which sizes each shader's descriptor pool.
+/// Bloom effects count `MAX_BLOOM_MIPS` levels so that their mip count can change later.
+#[derive(Default)]
+struct ShaderPassCounts {
+    bloom_downsample: u32,
+    bloom_upsample: u32,
+    bloom_composite: u32,
+    tonemap: u32,
+    fxaa: u32,
+    color_grading: u32,
+    vignette: u32,
+}
+
+impl ShaderPassCounts {
+    fn new(effects: &[PostProcessEffect]) -> Self {
+        let mut counts = Self::default();
+
+        for effect in effects {
+            match effect {
+                PostProcessEffect::Bloom { .. } => {
+                    counts.bloom_downsample += MAX_BLOOM_MIPS;
+                    counts.bloom_upsample += MAX_BLOOM_MIPS - 1;
+                    counts.bloom_composite += 1;
+                },
+                PostProcessEffect::Tonemap { .. } => counts.tonemap += 1,
+                PostProcessEffect::Fxaa => counts.fxaa += 1,
+                PostProcessEffect::ColorGrading { .. } => counts.color_grading += 1,
+                PostProcessEffect::Vignette { .. } => counts.vignette += 1,
+            }
+        }
+
+        counts
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PassInput {
+    Graph(GraphImage),
+    View(vk::ImageView),
+}
+
+struct FullscreenPass<'g, 'ctx> {
+    shader: &'g Shader<'ctx>,
+    inputs: Vec<PassInput>,
+    output: GraphImage,
+    load: AttachmentLoad,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct BloomDownsampleConstants {
+    source_texel_size: [f32; 2],
+    threshold: f32,
+    prefilter: f32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct BloomUpsampleConstants {
+    source_texel_size: [f32; 2],
+    radius: f32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct ColorGradingConstants {
+    lut_size: f32,
+    intensity: f32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct VignetteConstants {
+    intensity: f32,
+    smoothness: f32,
+}
+
+/// A chain of fullscreen passes between the HDR scene colour and the final output, added to a render graph with
+/// `add_passes`. Each effect renders into its own transient image and a last composite pass copies the result to the
+/// output, encoding sRGB if the output format does not.
+///
+/// Which effects run, and in which order, is fixed when the stack is created; their parameters can change every
+/// frame with `set_settings`. Descriptor sets are reused by the passes of every graph the stack is added to, so only
+/// the passes of the latest graph may be recorded.
+pub struct PostProcessStack<'ctx> {
+    pub output_format: vk::Format,
+
+    settings: RefCell<PostProcessSettings>,
+    frame_index: Cell<u32>,
+    /// One set per frame in flight for each pass using a shader, keyed by shader id and indexed by the pass's position
+    /// among the passes using that shader.
+    descriptor_sets: RefCell<HashMap<u32, Vec<Vec<vk::DescriptorSet>>>>,
+    /// How many passes of the graph being built use each shader so far.
+    pass_counts: RefCell<HashMap<u32, usize>>,
+    sampler: vk::Sampler,
+    color_grading_lut: Texture<'ctx>,
+    color_grading_lut_size: u32,
+
+    bloom_downsample_shader: Shader<'ctx>,
+    bloom_upsample_shader: Shader<'ctx>,
+    bloom_composite_shader: Shader<'ctx>,
+    tonemap_shader: Shader<'ctx>,
+    fxaa_shader: Shader<'ctx>,
+    color_grading_shader: Shader<'ctx>,
+    vignette_shader: Shader<'ctx>,
+    composite_shader: Shader<'ctx>,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> PostProcessStack<'ctx> {
+    /// Creates the stack for an output of `output_format`, queuing the upload of `color_grading_lut`.
+    pub fn new(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        output_format: vk::Format,
+        settings: PostProcessSettings,
+        color_grading_lut: &ColorGradingLut,
+    ) -> (Self, UploadTicket) {
+        let (lut_texture, ticket) = Texture::new(
+            vkcontext,
+            upload_manager,
+            "builtin.post.color_grading_lut",
+            Vec2UI { x: color_grading_lut.size * color_grading_lut.size, y: color_grading_lut.size },
+            vk::Format::R8G8B8A8_UNORM,
+            &color_grading_lut.pixels,
+        );
+
+        let pass_counts = ShaderPassCounts::new(&settings.effects);
+        let float = |push_constant_type| ShaderPushConstantInfo { push_constant_type, stage_flags: vk::ShaderStageFlags::FRAGMENT };
+
+        let bloom_downsample_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.bloom_downsample",
+            POST_PROCESS_FORMAT,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32_2), float(ShaderType::Float32), float(ShaderType::Float32)],
+            false,
+            pass_counts.bloom_downsample,
+        );
+
+        // Upsampled levels are added onto the next larger one.
+        let bloom_upsample_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.bloom_upsample",
+            POST_PROCESS_FORMAT,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32_2), float(ShaderType::Float32)],
+            true,
+            pass_counts.bloom_upsample,
+        );
+
+        let bloom_composite_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.bloom_composite",
+            POST_PROCESS_FORMAT,
+            TWO_INPUTS,
+            &[float(ShaderType::Float32)],
+            false,
+            pass_counts.bloom_composite,
+        );
+
+        let tonemap_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.tonemap",
+            POST_PROCESS_FORMAT,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32)],
+            false,
+            pass_counts.tonemap,
+        );
+
+        let fxaa_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.fxaa",
+            POST_PROCESS_FORMAT,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32_2)],
+            false,
+            pass_counts.fxaa,
+        );
+
+        let color_grading_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.color_grading",
+            POST_PROCESS_FORMAT,
+            TWO_INPUTS,
+            &[float(ShaderType::Float32), float(ShaderType::Float32)],
+            false,
+            pass_counts.color_grading,
+        );
+
+        let vignette_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.vignette",
+            POST_PROCESS_FORMAT,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32), float(ShaderType::Float32)],
+            false,
+            pass_counts.vignette,
+        );
+
+        let composite_shader = create_post_process_shader(
+            vkcontext,
+            "builtin.post.composite",
+            output_format,
+            SINGLE_INPUT,
+            &[float(ShaderType::Float32)],
+            false,
+            1,
+        );
+
+        let stack = Self {
+            output_format,
+            settings: RefCell::new(settings),
+            frame_index: Cell::new(0),
+            descriptor_sets: RefCell::new(HashMap::new()),
+            pass_counts: RefCell::new(HashMap::new()),
+            sampler: create_sampler(vkcontext, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE),
+            color_grading_lut: lut_texture,
+            color_grading_lut_size: color_grading_lut.size,
+            bloom_downsample_shader,
+            bloom_upsample_shader,
+            bloom_composite_shader,
+            tonemap_shader,
+            fxaa_shader,
+            color_grading_shader,
+            vignette_shader,
+            composite_shader,
+            vkcontext,
+        };
+
+        (stack, ticket)
+    }
+}
+
+fn create_post_process_shader<'ctx>(
+    vkcontext: &'ctx VkContext,
+    name: &str,
+    output_format: vk::Format,
+    inputs: &[ShaderDescriptorInfo],
+    push_constants: &[ShaderPushConstantInfo],
+    is_additive: bool,
+    max_passes: u32,
+) -> Shader<'ctx> {
+    let blend_attachment = vk::PipelineColorBlendAttachmentState {
+        blend_enable: if is_additive { vk::TRUE } else { vk::FALSE },
+        src_color_blend_factor: vk::BlendFactor::ONE,
+        dst_color_blend_factor: vk::BlendFactor::ONE,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+    };
+
+    let fragment_file = format!("shaders/{}.frag.spv", name);
+
+    Shader::new(
+        vkcontext,
+        name,
+        &PipelineRenderTarget::Dynamic {
+            color_attachment_formats: &[output_format],
+            depth_attachment_format: vk::Format::UNDEFINED,
+            stencil_attachment_format: vk::Format::UNDEFINED,
+        },
+        &[blend_attachment],
+        &[],
+        &[],
+        push_constants,
+        &[
+            ShaderDescriptorSetInfo {
+                // Pools must allow at least one set, even for shaders no effect uses.
+                max_set_allocations: max_passes.max(1) * MAX_FRAMES_IN_FLIGHT,
+                descriptors: inputs,
+            },
+        ],
+        &[
+            ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.fullscreen.vert.spv"),
+            ShaderStageInfo::new(vk::ShaderStageFlags::FRAGMENT, &fragment_file),
+        ],
+        &PipelineStateInfo::get_default_pipeline_state_info()
+            .depth_test(false)
+            .cull_mode(vk::CullModeFlags::NONE),
+    )
+}
+
+fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32 | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+    )
+}
+
+fn bloom_mip_count(mip_count: u32) -> u32 {
+    mip_count.clamp(1, MAX_BLOOM_MIPS)
+}
+
+fn texel_size(size: Vec2UI) -> [f32; 2] {
+    [1.0 / size.x as f32, 1.0 / size.y as f32]
+}
+
+impl<'ctx> PostProcessStack<'ctx> {
+    pub fn settings(&self) -> PostProcessSettings {
+        self.settings.borrow().clone()
+    }
+
+    /// Replaces the parameters of every effect. The effects must be the same, in the same order, as when the stack was
+    /// created. Returns true if a bloom's mip count changed, which changes its passes: the new count only applies once
+    /// the passes are added to a new graph.
+    pub fn set_settings(&self, settings: PostProcessSettings) -> bool {
+        let current = self.settings.borrow();
+
+        assert!(
+            current.effects.len() == settings.effects.len()
+                && current.effects.iter().zip(settings.effects.iter())
+                    .all(|(a, b)| std::mem::discriminant(a) == std::mem::discriminant(b)),
+            "Post-process effects cannot be added, removed or reordered once the stack is created."
+        );
+
+        let needs_new_graph = current.effects.iter().zip(settings.effects.iter()).any(|pair| match pair {
+            (PostProcessEffect::Bloom { mip_count: a, .. }, PostProcessEffect::Bloom { mip_count: b, .. }) => {
+                bloom_mip_count(*a) != bloom_mip_count(*b)
+            },
+            _ => false,
+        });
+
+        drop(current);
+
+        *self.settings.borrow_mut() = settings;
+
+        needs_new_graph
+    }
+
+    /// Selects the descriptor sets of `frame_index` for the passes recorded next.
+    pub fn prepare(&self, frame_index: u32) {
+        self.frame_index.set(frame_index);
+    }
+
+    /// Reloads the effect shaders built from any of `changed_files`. See `Shader::reload`.
+    pub fn reload_shaders(&self, changed_files: &[String]) {
+        for shader in [
+            &self.bloom_downsample_shader,
+            &self.bloom_upsample_shader,
+            &self.bloom_composite_shader,
+            &self.tonemap_shader,
+            &self.fxaa_shader,
+            &self.color_grading_shader,
+            &self.vignette_shader,
+            &self.composite_shader,
+        ] {
+            shader.reload(changed_files);
+        }
+    }
+
+    fn effect(&self, index: usize) -> PostProcessEffect {
+        self.settings.borrow().effects[index]
+    }
+
+    /// The descriptor sets of the next pass of the current graph using `shader`, allocated the first time a graph
+    /// needs that many passes of it.
+    fn next_descriptor_sets(&self, shader: &Shader) -> Vec<vk::DescriptorSet> {
+        let mut pass_counts = self.pass_counts.borrow_mut();
+        let pass_index = pass_counts.entry(shader.id).or_insert(0);
+
+        let mut descriptor_sets = self.descriptor_sets.borrow_mut();
+        let shader_sets = descriptor_sets.entry(shader.id).or_default();
+
+        if shader_sets.len() == *pass_index {
+            shader_sets.push(shader.allocate_descriptor_sets(0, MAX_FRAMES_IN_FLIGHT));
+        }
+
+        *pass_index += 1;
+        shader_sets[*pass_index - 1].clone()
+    }
+}
+
+impl<'ctx> PostProcessStack<'ctx> {
+    /// Adds a pass per effect reading `input`, which must be `POST_PROCESS_FORMAT`, and a final composite writing the
+    /// whole of `output`. `size` is the size of both.
+    pub fn add_passes<'g>(&'g self, render_graph: &mut RenderGraph<'g>, input: GraphImage, output: GraphImage, size: Vec2UI) {
+        let effects = self.settings.borrow().effects.clone();
+        let mut current = input;
+
+        self.pass_counts.borrow_mut().clear();
+
+        for (index, effect) in effects.iter().enumerate() {
+            let target = render_graph.create_image(
+                &format!("PostProcess{}", index),
+                TransientImageDescription { size, format: POST_PROCESS_FORMAT, samples: vk::SampleCountFlags::TYPE_1 },
+            );
+
+            match *effect {
+                PostProcessEffect::Bloom { mip_count, .. } => {
+                    self.add_bloom_passes(render_graph, index, current, target, size, mip_count);
+                },
+                PostProcessEffect::Tonemap { .. } => self.add_fullscreen_pass(
+                    render_graph,
+                    "Tonemap",
+                    FullscreenPass { shader: &self.tonemap_shader, inputs: vec![PassInput::Graph(current)], output: target, load: AttachmentLoad::DontCare },
+                    move |shader, command_buffer| {
+                        if let PostProcessEffect::Tonemap { exposure } = self.effect(index) {
+                            shader.push_constants(command_buffer, 0, &exposure);
+                        }
+                    },
+                ),
+                PostProcessEffect::Fxaa => self.add_fullscreen_pass(
+                    render_graph,
+                    "FXAA",
+                    FullscreenPass { shader: &self.fxaa_shader, inputs: vec![PassInput::Graph(current)], output: target, load: AttachmentLoad::DontCare },
+                    move |shader, command_buffer| shader.push_constants(command_buffer, 0, &texel_size(size)),
+                ),
+                PostProcessEffect::ColorGrading { .. } => self.add_fullscreen_pass(
+                    render_graph,
+                    "ColorGrading",
+                    FullscreenPass {
+                        shader: &self.color_grading_shader,
+                        inputs: vec![PassInput::Graph(current), PassInput::View(self.color_grading_lut.image_view())],
+                        output: target,
+                        load: AttachmentLoad::DontCare,
+                    },
+                    move |shader, command_buffer| {
+                        if let PostProcessEffect::ColorGrading { intensity } = self.effect(index) {
+                            let constants = ColorGradingConstants { lut_size: self.color_grading_lut_size as f32, intensity };
+                            shader.push_constants(command_buffer, 0, &constants);
+                        }
+                    },
+                ),
+                PostProcessEffect::Vignette { .. } => self.add_fullscreen_pass(
+                    render_graph,
+                    "Vignette",
+                    FullscreenPass { shader: &self.vignette_shader, inputs: vec![PassInput::Graph(current)], output: target, load: AttachmentLoad::DontCare },
+                    move |shader, command_buffer| {
+                        if let PostProcessEffect::Vignette { intensity, smoothness } = self.effect(index) {
+                            shader.push_constants(command_buffer, 0, &VignetteConstants { intensity, smoothness });
+                        }
+                    },
+                ),
+            }
+
+            current = target;
+        }
+
+        let encode_srgb: f32 = if is_srgb_format(self.output_format) { 0.0 } else { 1.0 };
+
+        self.add_fullscreen_pass(
+            render_graph,
+            "Composite",
+            FullscreenPass { shader: &self.composite_shader, inputs: vec![PassInput::Graph(current)], output, load: AttachmentLoad::DontCare },
+            move |shader, command_buffer| shader.push_constants(command_buffer, 0, &encode_srgb),
+        );
+    }
+
+    /// Thresholds and downsamples `input` through a chain of half-sized images, upsamples back up adding each level
+    /// onto the next larger one, and adds the result onto `input` into `output`.
+    fn add_bloom_passes<'g>(
+        &'g self,
+        render_graph: &mut RenderGraph<'g>,
+        effect_index: usize,
+        input: GraphImage,
+        output: GraphImage,
+        size: Vec2UI,
+        mip_count: u32,
+    ) {
+        let mips = (1..=bloom_mip_count(mip_count)).map(|level| {
+            let mip_size = Vec2UI { x: (size.x >> level).max(1), y: (size.y >> level).max(1) };
+
+            let image = render_graph.create_image(
+                &format!("Bloom{}Mip{}", effect_index, level),
+                TransientImageDescription { size: mip_size, format: POST_PROCESS_FORMAT, samples: vk::SampleCountFlags::TYPE_1 },
+            );
+
+            (image, mip_size)
+        })
+        .collect::<Vec<_>>();
+
+        let mut source = (input, size);
+
+        for (level, &(mip, mip_size)) in mips.iter().enumerate() {
+            let source_size = source.1;
+
+            self.add_fullscreen_pass(
+                render_graph,
+                &format!("BloomDownsample{}", level + 1),
+                FullscreenPass {
+                    shader: &self.bloom_downsample_shader,
+                    inputs: vec![PassInput::Graph(source.0)],
+                    output: mip,
+                    load: AttachmentLoad::DontCare,
+                },
+                move |shader, command_buffer| {
+                    if let PostProcessEffect::Bloom { threshold, .. } = self.effect(effect_index) {
+                        let constants = BloomDownsampleConstants {
+                            source_texel_size: texel_size(source_size),
+                            threshold,
+                            prefilter: if level == 0 { 1.0 } else { 0.0 },
+                        };
+
+                        shader.push_constants(command_buffer, 0, &constants);
+                    }
+                },
+            );
+
+            source = (mip, mip_size);
+        }
+
+        for level in (1..mips.len()).rev() {
+            let (source, source_size) = mips[level];
+
+            self.add_fullscreen_pass(
+                render_graph,
+                &format!("BloomUpsample{}", level),
+                FullscreenPass {
+                    shader: &self.bloom_upsample_shader,
+                    inputs: vec![PassInput::Graph(source)],
+                    output: mips[level - 1].0,
+                    load: AttachmentLoad::Load,
+                },
+                move |shader, command_buffer| {
+                    if let PostProcessEffect::Bloom { radius, .. } = self.effect(effect_index) {
+                        let constants = BloomUpsampleConstants { source_texel_size: texel_size(source_size), radius };
+                        shader.push_constants(command_buffer, 0, &constants);
+                    }
+                },
+            );
+        }
+
+        self.add_fullscreen_pass(
+            render_graph,
+            "BloomComposite",
+            FullscreenPass {
+                shader: &self.bloom_composite_shader,
+                inputs: vec![PassInput::Graph(input), PassInput::Graph(mips[0].0)],
+                output,
+                load: AttachmentLoad::DontCare,
+            },
+            move |shader, command_buffer| {
+                if let PostProcessEffect::Bloom { intensity, .. } = self.effect(effect_index) {
+                    shader.push_constants(command_buffer, 0, &intensity);
+                }
+            },
+        );
+    }
+
+    /// Adds a pass drawing a fullscreen triangle with `pass.shader` into `pass.output`, sampling `pass.inputs` through
+    /// set 0 in order. `push_constants` pushes the shader's parameters before the draw.
+    fn add_fullscreen_pass<'g, F>(&'g self, render_graph: &mut RenderGraph<'g>, name: &str, pass: FullscreenPass<'g, 'ctx>, push_constants: F)
+    where
+        F: Fn(&Shader, vk::CommandBuffer) + 'g,
+    {
+        let descriptor_sets = self.next_descriptor_sets(pass.shader);
+
+        let mut graph_pass = RenderGraphPass::new(name).color_attachment(pass.output, pass.load);
+
+        for input in pass.inputs.iter() {
+            if let PassInput::Graph(image) = *input {
+                graph_pass = graph_pass.image(image, ImageAccess::Sampled { stage: vk::PipelineStageFlags2::FRAGMENT_SHADER });
+            }
+        }
+
+        render_graph.add_pass(graph_pass.execute(move |context| {
+            let descriptor_set = descriptor_sets[self.frame_index.get() as usize];
+
+            // Transient views only exist once the graph is compiled, so the inputs are written just before use.
+            let image_infos = pass.inputs.iter().map(|input| {
+                let image_view = match *input {
+                    PassInput::Graph(image) => context.image_view(image),
+                    PassInput::View(view) => view,
+                };
+
+                vk::DescriptorImageInfo::default()
+                    .image_view(image_view)
+                    .sampler(self.sampler)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+
+            let writes = image_infos.iter().enumerate().map(|(binding, image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(slice::from_ref(image_info))
+            })
+            .collect::<Vec<_>>();
+
+            unsafe { self.vkcontext.device.update_descriptor_sets(&writes, &[]); }
+
+            utility::set_viewport_and_scissor(
+                &self.vkcontext.device,
+                context.command_buffer,
+                Vec2UI::default(),
+                context.render_area.unwrap(),
+            );
+
+            pass.shader.bind(context.command_buffer);
+            pass.shader.bind_descriptor_sets(context.command_buffer, 0, slice::from_ref(&descriptor_set));
+            push_constants(pass.shader, context.command_buffer);
+
+            unsafe { self.vkcontext.device.cmd_draw(context.command_buffer, 3, 1, 0, 0); }
+        }));
+    }
+}
+
+impl<'ctx> Drop for PostProcessStack<'ctx> {
+    fn drop(&mut self) {
+        unsafe { self.vkcontext.device.destroy_sampler(self.sampler, None); }
+    }
+}
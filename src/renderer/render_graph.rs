@@ -0,0 +1,1179 @@
+use std::{cmp::Reverse, fmt::Write};
+
+use ash::vk;
+
+use crate::math::vec2::Vec2UI;
+
+use super::{command_buffer::CommandBuffer, utility, vkcontext::VkContext};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphImage(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphBuffer(usize);
+
+#[derive(Clone, Copy)]
+pub struct TransientImageDescription {
+    pub size: Vec2UI,
+    pub format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+/// An image owned outside the graph, such as a swapchain image. Supplied anew every time the graph is executed.
+#[derive(Clone, Copy)]
+pub struct ImportedImage {
+    pub handle: vk::Image,
+    pub view: vk::ImageView,
+    pub size: Vec2UI,
+}
+
+#[derive(Clone, Copy)]
+pub enum AttachmentLoad {
+    Load,
+    Clear(vk::ClearValue),
+    DontCare,
+}
+
+impl AttachmentLoad {
+    fn as_vk_load_op(&self) -> vk::AttachmentLoadOp {
+        match self {
+            Self::Load => vk::AttachmentLoadOp::LOAD,
+            Self::Clear(_) => vk::AttachmentLoadOp::CLEAR,
+            Self::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+
+    fn reads_previous_contents(&self) -> bool {
+        matches!(self, Self::Load)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageAccess {
+    ColorAttachment,
+    ResolveAttachment,
+    DepthStencilAttachment,
+    DepthStencilReadOnly,
+    InputAttachment,
+    Sampled { stage: vk::PipelineStageFlags2 },
+    StorageRead { stage: vk::PipelineStageFlags2 },
+    StorageWrite { stage: vk::PipelineStageFlags2 },
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageAccess {
+    fn layout(&self) -> vk::ImageLayout {
+        match self {
+            Self::ColorAttachment | Self::ResolveAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Self::DepthStencilAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Self::DepthStencilReadOnly => vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            Self::InputAttachment | Self::Sampled { .. } => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Self::StorageRead { .. } | Self::StorageWrite { .. } => vk::ImageLayout::GENERAL,
+            Self::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            Self::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        }
+    }
+
+    fn stage_mask(&self) -> vk::PipelineStageFlags2 {
+        match *self {
+            Self::ColorAttachment | Self::ResolveAttachment => vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            Self::DepthStencilAttachment | Self::DepthStencilReadOnly => {
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+            },
+            Self::InputAttachment => vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            Self::Sampled { stage } | Self::StorageRead { stage } | Self::StorageWrite { stage } => stage,
+            Self::TransferSrc | Self::TransferDst => vk::PipelineStageFlags2::TRANSFER,
+        }
+    }
+
+    fn access_mask(&self) -> vk::AccessFlags2 {
+        match self {
+            Self::ColorAttachment => {
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            },
+            Self::ResolveAttachment => vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            Self::DepthStencilAttachment => {
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+            },
+            Self::DepthStencilReadOnly => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            Self::InputAttachment => vk::AccessFlags2::INPUT_ATTACHMENT_READ,
+            Self::Sampled { .. } => vk::AccessFlags2::SHADER_SAMPLED_READ,
+            Self::StorageRead { .. } => vk::AccessFlags2::SHADER_STORAGE_READ,
+            Self::StorageWrite { .. } => vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            Self::TransferSrc => vk::AccessFlags2::TRANSFER_READ,
+            Self::TransferDst => vk::AccessFlags2::TRANSFER_WRITE,
+        }
+    }
+
+    fn usage_flags(&self) -> vk::ImageUsageFlags {
+        match self {
+            Self::ColorAttachment | Self::ResolveAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Self::DepthStencilAttachment | Self::DepthStencilReadOnly => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            Self::InputAttachment => vk::ImageUsageFlags::INPUT_ATTACHMENT,
+            Self::Sampled { .. } => vk::ImageUsageFlags::SAMPLED,
+            Self::StorageRead { .. } | Self::StorageWrite { .. } => vk::ImageUsageFlags::STORAGE,
+            Self::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+
+    fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Self::ColorAttachment
+                | Self::ResolveAttachment
+                | Self::DepthStencilAttachment
+                | Self::StorageWrite { .. }
+                | Self::TransferDst
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferAccess {
+    VertexRead,
+    IndexRead,
+    IndirectRead,
+    UniformRead { stage: vk::PipelineStageFlags2 },
+    StorageRead { stage: vk::PipelineStageFlags2 },
+    StorageWrite { stage: vk::PipelineStageFlags2 },
+    TransferSrc,
+    TransferDst,
+}
+
+impl BufferAccess {
+    fn stage_mask(&self) -> vk::PipelineStageFlags2 {
+        match *self {
+            Self::VertexRead => vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+            Self::IndexRead => vk::PipelineStageFlags2::INDEX_INPUT,
+            Self::IndirectRead => vk::PipelineStageFlags2::DRAW_INDIRECT,
+            Self::UniformRead { stage } | Self::StorageRead { stage } | Self::StorageWrite { stage } => stage,
+            Self::TransferSrc | Self::TransferDst => vk::PipelineStageFlags2::TRANSFER,
+        }
+    }
+
+    fn access_mask(&self) -> vk::AccessFlags2 {
+        match self {
+            Self::VertexRead => vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+            Self::IndexRead => vk::AccessFlags2::INDEX_READ,
+            Self::IndirectRead => vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            Self::UniformRead { .. } => vk::AccessFlags2::UNIFORM_READ,
+            Self::StorageRead { .. } => vk::AccessFlags2::SHADER_STORAGE_READ,
+            Self::StorageWrite { .. } => vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            Self::TransferSrc => vk::AccessFlags2::TRANSFER_READ,
+            Self::TransferDst => vk::AccessFlags2::TRANSFER_WRITE,
+        }
+    }
+
+    fn is_write(&self) -> bool {
+        matches!(self, Self::StorageWrite { .. } | Self::TransferDst)
+    }
+}
+
+type PassCallback<'ctx> = Box<dyn Fn(&RenderGraphPassContext) + 'ctx>;
+
+struct ImageUse {
+    image: GraphImage,
+    access: ImageAccess,
+    load: Option<AttachmentLoad>,
+}
+
+/// A pass declares every image and buffer it touches. Colour and depth attachments are bound with dynamic rendering
+/// before the pass callback runs, in the order they were declared.
+pub struct RenderGraphPass<'ctx> {
+    name: String,
+    image_uses: Vec<ImageUse>,
+    resolves: Vec<(GraphImage, GraphImage)>,
+    buffer_uses: Vec<(GraphBuffer, BufferAccess)>,
+    has_side_effects: bool,
+    callback: Option<PassCallback<'ctx>>,
+}
+
+impl<'ctx> RenderGraphPass<'ctx> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            image_uses: Vec::new(),
+            resolves: Vec::new(),
+            buffer_uses: Vec::new(),
+            has_side_effects: false,
+            callback: None,
+        }
+    }
+
+    pub fn color_attachment(mut self, image: GraphImage, load: AttachmentLoad) -> Self {
+        self.image_uses.push(ImageUse { image, access: ImageAccess::ColorAttachment, load: Some(load) });
+        self
+    }
+
+    pub fn depth_attachment(mut self, image: GraphImage, load: AttachmentLoad) -> Self {
+        self.image_uses.push(ImageUse { image, access: ImageAccess::DepthStencilAttachment, load: Some(load) });
+        self
+    }
+
+    pub fn read_only_depth_attachment(mut self, image: GraphImage) -> Self {
+        self.image_uses.push(ImageUse {
+            image,
+            access: ImageAccess::DepthStencilReadOnly,
+            load: Some(AttachmentLoad::Load),
+        });
+        self
+    }
+
+    /// Declares an attachment of a `vk::RenderPass` that the pass callback begins itself, instead of having the graph
+    /// bind it with dynamic rendering. The graph puts the image in `access`'s layout before the pass, so the render pass
+    /// must use that layout as both the initial and final layout of the attachment.
+    pub fn render_pass_attachment(mut self, image: GraphImage, access: ImageAccess) -> Self {
+        assert!(
+            matches!(access, ImageAccess::ColorAttachment | ImageAccess::DepthStencilAttachment | ImageAccess::DepthStencilReadOnly),
+            "Render pass attachments must be colour or depth attachments."
+        );
+
+        self.image_uses.push(ImageUse { image, access, load: None });
+        self
+    }
+
+    /// Resolves the multisampled colour attachment `source` into the single-sampled `target` at the end of the pass by
+    /// averaging its samples, which rules out integer formats. `target` is overwritten entirely.
+    pub fn resolve_attachment(mut self, source: GraphImage, target: GraphImage) -> Self {
+        assert!(
+            self.image_uses.iter().any(|image_use| image_use.image == source && image_use.access == ImageAccess::ColorAttachment),
+            "Resolve source must be declared as a colour attachment of the pass first."
+        );
+
+        self.image_uses.push(ImageUse { image: target, access: ImageAccess::ResolveAttachment, load: Some(AttachmentLoad::DontCare) });
+        self.resolves.push((source, target));
+        self
+    }
+
+    pub fn image(mut self, image: GraphImage, access: ImageAccess) -> Self {
+        assert!(
+            !matches!(
+                access,
+                ImageAccess::ColorAttachment
+                    | ImageAccess::ResolveAttachment
+                    | ImageAccess::DepthStencilAttachment
+                    | ImageAccess::DepthStencilReadOnly
+            ),
+            "Attachments must be declared with color_attachment, resolve_attachment or depth_attachment."
+        );
+
+        self.image_uses.push(ImageUse { image, access, load: None });
+        self
+    }
+
+    pub fn buffer(mut self, buffer: GraphBuffer, access: BufferAccess) -> Self {
+        self.buffer_uses.push((buffer, access));
+        self
+    }
+
+    /// Keeps the pass even if nothing in the graph consumes what it writes.
+    pub fn side_effects(mut self) -> Self {
+        self.has_side_effects = true;
+        self
+    }
+
+    pub fn execute<F: Fn(&RenderGraphPassContext) + 'ctx>(mut self, callback: F) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    fn is_raster(&self) -> bool {
+        self.image_uses.iter().any(|image_use| image_use.load.is_some())
+    }
+}
+
+/// Handed to a pass callback while it records.
+pub struct RenderGraphPassContext<'a> {
+    pub command_buffer: vk::CommandBuffer,
+    pub render_area: Option<Vec2UI>,
+    images: &'a [(vk::Image, vk::ImageView)],
+    buffers: &'a [vk::Buffer],
+}
+
+impl<'a> RenderGraphPassContext<'a> {
+    pub fn image(&self, image: GraphImage) -> vk::Image {
+        self.images[image.0].0
+    }
+
+    pub fn image_view(&self, image: GraphImage) -> vk::ImageView {
+        self.images[image.0].1
+    }
+
+    pub fn buffer(&self, buffer: GraphBuffer) -> vk::Buffer {
+        self.buffers[buffer.0]
+    }
+}
+
+enum GraphImageKind {
+    Transient(TransientImageDescription),
+    Imported {
+        format: vk::Format,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    },
+}
+
+struct GraphImageInfo {
+    name: String,
+    kind: GraphImageKind,
+    is_output: bool,
+}
+
+impl GraphImageInfo {
+    fn format(&self) -> vk::Format {
+        match self.kind {
+            GraphImageKind::Transient(description) => description.format,
+            GraphImageKind::Imported { format, .. } => format,
+        }
+    }
+
+    fn is_imported(&self) -> bool {
+        matches!(self.kind, GraphImageKind::Imported { .. })
+    }
+}
+
+struct GraphBufferInfo {
+    name: String,
+    is_output: bool,
+}
+
+struct TransientImage {
+    handle: vk::Image,
+    view: vk::ImageView,
+    memory_block: usize,
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type: u32,
+    size: vk::DeviceSize,
+    lifetimes: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Copy)]
+struct ImageBarrier {
+    image: GraphImage,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+#[derive(Clone, Copy)]
+struct BufferBarrier {
+    buffer: GraphBuffer,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+#[derive(Default)]
+struct BarrierBatch {
+    images: Vec<ImageBarrier>,
+    buffers: Vec<BufferBarrier>,
+}
+
+impl BarrierBatch {
+    fn len(&self) -> usize {
+        self.images.len() + self.buffers.len()
+    }
+}
+
+/// Synchronization state of one resource while the graph is being compiled. Reads are tracked since the last write
+/// so that a later write waits for all of them.
+#[derive(Clone, Copy)]
+struct ResourceState {
+    layout: vk::ImageLayout,
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    read_stage: vk::PipelineStageFlags2,
+}
+
+impl ResourceState {
+    fn new(layout: vk::ImageLayout, is_externally_written: bool) -> Self {
+        let (write_stage, write_access) = if is_externally_written {
+            (vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_WRITE)
+        } else {
+            (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE)
+        };
+
+        Self {
+            layout,
+            write_stage,
+            write_access,
+            read_stage: vk::PipelineStageFlags2::NONE,
+        }
+    }
+
+    /// Returns the `(src_stage, src_access)` the access has to wait on, or `None` if no barrier is needed.
+    fn transition(
+        &mut self,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+        is_write: bool,
+    ) -> Option<(vk::PipelineStageFlags2, vk::AccessFlags2)> {
+        if !is_write && layout == self.layout {
+            if self.write_stage.is_empty() || self.read_stage.contains(stage) {
+                self.read_stage |= stage;
+                return None;
+            }
+
+            self.read_stage |= stage;
+            return Some((self.write_stage, self.write_access));
+        }
+
+        let src = (self.write_stage | self.read_stage, self.write_access);
+
+        self.layout = layout;
+
+        if is_write {
+            self.write_stage = stage;
+            self.write_access = access;
+            self.read_stage = vk::PipelineStageFlags2::NONE;
+        } else {
+            // The layout transition itself is the write subsequent readers have to wait on.
+            self.write_stage = stage;
+            self.write_access = vk::AccessFlags2::NONE;
+            self.read_stage = stage;
+        }
+
+        Some(src)
+    }
+}
+
+struct CompiledGraph {
+    live_passes: Vec<bool>,
+    pre_pass_barriers: Vec<BarrierBatch>,
+    final_barriers: BarrierBatch,
+    store_ops: Vec<Vec<vk::AttachmentStoreOp>>,
+    transient_images: Vec<Option<TransientImage>>,
+    memory_blocks: Vec<MemoryBlock>,
+}
+
+/// A frame graph. Passes and resources are declared up front, `compile` culls passes whose results are never used,
+/// allocates transient images (aliasing memory between images whose lifetimes don't overlap) and works out every
+/// layout transition and barrier, and `execute` records the whole graph into a command buffer.
+pub struct RenderGraph<'ctx> {
+    images: Vec<GraphImageInfo>,
+    buffers: Vec<GraphBufferInfo>,
+    passes: Vec<RenderGraphPass<'ctx>>,
+    compiled: Option<CompiledGraph>,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> RenderGraph<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext) -> Self {
+        Self {
+            images: Vec::new(),
+            buffers: Vec::new(),
+            passes: Vec::new(),
+            compiled: None,
+            vkcontext,
+        }
+    }
+}
+
+impl<'ctx> RenderGraph<'ctx> {
+    pub fn create_image(&mut self, name: &str, description: TransientImageDescription) -> GraphImage {
+        self.invalidate();
+
+        self.images.push(GraphImageInfo {
+            name: name.to_string(),
+            kind: GraphImageKind::Transient(description),
+            is_output: false,
+        });
+
+        GraphImage(self.images.len() - 1)
+    }
+
+    /// Imports an externally owned image. It is expected in `initial_layout` when the graph starts and is left in
+    /// `final_layout` when it ends. Anything written to an imported image counts as an output of the graph.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    ) -> GraphImage {
+        self.invalidate();
+
+        self.images.push(GraphImageInfo {
+            name: name.to_string(),
+            kind: GraphImageKind::Imported { format, initial_layout, final_layout },
+            is_output: true,
+        });
+
+        GraphImage(self.images.len() - 1)
+    }
+
+    /// Imports an externally owned buffer. Unlike imported images, writes to it only keep their passes alive if a
+    /// later pass reads it or it is marked with `mark_buffer_output`.
+    pub fn import_buffer(&mut self, name: &str) -> GraphBuffer {
+        self.invalidate();
+
+        self.buffers.push(GraphBufferInfo {
+            name: name.to_string(),
+            is_output: false,
+        });
+
+        GraphBuffer(self.buffers.len() - 1)
+    }
+
+    /// Keeps the passes producing `image` alive even though nothing in the graph reads it.
+    pub fn mark_output(&mut self, image: GraphImage) {
+        self.invalidate();
+
+        self.images[image.0].is_output = true;
+    }
+
+    /// Keeps the passes writing `buffer` alive even though nothing in the graph reads it.
+    pub fn mark_buffer_output(&mut self, buffer: GraphBuffer) {
+        self.invalidate();
+
+        self.buffers[buffer.0].is_output = true;
+    }
+
+    pub fn add_pass(&mut self, pass: RenderGraphPass<'ctx>) {
+        self.invalidate();
+
+        self.passes.push(pass);
+    }
+
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.is_some()
+    }
+
+    fn invalidate(&mut self) {
+        if let Some(compiled) = self.compiled.take() {
+            self.destroy_compiled(compiled);
+        }
+    }
+
+    fn destroy_compiled(&self, compiled: CompiledGraph) {
+        let device = &self.vkcontext.device;
+
+        unsafe {
+            for transient in compiled.transient_images.into_iter().flatten() {
+                device.destroy_image_view(transient.view, None);
+                device.destroy_image(transient.handle, None);
+            }
+
+            for block in compiled.memory_blocks {
+                device.free_memory(block.memory, None);
+            }
+        }
+    }
+}
+
+impl<'ctx> RenderGraph<'ctx> {
+    pub fn compile(&mut self) {
+        self.invalidate();
+
+        let live_passes = self.cull_passes();
+
+        if self.passes.iter().zip(live_passes.iter()).any(|(pass, live)| *live && pass.is_raster()) {
+            assert!(
+                self.vkcontext.is_dynamic_rendering_enabled(),
+                "RenderGraph raster passes require dynamic rendering."
+            );
+        }
+
+        let (transient_images, memory_blocks) = self.allocate_transient_images(&live_passes);
+        let store_ops = self.compute_store_ops(&live_passes);
+        let (pre_pass_barriers, final_barriers) = self.compute_barriers(&live_passes);
+
+        let culled = live_passes.iter().filter(|live| !**live).count();
+        if culled > 0 {
+            log::debug!("Render graph culled {} of {} passes.", culled, self.passes.len());
+        }
+
+        self.compiled = Some(CompiledGraph {
+            live_passes,
+            pre_pass_barriers,
+            final_barriers,
+            store_ops,
+            transient_images,
+            memory_blocks,
+        });
+    }
+
+    /// Walks the passes backwards from the graph outputs. A write that does not read the previous contents ends the
+    /// dependency on earlier writers of the same image. Buffer writes may be partial, so once a buffer is read every
+    /// earlier writer stays needed.
+    fn cull_passes(&self) -> Vec<bool> {
+        let mut needed_images = self.images.iter().map(|image| image.is_output).collect::<Vec<_>>();
+        let mut needed_buffers = self.buffers.iter().map(|buffer| buffer.is_output).collect::<Vec<_>>();
+        let mut live_passes = vec![false; self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            let writes_needed_image = pass.image_uses.iter()
+                .any(|image_use| image_use.access.is_write() && needed_images[image_use.image.0]);
+            let writes_needed_buffer = pass.buffer_uses.iter()
+                .any(|(buffer, access)| access.is_write() && needed_buffers[buffer.0]);
+
+            if !(pass.has_side_effects || writes_needed_image || writes_needed_buffer) {
+                continue;
+            }
+
+            live_passes[index] = true;
+
+            for image_use in pass.image_uses.iter() {
+                let overwrites = image_use.access.is_write()
+                    && image_use.load.is_some_and(|load| !load.reads_previous_contents())
+                    && !self.images[image_use.image.0].is_imported();
+
+                if overwrites {
+                    needed_images[image_use.image.0] = self.images[image_use.image.0].is_output;
+                }
+            }
+
+            for image_use in pass.image_uses.iter() {
+                let reads = !image_use.access.is_write()
+                    || image_use.load.is_none_or(|load| load.reads_previous_contents());
+
+                if reads {
+                    needed_images[image_use.image.0] = true;
+                }
+            }
+
+            for (buffer, access) in pass.buffer_uses.iter() {
+                if !access.is_write() {
+                    needed_buffers[buffer.0] = true;
+                }
+            }
+        }
+
+        live_passes
+    }
+
+    fn image_lifetimes(&self, live_passes: &[bool]) -> Vec<Option<(usize, usize, vk::ImageUsageFlags)>> {
+        let mut lifetimes: Vec<Option<(usize, usize, vk::ImageUsageFlags)>> = vec![None; self.images.len()];
+
+        for (index, pass) in self.passes.iter().enumerate().filter(|(index, _)| live_passes[*index]) {
+            for image_use in pass.image_uses.iter() {
+                let usage = image_use.access.usage_flags();
+
+                lifetimes[image_use.image.0] = Some(match lifetimes[image_use.image.0] {
+                    Some((first, _, usage_flags)) => (first, index, usage_flags | usage),
+                    None => (index, index, usage),
+                });
+            }
+        }
+
+        lifetimes
+    }
+
+    fn allocate_transient_images(&self, live_passes: &[bool]) -> (Vec<Option<TransientImage>>, Vec<MemoryBlock>) {
+        let device = &self.vkcontext.device;
+        let lifetimes = self.image_lifetimes(live_passes);
+
+        // Create the images first so their memory requirements are known.
+        let mut candidates = Vec::new();
+
+        for (index, image) in self.images.iter().enumerate() {
+            let (GraphImageKind::Transient(description), Some((first, last, usage))) = (&image.kind, lifetimes[index]) else {
+                continue;
+            };
+
+            let create_info = vk::ImageCreateInfo::default()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(description.format)
+                .extent(description.size.as_vk_extent_3d(1))
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(description.samples)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+
+            let handle = unsafe { device.create_image(&create_info, None).unwrap() };
+            let requirements = unsafe { device.get_image_memory_requirements(handle) };
+
+            let memory_type = utility::query_memory_type(
+                self.vkcontext.physical_device_memory_properties,
+                requirements,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )
+            .unwrap();
+
+            candidates.push((index, handle, requirements, memory_type, first, last));
+        }
+
+        // Largest first, so every block is created at the size of the largest image it will ever hold.
+        candidates.sort_by_key(|candidate| Reverse(candidate.2.size));
+
+        let mut memory_blocks: Vec<MemoryBlock> = Vec::new();
+        let mut transient_images = (0..self.images.len()).map(|_| None).collect::<Vec<Option<TransientImage>>>();
+
+        for (index, handle, requirements, memory_type, first, last) in candidates {
+            let block_index = memory_blocks.iter().position(|block| {
+                block.memory_type == memory_type
+                    && block.size >= requirements.size
+                    && block.lifetimes.iter().all(|&(block_first, block_last)| last < block_first || first > block_last)
+            });
+
+            let block_index = match block_index {
+                Some(block_index) => block_index,
+                None => {
+                    memory_blocks.push(MemoryBlock {
+                        memory: vk::DeviceMemory::null(),
+                        memory_type,
+                        size: requirements.size,
+                        lifetimes: Vec::new(),
+                    });
+
+                    memory_blocks.len() - 1
+                },
+            };
+
+            memory_blocks[block_index].lifetimes.push((first, last));
+
+            transient_images[index] = Some(TransientImage {
+                handle,
+                view: vk::ImageView::null(),
+                memory_block: block_index,
+            });
+        }
+
+        for block in memory_blocks.iter_mut() {
+            let allocate_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(block.size)
+                .memory_type_index(block.memory_type);
+
+            block.memory = unsafe { device.allocate_memory(&allocate_info, None).unwrap() };
+        }
+
+        for (index, transient) in transient_images.iter_mut().enumerate() {
+            let Some(transient) = transient else { continue; };
+
+            let format = self.images[index].format();
+
+            unsafe {
+                device.bind_image_memory(transient.handle, memory_blocks[transient.memory_block].memory, 0).unwrap();
+            }
+
+            transient.view = utility::create_image_view(device, transient.handle, format, format_aspect_mask(format), 1);
+        }
+
+        let aliased = transient_images.iter().flatten().count() - memory_blocks.len();
+        if aliased > 0 {
+            log::debug!("Render graph aliased {} transient images into {} memory blocks.", aliased, memory_blocks.len());
+        }
+
+        (transient_images, memory_blocks)
+    }
+
+    /// Attachments are only stored if a later pass or the outside world will look at them.
+    fn compute_store_ops(&self, live_passes: &[bool]) -> Vec<Vec<vk::AttachmentStoreOp>> {
+        self.passes.iter().enumerate().map(|(index, pass)| {
+            pass.image_uses.iter().filter(|image_use| image_use.load.is_some()).map(|image_use| {
+                let image = &self.images[image_use.image.0];
+
+                let is_used_later = self.passes.iter()
+                    .enumerate()
+                    .skip(index + 1)
+                    .filter(|(later, _)| live_passes[*later])
+                    .any(|(_, later)| later.image_uses.iter().any(|later_use| later_use.image == image_use.image));
+
+                if image.is_output || is_used_later {
+                    vk::AttachmentStoreOp::STORE
+                } else {
+                    vk::AttachmentStoreOp::DONT_CARE
+                }
+            })
+            .collect()
+        })
+        .collect()
+    }
+
+    fn compute_barriers(&self, live_passes: &[bool]) -> (Vec<BarrierBatch>, BarrierBatch) {
+        // Everything starts out as if written by earlier commands on the queue. For transient images that covers both
+        // the previous frame using the same image and other images aliasing its memory.
+        let mut image_states = self.images.iter().map(|image| {
+            match image.kind {
+                GraphImageKind::Imported { initial_layout, .. } => ResourceState::new(initial_layout, true),
+                GraphImageKind::Transient(_) => ResourceState::new(vk::ImageLayout::UNDEFINED, true),
+            }
+        })
+        .collect::<Vec<_>>();
+
+        let mut buffer_states = vec![ResourceState::new(vk::ImageLayout::UNDEFINED, true); self.buffers.len()];
+
+        let pre_pass_barriers = self.passes.iter().enumerate().map(|(index, pass)| {
+            let mut batch = BarrierBatch::default();
+
+            if !live_passes[index] {
+                return batch;
+            }
+
+            for image_use in pass.image_uses.iter() {
+                let state = &mut image_states[image_use.image.0];
+                let old_layout = state.layout;
+                let new_layout = image_use.access.layout();
+                let dst_stage = image_use.access.stage_mask();
+                let dst_access = image_use.access.access_mask();
+
+                if let Some((src_stage, src_access)) = state.transition(new_layout, dst_stage, dst_access, image_use.access.is_write()) {
+                    batch.images.push(ImageBarrier {
+                        image: image_use.image,
+                        old_layout,
+                        new_layout,
+                        src_stage,
+                        src_access,
+                        dst_stage,
+                        dst_access,
+                    });
+                }
+            }
+
+            for &(buffer, access) in pass.buffer_uses.iter() {
+                let state = &mut buffer_states[buffer.0];
+                let dst_stage = access.stage_mask();
+                let dst_access = access.access_mask();
+
+                if let Some((src_stage, src_access)) = state.transition(state.layout, dst_stage, dst_access, access.is_write()) {
+                    batch.buffers.push(BufferBarrier { buffer, src_stage, src_access, dst_stage, dst_access });
+                }
+            }
+
+            batch
+        })
+        .collect::<Vec<_>>();
+
+        let mut final_barriers = BarrierBatch::default();
+
+        for (index, image) in self.images.iter().enumerate() {
+            let GraphImageKind::Imported { final_layout, .. } = image.kind else { continue; };
+
+            let state = &image_states[index];
+
+            if state.layout != final_layout || !state.write_access.is_empty() {
+                final_barriers.images.push(ImageBarrier {
+                    image: GraphImage(index),
+                    old_layout: state.layout,
+                    new_layout: final_layout,
+                    src_stage: state.write_stage | state.read_stage,
+                    src_access: state.write_access,
+                    dst_stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    dst_access: vk::AccessFlags2::NONE,
+                });
+            }
+        }
+
+        (pre_pass_barriers, final_barriers)
+    }
+}
+
+impl<'ctx> RenderGraph<'ctx> {
+    /// Records every live pass into `command_buffer`. `imported_images` and `imported_buffers` supply the handles for
+    /// everything imported into the graph, for this frame only.
+    pub fn execute(
+        &self,
+        command_buffer: &CommandBuffer,
+        imported_images: &[(GraphImage, ImportedImage)],
+        imported_buffers: &[(GraphBuffer, vk::Buffer)],
+    ) {
+        let compiled = self.compiled.as_ref().expect("RenderGraph must be compiled before it is executed.");
+        let device = &self.vkcontext.device;
+
+        let mut images = vec![(vk::Image::null(), vk::ImageView::null()); self.images.len()];
+        let mut image_sizes = vec![Vec2UI::default(); self.images.len()];
+
+        for (index, image) in self.images.iter().enumerate() {
+            if let (GraphImageKind::Transient(description), Some(transient)) = (&image.kind, &compiled.transient_images[index]) {
+                images[index] = (transient.handle, transient.view);
+                image_sizes[index] = description.size;
+            }
+        }
+
+        for (image, imported) in imported_images {
+            images[image.0] = (imported.handle, imported.view);
+            image_sizes[image.0] = imported.size;
+        }
+
+        let mut buffers = vec![vk::Buffer::null(); self.buffers.len()];
+
+        for (buffer, handle) in imported_buffers {
+            buffers[buffer.0] = *handle;
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            if !compiled.live_passes[index] {
+                continue;
+            }
+
+            for image_use in pass.image_uses.iter() {
+                assert!(
+                    images[image_use.image.0].0 != vk::Image::null(),
+                    "Render graph image \"{}\" was not supplied.",
+                    self.images[image_use.image.0].name
+                );
+            }
+
+            self.record_barriers(command_buffer.handle, &compiled.pre_pass_barriers[index], &images, &buffers);
+
+            let render_area = if pass.is_raster() {
+                let render_area = self.begin_rendering(
+                    command_buffer.handle,
+                    pass,
+                    &compiled.store_ops[index],
+                    &images,
+                    &image_sizes,
+                );
+
+                Some(render_area)
+            } else {
+                None
+            };
+
+            if let Some(callback) = &pass.callback {
+                callback(&RenderGraphPassContext {
+                    command_buffer: command_buffer.handle,
+                    render_area,
+                    images: &images,
+                    buffers: &buffers,
+                });
+            }
+
+            if render_area.is_some() {
+                unsafe { device.cmd_end_rendering(command_buffer.handle); }
+            }
+        }
+
+        self.record_barriers(command_buffer.handle, &compiled.final_barriers, &images, &buffers);
+    }
+
+    fn begin_rendering(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pass: &RenderGraphPass,
+        store_ops: &[vk::AttachmentStoreOp],
+        images: &[(vk::Image, vk::ImageView)],
+        image_sizes: &[Vec2UI],
+    ) -> Vec2UI {
+        let mut color_attachments = Vec::new();
+        let mut depth_attachment = None;
+        let mut stencil_attachment = None;
+        let mut render_area = Vec2UI { x: u32::MAX, y: u32::MAX };
+
+        let attachments = pass.image_uses.iter().filter_map(|image_use| image_use.load.map(|load| (image_use, load)));
+
+        for ((image_use, load), store_op) in attachments.zip(store_ops.iter()) {
+            let size = image_sizes[image_use.image.0];
+            render_area = Vec2UI { x: render_area.x.min(size.x), y: render_area.y.min(size.y) };
+
+            let mut info = vk::RenderingAttachmentInfo::default()
+                .image_view(images[image_use.image.0].1)
+                .image_layout(image_use.access.layout())
+                .load_op(load.as_vk_load_op())
+                .store_op(*store_op);
+
+            if let AttachmentLoad::Clear(clear_value) = load {
+                info = info.clear_value(clear_value);
+            }
+
+            if image_use.access == ImageAccess::ResolveAttachment {
+                continue;
+            }
+
+            if image_use.access == ImageAccess::ColorAttachment {
+                if let Some((_, target)) = pass.resolves.iter().find(|(source, _)| *source == image_use.image) {
+                    info = info
+                        .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                        .resolve_image_view(images[target.0].1)
+                        .resolve_image_layout(ImageAccess::ResolveAttachment.layout());
+                }
+
+                color_attachments.push(info);
+            } else {
+                let format = self.images[image_use.image.0].format();
+
+                if format_aspect_mask(format).contains(vk::ImageAspectFlags::STENCIL) {
+                    stencil_attachment = Some(info);
+                }
+
+                depth_attachment = Some(info);
+            }
+        }
+
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D::default(),
+                extent: render_area.as_vk_extent_2d(),
+            })
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        if let Some(depth_attachment) = depth_attachment.as_ref() {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+
+        if let Some(stencil_attachment) = stencil_attachment.as_ref() {
+            rendering_info = rendering_info.stencil_attachment(stencil_attachment);
+        }
+
+        unsafe { self.vkcontext.device.cmd_begin_rendering(command_buffer, &rendering_info); }
+
+        render_area
+    }
+
+    fn record_barriers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        batch: &BarrierBatch,
+        images: &[(vk::Image, vk::ImageView)],
+        buffers: &[vk::Buffer],
+    ) {
+        if batch.len() == 0 {
+            return;
+        }
+
+        let image_barriers = batch.images.iter().map(|barrier| {
+            let format = self.images[barrier.image.0].format();
+
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(barrier.src_stage)
+                .src_access_mask(barrier.src_access)
+                .dst_stage_mask(barrier.dst_stage)
+                .dst_access_mask(barrier.dst_access)
+                .old_layout(barrier.old_layout)
+                .new_layout(barrier.new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(images[barrier.image.0].0)
+                .subresource_range(vk::ImageSubresourceRange::default()
+                    .aspect_mask(format_aspect_mask(format))
+                    .base_mip_level(0)
+                    .level_count(vk::REMAINING_MIP_LEVELS)
+                    .base_array_layer(0)
+                    .layer_count(vk::REMAINING_ARRAY_LAYERS)
+                )
+        })
+        .collect::<Vec<_>>();
+
+        let buffer_barriers = batch.buffers.iter().map(|barrier| {
+            vk::BufferMemoryBarrier2::default()
+                .src_stage_mask(barrier.src_stage)
+                .src_access_mask(barrier.src_access)
+                .dst_stage_mask(barrier.dst_stage)
+                .dst_access_mask(barrier.dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffers[barrier.buffer.0])
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+        })
+        .collect::<Vec<_>>();
+
+        let dependency_info = vk::DependencyInfo::default()
+            .image_memory_barriers(&image_barriers)
+            .buffer_memory_barriers(&buffer_barriers);
+
+        unsafe { self.vkcontext.device.cmd_pipeline_barrier2(command_buffer, &dependency_info); }
+    }
+}
+
+impl<'ctx> RenderGraph<'ctx> {
+    /// Renders the graph in Graphviz dot format. Culled passes are dashed, and transient images are labelled with the
+    /// memory block they alias into once the graph is compiled.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph RenderGraph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+
+        for (index, image) in self.images.iter().enumerate() {
+            let mut label = format!("{}\\n{:?}", image.name, image.format());
+
+            match &image.kind {
+                GraphImageKind::Transient(description) => {
+                    write!(label, "\\n{}x{}", description.size.x, description.size.y).unwrap();
+
+                    if description.samples != vk::SampleCountFlags::TYPE_1 {
+                        write!(label, " {}x MSAA", description.samples.as_raw()).unwrap();
+                    }
+
+                    if let Some(transient) = self.compiled.as_ref().and_then(|compiled| compiled.transient_images[index].as_ref()) {
+                        write!(label, "\\nmemory block {}", transient.memory_block).unwrap();
+                    }
+                },
+                GraphImageKind::Imported { .. } => label.push_str("\\nimported"),
+            }
+
+            let style = if image.is_imported() { "bold" } else { "solid" };
+
+            writeln!(dot, "    image{} [shape=ellipse, style={}, label=\"{}\"];", index, style, label).unwrap();
+        }
+
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            writeln!(dot, "    buffer{} [shape=ellipse, style=bold, label=\"{}\\nimported buffer\"];", index, buffer.name).unwrap();
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let (is_live, barrier_count) = match &self.compiled {
+                Some(compiled) => (compiled.live_passes[index], compiled.pre_pass_barriers[index].len()),
+                None => (true, 0),
+            };
+
+            let style = if is_live { "style=filled, fillcolor=lightblue" } else { "style=dashed, color=gray" };
+
+            writeln!(
+                dot,
+                "    pass{} [shape=box, {}, label=\"{}\\n{} barriers\"];",
+                index,
+                style,
+                pass.name,
+                barrier_count
+            )
+            .unwrap();
+
+            for image_use in pass.image_uses.iter() {
+                let label = format!("{:?}", image_use.access);
+
+                if image_use.access.is_write() {
+                    writeln!(dot, "    pass{} -> image{} [label=\"{}\"];", index, image_use.image.0, label).unwrap();
+                } else {
+                    writeln!(dot, "    image{} -> pass{} [label=\"{}\"];", image_use.image.0, index, label).unwrap();
+                }
+            }
+
+            for (buffer, access) in pass.buffer_uses.iter() {
+                let label = format!("{:?}", access);
+
+                if access.is_write() {
+                    writeln!(dot, "    pass{} -> buffer{} [label=\"{}\"];", index, buffer.0, label).unwrap();
+                } else {
+                    writeln!(dot, "    buffer{} -> pass{} [label=\"{}\"];", buffer.0, index, label).unwrap();
+                }
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+
+        dot
+    }
+}
+
+impl<'ctx> Drop for RenderGraph<'ctx> {
+    fn drop(&mut self) {
+        self.invalidate();
+    }
+}
+
+pub fn format_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        },
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
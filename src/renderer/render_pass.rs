@@ -2,13 +2,15 @@ use ash::vk;
 
 use crate::math::vec2::Vec2UI;
 
-use super::vkcontext::VkContext;
+use super::{image::Image, vkcontext::VkContext};
 
 pub struct RenderPass<'c> {
     pub handle: vk::RenderPass,
     pub render_area_start: Vec2UI,
     pub render_area_size: Vec2UI,
     pub attachment_clear_values: Vec<vk::ClearValue>,
+    /// The initial and final layout of each attachment.
+    attachment_layouts: Vec<(vk::ImageLayout, vk::ImageLayout)>,
     vkcontext: &'c VkContext,
 }
 
@@ -53,11 +55,14 @@ impl<'c> RenderPass<'c> {
         })
         .collect::<Vec<_>>();
 
+        let attachment_layouts = attachments.iter().map(|attachment| (attachment.initial_layout, attachment.final_layout)).collect();
+
         Self {
             handle,
             render_area_start,
             render_area_size,
             attachment_clear_values,
+            attachment_layouts,
             vkcontext,
         }
     }
@@ -77,6 +82,25 @@ impl<'c> RenderPass<'c> {
         unsafe { self.vkcontext.device.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE); }
     }
 
+    /// Begins the render pass on `attachments`, the images behind `frame_buffer` in attachment order. Debug builds
+    /// check that each image is in the initial layout the render pass declares, and the images are tracked as being in
+    /// their final layouts afterwards.
+    pub fn begin_tracked(&self, command_buffer: vk::CommandBuffer, frame_buffer: vk::Framebuffer, attachments: &[&Image]) {
+        debug_assert_eq!(attachments.len(), self.attachment_layouts.len(), "Render pass attachment count mismatch.");
+
+        for (index, (image, &(initial_layout, final_layout))) in attachments.iter().zip(self.attachment_layouts.iter()).enumerate() {
+            debug_assert!(
+                initial_layout == vk::ImageLayout::UNDEFINED || image.tracked_layout() == Some(initial_layout),
+                "Attachment {} of the render pass expects {:?}, but its image is in {:?}.",
+                index, initial_layout, image.tracked_layout()
+            );
+
+            image.mark_render_pass_layout(final_layout);
+        }
+
+        self.begin(command_buffer, frame_buffer);
+    }
+
     pub fn end(&self, command_buffer: vk::CommandBuffer) {
         unsafe { self.vkcontext.device.cmd_end_render_pass(command_buffer); }
     }
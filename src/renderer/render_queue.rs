@@ -0,0 +1,273 @@
+use ash::vk;
+
+use crate::math::{frustum::Frustum, mat4::Mat4, vec3::Vec3F};
+
+use super::{buffer::Buffer, material::Material, mesh::{InstanceData, Mesh, MeshInstance}, shader::Shader, vkcontext::VkContext, MAX_FRAMES_IN_FLIGHT};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum DrawPass {
+    Opaque = 0,
+    Transparent = 1,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RenderQueueStats {
+    pub submitted: usize,
+    pub culled: usize,
+    pub drawn: usize,
+    pub pipeline_binds: usize,
+    pub material_binds: usize,
+    pub mesh_binds: usize,
+    pub draw_calls: usize,
+}
+
+/// Layout of a draw's sort key, from the most significant bit down:
+///
+/// * Opaque: pass (2) | shader (10) | material (14) | mesh (14) | depth (24), so state changes are minimized, draws of
+///   the same mesh end up next to each other to be instanced, and otherwise go front-to-back.
+/// * Transparent: pass (2) | inverted depth (24) | shader (10) | material (14) | mesh (14), so blending happens
+///   back-to-front. Only consecutive draws at the same depth can be instanced.
+pub fn make_sort_key(pass: DrawPass, shader_id: u32, material_id: u32, mesh_id: u32, depth: f32) -> u64 {
+    const SHADER_BITS: u32 = 10;
+    const MATERIAL_BITS: u32 = 14;
+    const MESH_BITS: u32 = 14;
+    const DEPTH_BITS: u32 = 24;
+
+    let pass_bits = (pass as u64) << 62;
+    let shader = (shader_id as u64) & ((1 << SHADER_BITS) - 1);
+    let material = (material_id as u64) & ((1 << MATERIAL_BITS) - 1);
+    let mesh = (mesh_id as u64) & ((1 << MESH_BITS) - 1);
+    let state = (((shader << MATERIAL_BITS) | material) << MESH_BITS) | mesh;
+
+    // The bits of a non-negative float sort the same way as the float, so its top bits are a coarse depth.
+    let depth = (depth.max(0.0).to_bits() >> (32 - DEPTH_BITS)) as u64;
+
+    match pass {
+        DrawPass::Opaque => pass_bits | (state << DEPTH_BITS) | depth,
+        DrawPass::Transparent => pass_bits | ((!depth & ((1 << DEPTH_BITS) - 1)) << 38) | state,
+    }
+}
+
+/// A host-visible vertex buffer per frame in flight that render queues write instance data into. Every write goes
+/// after the previous ones of the same frame, so several queues can share the buffer. It grows as needed, keeping
+/// outgrown buffers alive until the frame comes around again, since commands recorded earlier still read them.
+pub struct InstanceBuffer<'ctx> {
+    frames: Vec<InstanceBufferFrame<'ctx>>,
+    vkcontext: &'ctx VkContext,
+}
+
+#[derive(Default)]
+struct InstanceBufferFrame<'ctx> {
+    buffer: Option<Buffer<'ctx>>,
+    offset: vk::DeviceSize,
+    retired_buffers: Vec<Buffer<'ctx>>,
+}
+
+impl<'ctx> InstanceBuffer<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext) -> Self {
+        Self {
+            frames: (0..MAX_FRAMES_IN_FLIGHT).map(|_| InstanceBufferFrame::default()).collect(),
+            vkcontext,
+        }
+    }
+
+    /// Starts writing the buffer of `frame_index` from the beginning again. Must be called once per frame, after the
+    /// renderer has waited for that frame's previous submission.
+    pub fn prepare(&mut self, frame_index: u32) {
+        let frame = &mut self.frames[frame_index as usize];
+
+        frame.offset = 0;
+        frame.retired_buffers.clear();
+    }
+
+    /// Writes `instances` after everything written to the buffer of `frame_index` so far this frame, and binds them as
+    /// vertex buffer `binding`.
+    pub fn write_and_bind(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: u32,
+        binding: u32,
+        instances: &[InstanceData],
+    ) {
+        let size = std::mem::size_of_val(instances).max(std::mem::size_of::<InstanceData>()) as u64;
+        let frame = &mut self.frames[frame_index as usize];
+
+        let capacity = frame.buffer.as_ref().map_or(0, |buffer| buffer.size);
+
+        if frame.offset + size > capacity {
+            let buffer = Buffer::new(
+                self.vkcontext,
+                (capacity * 2).max(size).next_power_of_two(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                true,
+            );
+
+            frame.retired_buffers.extend(frame.buffer.replace(buffer));
+            frame.offset = 0;
+        }
+
+        let offset = frame.offset;
+        let buffer = frame.buffer.as_mut().unwrap();
+
+        if !instances.is_empty() {
+            buffer.load_slice(offset, instances, vk::MemoryMapFlags::default());
+        }
+
+        unsafe { self.vkcontext.device.cmd_bind_vertex_buffers(command_buffer, binding, &[buffer.handle], &[offset]); }
+
+        frame.offset += size;
+    }
+}
+
+/// Collects draws for a frame, culls them against the camera and records them in an order that keeps pipeline and
+/// descriptor set changes to a minimum.
+pub struct RenderQueue<'a, 'ctx> {
+    items: Vec<MeshInstance<'a, 'ctx>>,
+    sorted: Vec<(u64, usize)>,
+    instances: Vec<InstanceData>,
+    pub stats: RenderQueueStats,
+}
+
+impl<'a, 'ctx> RenderQueue<'a, 'ctx> {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            sorted: Vec::new(),
+            instances: Vec::new(),
+            stats: RenderQueueStats::default(),
+        }
+    }
+}
+
+impl<'a, 'ctx> RenderQueue<'a, 'ctx> {
+    pub fn push(&mut self, mesh: &'a Mesh<'ctx>, material: &'a Material<'ctx, 'a>, world: Mat4) {
+        self.push_instance(MeshInstance {
+            mesh,
+            material,
+            instance: InstanceData { model: world, color: [1.0; 4] },
+        });
+    }
+
+    pub fn push_instance(&mut self, instance: MeshInstance<'a, 'ctx>) {
+        self.items.push(instance);
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.sorted.clear();
+        self.instances.clear();
+        self.stats = RenderQueueStats::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Culls every item against the frustum of `view_projection` and sorts what remains.
+    pub fn prepare(&mut self, view_projection: &Mat4, camera_position: Vec3F) {
+        let frustum = Frustum::from_view_projection(view_projection);
+
+        self.sorted.clear();
+        self.stats.submitted = self.items.len();
+
+        for (index, item) in self.items.iter().enumerate() {
+            let bounds = item.mesh.bounding_sphere.transformed(&item.instance.model);
+
+            if !frustum.intersects_sphere(&bounds) {
+                continue;
+            }
+
+            let dx = bounds.center.x - camera_position.x;
+            let dy = bounds.center.y - camera_position.y;
+            let dz = bounds.center.z - camera_position.z;
+            let depth = (dx * dx + dy * dy + dz * dz).sqrt();
+
+            let pass = if item.material.is_transparent { DrawPass::Transparent } else { DrawPass::Opaque };
+
+            let key = make_sort_key(pass, item.material.shader.id, item.material.id, item.mesh.id, depth);
+
+            self.sorted.push((key, index));
+        }
+
+        self.stats.culled = self.items.len() - self.sorted.len();
+
+        self.sorted.sort_unstable_by_key(|(key, _)| *key);
+    }
+
+    /// Records every visible item, drawing consecutive items that share a mesh and material as one instanced draw.
+    /// Materials must have been updated for `frame_index`, and the shaders must read `InstanceData` from
+    /// `instance_binding`. `on_shader_bound` runs each time a new shader is bound, so that per-frame descriptor sets
+    /// such as the camera can be bound alongside it.
+    pub fn record<F: FnMut(vk::CommandBuffer, &Shader)>(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: u32,
+        instance_buffer: &mut InstanceBuffer,
+        instance_binding: u32,
+        mut on_shader_bound: F,
+    ) {
+        self.instances.clear();
+        self.instances.extend(self.sorted.iter().map(|&(_, index)| self.items[index].instance));
+
+        instance_buffer.write_and_bind(command_buffer, frame_index, instance_binding, &self.instances);
+
+        let mut current_shader = None;
+        let mut current_material = None;
+        let mut current_mesh = None;
+        let mut first = 0;
+
+        while first < self.sorted.len() {
+            let item = &self.items[self.sorted[first].1];
+
+            let count = self.sorted[first..].iter()
+                .take_while(|&&(_, index)| {
+                    let other = &self.items[index];
+                    other.mesh.id == item.mesh.id && other.material.id == item.material.id
+                })
+                .count();
+
+            let shader = item.material.shader;
+
+            if current_shader != Some(shader.id) {
+                shader.bind(command_buffer);
+                on_shader_bound(command_buffer, shader);
+
+                current_shader = Some(shader.id);
+                current_material = None;
+                self.stats.pipeline_binds += 1;
+            }
+
+            if current_material != Some(item.material.id) {
+                item.material.bind(command_buffer, frame_index);
+
+                current_material = Some(item.material.id);
+                self.stats.material_binds += 1;
+            }
+
+            if current_mesh != Some(item.mesh.id) {
+                item.mesh.bind(command_buffer);
+
+                current_mesh = Some(item.mesh.id);
+                self.stats.mesh_binds += 1;
+            }
+
+            item.mesh.draw_instanced(command_buffer, count as u32, first as u32);
+
+            self.stats.draw_calls += 1;
+            first += count;
+        }
+
+        self.stats.drawn = self.sorted.len();
+    }
+}
+
+impl<'a, 'ctx> Default for RenderQueue<'a, 'ctx> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
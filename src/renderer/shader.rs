@@ -1,18 +1,32 @@
-use std::{ffi::CString, marker::PhantomData, ptr};
+pub mod reflection;
+pub mod watcher;
+
+use std::{cell::{Cell, RefCell}, ffi::CString, marker::PhantomData, ptr, sync::atomic::{AtomicU32, Ordering}};
 
 use ash::vk;
+use serde::Deserialize;
+
+use crate::math::vec3::Vec3UI;
+
+use super::{pipeline::{Pipeline, PipelineRenderTarget, PipelineStateInfo}, vkcontext::VkContext};
 
-use super::{pipeline::{Pipeline, PipelineStateInfo}, vkcontext::VkContext};
+use reflection::ShaderReflection;
+
+static NEXT_SHADER_ID: AtomicU32 = AtomicU32::new(0);
 
 pub struct Shader<'ctx> {
+    pub id: u32,
     pub name: String,
     pub minimum_uniform_alignment: u64,
     
     pub descriptor_pool: vk::DescriptorPool,
 
     pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub descriptor_set_layout_infos: Vec<ShaderDescriptorSetLayoutInfo>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
 
-    pub pipeline: Pipeline<'ctx>,
+    pipeline: RefCell<Pipeline<'ctx>>,
+    pipeline_source: GraphicsPipelineSource<'ctx>,
 
     vkcontext: &'ctx VkContext,
 }
@@ -21,120 +35,311 @@ impl<'ctx> Shader<'ctx> {
     pub fn new(
         vkcontext: &'ctx VkContext,
         name: &str,
-        render_pass: vk::RenderPass,
-        subpass_index: u32,
+        render_target: &PipelineRenderTarget,
         color_blend_attachment_states: &[vk::PipelineColorBlendAttachmentState],
         vertex_bindings: &[vk::VertexInputBindingDescription],
         vertex_attributes: &[ShaderVertexAttributeInfo],
         push_constants: &[ShaderPushConstantInfo],
         descriptor_sets: &[ShaderDescriptorSetInfo],
         shader_stages: &[ShaderStageInfo],
-        depth_test_enabled: bool,
+        pipeline_state_info: &PipelineStateInfo<'ctx>,
     ) -> Self {
         // Create Shader Stages.
         let shader_stages = shader_stages.iter().map(|stage| {
-            ShaderStage::new(vkcontext, stage.stage_file, stage.stage_type)
+            ShaderStage::new(vkcontext, stage)
         })
         .collect::<Vec<_>>();
 
-        // Vertex attributes.
-        let mut vertex_attribute_offset = 0u32;
+        // Vertex attributes. Offsets are tracked per binding, and matrices take up one location per column.
+        let mut vertex_attribute_offsets = std::collections::HashMap::<u32, u32>::new();
+        let mut location = 0u32;
 
-        let vertex_attributes = vertex_attributes.iter().enumerate().map(|(i, attrib)| {
-            let vertex_attribute = vk::VertexInputAttributeDescription::default()
-                .binding(attrib.binding)
-                .location(i as u32)
-                .format(attrib.attribute_type.as_vk_format())
-                .offset(vertex_attribute_offset);
+        let vertex_attributes = vertex_attributes.iter().flat_map(|attrib| {
+            let binding = vertex_bindings.iter()
+                .find(|binding| binding.binding == attrib.binding)
+                .unwrap_or_else(|| panic!("Vertex attribute uses undeclared binding {}.", attrib.binding));
 
-            vertex_attribute_offset += attrib.attribute_type.size();
+            if binding.input_rate != attrib.input_rate {
+                panic!("Vertex attribute input rate does not match binding {}.", attrib.binding);
+            }
 
-            vertex_attribute
-        })
-        .collect::<Vec<_>>();
+            let offset = vertex_attribute_offsets.entry(attrib.binding).or_insert(0);
 
-        // Descriptors.
-        let descriptor_set_layouts = descriptor_sets.iter().map(|set_info| {
-            let layout_bindings = set_info.descriptors.iter().enumerate().map(|(i, descriptor)| {
-                vk::DescriptorSetLayoutBinding::default()
-                    .binding(i as u32)
-                    .descriptor_type(descriptor.descriptor_type.as_vk_descriptor_type())
-                    .descriptor_count(1)
-                    .stage_flags(descriptor.stage_flags)
+            let descriptions = (0..attrib.attribute_type.location_count()).map(|column| {
+                vk::VertexInputAttributeDescription::default()
+                    .binding(attrib.binding)
+                    .location(location + column)
+                    .format(attrib.attribute_type.as_vk_attribute_format())
+                    .offset(*offset + column * attrib.attribute_type.location_size())
             })
             .collect::<Vec<_>>();
 
-            let create_info = vk::DescriptorSetLayoutCreateInfo::default()
-                .bindings(&layout_bindings);
+            *offset += attrib.attribute_type.size();
+            location += attrib.attribute_type.location_count();
 
-            unsafe { vkcontext.device.create_descriptor_set_layout(&create_info, None).unwrap() }
+            descriptions
         })
         .collect::<Vec<_>>();
 
-        let mut pool_sizes = Vec::new();
-        let mut max_pool_set_count = 0u32;
+        // Descriptors.
+        let (descriptor_set_layouts, descriptor_pool) = create_descriptor_set_layouts_and_pool(vkcontext, descriptor_sets);
 
-        for set_info in descriptor_sets {
-            pool_sizes.extend(set_info.descriptors.iter().map(|descriptor| {
-                vk::DescriptorPoolSize::default()
-                    .ty(descriptor.descriptor_type.as_vk_descriptor_type())
-                    .descriptor_count(set_info.max_set_allocations)
-            }));
+        let descriptor_set_layout_infos = describe_descriptor_sets(descriptor_sets);
+        let push_constant_ranges = create_push_constant_ranges(push_constants);
 
-            max_pool_set_count += set_info.max_set_allocations;
+        // The layout is taken as given, but anything that contradicts the SPIR-V is reported.
+        let reflection = ShaderReflection::merged(shader_stages.iter().map(|stage| &stage.reflection));
+        for mismatch in reflection.verify(&vertex_attributes, &descriptor_set_layout_infos, &push_constant_ranges) {
+            log::error!("Shader \"{}\" does not match its SPIR-V: {}.", name, mismatch);
         }
 
-        let descriptor_pool = {
-            let ci = vk::DescriptorPoolCreateInfo::default()
-                .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
-                .max_sets(max_pool_set_count)
-                .pool_sizes(&pool_sizes);
-
-            unsafe { vkcontext.device.create_descriptor_pool(&ci, None).unwrap() }
+        // Pipeline. Everything it is built from is kept so it can be rebuilt later.
+        let pipeline_source = GraphicsPipelineSource {
+            render_target: ShaderRenderTarget::from_pipeline_render_target(render_target),
+            color_blend_attachment_states: color_blend_attachment_states.to_vec(),
+            vertex_bindings: vertex_bindings.to_vec(),
+            vertex_attributes,
+            shader_stages: RefCell::new(shader_stages),
+            pipeline_state_info: Cell::new(*pipeline_state_info),
         };
 
-        let mut push_constant_offset = 0u32;
-        let push_constant_ranges = push_constants.iter().map(|push_constant| {
-            let push_constant_range = vk::PushConstantRange::default()
-                .stage_flags(push_constant.stage_flags)
-                .offset(push_constant_offset)
-                .size(push_constant.push_constant_type.size());
-
-            push_constant_offset += push_constant.push_constant_type.size();
+        Self::from_pipeline_source(
+            vkcontext,
+            name,
+            pipeline_source,
+            descriptor_pool,
+            descriptor_set_layouts,
+            descriptor_set_layout_infos,
+            push_constant_ranges,
+        )
+    }
 
-            push_constant_range
+    /// Like `new`, but derives the vertex input, descriptor set layouts and push constant range from the SPIR-V of
+    /// `shader_stages`. Vertex inputs are read per vertex from binding 0, tightly packed in location order, so shaders
+    /// with per-instance attributes need `new`. `max_set_allocations` holds one entry per descriptor set.
+    pub fn new_reflected(
+        vkcontext: &'ctx VkContext,
+        name: &str,
+        render_target: &PipelineRenderTarget,
+        color_blend_attachment_states: &[vk::PipelineColorBlendAttachmentState],
+        shader_stages: &[ShaderStageInfo],
+        pipeline_state_info: &PipelineStateInfo<'ctx>,
+        max_set_allocations: &[u32],
+    ) -> Self {
+        let shader_stages = shader_stages.iter().map(|stage| {
+            ShaderStage::new(vkcontext, stage)
         })
         .collect::<Vec<_>>();
 
-        // Pipeline.
-        let pipeline = Pipeline::new_graphics(
+        let reflection = ShaderReflection::merged(shader_stages.iter().map(|stage| &stage.reflection));
+
+        let (vertex_binding, vertex_attributes) = reflection.vertex_input(0);
+        let vertex_bindings = if vertex_attributes.is_empty() { Vec::new() } else { vec![vertex_binding] };
+
+        let set_bindings = reflection.descriptor_set_layout_bindings();
+        assert!(
+            max_set_allocations.len() >= set_bindings.len(),
+            "Shader \"{}\" uses {} descriptor sets but only {} allocation counts were given.",
+            name,
+            set_bindings.len(),
+            max_set_allocations.len()
+        );
+
+        let (descriptor_set_layouts, descriptor_pool) = create_descriptor_set_layouts_and_pool_from_bindings(
             vkcontext,
-            render_pass,
-            subpass_index,
-            &PipelineStateInfo::get_default_pipeline_state_info(),
-            vertex_bindings,
-            &vertex_attributes,
-            &push_constant_ranges,
-            &descriptor_set_layouts,
-            &shader_stages.iter().map(|stage| stage.shader_stage_create_info).collect::<Vec<_>>(),
-            color_blend_attachment_states,
-            depth_test_enabled,
+            &set_bindings,
+            max_set_allocations,
         );
 
+        let pipeline_source = GraphicsPipelineSource {
+            render_target: ShaderRenderTarget::from_pipeline_render_target(render_target),
+            color_blend_attachment_states: color_blend_attachment_states.to_vec(),
+            vertex_bindings,
+            vertex_attributes,
+            shader_stages: RefCell::new(shader_stages),
+            pipeline_state_info: Cell::new(*pipeline_state_info),
+        };
+
+        Self::from_pipeline_source(
+            vkcontext,
+            name,
+            pipeline_source,
+            descriptor_pool,
+            descriptor_set_layouts,
+            reflection.descriptor_set_layout_infos(),
+            reflection.push_constant_ranges(),
+        )
+    }
+
+    fn from_pipeline_source(
+        vkcontext: &'ctx VkContext,
+        name: &str,
+        pipeline_source: GraphicsPipelineSource<'ctx>,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+        descriptor_set_layout_infos: Vec<ShaderDescriptorSetLayoutInfo>,
+        push_constant_ranges: Vec<vk::PushConstantRange>,
+    ) -> Self {
+        let pipeline = pipeline_source.create_pipeline(vkcontext, &push_constant_ranges, &descriptor_set_layouts)
+            .unwrap_or_else(|error| panic!("Failed to create pipeline for shader \"{}\": {}", name, error));
+
         Self {
+            id: NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed),
             name: name.to_string(),
             minimum_uniform_alignment: vkcontext.physical_device_properties.limits.min_uniform_buffer_offset_alignment,
             descriptor_pool,
             descriptor_set_layouts,
-            pipeline,
+            descriptor_set_layout_infos,
+            push_constant_ranges,
+            pipeline: RefCell::new(pipeline),
+            pipeline_source,
             vkcontext,
         }
     }
 }
 
+impl<'ctx> Shader<'ctx> {
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        self.pipeline_source.pipeline_state_info.get().rasterization_samples()
+    }
+
+    /// Rebuilds the pipeline to rasterize with `sample_count` samples per pixel, matching the attachments it will
+    /// render into. The old pipeline is destroyed straight away, so the GPU must not be using it.
+    pub fn set_sample_count(&self, sample_count: vk::SampleCountFlags) {
+        if sample_count == self.sample_count() {
+            return;
+        }
+
+        if !self.vkcontext.supported_sample_counts().contains(sample_count) {
+            panic!("Shader \"{}\" cannot use unsupported sample count {:?}.", self.name, sample_count);
+        }
+
+        let pipeline_state_info = self.pipeline_source.pipeline_state_info.get().sample_count(sample_count);
+        self.pipeline_source.pipeline_state_info.set(pipeline_state_info);
+
+        let pipeline = self.pipeline_source.create_pipeline(
+            self.vkcontext,
+            &self.push_constant_ranges,
+            &self.descriptor_set_layouts,
+        )
+        .unwrap_or_else(|error| panic!("Failed to create pipeline for shader \"{}\": {}", self.name, error));
+
+        *self.pipeline.borrow_mut() = pipeline;
+    }
+
+    /// Recreates the stages loaded from any of `changed_files` and rebuilds the pipeline from them, returning whether
+    /// the pipeline was replaced. The vertex input, descriptor set layouts and push constant ranges are kept, so the
+    /// new stages are verified against them. If a stage fails to build or verify, or the pipeline fails to build, the
+    /// error is logged and the old pipeline stays in use. Like `set_sample_count`, the GPU must not be using the
+    /// pipeline.
+    pub fn reload(&self, changed_files: &[String]) -> bool {
+        let stages = self.pipeline_source.shader_stages.borrow();
+
+        if !stages.iter().any(|stage| changed_files.contains(&stage.path)) {
+            return false;
+        }
+
+        let shader_stages = stages.iter().map(ShaderStage::reloaded).collect::<Result<Vec<_>, _>>();
+        drop(stages);
+
+        let shader_stages = match shader_stages {
+            Ok(shader_stages) => shader_stages,
+            Err(message) => {
+                log::error!("Failed to reload shader \"{}\", keeping the old pipeline: {}", self.name, message);
+                return false;
+            },
+        };
+
+        let reflection = ShaderReflection::merged(shader_stages.iter().map(|stage| &stage.reflection));
+        let mismatches = reflection.verify(
+            &self.pipeline_source.vertex_attributes,
+            &self.descriptor_set_layout_infos,
+            &self.push_constant_ranges,
+        );
+
+        if !mismatches.is_empty() {
+            for mismatch in mismatches {
+                log::error!("Reloaded shader \"{}\" does not match its layout, keeping the old pipeline: {}.", self.name, mismatch);
+            }
+
+            return false;
+        }
+
+        let pipeline = self.pipeline_source.create_pipeline_from_stages(
+            self.vkcontext,
+            &self.push_constant_ranges,
+            &self.descriptor_set_layouts,
+            &shader_stages,
+        );
+
+        match pipeline {
+            Ok(pipeline) => {
+                *self.pipeline.borrow_mut() = pipeline;
+                *self.pipeline_source.shader_stages.borrow_mut() = shader_stages;
+
+                log::info!("Reloaded shader \"{}\".", self.name);
+                true
+            },
+            Err(error) => {
+                log::error!("Failed to rebuild pipeline for shader \"{}\", keeping the old one: {}", self.name, error);
+                false
+            },
+        }
+    }
+}
+
 impl<'ctx> Shader<'ctx> {
     pub fn bind(&self, command_buffer: vk::CommandBuffer) {
-        self.pipeline.bind(command_buffer, vk::PipelineBindPoint::GRAPHICS);
+        self.pipeline.borrow().bind(command_buffer, vk::PipelineBindPoint::GRAPHICS);
+    }
+
+    pub fn bind_descriptor_sets(&self, command_buffer: vk::CommandBuffer, first_set: u32, descriptor_sets: &[vk::DescriptorSet]) {
+        unsafe {
+            self.vkcontext.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.borrow().layout,
+                first_set,
+                descriptor_sets,
+                &[],
+            );
+        }
+    }
+
+    pub fn push_constants<T: Copy>(&self, command_buffer: vk::CommandBuffer, offset: u32, value: &T) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts((value as *const T) as *const u8, std::mem::size_of::<T>())
+        };
+
+        // Exactly the stages whose ranges overlap the update must be given.
+        let end = offset + bytes.len() as u32;
+        let stage_flags = self.push_constant_ranges.iter()
+            .filter(|range| range.offset < end && offset < range.offset + range.size)
+            .fold(vk::ShaderStageFlags::empty(), |flags, range| flags | range.stage_flags);
+
+        unsafe {
+            self.vkcontext.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline.borrow().layout,
+                stage_flags,
+                offset,
+                bytes,
+            );
+        }
+    }
+
+    pub fn allocate_descriptor_sets(&self, set_index: u32, count: u32) -> Vec<vk::DescriptorSet> {
+        let set_layouts = vec![self.descriptor_set_layouts[set_index as usize]; count as usize];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        unsafe { self.vkcontext.device.allocate_descriptor_sets(&allocate_info).unwrap() }
+    }
+
+    pub fn free_descriptor_sets(&self, descriptor_sets: &[vk::DescriptorSet]) {
+        unsafe { self.vkcontext.device.free_descriptor_sets(self.descriptor_pool, descriptor_sets).unwrap(); }
     }
 }
 
@@ -150,49 +355,532 @@ impl<'ctx> Drop for Shader<'ctx> {
     }
 }
 
+pub struct ComputeShader<'ctx> {
+    pub name: String,
+
+    pub descriptor_pool: vk::DescriptorPool,
+
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub descriptor_set_layout_infos: Vec<ShaderDescriptorSetLayoutInfo>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+
+    pipeline: RefCell<Pipeline<'ctx>>,
+    shader_stage: RefCell<ShaderStage<'ctx, 'ctx>>,
+
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> ComputeShader<'ctx> {
+    pub fn new(
+        vkcontext: &'ctx VkContext,
+        name: &str,
+        push_constants: &[ShaderPushConstantInfo],
+        descriptor_sets: &[ShaderDescriptorSetInfo],
+        shader_stage: &ShaderStageInfo,
+    ) -> Self {
+        assert_eq!(
+            shader_stage.stage_type,
+            vk::ShaderStageFlags::COMPUTE,
+            "ComputeShader requires a compute shader stage."
+        );
+
+        let shader_stage = ShaderStage::new(vkcontext, shader_stage);
+
+        let (descriptor_set_layouts, descriptor_pool) = create_descriptor_set_layouts_and_pool(vkcontext, descriptor_sets);
+
+        let descriptor_set_layout_infos = describe_descriptor_sets(descriptor_sets);
+        let push_constant_ranges = create_push_constant_ranges(push_constants);
+
+        for mismatch in shader_stage.reflection.verify(&[], &descriptor_set_layout_infos, &push_constant_ranges) {
+            log::error!("Compute shader \"{}\" does not match its SPIR-V: {}.", name, mismatch);
+        }
+
+        let pipeline = Pipeline::new_compute(
+            vkcontext,
+            &push_constant_ranges,
+            &descriptor_set_layouts,
+            shader_stage.shader_stage_create_info,
+        )
+        .unwrap_or_else(|error| panic!("Failed to create pipeline for compute shader \"{}\": {}", name, error));
+
+        Self {
+            name: name.to_string(),
+            descriptor_pool,
+            descriptor_set_layouts,
+            descriptor_set_layout_infos,
+            push_constant_ranges,
+            pipeline: RefCell::new(pipeline),
+            shader_stage: RefCell::new(shader_stage),
+            vkcontext,
+        }
+    }
+
+    /// Like `Shader::reload`: rebuilds the pipeline if its stage was loaded from one of `changed_files`, keeping the
+    /// old one if the new stage fails to build or does not match the layout. The GPU must not be using the pipeline.
+    pub fn reload(&self, changed_files: &[String]) -> bool {
+        let shader_stage = self.shader_stage.borrow();
+
+        if !changed_files.contains(&shader_stage.path) {
+            return false;
+        }
+
+        let reloaded = shader_stage.reloaded();
+        drop(shader_stage);
+
+        let shader_stage = match reloaded {
+            Ok(shader_stage) => shader_stage,
+            Err(message) => {
+                log::error!("Failed to reload compute shader \"{}\", keeping the old pipeline: {}", self.name, message);
+                return false;
+            },
+        };
+
+        let mismatches = shader_stage.reflection.verify(&[], &self.descriptor_set_layout_infos, &self.push_constant_ranges);
+
+        if !mismatches.is_empty() {
+            for mismatch in mismatches {
+                log::error!(
+                    "Reloaded compute shader \"{}\" does not match its layout, keeping the old pipeline: {}.",
+                    self.name,
+                    mismatch
+                );
+            }
+
+            return false;
+        }
+
+        let pipeline = Pipeline::new_compute(
+            self.vkcontext,
+            &self.push_constant_ranges,
+            &self.descriptor_set_layouts,
+            shader_stage.shader_stage_create_info,
+        );
+
+        match pipeline {
+            Ok(pipeline) => {
+                *self.pipeline.borrow_mut() = pipeline;
+                *self.shader_stage.borrow_mut() = shader_stage;
+
+                log::info!("Reloaded compute shader \"{}\".", self.name);
+                true
+            },
+            Err(error) => {
+                log::error!("Failed to rebuild pipeline for compute shader \"{}\", keeping the old one: {}", self.name, error);
+                false
+            },
+        }
+    }
+}
+
+impl<'ctx> ComputeShader<'ctx> {
+    pub fn bind(&self, command_buffer: vk::CommandBuffer) {
+        self.pipeline.borrow().bind(command_buffer, vk::PipelineBindPoint::COMPUTE);
+    }
+
+    pub fn bind_descriptor_sets(&self, command_buffer: vk::CommandBuffer, first_set: u32, descriptor_sets: &[vk::DescriptorSet]) {
+        unsafe {
+            self.vkcontext.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.borrow().layout,
+                first_set,
+                descriptor_sets,
+                &[],
+            );
+        }
+    }
+
+    pub fn push_constants<T: Copy>(&self, command_buffer: vk::CommandBuffer, offset: u32, value: &T) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts((value as *const T) as *const u8, std::mem::size_of::<T>())
+        };
+
+        unsafe {
+            self.vkcontext.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline.borrow().layout,
+                vk::ShaderStageFlags::COMPUTE,
+                offset,
+                bytes,
+            );
+        }
+    }
+
+    pub fn allocate_descriptor_sets(&self, set_index: u32, count: u32) -> Vec<vk::DescriptorSet> {
+        let set_layouts = vec![self.descriptor_set_layouts[set_index as usize]; count as usize];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        unsafe { self.vkcontext.device.allocate_descriptor_sets(&allocate_info).unwrap() }
+    }
+
+    /// Records a dispatch of `group_count` work groups, followed by a barrier that makes everything the shader wrote to
+    /// `barriers` visible to the consumers they name.
+    pub fn dispatch(&self, command_buffer: vk::CommandBuffer, group_count: Vec3UI, barriers: &[ComputeResourceBarrier]) {
+        unsafe {
+            self.vkcontext.device.cmd_dispatch(command_buffer, group_count.x, group_count.y, group_count.z);
+        }
+
+        self.record_output_barriers(command_buffer, barriers);
+    }
+
+    /// Like `dispatch`, but reads the work group count from a `vk::DispatchIndirectCommand` stored in `buffer` at
+    /// `offset`. The command is assumed to have been written by an earlier transfer or compute pass.
+    pub fn dispatch_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        barriers: &[ComputeResourceBarrier],
+    ) {
+        let indirect_barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(buffer)
+            .offset(offset)
+            .size(std::mem::size_of::<vk::DispatchIndirectCommand>() as vk::DeviceSize);
+
+        unsafe {
+            self.vkcontext.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::DependencyFlags::default(),
+                &[],
+                std::slice::from_ref(&indirect_barrier),
+                &[],
+            );
+
+            self.vkcontext.device.cmd_dispatch_indirect(command_buffer, buffer, offset);
+        }
+
+        self.record_output_barriers(command_buffer, barriers);
+    }
+
+    fn record_output_barriers(&self, command_buffer: vk::CommandBuffer, barriers: &[ComputeResourceBarrier]) {
+        if barriers.is_empty() {
+            return;
+        }
+
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+        let mut buffer_barriers = Vec::new();
+        let mut image_barriers = Vec::new();
+
+        for barrier in barriers {
+            match *barrier {
+                ComputeResourceBarrier::Buffer { buffer, offset, size, dst_access_mask, dst_stage_mask } => {
+                    dst_stage |= dst_stage_mask;
+
+                    buffer_barriers.push(vk::BufferMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                        .dst_access_mask(dst_access_mask)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .buffer(buffer)
+                        .offset(offset)
+                        .size(size)
+                    );
+                },
+                ComputeResourceBarrier::Image {
+                    image,
+                    subresource_range,
+                    old_layout,
+                    new_layout,
+                    dst_access_mask,
+                    dst_stage_mask,
+                } => {
+                    dst_stage |= dst_stage_mask;
+
+                    image_barriers.push(vk::ImageMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                        .dst_access_mask(dst_access_mask)
+                        .old_layout(old_layout)
+                        .new_layout(new_layout)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(image)
+                        .subresource_range(subresource_range)
+                    );
+                },
+            }
+        }
+
+        unsafe {
+            self.vkcontext.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage,
+                vk::DependencyFlags::default(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+        }
+    }
+}
+
+impl<'ctx> Drop for ComputeShader<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            self.vkcontext.device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+            for descriptor_set_layout in self.descriptor_set_layouts.iter() {
+                self.vkcontext.device.destroy_descriptor_set_layout(*descriptor_set_layout, None);
+            }
+        }
+    }
+}
+
+/// A resource written by a compute dispatch, together with the access that will consume it afterwards.
+#[derive(Clone, Copy)]
+pub enum ComputeResourceBarrier {
+    Buffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+    },
+    Image {
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+    },
+}
+
 struct ShaderAttribute {
     name: String,
     format: vk::Format,
     size: u32,
 }
 
+/// An owned copy of a `PipelineRenderTarget`.
+enum ShaderRenderTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        subpass_index: u32,
+    },
+    Dynamic {
+        color_attachment_formats: Vec<vk::Format>,
+        depth_attachment_format: vk::Format,
+        stencil_attachment_format: vk::Format,
+    },
+}
+
+impl ShaderRenderTarget {
+    fn from_pipeline_render_target(render_target: &PipelineRenderTarget) -> Self {
+        match *render_target {
+            PipelineRenderTarget::RenderPass { render_pass, subpass_index } => Self::RenderPass { render_pass, subpass_index },
+            PipelineRenderTarget::Dynamic {
+                color_attachment_formats,
+                depth_attachment_format,
+                stencil_attachment_format,
+            } => Self::Dynamic {
+                color_attachment_formats: color_attachment_formats.to_vec(),
+                depth_attachment_format,
+                stencil_attachment_format,
+            },
+        }
+    }
+
+    fn as_pipeline_render_target(&self) -> PipelineRenderTarget<'_> {
+        match self {
+            Self::RenderPass { render_pass, subpass_index } => PipelineRenderTarget::RenderPass {
+                render_pass: *render_pass,
+                subpass_index: *subpass_index,
+            },
+            Self::Dynamic {
+                color_attachment_formats,
+                depth_attachment_format,
+                stencil_attachment_format,
+            } => PipelineRenderTarget::Dynamic {
+                color_attachment_formats,
+                depth_attachment_format: *depth_attachment_format,
+                stencil_attachment_format: *stencil_attachment_format,
+            },
+        }
+    }
+}
+
+/// Everything a graphics `Shader` builds its pipeline from. The stage modules stay alive for rebuilds.
+struct GraphicsPipelineSource<'ctx> {
+    render_target: ShaderRenderTarget,
+    color_blend_attachment_states: Vec<vk::PipelineColorBlendAttachmentState>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    shader_stages: RefCell<Vec<ShaderStage<'ctx, 'ctx>>>,
+    pipeline_state_info: Cell<PipelineStateInfo<'ctx>>,
+}
+
+impl<'ctx> GraphicsPipelineSource<'ctx> {
+    fn create_pipeline(
+        &self,
+        vkcontext: &'ctx VkContext,
+        push_constant_ranges: &[vk::PushConstantRange],
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Pipeline<'ctx>, vk::Result> {
+        self.create_pipeline_from_stages(
+            vkcontext,
+            push_constant_ranges,
+            descriptor_set_layouts,
+            &self.shader_stages.borrow(),
+        )
+    }
+
+    fn create_pipeline_from_stages(
+        &self,
+        vkcontext: &'ctx VkContext,
+        push_constant_ranges: &[vk::PushConstantRange],
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
+        shader_stages: &[ShaderStage<'ctx, 'ctx>],
+    ) -> Result<Pipeline<'ctx>, vk::Result> {
+        let pipeline_state_info = self.pipeline_state_info.get();
+
+        Pipeline::new_graphics(
+            vkcontext,
+            &self.render_target.as_pipeline_render_target(),
+            &pipeline_state_info,
+            &self.vertex_bindings,
+            &self.vertex_attributes,
+            push_constant_ranges,
+            descriptor_set_layouts,
+            &shader_stages.iter().map(|stage| stage.shader_stage_create_info).collect::<Vec<_>>(),
+            &self.color_blend_attachment_states,
+            pipeline_state_info.is_depth_test_enabled(),
+        )
+    }
+}
+
 struct ShaderStage<'ctx, 'a> {
+    /// Relative to the assets directory, as given in `ShaderStageInfo::stage_file`.
+    path: String,
     module: vk::ShaderModule,
     shader_stage_create_info: vk::PipelineShaderStageCreateInfo<'a>,
     stage_entry_point_name: CString,
+    specialization_constants: Vec<ShaderSpecializationConstant>,
+    // Pointed to by `shader_stage_create_info`, so they must not move or change while the stage is alive.
+    _specialization_data: Vec<u8>,
+    _specialization_map_entries: Vec<vk::SpecializationMapEntry>,
+    _specialization_info: Box<vk::SpecializationInfo<'a>>,
+    reflection: ShaderReflection,
     vkcontext: &'ctx VkContext,
 }
 
 impl<'ctx, 'a> ShaderStage<'ctx, 'a> {
-    fn new<P: AsRef<std::path::Path>>(vkcontext: &'ctx VkContext, path: P, shader_stage: vk::ShaderStageFlags) -> Self {
-        let compute_code = read_shader_from_file(path);
+    fn new(vkcontext: &'ctx VkContext, stage_info: &ShaderStageInfo) -> Self {
+        Self::try_new(vkcontext, stage_info).unwrap_or_else(|message| panic!("{}", message))
+    }
+
+    fn try_new(vkcontext: &'ctx VkContext, stage_info: &ShaderStageInfo) -> Result<Self, String> {
+        let path = stage_info.stage_file;
+
+        let compute_code = read_shader_from_file(path)
+            .map_err(|message| format!("Failed to read {}: {}", path, message))?;
+
+        let reflection = ShaderReflection::from_spirv(&compute_code)
+            .map_err(|message| format!("Failed to reflect {}: {}", path, message))?;
+
+        if !reflection.entry_points.iter().any(|entry_point| {
+            entry_point.name == stage_info.entry_point && entry_point.stage == stage_info.stage_type
+        }) {
+            return Err(format!(
+                "{} has no {:?} entry point named \"{}\"",
+                path,
+                stage_info.stage_type,
+                stage_info.entry_point
+            ));
+        }
+
+        for constant in stage_info.specialization_constants {
+            let Some(reflected) = reflection.specialization_constants.iter().find(|reflected| reflected.id == constant.id) else {
+                continue;
+            };
+
+            let matches = reflected.default_value
+                .is_some_and(|default| std::mem::discriminant(&default) == std::mem::discriminant(&constant.value));
+
+            if !matches {
+                return Err(format!(
+                    "Specialization constant {} (\"{}\") of {} is {}, but was given {:?}",
+                    constant.id,
+                    reflected.name,
+                    path,
+                    reflected.default_value.map_or("of an unsupported type", |default| default.type_name()),
+                    constant.value
+                ));
+            }
+        }
 
         let module = {
             let create_info = vk::ShaderModuleCreateInfo::default()
                 .code(&compute_code);
 
-            unsafe { vkcontext.device.create_shader_module(&create_info, None).unwrap() }
+            unsafe { vkcontext.device.create_shader_module(&create_info, None) }
+                .map_err(|error| format!("Failed to create shader module for {}: {}", path, error))?
         };
 
-        let entry_point_name = CString::new("main").unwrap();
+        let entry_point_name = CString::new(stage_info.entry_point).unwrap();
+
+        // Every constant takes up four bytes, in the order given.
+        let specialization_data = stage_info.specialization_constants.iter()
+            .flat_map(|constant| constant.value.to_bytes())
+            .collect::<Vec<_>>();
+
+        let specialization_map_entries = stage_info.specialization_constants.iter().enumerate()
+            .map(|(i, constant)| vk::SpecializationMapEntry {
+                constant_id: constant.id,
+                offset: i as u32 * 4,
+                size: 4,
+            })
+            .collect::<Vec<_>>();
+
+        let specialization_info = Box::new(vk::SpecializationInfo {
+            map_entry_count: specialization_map_entries.len() as u32,
+            p_map_entries: specialization_map_entries.as_ptr(),
+            data_size: specialization_data.len(),
+            p_data: specialization_data.as_ptr().cast(),
+            _marker: PhantomData,
+        });
 
         let shader_stage_create_info = vk::PipelineShaderStageCreateInfo {
             s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineShaderStageCreateFlags::default(),
-            stage: shader_stage,
+            stage: stage_info.stage_type,
             module,
             p_name: entry_point_name.as_ptr(),
-            p_specialization_info: ptr::null(),
+            p_specialization_info: if specialization_map_entries.is_empty() { ptr::null() } else { &*specialization_info },
             _marker: PhantomData,
         };
 
-        Self {
+        Ok(Self {
+            path: path.to_string(),
             module,
             shader_stage_create_info,
             stage_entry_point_name: entry_point_name,
+            specialization_constants: stage_info.specialization_constants.to_vec(),
+            _specialization_data: specialization_data,
+            _specialization_map_entries: specialization_map_entries,
+            _specialization_info: specialization_info,
+            reflection,
             vkcontext,
-        }
+        })
+    }
+
+    /// Creates the stage again from the same file, picking up any changes to it.
+    fn reloaded(&self) -> Result<Self, String> {
+        let stage_info = ShaderStageInfo::new(self.shader_stage_create_info.stage, &self.path)
+            .entry_point(self.stage_entry_point_name.to_str().unwrap())
+            .specialization_constants(&self.specialization_constants);
+
+        Self::try_new(self.vkcontext, &stage_info)
     }
 }
 
@@ -207,6 +895,156 @@ impl<'ctx, 'a> Drop for ShaderStage<'ctx, 'a> {
 pub struct ShaderStageInfo<'a> {
     pub stage_type: vk::ShaderStageFlags,
     pub stage_file: &'a str,
+    pub entry_point: &'a str,
+    /// Lets one SPIR-V module produce several pipeline variants. Constants the module doesn't declare are ignored, and
+    /// the rest must have the type the module gives them.
+    pub specialization_constants: &'a [ShaderSpecializationConstant],
+}
+
+impl<'a> ShaderStageInfo<'a> {
+    /// A stage using the `main` entry point and the default values of all specialization constants.
+    pub fn new(stage_type: vk::ShaderStageFlags, stage_file: &'a str) -> Self {
+        Self {
+            stage_type,
+            stage_file,
+            entry_point: "main",
+            specialization_constants: &[],
+        }
+    }
+
+    pub fn entry_point(mut self, entry_point: &'a str) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    pub fn specialization_constants(mut self, specialization_constants: &'a [ShaderSpecializationConstant]) -> Self {
+        self.specialization_constants = specialization_constants;
+        self
+    }
+}
+
+/// A shader stage as written in the `stages` array of a shader JSON file, which owns what a `ShaderStageInfo`
+/// borrows.
+pub struct ShaderStageConfig {
+    pub stage_type: vk::ShaderStageFlags,
+    pub stage_file: String,
+    pub entry_point: String,
+    pub specialization_constants: Vec<ShaderSpecializationConstant>,
+}
+
+#[derive(Deserialize)]
+struct ShaderConfig {
+    stages: Vec<ShaderStageJson>,
+}
+
+#[derive(Deserialize)]
+struct ShaderStageJson {
+    stage_type: String,
+    stage_file: String,
+    #[serde(default)]
+    entry_point: Option<String>,
+    #[serde(default)]
+    specialization_constants: Vec<ShaderSpecializationConstantJson>,
+}
+
+#[derive(Deserialize)]
+struct ShaderSpecializationConstantJson {
+    id: u32,
+    value: serde_json::Value,
+}
+
+impl ShaderStageConfig {
+    /// Reads the stages of a shader JSON file. Each may name an `entry_point` and list `specialization_constants` as
+    /// `{ "id": 0, "value": true }` objects.
+    pub fn load_stages<P: AsRef<std::path::Path>>(path: P) -> Vec<Self> {
+        use crate::utility::fs;
+
+        log::debug!("Reading shader file: {}", path.as_ref().to_str().unwrap());
+
+        let config: ShaderConfig = serde_json::from_reader(fs::load(&path))
+            .unwrap_or_else(|error| panic!("Failed to parse shader {}: {}", path.as_ref().display(), error));
+
+        config.stages.into_iter().map(|stage| {
+            let stage_type = match stage.stage_type.as_str() {
+                "vertex" => vk::ShaderStageFlags::VERTEX,
+                "fragment" => vk::ShaderStageFlags::FRAGMENT,
+                "compute" => vk::ShaderStageFlags::COMPUTE,
+                "geometry" => vk::ShaderStageFlags::GEOMETRY,
+                "tessellation_control" => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                "tessellation_evaluation" => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                other => panic!("Shader {} has a stage of unknown type \"{}\".", path.as_ref().display(), other),
+            };
+
+            let specialization_constants = stage.specialization_constants.iter().map(|constant| {
+                let value = ShaderSpecializationValue::from_json(&constant.value).unwrap_or_else(|| panic!(
+                    "Specialization constant {} of {} is not a bool, 32-bit integer or float.",
+                    constant.id,
+                    stage.stage_file
+                ));
+
+                ShaderSpecializationConstant { id: constant.id, value }
+            })
+            .collect();
+
+            Self {
+                stage_type,
+                entry_point: stage.entry_point.unwrap_or_else(|| "main".to_string()),
+                stage_file: stage.stage_file,
+                specialization_constants,
+            }
+        })
+        .collect()
+    }
+
+    pub fn as_stage_info(&self) -> ShaderStageInfo<'_> {
+        ShaderStageInfo::new(self.stage_type, &self.stage_file)
+            .entry_point(&self.entry_point)
+            .specialization_constants(&self.specialization_constants)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShaderSpecializationConstant {
+    /// The `constant_id` given in the shader's layout qualifier.
+    pub id: u32,
+    pub value: ShaderSpecializationValue,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ShaderSpecializationValue {
+    Bool(bool),
+    Int32(i32),
+    Float32(f32),
+}
+
+impl ShaderSpecializationValue {
+    /// Reads a value from JSON: `true`/`false`, an integer, or a number with a fractional part or exponent. Float
+    /// constants therefore need a fractional part, `2.0` rather than `2`; stages check the type against the SPIR-V.
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(match value {
+            serde_json::Value::Bool(value) => Self::Bool(*value),
+            serde_json::Value::Number(number) if number.is_f64() => Self::Float32(number.as_f64()? as f32),
+            serde_json::Value::Number(number) => Self::Int32(number.as_i64()?.try_into().ok()?),
+            _ => return None,
+        })
+    }
+
+    /// How the type reads in GLSL, with `int` covering `uint` too.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Bool(_) => "a bool",
+            Self::Int32(_) => "an int",
+            Self::Float32(_) => "a float",
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 4] {
+        match self {
+            Self::Bool(value) => vk::Bool32::from(value).to_ne_bytes(),
+            Self::Int32(value) => value.to_ne_bytes(),
+            Self::Float32(value) => value.to_ne_bytes(),
+        }
+    }
 }
 
 pub struct ShaderPushConstantInfo {
@@ -214,9 +1052,22 @@ pub struct ShaderPushConstantInfo {
     pub stage_flags: vk::ShaderStageFlags,
 }
 
+#[derive(Clone, Copy)]
 pub struct ShaderVertexAttributeInfo {
     pub attribute_type: ShaderType,
     pub binding: u32,
+    /// Must match the input rate of `binding`. Instance-rate attributes advance once per instance.
+    pub input_rate: vk::VertexInputRate,
+}
+
+impl ShaderVertexAttributeInfo {
+    pub fn per_vertex(attribute_type: ShaderType, binding: u32) -> Self {
+        Self { attribute_type, binding, input_rate: vk::VertexInputRate::VERTEX }
+    }
+
+    pub fn per_instance(attribute_type: ShaderType, binding: u32) -> Self {
+        Self { attribute_type, binding, input_rate: vk::VertexInputRate::INSTANCE }
+    }
 }
 
 pub struct ShaderDescriptorSetInfo<'a> {
@@ -225,24 +1076,38 @@ pub struct ShaderDescriptorSetInfo<'a> {
 }
 
 pub struct ShaderDescriptorInfo<'a> {
+    pub name: &'a str,
     pub descriptor_type: ShaderDescriptorTypeInfo<'a>,
     pub stage_flags: vk::ShaderStageFlags,
 }
 
+pub struct ShaderUniformFieldInfo<'a> {
+    pub name: &'a str,
+    pub field_type: ShaderType,
+}
+
 pub enum ShaderDescriptorTypeInfo<'a> {
-    UniformBuffer { fields: &'a [ShaderType] },
-    Sampler
+    UniformBuffer { fields: &'a [ShaderUniformFieldInfo<'a>] },
+    StorageBuffer,
+    StorageImage,
+    Sampler,
+    /// An attachment written by an earlier subpass of the same render pass, read at the fragment's own position.
+    InputAttachment,
 }
 
 impl<'a> ShaderDescriptorTypeInfo<'a> {
     pub fn as_vk_descriptor_type(&self) -> vk::DescriptorType {
         match self {
             Self::UniformBuffer { .. } => vk::DescriptorType::UNIFORM_BUFFER,
+            Self::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+            Self::StorageImage => vk::DescriptorType::STORAGE_IMAGE,
             Self::Sampler => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            Self::InputAttachment => vk::DescriptorType::INPUT_ATTACHMENT,
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ShaderType {
     Float32,
     Float32_2,
@@ -272,6 +1137,35 @@ impl ShaderType {
         }
     }
 
+    /// Number of vertex input locations an attribute of this type occupies.
+    pub fn location_count(&self) -> u32 {
+        match self {
+            Self::Matrix4 => 4,
+            _ => 1,
+        }
+    }
+
+    fn location_size(&self) -> u32 {
+        self.size() / self.location_count()
+    }
+
+    /// The format of each location of a vertex attribute of this type.
+    pub fn as_vk_attribute_format(&self) -> vk::Format {
+        match self {
+            Self::Matrix4 => vk::Format::R32G32B32A32_SFLOAT,
+            _ => self.as_vk_format(),
+        }
+    }
+
+    /// Base alignment of the type inside a std140 uniform block.
+    pub fn std140_alignment(&self) -> u32 {
+        match self {
+            Self::Float32_2 => 8,
+            Self::Float32_3 | Self::Float32_4 | Self::Matrix4 => 16,
+            _ => self.size().max(4),
+        }
+    }
+
     pub fn as_vk_format(&self) -> vk::Format {
         match self {
             Self::Float32 => vk::Format::R32_SFLOAT,
@@ -289,12 +1183,185 @@ impl ShaderType {
     }
 }
 
-fn read_shader_from_file<P: AsRef<std::path::Path>>(path: P) -> Vec<u32> {
+/// The layout of one descriptor set, kept by the shader so that materials can be validated against it.
+#[derive(Clone, Debug)]
+pub struct ShaderDescriptorSetLayoutInfo {
+    pub bindings: Vec<ShaderDescriptorBindingInfo>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderDescriptorBindingInfo {
+    pub name: String,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    pub uniform_fields: Vec<ShaderUniformField>,
+    pub uniform_size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderUniformField {
+    pub name: String,
+    pub field_type: ShaderType,
+    pub offset: u32,
+}
+
+impl ShaderDescriptorSetLayoutInfo {
+    pub fn find_binding(&self, name: &str) -> Option<&ShaderDescriptorBindingInfo> {
+        self.bindings.iter().find(|binding| binding.name == name)
+    }
+
+    /// Finds the uniform buffer binding that contains the field `name`.
+    pub fn find_uniform_field(&self, name: &str) -> Option<(&ShaderDescriptorBindingInfo, &ShaderUniformField)> {
+        self.bindings.iter().find_map(|binding| {
+            binding.uniform_fields.iter()
+                .find(|field| field.name == name)
+                .map(|field| (binding, field))
+        })
+    }
+}
+
+fn describe_descriptor_sets(descriptor_sets: &[ShaderDescriptorSetInfo]) -> Vec<ShaderDescriptorSetLayoutInfo> {
+    descriptor_sets.iter().map(|set_info| {
+        let bindings = set_info.descriptors.iter().enumerate().map(|(i, descriptor)| {
+            let mut uniform_fields = Vec::new();
+            let mut uniform_size = 0u32;
+
+            if let ShaderDescriptorTypeInfo::UniformBuffer { fields } = descriptor.descriptor_type {
+                for field in fields {
+                    let offset = uniform_size.next_multiple_of(field.field_type.std140_alignment());
+
+                    uniform_fields.push(ShaderUniformField {
+                        name: field.name.to_string(),
+                        field_type: field.field_type,
+                        offset,
+                    });
+
+                    uniform_size = offset + field.field_type.size();
+                }
+
+                uniform_size = uniform_size.next_multiple_of(16);
+            }
+
+            ShaderDescriptorBindingInfo {
+                name: descriptor.name.to_string(),
+                binding: i as u32,
+                descriptor_type: descriptor.descriptor_type.as_vk_descriptor_type(),
+                descriptor_count: 1,
+                stage_flags: descriptor.stage_flags,
+                uniform_fields,
+                uniform_size,
+            }
+        })
+        .collect();
+
+        ShaderDescriptorSetLayoutInfo { bindings }
+    })
+    .collect()
+}
+
+pub(crate) fn create_descriptor_set_layouts_and_pool(
+    vkcontext: &VkContext,
+    descriptor_sets: &[ShaderDescriptorSetInfo],
+) -> (Vec<vk::DescriptorSetLayout>, vk::DescriptorPool) {
+    let set_bindings = descriptor_sets.iter().map(|set_info| {
+        set_info.descriptors.iter().enumerate().map(|(i, descriptor)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(i as u32)
+                .descriptor_type(descriptor.descriptor_type.as_vk_descriptor_type())
+                .descriptor_count(1)
+                .stage_flags(descriptor.stage_flags)
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+
+    let max_set_allocations = descriptor_sets.iter().map(|set_info| set_info.max_set_allocations).collect::<Vec<_>>();
+
+    create_descriptor_set_layouts_and_pool_from_bindings(vkcontext, &set_bindings, &max_set_allocations)
+}
+
+/// One layout per entry of `set_bindings`, and a pool that can hold `max_set_allocations[i]` sets of layout `i`.
+fn create_descriptor_set_layouts_and_pool_from_bindings(
+    vkcontext: &VkContext,
+    set_bindings: &[Vec<vk::DescriptorSetLayoutBinding>],
+    max_set_allocations: &[u32],
+) -> (Vec<vk::DescriptorSetLayout>, vk::DescriptorPool) {
+    let descriptor_set_layouts = set_bindings.iter().map(|layout_bindings| {
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(layout_bindings);
+
+        unsafe { vkcontext.device.create_descriptor_set_layout(&create_info, None).unwrap() }
+    })
+    .collect::<Vec<_>>();
+
+    let mut pool_sizes = Vec::new();
+    let mut max_pool_set_count = 0u32;
+
+    for (layout_bindings, &set_allocations) in set_bindings.iter().zip(max_set_allocations) {
+        pool_sizes.extend(layout_bindings.iter().map(|binding| {
+            vk::DescriptorPoolSize::default()
+                .ty(binding.descriptor_type)
+                .descriptor_count(binding.descriptor_count * set_allocations)
+        }));
+
+        max_pool_set_count += set_allocations;
+    }
+
+    let descriptor_pool = {
+        let ci = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .max_sets(max_pool_set_count)
+            .pool_sizes(&pool_sizes);
+
+        unsafe { vkcontext.device.create_descriptor_pool(&ci, None).unwrap() }
+    };
+
+    (descriptor_set_layouts, descriptor_pool)
+}
+
+/// Lays the push constants out back to back. Vulkan allows a stage in only one range, so each stage gets a range
+/// spanning every push constant it uses, and stages whose spans are identical share one.
+fn create_push_constant_ranges(push_constants: &[ShaderPushConstantInfo]) -> Vec<vk::PushConstantRange> {
+    let mut push_constant_offset = 0u32;
+
+    let spans = push_constants.iter().map(|push_constant| {
+        let start = push_constant_offset;
+        push_constant_offset += push_constant.push_constant_type.size();
+
+        (push_constant.stage_flags, start, push_constant_offset)
+    })
+    .collect::<Vec<_>>();
+
+    let mut push_constant_ranges = Vec::<vk::PushConstantRange>::new();
+
+    for stage in (0..u32::BITS).map(|bit| vk::ShaderStageFlags::from_raw(1 << bit)) {
+        let Some((start, end)) = spans.iter()
+            .filter(|(stage_flags, ..)| stage_flags.contains(stage))
+            .fold(None, |span: Option<(u32, u32)>, &(_, start, end)| match span {
+                Some((span_start, span_end)) => Some((span_start.min(start), span_end.max(end))),
+                None => Some((start, end)),
+            })
+        else {
+            continue;
+        };
+
+        match push_constant_ranges.iter_mut().find(|range| range.offset == start && range.size == end - start) {
+            Some(range) => range.stage_flags |= stage,
+            None => push_constant_ranges.push(vk::PushConstantRange::default().stage_flags(stage).offset(start).size(end - start)),
+        }
+    }
+
+    push_constant_ranges
+}
+
+fn read_shader_from_file<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Vec<u32>> {
     use crate::utility::fs;
 
     log::debug!("Reading shader file: {}", path.as_ref().to_str().unwrap());
 
-    let mut cursor = fs::load(path);
+    let mut cursor = fs::try_load(path)?;
 
-    ash::util::read_spv(&mut cursor).unwrap()
+    ash::util::read_spv(&mut cursor)
 }
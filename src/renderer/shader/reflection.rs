@@ -0,0 +1,1286 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use ash::vk;
+
+use super::{
+    ShaderDescriptorBindingInfo, ShaderDescriptorSetLayoutInfo, ShaderSpecializationValue, ShaderType, ShaderUniformField,
+};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_FUNCTION: u32 = 54;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// What a SPIR-V module declares: its entry points, vertex inputs, descriptor bindings and push constant block.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<ReflectedEntryPoint>,
+    /// Inputs of vertex entry points, one per location, sorted by location. Other stages' inputs are not reflected.
+    pub inputs: Vec<ReflectedInput>,
+    /// Sorted by set, then binding.
+    pub descriptor_bindings: Vec<ReflectedDescriptorBinding>,
+    pub push_constants: Option<ReflectedBlock>,
+    /// Sorted by id.
+    pub specialization_constants: Vec<ReflectedSpecializationConstant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedEntryPoint {
+    pub name: String,
+    pub stage: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedSpecializationConstant {
+    pub id: u32,
+    pub name: String,
+    /// The value used when the pipeline doesn't specialize the constant. Constants of types a
+    /// `ShaderSpecializationValue` can't hold, such as 64-bit ones, have none.
+    pub default_value: Option<ShaderSpecializationValue>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedInput {
+    pub name: String,
+    pub location: u32,
+    pub format: vk::Format,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedDescriptorBinding {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Zero for runtime-sized arrays and arrays sized by a specialization constant expression.
+    pub count: u32,
+    /// The stages that use the descriptor. Declared but unused descriptors have none.
+    pub stage_flags: vk::ShaderStageFlags,
+    /// The layout of uniform and storage buffer blocks.
+    pub block: Option<ReflectedBlock>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedBlock {
+    pub name: String,
+    pub size: u32,
+    /// The stages that use the block.
+    pub stage_flags: vk::ShaderStageFlags,
+    pub members: Vec<ReflectedBlockMember>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedBlockMember {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+    /// Members such as arrays, structs and non-square matrices have no `ShaderType`.
+    pub shader_type: Option<ShaderType>,
+}
+
+/// A difference between the layout a shader was created with and what its SPIR-V declares.
+#[derive(Clone, Debug)]
+pub enum LayoutMismatch {
+    MissingVertexAttribute { location: u32, name: String },
+    VertexAttributeFormat { location: u32, name: String, shader: vk::Format, pipeline: vk::Format },
+    MissingDescriptor { set: u32, binding: u32, name: String },
+    DescriptorType { set: u32, binding: u32, name: String, shader: vk::DescriptorType, pipeline: vk::DescriptorType },
+    DescriptorCount { set: u32, binding: u32, name: String, shader: u32, pipeline: u32 },
+    DescriptorStages { set: u32, binding: u32, name: String, missing: vk::ShaderStageFlags },
+    UniformFieldOffset { set: u32, binding: u32, field: String, shader: u32, pipeline: u32 },
+    UniformSize { set: u32, binding: u32, name: String, shader: u32, pipeline: u32 },
+    PushConstantStages { missing: vk::ShaderStageFlags },
+    PushConstantSize { shader: u32, pipeline: u32 },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingVertexAttribute { location, name } => {
+                write!(f, "vertex input \"{}\" at location {} has no vertex attribute", name, location)
+            },
+            Self::VertexAttributeFormat { location, name, shader, pipeline } => write!(
+                f,
+                "vertex input \"{}\" at location {} is {:?} in the shader but the vertex attribute is {:?}",
+                name, location, shader, pipeline
+            ),
+            Self::MissingDescriptor { set, binding, name } => {
+                write!(f, "descriptor \"{}\" at set {} binding {} is not in the layout", name, set, binding)
+            },
+            Self::DescriptorType { set, binding, name, shader, pipeline } => write!(
+                f,
+                "descriptor \"{}\" at set {} binding {} is {:?} in the shader but {:?} in the layout",
+                name, set, binding, shader, pipeline
+            ),
+            Self::DescriptorCount { set, binding, name, shader, pipeline } => write!(
+                f,
+                "descriptor \"{}\" at set {} binding {} is an array of {} in the shader but {} in the layout",
+                name, set, binding, shader, pipeline
+            ),
+            Self::DescriptorStages { set, binding, name, missing } => write!(
+                f,
+                "descriptor \"{}\" at set {} binding {} is used by {:?} which the layout does not include",
+                name, set, binding, missing
+            ),
+            Self::UniformFieldOffset { set, binding, field, shader, pipeline } => write!(
+                f,
+                "uniform field \"{}\" at set {} binding {} is at offset {} in the shader but {} in the layout",
+                field, set, binding, shader, pipeline
+            ),
+            Self::UniformSize { set, binding, name, shader, pipeline } => write!(
+                f,
+                "uniform block \"{}\" at set {} binding {} is {} bytes in the shader but only {} in the layout",
+                name, set, binding, shader, pipeline
+            ),
+            Self::PushConstantStages { missing } => {
+                write!(f, "push constants are used by {:?} which no push constant range includes", missing)
+            },
+            Self::PushConstantSize { shader, pipeline } => write!(
+                f,
+                "push constant block is {} bytes in the shader but the push constant ranges cover only {}",
+                shader, pipeline
+            ),
+        }
+    }
+}
+
+impl ShaderReflection {
+    /// Reflects a SPIR-V module given as words, as returned by `ash::util::read_spv`.
+    pub fn from_spirv(words: &[u32]) -> Result<Self, String> {
+        let module = SpirvModule::parse(words)?;
+
+        module.reflect()
+    }
+
+    /// Combines the reflections of every stage of a pipeline. Bindings and push constants used by several stages are
+    /// merged and keep every stage that uses them.
+    pub fn merged<'r>(reflections: impl IntoIterator<Item = &'r ShaderReflection>) -> Self {
+        let mut merged = Self::default();
+
+        for reflection in reflections {
+            merged.entry_points.extend(reflection.entry_points.iter().cloned());
+            merged.inputs.extend(reflection.inputs.iter().cloned());
+
+            for constant in &reflection.specialization_constants {
+                if !merged.specialization_constants.iter().any(|c| c.id == constant.id) {
+                    merged.specialization_constants.push(constant.clone());
+                }
+            }
+
+            for binding in &reflection.descriptor_bindings {
+                match merged.descriptor_bindings.iter_mut().find(|b| b.set == binding.set && b.binding == binding.binding) {
+                    Some(existing) => existing.stage_flags |= binding.stage_flags,
+                    None => merged.descriptor_bindings.push(binding.clone()),
+                }
+            }
+
+            if let Some(block) = &reflection.push_constants {
+                match &mut merged.push_constants {
+                    Some(existing) => {
+                        existing.stage_flags |= block.stage_flags;
+                        existing.size = existing.size.max(block.size);
+
+                        for member in &block.members {
+                            if !existing.members.iter().any(|m| m.offset == member.offset) {
+                                existing.members.push(member.clone());
+                            }
+                        }
+
+                        existing.members.sort_by_key(|member| member.offset);
+                    },
+                    None => merged.push_constants = Some(block.clone()),
+                }
+            }
+        }
+
+        merged.inputs.sort_by_key(|input| input.location);
+        merged.specialization_constants.sort_by_key(|constant| constant.id);
+        merged.descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        merged
+    }
+
+    pub fn stage_flags(&self) -> vk::ShaderStageFlags {
+        self.entry_points.iter().fold(vk::ShaderStageFlags::empty(), |flags, entry_point| flags | entry_point.stage)
+    }
+
+    /// Number of descriptor set layouts the pipeline layout needs, including unused sets below the highest one.
+    pub fn descriptor_set_count(&self) -> u32 {
+        self.descriptor_bindings.iter().map(|binding| binding.set + 1).max().unwrap_or(0)
+    }
+}
+
+impl ShaderReflection {
+    /// A per-vertex binding and attributes that read every vertex input tightly packed, in location order.
+    pub fn vertex_input(&self, binding: u32) -> (vk::VertexInputBindingDescription, Vec<vk::VertexInputAttributeDescription>) {
+        let mut offset = 0;
+
+        let attributes = self.inputs.iter().map(|input| {
+            let attribute = vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(input.location)
+                .format(input.format)
+                .offset(offset);
+
+            offset += input.size;
+
+            attribute
+        })
+        .collect::<Vec<_>>();
+
+        let binding_description = vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(offset)
+            .input_rate(vk::VertexInputRate::VERTEX);
+
+        (binding_description, attributes)
+    }
+
+    /// The bindings of each descriptor set layout, with empty sets for indices the shader skips.
+    pub fn descriptor_set_layout_bindings(&self) -> Vec<Vec<vk::DescriptorSetLayoutBinding<'static>>> {
+        (0..self.descriptor_set_count()).map(|set| {
+            self.descriptor_bindings.iter()
+                .filter(|binding| binding.set == set)
+                .map(|binding| {
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(binding.binding)
+                        .descriptor_type(binding.descriptor_type)
+                        .descriptor_count(binding.count.max(1))
+                        .stage_flags(binding.stage_flags)
+                })
+                .collect()
+        })
+        .collect()
+    }
+
+    /// Descriptor set layout descriptions for materials. Uniform fields without a `ShaderType` are left out.
+    pub fn descriptor_set_layout_infos(&self) -> Vec<ShaderDescriptorSetLayoutInfo> {
+        (0..self.descriptor_set_count()).map(|set| {
+            let bindings = self.descriptor_bindings.iter()
+                .filter(|binding| binding.set == set)
+                .map(|binding| {
+                    let (uniform_fields, uniform_size) = match &binding.block {
+                        Some(block) if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER => {
+                            let fields = block.members.iter()
+                                .filter_map(|member| member.shader_type.map(|field_type| ShaderUniformField {
+                                    name: member.name.clone(),
+                                    field_type,
+                                    offset: member.offset,
+                                }))
+                                .collect();
+
+                            (fields, block.size.next_multiple_of(16))
+                        },
+                        _ => (Vec::new(), 0),
+                    };
+
+                    ShaderDescriptorBindingInfo {
+                        name: binding.name.clone(),
+                        binding: binding.binding,
+                        descriptor_type: binding.descriptor_type,
+                        descriptor_count: binding.count.max(1),
+                        stage_flags: binding.stage_flags,
+                        uniform_fields,
+                        uniform_size,
+                    }
+                })
+                .collect();
+
+            ShaderDescriptorSetLayoutInfo { bindings }
+        })
+        .collect()
+    }
+
+    /// One range covering the whole push constant block, visible to every stage that uses it.
+    pub fn push_constant_ranges(&self) -> Vec<vk::PushConstantRange> {
+        self.push_constants.iter().filter(|block| !block.stage_flags.is_empty()).map(|block| {
+            vk::PushConstantRange::default()
+                .stage_flags(block.stage_flags)
+                .offset(0)
+                .size(block.size)
+        })
+        .collect()
+    }
+}
+
+impl ShaderReflection {
+    /// Compares a pipeline layout against what the SPIR-V declares. Anything the shader uses must be provided with a
+    /// matching type; extra attributes, descriptors and push constant bytes are fine.
+    pub fn verify(
+        &self,
+        vertex_attributes: &[vk::VertexInputAttributeDescription],
+        descriptor_sets: &[ShaderDescriptorSetLayoutInfo],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Vec<LayoutMismatch> {
+        let mut mismatches = Vec::new();
+
+        for input in &self.inputs {
+            match vertex_attributes.iter().find(|attribute| attribute.location == input.location) {
+                None => mismatches.push(LayoutMismatch::MissingVertexAttribute {
+                    location: input.location,
+                    name: input.name.clone(),
+                }),
+                Some(attribute) if attribute.format != input.format => mismatches.push(LayoutMismatch::VertexAttributeFormat {
+                    location: input.location,
+                    name: input.name.clone(),
+                    shader: input.format,
+                    pipeline: attribute.format,
+                }),
+                Some(_) => (),
+            }
+        }
+
+        for reflected in &self.descriptor_bindings {
+            let (set, binding, name) = (reflected.set, reflected.binding, reflected.name.clone());
+
+            let Some(descriptor) = descriptor_sets.get(set as usize)
+                .and_then(|set_info| set_info.bindings.iter().find(|descriptor| descriptor.binding == binding))
+            else {
+                mismatches.push(LayoutMismatch::MissingDescriptor { set, binding, name });
+                continue;
+            };
+
+            if descriptor.descriptor_type != reflected.descriptor_type {
+                mismatches.push(LayoutMismatch::DescriptorType {
+                    set,
+                    binding,
+                    name,
+                    shader: reflected.descriptor_type,
+                    pipeline: descriptor.descriptor_type,
+                });
+                continue;
+            }
+
+            // Runtime-sized arrays can be bound with any number of descriptors.
+            if reflected.count != 0 && reflected.count != descriptor.descriptor_count {
+                mismatches.push(LayoutMismatch::DescriptorCount {
+                    set,
+                    binding,
+                    name: name.clone(),
+                    shader: reflected.count,
+                    pipeline: descriptor.descriptor_count,
+                });
+            }
+
+            let missing_stages = reflected.stage_flags & !descriptor.stage_flags;
+            if !missing_stages.is_empty() {
+                mismatches.push(LayoutMismatch::DescriptorStages { set, binding, name: name.clone(), missing: missing_stages });
+            }
+
+            // Uniform buffers declared without fields leave their layout to the caller. Layouts can only describe members
+            // with a `ShaderType`, so the others are skipped.
+            let has_fields = descriptor.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER && !descriptor.uniform_fields.is_empty();
+
+            if let (Some(block), true) = (&reflected.block, has_fields) {
+                let members = block.members.iter().filter(|member| member.shader_type.is_some());
+
+                for (field, member) in descriptor.uniform_fields.iter().zip(members) {
+                    if field.offset != member.offset {
+                        mismatches.push(LayoutMismatch::UniformFieldOffset {
+                            set,
+                            binding,
+                            field: field.name.clone(),
+                            shader: member.offset,
+                            pipeline: field.offset,
+                        });
+                    }
+                }
+
+                if descriptor.uniform_size < block.size {
+                    mismatches.push(LayoutMismatch::UniformSize { set, binding, name, shader: block.size, pipeline: descriptor.uniform_size });
+                }
+            }
+        }
+
+        if let Some(block) = self.push_constants.as_ref().filter(|block| !block.stage_flags.is_empty()) {
+            let provided_stages = push_constant_ranges.iter().fold(vk::ShaderStageFlags::empty(), |flags, range| flags | range.stage_flags);
+            let provided_size = push_constant_ranges.iter().map(|range| range.offset + range.size).max().unwrap_or(0);
+
+            let missing = block.stage_flags & !provided_stages;
+            if !missing.is_empty() {
+                mismatches.push(LayoutMismatch::PushConstantStages { missing });
+            }
+
+            if provided_size < block.size {
+                mismatches.push(LayoutMismatch::PushConstantSize { shader: block.size, pipeline: provided_size });
+            }
+        }
+
+        mismatches
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Decorations {
+    block: bool,
+    buffer_block: bool,
+    built_in: bool,
+    location: Option<u32>,
+    binding: Option<u32>,
+    descriptor_set: Option<u32>,
+    offset: Option<u32>,
+    array_stride: Option<u32>,
+    matrix_stride: Option<u32>,
+    spec_id: Option<u32>,
+}
+
+impl Decorations {
+    fn apply(&mut self, decoration: u32, operand: Option<u32>) {
+        match decoration {
+            DECORATION_BLOCK => self.block = true,
+            DECORATION_BUFFER_BLOCK => self.buffer_block = true,
+            DECORATION_BUILT_IN => self.built_in = true,
+            DECORATION_LOCATION => self.location = operand,
+            DECORATION_BINDING => self.binding = operand,
+            DECORATION_DESCRIPTOR_SET => self.descriptor_set = operand,
+            DECORATION_OFFSET => self.offset = operand,
+            DECORATION_ARRAY_STRIDE => self.array_stride = operand,
+            DECORATION_MATRIX_STRIDE => self.matrix_stride = operand,
+            DECORATION_SPEC_ID => self.spec_id = operand,
+            _ => (),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum SpirvType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, columns: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+/// A scalar specialization constant and the low word of its default value, 1 or 0 for booleans.
+struct SpirvSpecConstant {
+    id: u32,
+    type_id: u32,
+    value: u32,
+}
+
+struct SpirvVariable {
+    id: u32,
+    pointer_type: u32,
+    storage_class: u32,
+}
+
+#[derive(Default)]
+struct SpirvModule {
+    entry_points: Vec<ReflectedEntryPoint>,
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), Decorations>,
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    spec_constants: Vec<SpirvSpecConstant>,
+    variables: Vec<SpirvVariable>,
+    /// Every id that appears as an operand inside a function body, which covers every statically used variable.
+    referenced_ids: HashSet<u32>,
+}
+
+impl SpirvModule {
+    fn parse(words: &[u32]) -> Result<Self, String> {
+        if words.len() < 5 || words[0] != SPIRV_MAGIC {
+            return Err("not a SPIR-V module".to_string());
+        }
+
+        let mut module = Self::default();
+        let mut position = 5;
+        let mut in_function_bodies = false;
+
+        while position < words.len() {
+            let word_count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+
+            if word_count == 0 || position + word_count > words.len() {
+                return Err(format!("malformed instruction at word {}", position));
+            }
+
+            let operands = &words[position + 1..position + word_count];
+
+            in_function_bodies |= opcode == OP_FUNCTION;
+            if in_function_bodies {
+                module.referenced_ids.extend(operands.iter().copied());
+            }
+
+            module.parse_instruction(opcode, operands)?;
+            position += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), String> {
+        let operand = |index: usize| -> Result<u32, String> {
+            operands.get(index).copied().ok_or_else(|| format!("instruction {} is missing operand {}", opcode, index))
+        };
+        let operands_from = |index: usize| -> Result<&[u32], String> {
+            operands.get(index..).ok_or_else(|| format!("instruction {} is missing operand {}", opcode, index))
+        };
+
+        match opcode {
+            OP_NAME => {
+                self.names.insert(operand(0)?, read_string(operands_from(1)?));
+            },
+            OP_MEMBER_NAME => {
+                self.member_names.insert((operand(0)?, operand(1)?), read_string(operands_from(2)?));
+            },
+            OP_ENTRY_POINT => {
+                let stage = match operand(0)? {
+                    0 => vk::ShaderStageFlags::VERTEX,
+                    1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                    2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                    3 => vk::ShaderStageFlags::GEOMETRY,
+                    4 => vk::ShaderStageFlags::FRAGMENT,
+                    5 => vk::ShaderStageFlags::COMPUTE,
+                    5267 | 5364 => vk::ShaderStageFlags::TASK_EXT,
+                    5268 | 5365 => vk::ShaderStageFlags::MESH_EXT,
+                    5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+                    5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+                    5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+                    5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                    5317 => vk::ShaderStageFlags::MISS_KHR,
+                    5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+                    model => return Err(format!("unsupported execution model {}", model)),
+                };
+
+                self.entry_points.push(ReflectedEntryPoint { name: read_string(operands_from(2)?), stage });
+            },
+            OP_DECORATE => {
+                self.decorations.entry(operand(0)?).or_default().apply(operand(1)?, operands.get(2).copied());
+            },
+            OP_MEMBER_DECORATE => {
+                self.member_decorations.entry((operand(0)?, operand(1)?)).or_default().apply(operand(2)?, operands.get(3).copied());
+            },
+            OP_TYPE_BOOL => {
+                self.types.insert(operand(0)?, SpirvType::Bool);
+            },
+            OP_TYPE_INT => {
+                self.types.insert(operand(0)?, SpirvType::Int { width: operand(1)?, signed: operand(2)? == 1 });
+            },
+            OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, SpirvType::Float { width: operand(1)? });
+            },
+            OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, SpirvType::Vector { component: operand(1)?, count: operand(2)? });
+            },
+            OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, SpirvType::Matrix { column: operand(1)?, columns: operand(2)? });
+            },
+            OP_TYPE_IMAGE => {
+                self.types.insert(operand(0)?, SpirvType::Image { dim: operand(2)?, sampled: operand(6)? });
+            },
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, SpirvType::Sampler);
+            },
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, SpirvType::SampledImage);
+            },
+            OP_TYPE_ARRAY => {
+                // Lengths computed by OpSpecConstantOp aren't evaluated, so those arrays are reflected as empty.
+                let length = match self.constants.get(&operand(2)?) {
+                    Some(&length) => length,
+                    None => {
+                        log::warn!("Array %{} is sized by a specialization constant expression; reflecting it as empty.", operand(0)?);
+                        0
+                    },
+                };
+
+                self.types.insert(operand(0)?, SpirvType::Array { element: operand(1)?, length });
+            },
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, SpirvType::RuntimeArray { element: operand(1)? });
+            },
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, SpirvType::Struct { members: operands_from(1)?.to_vec() });
+            },
+            OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, SpirvType::Pointer { pointee: operand(2)? });
+            },
+            OP_TYPE_ACCELERATION_STRUCTURE => {
+                self.types.insert(operand(0)?, SpirvType::AccelerationStructure);
+            },
+            OP_CONSTANT => {
+                // Only the low word matters; constants are only looked up as array lengths.
+                self.constants.insert(operand(1)?, operand(2)?);
+            },
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                let value = (opcode == OP_SPEC_CONSTANT_TRUE) as u32;
+                self.spec_constants.push(SpirvSpecConstant { id: operand(1)?, type_id: operand(0)?, value });
+            },
+            OP_SPEC_CONSTANT => {
+                // Array lengths may be specialization constants, sized by their default.
+                self.constants.insert(operand(1)?, operand(2)?);
+                self.spec_constants.push(SpirvSpecConstant { id: operand(1)?, type_id: operand(0)?, value: operand(2)? });
+            },
+            OP_VARIABLE => {
+                self.variables.push(SpirvVariable { id: operand(1)?, pointer_type: operand(0)?, storage_class: operand(2)? });
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl SpirvModule {
+    fn reflect(&self) -> Result<ShaderReflection, String> {
+        let stage_flags = self.entry_points.iter().fold(vk::ShaderStageFlags::empty(), |flags, entry_point| flags | entry_point.stage);
+
+        let mut reflection = ShaderReflection {
+            entry_points: self.entry_points.clone(),
+            ..Default::default()
+        };
+
+        for variable in &self.variables {
+            let decorations = self.decorations.get(&variable.id).copied().unwrap_or_default();
+            let type_id = match self.types.get(&variable.pointer_type) {
+                Some(SpirvType::Pointer { pointee }) => *pointee,
+                _ => return Err(format!("variable %{} is not a pointer", variable.id)),
+            };
+
+            let used_by = if self.referenced_ids.contains(&variable.id) { stage_flags } else { vk::ShaderStageFlags::empty() };
+
+            match variable.storage_class {
+                STORAGE_CLASS_INPUT if stage_flags.contains(vk::ShaderStageFlags::VERTEX) && !decorations.built_in => {
+                    let Some(location) = decorations.location else { continue; };
+
+                    self.reflect_input(self.name(variable.id), location, type_id, &mut reflection.inputs)?;
+                },
+                STORAGE_CLASS_UNIFORM_CONSTANT | STORAGE_CLASS_UNIFORM | STORAGE_CLASS_STORAGE_BUFFER => {
+                    let (Some(set), Some(binding)) = (decorations.descriptor_set, decorations.binding) else { continue; };
+
+                    reflection.descriptor_bindings.push(self.reflect_descriptor(variable, set, binding, type_id, used_by)?);
+                },
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    let mut block = self.reflect_block(type_id, used_by)?;
+                    block.name = self.name(variable.id);
+
+                    reflection.push_constants = Some(block);
+                },
+                _ => (),
+            }
+        }
+
+        for constant in &self.spec_constants {
+            let Some(id) = self.decorations.get(&constant.id).and_then(|decorations| decorations.spec_id) else { continue; };
+
+            let default_value = match self.get_type(constant.type_id)? {
+                SpirvType::Bool => Some(ShaderSpecializationValue::Bool(constant.value != 0)),
+                SpirvType::Int { width: 32, .. } => Some(ShaderSpecializationValue::Int32(constant.value as i32)),
+                SpirvType::Float { width: 32 } => Some(ShaderSpecializationValue::Float32(f32::from_bits(constant.value))),
+                _ => None,
+            };
+
+            reflection.specialization_constants.push(ReflectedSpecializationConstant { id, name: self.name(constant.id), default_value });
+        }
+
+        reflection.inputs.sort_by_key(|input| input.location);
+        reflection.specialization_constants.sort_by_key(|constant| constant.id);
+        reflection.descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        Ok(reflection)
+    }
+
+    fn reflect_input(&self, name: String, location: u32, type_id: u32, inputs: &mut Vec<ReflectedInput>) -> Result<(), String> {
+        match self.get_type(type_id)? {
+            // Matrices and arrays take one location per column or element.
+            SpirvType::Matrix { column, columns } => {
+                for i in 0..*columns {
+                    self.reflect_input(format!("{}[{}]", name, i), location + i, *column, inputs)?;
+                }
+            },
+            SpirvType::Array { element, length } => {
+                for i in 0..*length {
+                    self.reflect_input(format!("{}[{}]", name, i), location + i, *element, inputs)?;
+                }
+            },
+            _ => {
+                let (format, size) = self.input_format(type_id)
+                    .ok_or_else(|| format!("vertex input \"{}\" has an unsupported type", name))?;
+
+                inputs.push(ReflectedInput { name, location, format, size });
+            },
+        }
+
+        Ok(())
+    }
+
+    fn reflect_descriptor(
+        &self,
+        variable: &SpirvVariable,
+        set: u32,
+        binding: u32,
+        type_id: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> Result<ReflectedDescriptorBinding, String> {
+        let (element_type, count) = match self.get_type(type_id)? {
+            SpirvType::Array { element, length } => (*element, *length),
+            SpirvType::RuntimeArray { element } => (*element, 0),
+            _ => (type_id, 1),
+        };
+
+        let type_decorations = self.decorations.get(&element_type).copied().unwrap_or_default();
+
+        let descriptor_type = match (self.get_type(element_type)?, variable.storage_class) {
+            (SpirvType::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (SpirvType::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (SpirvType::Image { dim: DIM_SUBPASS_DATA, .. }, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (SpirvType::Image { dim: DIM_BUFFER, sampled: 2 }, _) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (SpirvType::Image { dim: DIM_BUFFER, .. }, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (SpirvType::Image { sampled: 2, .. }, _) => vk::DescriptorType::STORAGE_IMAGE,
+            (SpirvType::Image { .. }, _) => vk::DescriptorType::SAMPLED_IMAGE,
+            (SpirvType::AccelerationStructure, _) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (SpirvType::Struct { .. }, STORAGE_CLASS_STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            (SpirvType::Struct { .. }, STORAGE_CLASS_UNIFORM) if type_decorations.buffer_block => vk::DescriptorType::STORAGE_BUFFER,
+            (SpirvType::Struct { .. }, STORAGE_CLASS_UNIFORM) => vk::DescriptorType::UNIFORM_BUFFER,
+            _ => return Err(format!("descriptor at set {} binding {} has an unsupported type", set, binding)),
+        };
+
+        let block = match descriptor_type {
+            vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::STORAGE_BUFFER => Some(self.reflect_block(element_type, stage_flags)?),
+            _ => None,
+        };
+
+        // Blocks are usually named after their instance, falling back to the block type for anonymous instances.
+        let name = match self.names.get(&variable.id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => self.name(element_type),
+        };
+
+        Ok(ReflectedDescriptorBinding { name, set, binding, descriptor_type, count, stage_flags, block })
+    }
+
+    fn reflect_block(&self, type_id: u32, stage_flags: vk::ShaderStageFlags) -> Result<ReflectedBlock, String> {
+        let SpirvType::Struct { members } = self.get_type(type_id)? else {
+            return Err(format!("block %{} is not a struct", type_id));
+        };
+
+        let members = members.iter().enumerate().map(|(index, &member_type)| {
+            let decorations = self.member_decorations.get(&(type_id, index as u32)).copied().unwrap_or_default();
+
+            Ok(ReflectedBlockMember {
+                name: self.member_names.get(&(type_id, index as u32)).cloned().unwrap_or_default(),
+                offset: decorations.offset.unwrap_or(0),
+                size: self.type_size(member_type, decorations.matrix_stride)?,
+                shader_type: self.shader_type(member_type),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+        let size = members.iter().map(|member| member.offset + member.size).max().unwrap_or(0);
+
+        Ok(ReflectedBlock { name: self.name(type_id), size, stage_flags, members })
+    }
+}
+
+impl SpirvModule {
+    fn get_type(&self, type_id: u32) -> Result<&SpirvType, String> {
+        self.types.get(&type_id).ok_or_else(|| format!("unknown type %{}", type_id))
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Bytes a value of `type_id` occupies in a block. Runtime arrays count as empty.
+    fn type_size(&self, type_id: u32, matrix_stride: Option<u32>) -> Result<u32, String> {
+        let size = match self.get_type(type_id)? {
+            SpirvType::Bool => 4,
+            SpirvType::Int { width, .. } | SpirvType::Float { width } => width / 8,
+            SpirvType::Vector { component, count } => count * self.type_size(*component, None)?,
+            SpirvType::Matrix { column, columns } => match matrix_stride {
+                Some(stride) => columns * stride,
+                None => columns * self.type_size(*column, None)?,
+            },
+            SpirvType::Array { element, length } => {
+                let stride = match self.decorations.get(&type_id).and_then(|decorations| decorations.array_stride) {
+                    Some(stride) => stride,
+                    None => self.type_size(*element, matrix_stride)?,
+                };
+
+                length * stride
+            },
+            SpirvType::RuntimeArray { .. } => 0,
+            SpirvType::Struct { members } => {
+                let mut size = 0;
+
+                for (index, &member_type) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(type_id, index as u32)).copied().unwrap_or_default();
+                    let member_size = self.type_size(member_type, decorations.matrix_stride)?;
+
+                    size = size.max(decorations.offset.unwrap_or(size) + member_size);
+                }
+
+                size
+            },
+            _ => return Err(format!("type %{} has no size", type_id)),
+        };
+
+        Ok(size)
+    }
+
+    fn shader_type(&self, type_id: u32) -> Option<ShaderType> {
+        let shader_type = match self.types.get(&type_id)? {
+            SpirvType::Float { width: 32 } => ShaderType::Float32,
+            SpirvType::Int { width: 8, signed } => if *signed { ShaderType::Int8 } else { ShaderType::UInt8 },
+            SpirvType::Int { width: 16, signed } => if *signed { ShaderType::Int16 } else { ShaderType::UInt16 },
+            SpirvType::Int { width: 32, signed } => if *signed { ShaderType::Int32 } else { ShaderType::UInt32 },
+            SpirvType::Vector { component, count } => match (self.shader_type(*component)?, count) {
+                (ShaderType::Float32, 2) => ShaderType::Float32_2,
+                (ShaderType::Float32, 3) => ShaderType::Float32_3,
+                (ShaderType::Float32, 4) => ShaderType::Float32_4,
+                _ => return None,
+            },
+            SpirvType::Matrix { column, columns: 4 } => match self.shader_type(*column)? {
+                ShaderType::Float32_4 => ShaderType::Matrix4,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(shader_type)
+    }
+
+    /// The vertex attribute format that feeds a scalar or vector input, and its size.
+    fn input_format(&self, type_id: u32) -> Option<(vk::Format, u32)> {
+        let (component, count) = match self.types.get(&type_id)? {
+            SpirvType::Vector { component, count } => (self.types.get(component)?, *count),
+            scalar => (scalar, 1),
+        };
+
+        let formats = match component {
+            SpirvType::Float { width: 16 } => [vk::Format::R16_SFLOAT, vk::Format::R16G16_SFLOAT, vk::Format::R16G16B16_SFLOAT, vk::Format::R16G16B16A16_SFLOAT],
+            SpirvType::Float { width: 32 } => [vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT, vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT],
+            SpirvType::Float { width: 64 } => [vk::Format::R64_SFLOAT, vk::Format::R64G64_SFLOAT, vk::Format::R64G64B64_SFLOAT, vk::Format::R64G64B64A64_SFLOAT],
+            SpirvType::Int { width: 8, signed: true } => [vk::Format::R8_SINT, vk::Format::R8G8_SINT, vk::Format::R8G8B8_SINT, vk::Format::R8G8B8A8_SINT],
+            SpirvType::Int { width: 8, signed: false } => [vk::Format::R8_UINT, vk::Format::R8G8_UINT, vk::Format::R8G8B8_UINT, vk::Format::R8G8B8A8_UINT],
+            SpirvType::Int { width: 16, signed: true } => [vk::Format::R16_SINT, vk::Format::R16G16_SINT, vk::Format::R16G16B16_SINT, vk::Format::R16G16B16A16_SINT],
+            SpirvType::Int { width: 16, signed: false } => [vk::Format::R16_UINT, vk::Format::R16G16_UINT, vk::Format::R16G16B16_UINT, vk::Format::R16G16B16A16_UINT],
+            SpirvType::Int { width: 32, signed: true } => [vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT],
+            SpirvType::Int { width: 32, signed: false } => [vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT],
+            _ => return None,
+        };
+
+        Some((*formats.get((count as usize).checked_sub(1)?)?, self.type_size(type_id, None).ok()?))
+    }
+}
+
+/// Reads a nul-terminated literal string packed into words.
+fn read_string(words: &[u32]) -> String {
+    let bytes = words.iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_LOAD: u32 = 61;
+    const OP_SPEC_CONSTANT_OP: u32 = 52;
+    const OP_I_ADD: u32 = 128;
+
+    const MODEL_VERTEX: u32 = 0;
+    const MODEL_FRAGMENT: u32 = 4;
+    const MODEL_GL_COMPUTE: u32 = 5;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+
+        words
+    }
+
+    fn string(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+
+        bytes.chunks(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect()
+    }
+
+    fn entry_point(model: u32) -> Vec<u32> {
+        instruction(OP_ENTRY_POINT, &[[model, 100].as_slice(), &string("main")].concat())
+    }
+
+    fn name(id: u32, value: &str) -> Vec<u32> {
+        instruction(OP_NAME, &[[id].as_slice(), &string(value)].concat())
+    }
+
+    fn member_name(id: u32, member: u32, value: &str) -> Vec<u32> {
+        instruction(OP_MEMBER_NAME, &[[id, member].as_slice(), &string(value)].concat())
+    }
+
+    /// A function body that loads each of `used`, which is all reflection looks at to decide what is used.
+    fn function(used: &[u32]) -> Vec<u32> {
+        let mut words = instruction(OP_FUNCTION, &[101, 100, 0, 102]);
+
+        for (index, &id) in used.iter().enumerate() {
+            words.extend(instruction(OP_LOAD, &[103, 200 + index as u32, id]));
+        }
+
+        words
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 300, 0];
+        words.extend(instructions.concat());
+
+        words
+    }
+
+    fn reflect(instructions: &[Vec<u32>]) -> ShaderReflection {
+        ShaderReflection::from_spirv(&module(instructions)).unwrap()
+    }
+
+    /// A vertex shader using a uniform block at set 1 binding 2 whose members are a mat4, a mat3, a vec4 and a float.
+    fn uniform_block_module() -> Vec<Vec<u32>> {
+        vec![
+            entry_point(MODEL_VERTEX),
+            name(9, "camera"),
+            member_name(6, 0, "view_projection"),
+            member_name(6, 1, "normal_matrix"),
+            member_name(6, 2, "position"),
+            member_name(6, 3, "exposure"),
+            instruction(OP_DECORATE, &[6, DECORATION_BLOCK]),
+            instruction(OP_DECORATE, &[9, DECORATION_DESCRIPTOR_SET, 1]),
+            instruction(OP_DECORATE, &[9, DECORATION_BINDING, 2]),
+            instruction(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_MEMBER_DECORATE, &[6, 0, DECORATION_MATRIX_STRIDE, 16]),
+            instruction(OP_MEMBER_DECORATE, &[6, 1, DECORATION_OFFSET, 64]),
+            instruction(OP_MEMBER_DECORATE, &[6, 1, DECORATION_MATRIX_STRIDE, 16]),
+            instruction(OP_MEMBER_DECORATE, &[6, 2, DECORATION_OFFSET, 112]),
+            instruction(OP_MEMBER_DECORATE, &[6, 3, DECORATION_OFFSET, 128]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_VECTOR, &[2, 1, 4]),
+            instruction(OP_TYPE_MATRIX, &[3, 2, 4]),
+            instruction(OP_TYPE_VECTOR, &[4, 1, 3]),
+            instruction(OP_TYPE_MATRIX, &[5, 4, 3]),
+            instruction(OP_TYPE_STRUCT, &[6, 3, 5, 2, 1]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_UNIFORM, 6]),
+            instruction(OP_VARIABLE, &[7, 9, STORAGE_CLASS_UNIFORM]),
+            function(&[9]),
+        ]
+    }
+
+    #[test]
+    fn uniform_block_offsets_and_matrix_stride() {
+        let reflection = reflect(&uniform_block_module());
+
+        assert_eq!(reflection.descriptor_set_count(), 2);
+        assert_eq!(reflection.descriptor_bindings.len(), 1);
+
+        let binding = &reflection.descriptor_bindings[0];
+        assert_eq!((binding.set, binding.binding, binding.name.as_str()), (1, 2, "camera"));
+        assert_eq!(binding.descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(binding.count, 1);
+        assert_eq!(binding.stage_flags, vk::ShaderStageFlags::VERTEX);
+
+        // The mat3 is padded to three 16 byte columns by its matrix stride.
+        let block = binding.block.as_ref().unwrap();
+        let members = block.members.iter().map(|member| (member.name.as_str(), member.offset, member.size, member.shader_type)).collect::<Vec<_>>();
+        assert_eq!(members, [
+            ("view_projection", 0, 64, Some(ShaderType::Matrix4)),
+            ("normal_matrix", 64, 48, None),
+            ("position", 112, 16, Some(ShaderType::Float32_4)),
+            ("exposure", 128, 4, Some(ShaderType::Float32)),
+        ]);
+        assert_eq!(block.size, 132);
+
+        let layout_infos = reflection.descriptor_set_layout_infos();
+        assert!(layout_infos[0].bindings.is_empty());
+        assert_eq!(layout_infos[1].bindings[0].uniform_fields.len(), 3);
+        assert_eq!(layout_infos[1].bindings[0].uniform_size, 144);
+    }
+
+    #[test]
+    fn verify_against_layout_infos() {
+        let reflection = reflect(&uniform_block_module());
+        let layout_infos = reflection.descriptor_set_layout_infos();
+
+        assert!(reflection.verify(&[], &layout_infos, &[]).is_empty());
+
+        let mut other_stage = layout_infos.clone();
+        other_stage[1].bindings[0].stage_flags = vk::ShaderStageFlags::FRAGMENT;
+        assert!(matches!(
+            reflection.verify(&[], &other_stage, &[]).as_slice(),
+            [LayoutMismatch::DescriptorStages { set: 1, binding: 2, missing: vk::ShaderStageFlags::VERTEX, .. }]
+        ));
+
+        let mut moved_field = layout_infos.clone();
+        moved_field[1].bindings[0].uniform_fields[1].offset = 80;
+        moved_field[1].bindings[0].uniform_size = 128;
+        assert!(matches!(
+            reflection.verify(&[], &moved_field, &[]).as_slice(),
+            [
+                LayoutMismatch::UniformFieldOffset { shader: 112, pipeline: 80, .. },
+                LayoutMismatch::UniformSize { shader: 132, pipeline: 128, .. },
+            ]
+        ));
+
+        let mut array = layout_infos.clone();
+        array[1].bindings[0].descriptor_count = 2;
+        assert!(matches!(reflection.verify(&[], &array, &[]).as_slice(), [LayoutMismatch::DescriptorCount { shader: 1, pipeline: 2, .. }]));
+
+        assert!(matches!(reflection.verify(&[], &layout_infos[..1], &[]).as_slice(), [LayoutMismatch::MissingDescriptor { set: 1, binding: 2, .. }]));
+    }
+
+    #[test]
+    fn matrix_vertex_input_takes_a_location_per_column() {
+        let reflection = reflect(&[
+            entry_point(MODEL_VERTEX),
+            name(10, "uv"),
+            name(11, "model"),
+            instruction(OP_DECORATE, &[10, DECORATION_LOCATION, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_LOCATION, 2]),
+            instruction(OP_DECORATE, &[12, DECORATION_BUILT_IN, 42]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_VECTOR, &[2, 1, 2]),
+            instruction(OP_TYPE_VECTOR, &[3, 1, 4]),
+            instruction(OP_TYPE_MATRIX, &[4, 3, 4]),
+            instruction(OP_TYPE_INT, &[5, 32, 1]),
+            instruction(OP_TYPE_POINTER, &[6, STORAGE_CLASS_INPUT, 2]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_INPUT, 4]),
+            instruction(OP_TYPE_POINTER, &[8, STORAGE_CLASS_INPUT, 5]),
+            instruction(OP_VARIABLE, &[6, 10, STORAGE_CLASS_INPUT]),
+            instruction(OP_VARIABLE, &[7, 11, STORAGE_CLASS_INPUT]),
+            instruction(OP_VARIABLE, &[8, 12, STORAGE_CLASS_INPUT]),
+            function(&[10, 11, 12]),
+        ]);
+
+        let inputs = reflection.inputs.iter().map(|input| (input.name.as_str(), input.location, input.format, input.size)).collect::<Vec<_>>();
+        assert_eq!(inputs, [
+            ("uv", 0, vk::Format::R32G32_SFLOAT, 8),
+            ("model[0]", 2, vk::Format::R32G32B32A32_SFLOAT, 16),
+            ("model[1]", 3, vk::Format::R32G32B32A32_SFLOAT, 16),
+            ("model[2]", 4, vk::Format::R32G32B32A32_SFLOAT, 16),
+            ("model[3]", 5, vk::Format::R32G32B32A32_SFLOAT, 16),
+        ]);
+
+        let (binding, attributes) = reflection.vertex_input(0);
+        assert_eq!(binding.stride, 72);
+        assert_eq!(attributes.iter().map(|attribute| attribute.offset).collect::<Vec<_>>(), [0, 8, 24, 40, 56]);
+    }
+
+    #[test]
+    fn buffer_block_and_storage_buffer_are_storage_buffers() {
+        let reflection = reflect(&[
+            entry_point(MODEL_GL_COMPUTE),
+            name(10, "legacy"),
+            name(11, "modern"),
+            name(12, "uniforms"),
+            instruction(OP_DECORATE, &[3, DECORATION_ARRAY_STRIDE, 4]),
+            instruction(OP_DECORATE, &[4, DECORATION_BUFFER_BLOCK]),
+            instruction(OP_DECORATE, &[5, DECORATION_BLOCK]),
+            instruction(OP_MEMBER_DECORATE, &[4, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_MEMBER_DECORATE, &[4, 1, DECORATION_OFFSET, 4]),
+            instruction(OP_MEMBER_DECORATE, &[5, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_MEMBER_DECORATE, &[5, 1, DECORATION_OFFSET, 4]),
+            instruction(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[10, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_BINDING, 1]),
+            instruction(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[12, DECORATION_BINDING, 2]),
+            instruction(OP_TYPE_INT, &[1, 32, 0]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_RUNTIME_ARRAY, &[3, 2]),
+            instruction(OP_TYPE_STRUCT, &[4, 1, 3]),
+            instruction(OP_TYPE_STRUCT, &[5, 1, 3]),
+            instruction(OP_TYPE_POINTER, &[6, STORAGE_CLASS_UNIFORM, 4]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_STORAGE_BUFFER, 5]),
+            instruction(OP_TYPE_POINTER, &[8, STORAGE_CLASS_UNIFORM, 5]),
+            instruction(OP_VARIABLE, &[6, 10, STORAGE_CLASS_UNIFORM]),
+            instruction(OP_VARIABLE, &[7, 11, STORAGE_CLASS_STORAGE_BUFFER]),
+            instruction(OP_VARIABLE, &[8, 12, STORAGE_CLASS_UNIFORM]),
+            function(&[10, 11, 12]),
+        ]);
+
+        let bindings = reflection.descriptor_bindings.iter().map(|binding| (binding.name.as_str(), binding.descriptor_type)).collect::<Vec<_>>();
+        assert_eq!(bindings, [
+            ("legacy", vk::DescriptorType::STORAGE_BUFFER),
+            ("modern", vk::DescriptorType::STORAGE_BUFFER),
+            ("uniforms", vk::DescriptorType::UNIFORM_BUFFER),
+        ]);
+
+        // The runtime array adds nothing to the block size.
+        for binding in &reflection.descriptor_bindings {
+            let block = binding.block.as_ref().unwrap();
+            assert_eq!(block.size, 4);
+            assert_eq!(block.members[1].size, 0);
+            assert_eq!(binding.stage_flags, vk::ShaderStageFlags::COMPUTE);
+        }
+    }
+
+    #[test]
+    fn subpass_input_is_an_input_attachment() {
+        let reflection = reflect(&[
+            entry_point(MODEL_FRAGMENT),
+            name(4, "gbuffer"),
+            instruction(OP_DECORATE, &[4, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[4, DECORATION_BINDING, 3]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_IMAGE, &[2, 1, DIM_SUBPASS_DATA, 0, 0, 0, 2, 0]),
+            instruction(OP_TYPE_POINTER, &[3, STORAGE_CLASS_UNIFORM_CONSTANT, 2]),
+            instruction(OP_VARIABLE, &[3, 4, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            function(&[4]),
+        ]);
+
+        let binding = &reflection.descriptor_bindings[0];
+        assert_eq!((binding.set, binding.binding, binding.name.as_str()), (0, 3, "gbuffer"));
+        assert_eq!(binding.descriptor_type, vk::DescriptorType::INPUT_ATTACHMENT);
+        assert_eq!(binding.stage_flags, vk::ShaderStageFlags::FRAGMENT);
+        assert!(binding.block.is_none());
+    }
+
+    #[test]
+    fn unused_bindings_and_push_constants_have_no_stages() {
+        let reflection = reflect(&[
+            entry_point(MODEL_FRAGMENT),
+            instruction(OP_DECORATE, &[3, DECORATION_BLOCK]),
+            instruction(OP_MEMBER_DECORATE, &[3, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[10, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_BINDING, 1]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_VECTOR, &[2, 1, 4]),
+            instruction(OP_TYPE_STRUCT, &[3, 2]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_CLASS_UNIFORM, 3]),
+            instruction(OP_TYPE_POINTER, &[5, STORAGE_CLASS_PUSH_CONSTANT, 3]),
+            instruction(OP_VARIABLE, &[4, 10, STORAGE_CLASS_UNIFORM]),
+            instruction(OP_VARIABLE, &[4, 11, STORAGE_CLASS_UNIFORM]),
+            instruction(OP_VARIABLE, &[5, 12, STORAGE_CLASS_PUSH_CONSTANT]),
+            function(&[10]),
+        ]);
+
+        assert_eq!(reflection.descriptor_bindings[0].stage_flags, vk::ShaderStageFlags::FRAGMENT);
+        assert!(reflection.descriptor_bindings[1].stage_flags.is_empty());
+        assert!(reflection.push_constants.as_ref().unwrap().stage_flags.is_empty());
+        assert!(reflection.push_constant_ranges().is_empty());
+
+        // The unused binding still takes its slot in the layout.
+        let layout_bindings = reflection.descriptor_set_layout_bindings();
+        assert_eq!(layout_bindings[0].len(), 2);
+        assert!(layout_bindings[0][1].stage_flags.is_empty());
+    }
+
+    #[test]
+    fn array_sized_by_spec_constant_expression_is_empty() {
+        let reflection = reflect(&[
+            entry_point(MODEL_FRAGMENT),
+            name(5, "texture_count"),
+            instruction(OP_DECORATE, &[5, DECORATION_SPEC_ID, 7]),
+            instruction(OP_DECORATE, &[11, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[11, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[12, DECORATION_BINDING, 1]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_IMAGE, &[2, 1, 1, 0, 0, 0, 1, 0]),
+            instruction(OP_TYPE_SAMPLED_IMAGE, &[3, 2]),
+            instruction(OP_TYPE_INT, &[4, 32, 0]),
+            instruction(OP_SPEC_CONSTANT, &[4, 5, 4]),
+            instruction(OP_SPEC_CONSTANT_OP, &[4, 6, OP_I_ADD, 5, 5]),
+            instruction(OP_TYPE_ARRAY, &[7, 3, 5]),
+            instruction(OP_TYPE_ARRAY, &[8, 3, 6]),
+            instruction(OP_TYPE_POINTER, &[9, STORAGE_CLASS_UNIFORM_CONSTANT, 7]),
+            instruction(OP_TYPE_POINTER, &[10, STORAGE_CLASS_UNIFORM_CONSTANT, 8]),
+            instruction(OP_VARIABLE, &[9, 11, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            instruction(OP_VARIABLE, &[10, 12, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            function(&[11, 12]),
+        ]);
+
+        let bindings = reflection.descriptor_bindings.iter().map(|binding| (binding.descriptor_type, binding.count)).collect::<Vec<_>>();
+        assert_eq!(bindings, [(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4), (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 0)]);
+
+        let constant = &reflection.specialization_constants[0];
+        assert_eq!((constant.id, constant.name.as_str()), (7, "texture_count"));
+        assert_eq!(constant.default_value, Some(ShaderSpecializationValue::Int32(4)));
+    }
+
+    #[test]
+    fn malformed_modules_are_rejected() {
+        assert!(ShaderReflection::from_spirv(&[]).is_err());
+        assert!(ShaderReflection::from_spirv(&[0, 0, 0, 0, 0]).is_err());
+
+        // An instruction with a word count of zero, and one that runs past the end of the module.
+        assert!(ShaderReflection::from_spirv(&module(&[vec![OP_TYPE_BOOL]])).is_err());
+        let mut truncated = module(&[instruction(OP_TYPE_VECTOR, &[2, 1, 4])]);
+        truncated.pop();
+        assert_eq!(ShaderReflection::from_spirv(&truncated).unwrap_err(), "malformed instruction at word 5");
+
+        // Instructions missing operands.
+        assert!(ShaderReflection::from_spirv(&module(&[instruction(OP_TYPE_VECTOR, &[2, 1])])).is_err());
+        assert!(ShaderReflection::from_spirv(&module(&[instruction(OP_MEMBER_NAME, &[2])])).is_err());
+        assert!(ShaderReflection::from_spirv(&module(&[instruction(OP_ENTRY_POINT, &[99, 100])])).is_err());
+
+        // Variables whose types are missing or unusable.
+        let undefined_pointer = module(&[entry_point(MODEL_VERTEX), instruction(OP_VARIABLE, &[7, 8, STORAGE_CLASS_UNIFORM])]);
+        assert!(ShaderReflection::from_spirv(&undefined_pointer).is_err());
+
+        let empty_vector_input = module(&[
+            entry_point(MODEL_VERTEX),
+            instruction(OP_DECORATE, &[4, DECORATION_LOCATION, 0]),
+            instruction(OP_TYPE_FLOAT, &[1, 32]),
+            instruction(OP_TYPE_VECTOR, &[2, 1, 0]),
+            instruction(OP_TYPE_POINTER, &[3, STORAGE_CLASS_INPUT, 2]),
+            instruction(OP_VARIABLE, &[3, 4, STORAGE_CLASS_INPUT]),
+        ]);
+        assert!(ShaderReflection::from_spirv(&empty_vector_input).is_err());
+    }
+}
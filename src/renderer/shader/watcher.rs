@@ -0,0 +1,122 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, process::Command, time::{Duration, Instant, SystemTime}};
+
+use crate::utility;
+
+/// Where the watcher looks for shaders, relative to the assets directory.
+const SHADER_DIRECTORY: &str = "shaders";
+
+const SOURCE_EXTENSIONS: [&str; 6] = ["vert", "frag", "comp", "geom", "tesc", "tese"];
+
+/// Watches the shader directory `utility::fs` loads shaders from for changed files during development by polling
+/// modification times. Changed GLSL sources are recompiled with `glslc`, the same way `compile.sh` does, if it can be
+/// found on the `PATH`.
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    poll_interval: Duration,
+    last_poll: Instant,
+    modified_times: HashMap<PathBuf, SystemTime>,
+    has_glslc: bool,
+}
+
+impl ShaderWatcher {
+    pub fn new(poll_interval: Duration) -> Self {
+        let has_glslc = Command::new("glslc")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+
+        if !has_glslc {
+            log::info!("glslc not found, only changes to compiled shaders will be reloaded.");
+        }
+
+        let mut watcher = Self {
+            directory: utility::fs::asset_path(SHADER_DIRECTORY),
+            poll_interval,
+            last_poll: Instant::now(),
+            modified_times: HashMap::new(),
+            has_glslc,
+        };
+
+        watcher.modified_times = watcher.scan();
+        watcher
+    }
+
+    /// Returns the SPIR-V files that changed since the last poll, as paths relative to the assets directory like the
+    /// ones in `ShaderStageInfo::stage_file`. Does nothing until `poll_interval` has passed since the last poll.
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < self.poll_interval {
+            return Vec::new();
+        }
+
+        self.last_poll = Instant::now();
+
+        let modified_times = self.scan();
+
+        let changed_sources = modified_times.iter()
+            .filter(|(path, modified)| self.modified_times.get(*path) != Some(modified))
+            .map(|(path, _)| path)
+            .filter(|path| path.extension().is_some_and(|extension| SOURCE_EXTENSIONS.iter().any(|source| extension == *source)))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if self.has_glslc {
+            for source in &changed_sources {
+                self.compile(source);
+            }
+        }
+
+        // Compiling writes new SPIR-V, so look again to pick it up in the same poll.
+        let modified_times = self.scan();
+
+        let mut changed_files = modified_times.iter()
+            .filter(|(path, modified)| self.modified_times.get(*path) != Some(modified))
+            .filter(|(path, _)| path.extension().is_some_and(|extension| extension == "spv"))
+            .map(|(path, _)| format!("{}/{}", SHADER_DIRECTORY, path.file_name().unwrap().to_string_lossy()))
+            .collect::<Vec<_>>();
+
+        changed_files.sort();
+
+        self.modified_times = modified_times;
+
+        for file in &changed_files {
+            log::info!("Shader file changed: {}", file);
+        }
+
+        changed_files
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let Ok(entries) = std::fs::read_dir(&self.directory) else {
+            log::warn!("Failed to read shader directory {}.", self.directory.display());
+            return HashMap::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                metadata.is_file().then_some((entry.path(), metadata.modified().ok()?))
+            })
+            .collect()
+    }
+
+    fn compile(&self, source: &Path) {
+        let file_name = source.file_name().unwrap().to_string_lossy();
+        let output_name = format!("{}.spv", file_name);
+
+        log::info!("Compiling {}", file_name);
+
+        let output = Command::new("glslc")
+            .current_dir(source.parent().unwrap())
+            .arg(file_name.as_ref())
+            .arg("-o")
+            .arg(&output_name)
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => (),
+            Ok(output) => log::error!("Failed to compile {}:\n{}", file_name, String::from_utf8_lossy(&output.stderr).trim_end()),
+            Err(error) => log::error!("Failed to run glslc for {}: {}", file_name, error),
+        }
+    }
+}
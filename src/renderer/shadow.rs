@@ -0,0 +1,434 @@
+use std::slice;
+
+use ash::vk;
+
+use crate::math::{
+    frustum::{BoundingSphere, Frustum},
+    mat4::{normalize, Mat4},
+    vec2::Vec2UI,
+    vec3::Vec3F,
+};
+
+use super::{
+    buffer::Buffer,
+    image::Image,
+    mesh::{InstanceData, Vertex},
+    pipeline::{PipelineRenderTarget, PipelineStateInfo},
+    shader::{
+        create_descriptor_set_layouts_and_pool,
+        Shader,
+        ShaderDescriptorInfo,
+        ShaderDescriptorSetInfo,
+        ShaderDescriptorTypeInfo,
+        ShaderPushConstantInfo,
+        ShaderStageInfo,
+        ShaderType,
+    },
+    texture::create_shadow_sampler,
+    utility,
+    vkcontext::VkContext,
+    MAX_FRAMES_IN_FLIGHT,
+};
+
+pub const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+pub const MAX_SHADOW_CASCADES: usize = 4;
+pub const MAX_SHADOW_VIEWS: usize = 8;
+
+/// The global descriptor set material shaders read shadows from: a uniform buffer laid out like `ShadowUniform`
+/// followed by the atlas as a `sampler2DShadow`. Include it in a shader's descriptor sets and bind it with `bind`.
+pub const SHADOW_DESCRIPTOR_SET: ShaderDescriptorSetInfo<'static> = ShaderDescriptorSetInfo {
+    max_set_allocations: MAX_FRAMES_IN_FLIGHT,
+    descriptors: &[
+        ShaderDescriptorInfo {
+            name: "shadows",
+            descriptor_type: ShaderDescriptorTypeInfo::UniformBuffer { fields: &[] },
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+        ShaderDescriptorInfo {
+            name: "shadow_atlas",
+            descriptor_type: ShaderDescriptorTypeInfo::Sampler,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        },
+    ],
+};
+
+#[derive(Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction: Vec3F,
+    pub cascade_count: u32,
+    /// How far from the camera shadows are drawn. Cascades split this range, with more resolution close by.
+    pub max_distance: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct SpotLight {
+    pub position: Vec3F,
+    pub direction: Vec3F,
+    /// Half-angle of the cone, in radians.
+    pub outer_angle: f32,
+    pub range: f32,
+}
+
+/// The perspective camera that directional cascades are fitted to.
+#[derive(Clone, Copy)]
+pub struct ShadowCamera {
+    pub view: Mat4,
+    pub fov_y: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct ShadowDepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    pub clamp: f32,
+}
+
+impl Default for ShadowDepthBias {
+    fn default() -> Self {
+        Self {
+            constant_factor: 1.25,
+            slope_factor: 1.75,
+            clamp: 0.0,
+        }
+    }
+}
+
+/// One depth-only render into a tile of the atlas.
+#[derive(Clone, Copy)]
+pub struct ShadowView {
+    pub view_projection: Mat4,
+    pub frustum: Frustum,
+    pub atlas_offset: Vec2UI,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct ShadowUniform {
+    view_projections: [Mat4; MAX_SHADOW_VIEWS],
+    atlas_rects: [[f32; 4]; MAX_SHADOW_VIEWS],
+    cascade_splits: [f32; 4],
+    cascade_count: u32,
+    spot_count: u32,
+    _padding: [u32; 2],
+}
+
+/// Renders depth from every shadow-casting light into square tiles of a single depth atlas: one tile per cascade of
+/// the directional light, followed by one per spot light.
+pub struct ShadowMaps<'ctx> {
+    pub atlas: Image<'ctx>,
+    pub atlas_size: u32,
+    pub tile_size: u32,
+    pub sampler: vk::Sampler,
+    pub caster_shader: Shader<'ctx>,
+    pub views: Vec<ShadowView>,
+
+    cascade_count: u32,
+    cascade_splits: [f32; 4],
+    /// Spot lights left without a shadow view by the last `prepare`, so the warning is only logged when it changes.
+    dropped_spot_light_count: usize,
+    uniform_buffers: Vec<Buffer<'ctx>>,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> ShadowMaps<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext, atlas_size: u32, tile_size: u32, depth_bias: ShadowDepthBias) -> Self {
+        let tiles_per_row = atlas_size / tile_size;
+
+        assert!(
+            (tiles_per_row * tiles_per_row) as usize >= MAX_SHADOW_VIEWS,
+            "A {}x{} shadow atlas cannot fit {} tiles of {}x{}.",
+            atlas_size, atlas_size, MAX_SHADOW_VIEWS, tile_size, tile_size
+        );
+
+        let atlas = Image::new(
+            vkcontext,
+            vk::ImageType::TYPE_2D,
+            Vec2UI { x: atlas_size, y: atlas_size },
+            SHADOW_MAP_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some(vk::ImageAspectFlags::DEPTH),
+        );
+
+        // Quads and other open meshes cast shadows from both sides.
+        let caster_shader = Shader::new(
+            vkcontext,
+            "builtin.shadow",
+            &PipelineRenderTarget::Dynamic {
+                color_attachment_formats: &[],
+                depth_attachment_format: SHADOW_MAP_FORMAT,
+                stencil_attachment_format: vk::Format::UNDEFINED,
+            },
+            &[],
+            &[ Vertex::get_binding_description(0), InstanceData::get_binding_description(1) ],
+            &[ Vertex::get_attributes(0).as_slice(), InstanceData::get_attributes(1).as_slice() ].concat(),
+            &[
+                ShaderPushConstantInfo { push_constant_type: ShaderType::Matrix4, stage_flags: vk::ShaderStageFlags::VERTEX },
+            ],
+            &[],
+            &[
+                ShaderStageInfo::new(vk::ShaderStageFlags::VERTEX, "shaders/builtin.shadow.vert.spv"),
+            ],
+            &PipelineStateInfo::get_default_pipeline_state_info()
+                .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                .depth_bias(depth_bias.constant_factor, depth_bias.slope_factor, depth_bias.clamp)
+                .cull_mode(vk::CullModeFlags::NONE),
+        );
+
+        let (descriptor_set_layouts, descriptor_pool) = create_descriptor_set_layouts_and_pool(vkcontext, &[SHADOW_DESCRIPTOR_SET]);
+        let descriptor_set_layout = descriptor_set_layouts[0];
+
+        let descriptor_sets = {
+            let set_layouts = vec![descriptor_set_layout; MAX_FRAMES_IN_FLIGHT as usize];
+
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&set_layouts);
+
+            unsafe { vkcontext.device.allocate_descriptor_sets(&allocate_info).unwrap() }
+        };
+
+        let uniform_buffers = (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+            Buffer::new(
+                vkcontext,
+                std::mem::size_of::<ShadowUniform>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                true,
+            )
+        })
+        .collect::<Vec<_>>();
+
+        let sampler = create_shadow_sampler(vkcontext);
+
+        for (descriptor_set, uniform_buffer) in descriptor_sets.iter().zip(uniform_buffers.iter()) {
+            let buffer_info = vk::DescriptorBufferInfo::default()
+                .buffer(uniform_buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE);
+
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_view(atlas.image_view.unwrap())
+                .sampler(sampler)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+            let writes = [
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(slice::from_ref(&buffer_info)),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(slice::from_ref(&image_info)),
+            ];
+
+            unsafe { vkcontext.device.update_descriptor_sets(&writes, &[]); }
+        }
+
+        Self {
+            atlas,
+            atlas_size,
+            tile_size,
+            sampler,
+            caster_shader,
+            views: Vec::new(),
+            cascade_count: 0,
+            cascade_splits: [0.0; 4],
+            dropped_spot_light_count: 0,
+            uniform_buffers,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            vkcontext,
+        }
+    }
+}
+
+impl<'ctx> ShadowMaps<'ctx> {
+    /// Fits the views of every light and writes them to the uniform buffer of `frame_index`.
+    pub fn prepare(
+        &mut self,
+        frame_index: u32,
+        camera: &ShadowCamera,
+        directional_light: Option<&DirectionalLight>,
+        spot_lights: &[SpotLight],
+    ) {
+        self.views.clear();
+        self.cascade_count = 0;
+        self.cascade_splits = [0.0; 4];
+
+        if let Some(light) = directional_light {
+            self.prepare_cascades(camera, light);
+        }
+
+        for light in spot_lights.iter().take(MAX_SHADOW_VIEWS - self.views.len()) {
+            let up = if light.direction.y.abs() > 0.99 { Vec3F::new(1.0, 0.0, 0.0) } else { Vec3F::new(0.0, 1.0, 0.0) };
+            let target = Vec3F::new(
+                light.position.x + light.direction.x,
+                light.position.y + light.direction.y,
+                light.position.z + light.direction.z,
+            );
+
+            let view = Mat4::look_at(light.position, target, up);
+            let projection = Mat4::perspective(light.outer_angle * 2.0, 1.0, light.range * 0.01, light.range);
+
+            self.push_view(projection * view);
+        }
+
+        let dropped_spot_light_count = spot_lights.len() - (self.views.len() - self.cascade_count as usize);
+
+        if dropped_spot_light_count != self.dropped_spot_light_count && dropped_spot_light_count > 0 {
+            log::warn!(
+                "Only {} shadow views fit in the atlas; {} spot lights cast no shadows.",
+                MAX_SHADOW_VIEWS,
+                dropped_spot_light_count
+            );
+        }
+
+        self.dropped_spot_light_count = dropped_spot_light_count;
+
+        let mut uniform = ShadowUniform {
+            view_projections: [Mat4::IDENTITY; MAX_SHADOW_VIEWS],
+            atlas_rects: [[0.0; 4]; MAX_SHADOW_VIEWS],
+            cascade_splits: self.cascade_splits,
+            cascade_count: self.cascade_count,
+            spot_count: self.views.len() as u32 - self.cascade_count,
+            _padding: [0; 2],
+        };
+
+        for (index, view) in self.views.iter().enumerate() {
+            let scale = self.tile_size as f32 / self.atlas_size as f32;
+
+            uniform.view_projections[index] = view.view_projection;
+            uniform.atlas_rects[index] = [
+                view.atlas_offset.x as f32 / self.atlas_size as f32,
+                view.atlas_offset.y as f32 / self.atlas_size as f32,
+                scale,
+                scale,
+            ];
+        }
+
+        self.uniform_buffers[frame_index as usize].load_value(0, &uniform, vk::MemoryMapFlags::default());
+    }
+
+    fn prepare_cascades(&mut self, camera: &ShadowCamera, light: &DirectionalLight) {
+        let cascade_count = light.cascade_count.clamp(1, MAX_SHADOW_CASCADES as u32);
+        let direction = normalize(light.direction);
+        let up = if direction.y.abs() > 0.99 { Vec3F::new(1.0, 0.0, 0.0) } else { Vec3F::new(0.0, 1.0, 0.0) };
+
+        let camera_to_world = camera.view.inverse_affine();
+        let tan_half_fov = (camera.fov_y * 0.5).tan();
+
+        // Light space at the origin, used to snap cascade centres to whole texels so shadows don't shimmer.
+        let light_rotation = Mat4::look_at(Vec3F::new(0.0, 0.0, 0.0), direction, up);
+        let light_rotation_inverse = light_rotation.inverse_affine();
+
+        let mut split_near = camera.near;
+
+        for cascade in 0..cascade_count {
+            // Blend logarithmic and uniform splits.
+            let t = (cascade + 1) as f32 / cascade_count as f32;
+            let logarithmic = camera.near * (light.max_distance / camera.near).powf(t);
+            let uniform = camera.near + (light.max_distance - camera.near) * t;
+            let split_far = 0.5 * logarithmic + 0.5 * uniform;
+
+            let corners = [split_near, split_far].into_iter().flat_map(|distance| {
+                let half_height = distance * tan_half_fov;
+                let half_width = half_height * camera.aspect_ratio;
+
+                [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+                    camera_to_world.transform_point(Vec3F::new(x * half_width, y * half_height, -distance))
+                })
+            });
+
+            let bounds = BoundingSphere::from_points(corners);
+            let radius = (bounds.radius * 16.0).ceil() / 16.0;
+
+            let texels_per_unit = self.tile_size as f32 / (radius * 2.0);
+            let center = light_rotation.transform_point(bounds.center);
+            let center = light_rotation_inverse.transform_point(Vec3F::new(
+                (center.x * texels_per_unit).floor() / texels_per_unit,
+                (center.y * texels_per_unit).floor() / texels_per_unit,
+                center.z,
+            ));
+
+            // Pull the eye back past the cascade so casters between it and the light still land in the map.
+            let eye = Vec3F::new(
+                center.x - direction.x * radius * 2.0,
+                center.y - direction.y * radius * 2.0,
+                center.z - direction.z * radius * 2.0,
+            );
+
+            let view = Mat4::look_at(eye, center, up);
+            let projection = Mat4::orthographic(-radius, radius, -radius, radius, 0.0, radius * 3.0);
+
+            self.push_view(projection * view);
+            self.cascade_splits[cascade as usize] = split_far;
+
+            split_near = split_far;
+        }
+
+        self.cascade_count = cascade_count;
+    }
+
+    fn push_view(&mut self, view_projection: Mat4) {
+        let tiles_per_row = self.atlas_size / self.tile_size;
+        let index = self.views.len() as u32;
+
+        self.views.push(ShadowView {
+            view_projection,
+            frustum: Frustum::from_view_projection(&view_projection),
+            atlas_offset: Vec2UI {
+                x: (index % tiles_per_row) * self.tile_size,
+                y: (index / tiles_per_row) * self.tile_size,
+            },
+        });
+    }
+}
+
+impl<'ctx> ShadowMaps<'ctx> {
+    /// Renders every view prepared for this frame. Must be called while rendering to the atlas as a depth attachment,
+    /// cleared to 1. `draw_casters` binds and draws the casters of a view with `InstanceData` at binding 1; the caster
+    /// shader and the view's viewport and matrix are already set. Leaves the viewport on the last tile.
+    pub fn record<F: FnMut(vk::CommandBuffer, &ShadowView)>(&self, command_buffer: vk::CommandBuffer, mut draw_casters: F) {
+        self.caster_shader.bind(command_buffer);
+
+        for view in self.views.iter() {
+            utility::set_viewport_and_scissor(
+                &self.vkcontext.device,
+                command_buffer,
+                view.atlas_offset,
+                Vec2UI { x: self.tile_size, y: self.tile_size },
+            );
+
+            self.caster_shader.push_constants(command_buffer, 0, &view.view_projection);
+
+            draw_casters(command_buffer, view);
+        }
+    }
+
+    /// Binds the shadow descriptor set of `frame_index` at `set_index` of `shader`, whose layout there must be
+    /// `SHADOW_DESCRIPTOR_SET`.
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, shader: &Shader, set_index: u32, frame_index: u32) {
+        shader.bind_descriptor_sets(command_buffer, set_index, slice::from_ref(&self.descriptor_sets[frame_index as usize]));
+    }
+}
+
+impl<'ctx> Drop for ShadowMaps<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            self.vkcontext.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.vkcontext.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.vkcontext.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{collections::VecDeque, slice};
+
+use ash::vk;
+
+use super::vkcontext::VkContext;
+
+/// A timeline semaphore that the renderer signals with a monotonically increasing frame value at the end of every
+/// submitted frame. Frame `n` is complete on the GPU once the semaphore's counter has reached `n`.
+pub struct FrameTimeline<'ctx> {
+    pub semaphore: vk::Semaphore,
+    current_value: u64,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> FrameTimeline<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext) -> Self {
+        Self {
+            semaphore: create_timeline_semaphore(vkcontext, 0),
+            current_value: 1,
+            vkcontext,
+        }
+    }
+}
+
+impl<'ctx> FrameTimeline<'ctx> {
+    /// The value the frame currently being recorded will signal when it completes.
+    pub fn current_value(&self) -> u64 {
+        self.current_value
+    }
+
+    /// The value of the most recent frame the GPU has finished.
+    pub fn completed_value(&self) -> u64 {
+        unsafe { self.vkcontext.device.get_semaphore_counter_value(self.semaphore).unwrap() }
+    }
+
+    pub fn is_frame_complete(&self, frame_value: u64) -> bool {
+        frame_value <= self.completed_value()
+    }
+
+    pub fn wait_for_frame(&self, frame_value: u64) {
+        if frame_value == 0 {
+            return;
+        }
+
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(slice::from_ref(&self.semaphore))
+            .values(slice::from_ref(&frame_value));
+
+        unsafe { self.vkcontext.device.wait_semaphores(&wait_info, u64::MAX).unwrap(); }
+    }
+
+    /// Moves on to the next frame, returning the value of the frame that was just submitted.
+    pub fn advance(&mut self) -> u64 {
+        let submitted = self.current_value;
+        self.current_value += 1;
+
+        submitted
+    }
+}
+
+impl<'ctx> Drop for FrameTimeline<'ctx> {
+    fn drop(&mut self) {
+        unsafe { self.vkcontext.device.destroy_semaphore(self.semaphore, None); }
+    }
+}
+
+type Deleter<'ctx> = Box<dyn FnOnce(&VkContext) + 'ctx>;
+
+/// Destroys GPU objects once the frame that last used them has completed.
+pub struct DeletionQueue<'ctx> {
+    entries: VecDeque<(u64, Deleter<'ctx>)>,
+}
+
+impl<'ctx> DeletionQueue<'ctx> {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Schedules `deleter` to run once frame `frame_value` is complete. Values must be pushed in non-decreasing order.
+    pub fn push<F: FnOnce(&VkContext) + 'ctx>(&mut self, frame_value: u64, deleter: F) {
+        debug_assert!(self.entries.back().is_none_or(|(value, _)| *value <= frame_value));
+
+        self.entries.push_back((frame_value, Box::new(deleter)));
+    }
+
+    pub fn collect(&mut self, vkcontext: &VkContext, completed_value: u64) {
+        while let Some((value, _)) = self.entries.front() {
+            if *value > completed_value {
+                break;
+            }
+
+            let (_, deleter) = self.entries.pop_front().unwrap();
+            deleter(vkcontext);
+        }
+    }
+
+    pub fn flush(&mut self, vkcontext: &VkContext) {
+        for (_, deleter) in self.entries.drain(..) {
+            deleter(vkcontext);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<'ctx> Default for DeletionQueue<'ctx> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn create_timeline_semaphore(vkcontext: &VkContext, initial_value: u64) -> vk::Semaphore {
+    let mut type_create_info = vk::SemaphoreTypeCreateInfo::default()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .initial_value(initial_value);
+
+    let create_info = vk::SemaphoreCreateInfo::default()
+        .push_next(&mut type_create_info);
+
+    unsafe { vkcontext.device.create_semaphore(&create_info, None).unwrap() }
+}
+
+pub fn create_binary_semaphore(vkcontext: &VkContext) -> vk::Semaphore {
+    let create_info = vk::SemaphoreCreateInfo::default();
+
+    unsafe { vkcontext.device.create_semaphore(&create_info, None).unwrap() }
+}
@@ -1,3 +1,175 @@
-pub struct Texture {
+use std::path::Path;
 
+use ash::vk;
+
+use crate::math::vec2::Vec2UI;
+
+use super::{
+    block_compression::{self, CompressionFamily},
+    image::Image,
+    texture_container::TextureContainer,
+    upload::{UploadManager, UploadTicket},
+    vkcontext::VkContext,
+};
+
+pub struct Texture<'ctx> {
+    pub name: String,
+    pub image: Image<'ctx>,
+    pub sampler: vk::Sampler,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> Texture<'ctx> {
+    /// Creates a sampled texture from tightly packed `pixels` in `format` and queues the upload.
+    pub fn new(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        name: &str,
+        size: Vec2UI,
+        format: vk::Format,
+        pixels: &[u8],
+    ) -> (Self, UploadTicket) {
+        let image = Image::new(
+            vkcontext,
+            vk::ImageType::TYPE_2D,
+            size,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some(vk::ImageAspectFlags::COLOR),
+        );
+
+        let ticket = upload_manager.upload_image(&image, pixels);
+
+        let texture = Self {
+            name: name.to_string(),
+            image,
+            sampler: create_sampler(vkcontext, vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT),
+            vkcontext,
+        };
+
+        (texture, ticket)
+    }
+
+    /// Loads a KTX2 or DDS file relative to the assets directory. See `Texture::from_container`.
+    pub fn load<P: AsRef<Path>>(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        name: &str,
+        path: P,
+    ) -> (Self, UploadTicket) {
+        Self::from_container(vkcontext, upload_manager, name, &TextureContainer::load(path))
+    }
+
+    /// Creates a sampled texture with every mip level, layer and cube face of `container` and queues the upload.
+    /// Block-compressed data is uploaded as is when the device can sample its format, and decoded on the CPU otherwise
+    /// (see `TextureContainer::decompress`).
+    pub fn from_container(
+        vkcontext: &'ctx VkContext,
+        upload_manager: &mut UploadManager,
+        name: &str,
+        container: &TextureContainer,
+    ) -> (Self, UploadTicket) {
+        let decompressed;
+
+        let container = if is_sampleable(vkcontext, container.format) {
+            container
+        } else {
+            log::info!("{:?} is not supported by the device, decoding texture \"{}\" on the CPU.", container.format, name);
+
+            decompressed = container.decompress()
+                .unwrap_or_else(|message| panic!("Failed to decode texture \"{}\": {}", name, message));
+            &decompressed
+        };
+
+        let description = container.image_description(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST);
+        let image = Image::from_description(
+            vkcontext,
+            &description,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some(vk::ImageAspectFlags::COLOR),
+        );
+
+        let levels = container.levels.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let ticket = upload_manager.upload_image_levels(&image, &levels);
+
+        let texture = Self {
+            name: name.to_string(),
+            image,
+            sampler: create_sampler(vkcontext, vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT),
+            vkcontext,
+        };
+
+        (texture, ticket)
+    }
+
+    pub fn image_view(&self) -> vk::ImageView {
+        self.image.image_view.unwrap()
+    }
+}
+
+impl<'ctx> Drop for Texture<'ctx> {
+    fn drop(&mut self) {
+        unsafe { self.vkcontext.device.destroy_sampler(self.sampler, None); }
+    }
+}
+
+/// Whether textures of `format` can be uploaded and sampled. Compressed formats also need their device feature enabled.
+fn is_sampleable(vkcontext: &VkContext, format: vk::Format) -> bool {
+    let features = &vkcontext.enabled_features.core;
+
+    let feature_enabled = match block_compression::compression_family(format) {
+        Some(CompressionFamily::Bc) => features.texture_compression_bc == vk::TRUE,
+        Some(CompressionFamily::Etc2) => features.texture_compression_etc2 == vk::TRUE,
+        Some(CompressionFamily::Astc) => features.texture_compression_astc_ldr == vk::TRUE,
+        None => true,
+    };
+
+    let required = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST;
+
+    feature_enabled && vkcontext.format_features(format, vk::ImageTiling::OPTIMAL).contains(required)
+}
+
+pub fn create_sampler(vkcontext: &VkContext, filter: vk::Filter, address_mode: vk::SamplerAddressMode) -> vk::Sampler {
+    let anisotropy_enabled = vkcontext.enabled_features.core.sampler_anisotropy == vk::TRUE;
+
+    let create_info = vk::SamplerCreateInfo::default()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(anisotropy_enabled)
+        .max_anisotropy(if anisotropy_enabled { vkcontext.physical_device_properties.limits.max_sampler_anisotropy } else { 1.0 })
+        .compare_enable(false)
+        .min_lod(0.0)
+        .max_lod(vk::LOD_CLAMP_NONE)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false);
+
+    unsafe { vkcontext.device.create_sampler(&create_info, None).unwrap() }
+}
+
+/// A sampler for `sampler2DShadow` lookups: the comparison against the reference depth is filtered in hardware, and
+/// lookups outside the map compare against the far plane so they are never in shadow.
+pub fn create_shadow_sampler(vkcontext: &VkContext) -> vk::Sampler {
+    let create_info = vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .min_lod(0.0)
+        .max_lod(0.0)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .unnormalized_coordinates(false);
+
+    unsafe { vkcontext.device.create_sampler(&create_info, None).unwrap() }
 }
@@ -0,0 +1,472 @@
+use std::{io::Read, path::Path};
+
+use ash::vk;
+
+use crate::{math::vec2::Vec2UI, utility};
+
+use super::{block_compression, image::ImageDescription};
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE3D: u32 = 4;
+
+/// Texel data read from a KTX2 or DDS container, with every mip level, array layer and cube face the file provides.
+pub struct TextureContainer {
+    pub format: vk::Format,
+    pub size: Vec2UI,
+    /// Slices of a 3D texture, 1 otherwise.
+    pub depth: u32,
+    /// Array layers including cube faces, so six per cube.
+    pub array_layers: u32,
+    pub is_cube: bool,
+    /// One entry per mip level, largest first. Each holds every layer of that level tightly packed, layer after layer,
+    /// as `UploadManager::upload_image_levels` expects.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureContainer {
+    /// Loads a `.ktx2` or `.dds` file relative to the assets directory, telling them apart by their signature.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let mut data = Vec::new();
+        utility::fs::load(&path).read_to_end(&mut data).unwrap();
+
+        Self::decode(&data).unwrap_or_else(|message| panic!("Failed to load {}: {}", path.as_ref().display(), message))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        if data.starts_with(&KTX2_IDENTIFIER) {
+            Self::decode_ktx2(data)
+        } else if data.starts_with(&DDS_MAGIC) {
+            Self::decode_dds(data)
+        } else {
+            Err("not a KTX2 or DDS file".to_string())
+        }
+    }
+
+    /// Decodes a KTX2 file whose levels are stored without supercompression. Basis Universal payloads are rejected.
+    pub fn decode_ktx2(data: &[u8]) -> Result<Self, String> {
+        if !data.starts_with(&KTX2_IDENTIFIER) {
+            return Err("missing KTX2 identifier".to_string());
+        }
+
+        let format = vk::Format::from_raw(read_u32(data, 12)? as i32);
+        let width = read_u32(data, 20)?;
+        let height = read_u32(data, 24)?.max(1);
+        let depth = read_u32(data, 28)?.max(1);
+        let layer_count = read_u32(data, 32)?.max(1);
+        let face_count = read_u32(data, 36)?;
+        let level_count = read_u32(data, 40)?.max(1);
+        let supercompression_scheme = read_u32(data, 44)?;
+
+        if format == vk::Format::UNDEFINED {
+            return Err("Basis Universal textures are not supported".to_string());
+        }
+
+        if supercompression_scheme != 0 {
+            return Err(format!("unsupported supercompression scheme {}", supercompression_scheme));
+        }
+
+        if face_count != 1 && face_count != 6 {
+            return Err(format!("invalid face count {}", face_count));
+        }
+
+        validate_extent(width, height, depth, level_count)?;
+
+        // The level index follows the 80-byte header and the 32-byte section index.
+        if data.len() < 80 + level_count as usize * 24 {
+            return Err(format!("level index of {} levels is truncated", level_count));
+        }
+
+        let mut container = Self {
+            format,
+            size: Vec2UI { x: width, y: height },
+            depth,
+            array_layers: layer_count.checked_mul(face_count).ok_or("too many array layers")?,
+            is_cube: face_count == 6,
+            levels: Vec::with_capacity(level_count as usize),
+        };
+
+        for level in 0..level_count {
+            let entry = 80 + level as usize * 24;
+            let offset = read_u64(data, entry)? as usize;
+            let length = read_u64(data, entry + 8)? as usize;
+
+            let expected_length = container.level_size(level)?;
+            if length != expected_length {
+                return Err(format!("level {} holds {} bytes but {} were expected", level, length, expected_length));
+            }
+
+            let end = offset.checked_add(length).ok_or_else(|| format!("level {} is out of range", level))?;
+            let bytes = data.get(offset..end).ok_or_else(|| format!("level {} is truncated", level))?;
+            container.levels.push(bytes.to_vec());
+        }
+
+        Ok(container)
+    }
+
+    /// Decodes a DDS file, including the DX10 header extension needed for BC6H, BC7, sRGB formats and texture arrays.
+    pub fn decode_dds(data: &[u8]) -> Result<Self, String> {
+        if !data.starts_with(&DDS_MAGIC) {
+            return Err("missing DDS magic".to_string());
+        }
+
+        let flags = read_u32(data, 8)?;
+        let height = read_u32(data, 12)?;
+        let width = read_u32(data, 16)?;
+        let depth = read_u32(data, 24)?;
+        let mip_count = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(data, 28)?.max(1) } else { 1 };
+        let pixel_format_flags = read_u32(data, 80)?;
+        let four_cc = data.get(84..88).ok_or("truncated header")?;
+        let caps2 = read_u32(data, 112)?;
+
+        let mut data_offset: usize = 128;
+        let mut is_cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+        let mut is_volume = caps2 & DDSCAPS2_VOLUME != 0;
+        let mut array_size = 1;
+
+        let format = if pixel_format_flags & DDPF_FOURCC != 0 {
+            match four_cc {
+                b"DX10" => {
+                    let dxgi_format = read_u32(data, 128)?;
+                    let resource_dimension = read_u32(data, 132)?;
+                    let misc_flag = read_u32(data, 136)?;
+
+                    array_size = read_u32(data, 140)?.max(1);
+                    is_cube = misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+                    is_volume = resource_dimension == DDS_DIMENSION_TEXTURE3D;
+                    data_offset = 148;
+
+                    dxgi_to_vk_format(dxgi_format).ok_or_else(|| format!("unsupported DXGI format {}", dxgi_format))?
+                },
+                b"DXT1" => vk::Format::BC1_RGBA_UNORM_BLOCK,
+                b"DXT2" | b"DXT3" => vk::Format::BC2_UNORM_BLOCK,
+                b"DXT4" | b"DXT5" => vk::Format::BC3_UNORM_BLOCK,
+                b"ATI1" | b"BC4U" => vk::Format::BC4_UNORM_BLOCK,
+                b"BC4S" => vk::Format::BC4_SNORM_BLOCK,
+                b"ATI2" | b"BC5U" => vk::Format::BC5_UNORM_BLOCK,
+                b"BC5S" => vk::Format::BC5_SNORM_BLOCK,
+                _ => return Err(format!("unsupported FourCC \"{}\"", String::from_utf8_lossy(four_cc))),
+            }
+        } else if pixel_format_flags & DDPF_RGB != 0 {
+            let bit_count = read_u32(data, 88)?;
+            let masks = [read_u32(data, 92)?, read_u32(data, 96)?, read_u32(data, 100)?, read_u32(data, 104)?];
+
+            match (bit_count, masks) {
+                (32, [0xff, 0xff00, 0xff0000, 0xff000000]) => vk::Format::R8G8B8A8_UNORM,
+                (32, [0xff0000, 0xff00, 0xff, 0xff000000]) => vk::Format::B8G8R8A8_UNORM,
+                _ => return Err(format!("unsupported {}-bit RGB masks {:x?}", bit_count, masks)),
+            }
+        } else {
+            return Err(format!("unsupported pixel format flags {:#x}", pixel_format_flags));
+        };
+
+        let faces = if is_cube { 6 } else { 1 };
+        let depth = if is_volume { depth.max(1) } else { 1 };
+
+        validate_extent(width, height.max(1), depth, mip_count)?;
+
+        let mut container = Self {
+            format,
+            size: Vec2UI { x: width, y: height.max(1) },
+            depth,
+            array_layers: array_size.checked_mul(faces).ok_or("too many array layers")?,
+            is_cube,
+            levels: vec![Vec::new(); mip_count as usize],
+        };
+
+        // DDS stores every mip of a layer before moving on to the next layer, the opposite of what uploads need.
+        let mut offset = data_offset;
+
+        for layer in 0..container.array_layers {
+            for level in 0..mip_count {
+                let size = container.level_size(level)? / container.array_layers as usize;
+                let bytes = offset.checked_add(size)
+                    .and_then(|end| data.get(offset..end))
+                    .ok_or_else(|| format!("layer {} level {} is truncated", layer, level))?;
+
+                container.levels[level as usize].extend_from_slice(bytes);
+                offset += size;
+            }
+        }
+
+        Ok(container)
+    }
+}
+
+impl TextureContainer {
+    pub fn mip_levels(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent3D {
+        vk::Extent3D {
+            width: self.size.x.checked_shr(mip_level).unwrap_or(0).max(1),
+            height: self.size.y.checked_shr(mip_level).unwrap_or(0).max(1),
+            depth: self.depth.checked_shr(mip_level).unwrap_or(0).max(1),
+        }
+    }
+
+    /// Byte size of `mip_level` across every layer.
+    pub fn level_size(&self, mip_level: u32) -> Result<usize, String> {
+        let layer_size = block_compression::image_size(self.format, self.mip_extent(mip_level))?;
+
+        layer_size.checked_mul(self.array_layers as usize).ok_or_else(|| format!("level {} is too large", mip_level))
+    }
+
+    /// An image description with room for every level and layer of the container.
+    pub fn image_description(&self, usage: vk::ImageUsageFlags) -> ImageDescription {
+        let description = if self.is_cube {
+            ImageDescription::new_cube(self.size.x, self.format, usage).array_layers(self.array_layers)
+        } else if self.depth > 1 {
+            ImageDescription::new_3d(self.size, self.depth, self.format, usage)
+        } else {
+            ImageDescription::new_2d(self.size, self.format, usage).array_layers(self.array_layers)
+        };
+
+        description.mip_levels(self.mip_levels())
+    }
+
+    /// Decodes block-compressed levels on the CPU to RGBA8, or RGBA16F for BC6H, keeping sRGB encoding and signedness,
+    /// for devices that cannot sample the stored format.
+    pub fn decompress(&self) -> Result<Self, String> {
+        let format = block_compression::decoded_format(self.format)
+            .ok_or_else(|| format!("no CPU decoder for {:?}", self.format))?;
+
+        let mut levels = Vec::with_capacity(self.levels.len());
+
+        for (mip_level, data) in self.levels.iter().enumerate() {
+            let extent = self.mip_extent(mip_level as u32);
+            let layer_size = data.len() / self.array_layers as usize;
+
+            let mut level = Vec::new();
+            for layer in data.chunks_exact(layer_size) {
+                level.extend(block_compression::decode_image(self.format, extent, layer)?);
+            }
+
+            levels.push(level);
+        }
+
+        Ok(Self { format, levels, ..*self })
+    }
+}
+
+/// Rejects empty images and more mip levels than a full chain of the extent has, before anything is allocated for
+/// them.
+fn validate_extent(width: u32, height: u32, depth: u32, level_count: u32) -> Result<(), String> {
+    if width == 0 {
+        return Err("width is 0".to_string());
+    }
+
+    let max_level_count = 32 - width.max(height).max(depth).leading_zeros();
+    if level_count > max_level_count {
+        return Err(format!("{} mip levels for a {}x{}x{} image", level_count, width, height, depth));
+    }
+
+    Ok(())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| "unexpected end of file".to_string())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, String> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| "unexpected end of file".to_string())
+}
+
+fn dxgi_to_vk_format(dxgi_format: u32) -> Option<vk::Format> {
+    let format = match dxgi_format {
+        2 => vk::Format::R32G32B32A32_SFLOAT,
+        10 => vk::Format::R16G16B16A16_SFLOAT,
+        28 => vk::Format::R8G8B8A8_UNORM,
+        29 => vk::Format::R8G8B8A8_SRGB,
+        49 => vk::Format::R8G8_UNORM,
+        61 => vk::Format::R8_UNORM,
+        71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        74 => vk::Format::BC2_UNORM_BLOCK,
+        75 => vk::Format::BC2_SRGB_BLOCK,
+        77 => vk::Format::BC3_UNORM_BLOCK,
+        78 => vk::Format::BC3_SRGB_BLOCK,
+        80 => vk::Format::BC4_UNORM_BLOCK,
+        81 => vk::Format::BC4_SNORM_BLOCK,
+        83 => vk::Format::BC5_UNORM_BLOCK,
+        84 => vk::Format::BC5_SNORM_BLOCK,
+        87 => vk::Format::B8G8R8A8_UNORM,
+        91 => vk::Format::B8G8R8A8_SRGB,
+        95 => vk::Format::BC6H_UFLOAT_BLOCK,
+        96 => vk::Format::BC6H_SFLOAT_BLOCK,
+        98 => vk::Format::BC7_UNORM_BLOCK,
+        99 => vk::Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(data: &mut [u8], offset: usize, value: u64) {
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// A 2x2 RGBA8 KTX2 file with two levels, stored smallest first as the specification recommends.
+    fn ktx2_file() -> Vec<u8> {
+        let mut data = vec![0; 148];
+
+        data[..12].copy_from_slice(&KTX2_IDENTIFIER);
+        put_u32(&mut data, 12, vk::Format::R8G8B8A8_UNORM.as_raw() as u32);
+        put_u32(&mut data, 16, 1);
+        put_u32(&mut data, 20, 2);
+        put_u32(&mut data, 24, 2);
+        put_u32(&mut data, 36, 1);
+        put_u32(&mut data, 40, 2);
+
+        put_u64(&mut data, 80, 132);
+        put_u64(&mut data, 88, 16);
+        put_u64(&mut data, 104, 128);
+        put_u64(&mut data, 112, 4);
+
+        data[128..132].fill(1);
+        data[132..148].fill(0);
+        data
+    }
+
+    #[test]
+    fn ktx2_header() {
+        let container = TextureContainer::decode(&ktx2_file()).unwrap();
+
+        assert_eq!(container.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!((container.size.x, container.size.y, container.depth), (2, 2, 1));
+        assert_eq!(container.array_layers, 1);
+        assert!(!container.is_cube);
+        assert_eq!(container.levels, [vec![0; 16], vec![1; 4]]);
+    }
+
+    #[test]
+    fn ktx2_rejects_bad_levels() {
+        let mut data = ktx2_file();
+        put_u64(&mut data, 104, u64::MAX);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        let mut data = ktx2_file();
+        put_u64(&mut data, 104, 146);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        assert!(TextureContainer::decode_ktx2(&ktx2_file()[..100]).is_err());
+    }
+
+    #[test]
+    fn ktx2_rejects_bad_extents_and_counts() {
+        let mut data = ktx2_file();
+        put_u32(&mut data, 20, 0);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        // A 2x2 image has at most two levels.
+        let mut data = ktx2_file();
+        put_u32(&mut data, 40, 3);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        let mut data = ktx2_file();
+        put_u32(&mut data, 20, u32::MAX);
+        put_u32(&mut data, 40, u32::MAX);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        // A full chain of a huge image whose level index doesn't fit the file.
+        let mut data = ktx2_file();
+        put_u32(&mut data, 20, u32::MAX);
+        put_u32(&mut data, 24, u32::MAX);
+        put_u32(&mut data, 40, 32);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+
+        // The level sizes of a huge array overflow.
+        let mut data = ktx2_file();
+        put_u32(&mut data, 20, u32::MAX);
+        put_u32(&mut data, 24, u32::MAX);
+        put_u32(&mut data, 32, u32::MAX);
+        put_u32(&mut data, 40, 1);
+        assert!(TextureContainer::decode_ktx2(&data).is_err());
+    }
+
+    fn dds_header(width: u32, height: u32, mip_count: u32) -> Vec<u8> {
+        let mut data = vec![0; 128];
+
+        data[..4].copy_from_slice(&DDS_MAGIC);
+        put_u32(&mut data, 4, 124);
+        put_u32(&mut data, 8, DDSD_MIPMAPCOUNT);
+        put_u32(&mut data, 12, height);
+        put_u32(&mut data, 16, width);
+        put_u32(&mut data, 28, mip_count);
+        put_u32(&mut data, 76, 32);
+        data
+    }
+
+    #[test]
+    fn dds_four_cc() {
+        let mut data = dds_header(8, 8, 2);
+        put_u32(&mut data, 80, DDPF_FOURCC);
+        data[84..88].copy_from_slice(b"DXT1");
+        data.extend([0; 32]);
+        data.extend([1; 8]);
+
+        let container = TextureContainer::decode(&data).unwrap();
+
+        assert_eq!(container.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((container.size.x, container.size.y), (8, 8));
+        assert_eq!(container.levels, [vec![0; 32], vec![1; 8]]);
+
+        assert!(TextureContainer::decode(&data[..160]).is_err());
+
+        put_u32(&mut data, 28, 5);
+        assert!(TextureContainer::decode(&data).is_err());
+
+        put_u32(&mut data, 28, u32::MAX);
+        assert!(TextureContainer::decode(&data).is_err());
+
+        put_u32(&mut data, 28, 1);
+        put_u32(&mut data, 16, 0);
+        assert!(TextureContainer::decode(&data).is_err());
+    }
+
+    #[test]
+    fn dds_dx10_array_is_reordered_by_level() {
+        let mut data = dds_header(2, 2, 2);
+        put_u32(&mut data, 80, DDPF_FOURCC);
+        data[84..88].copy_from_slice(b"DX10");
+
+        // DXGI_FORMAT_R8G8B8A8_UNORM, a 2D texture with two array layers.
+        data.extend([0; 20]);
+        put_u32(&mut data, 128, 28);
+        put_u32(&mut data, 132, 3);
+        put_u32(&mut data, 140, 2);
+
+        data.extend([0x00; 16]);
+        data.extend([0x01; 4]);
+        data.extend([0x10; 16]);
+        data.extend([0x11; 4]);
+
+        let container = TextureContainer::decode(&data).unwrap();
+
+        assert_eq!(container.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(container.array_layers, 2);
+        assert!(!container.is_cube);
+        assert_eq!(container.levels[0], [[0x00; 16], [0x10; 16]].concat());
+        assert_eq!(container.levels[1], [[0x01; 4], [0x11; 4]].concat());
+    }
+}
@@ -0,0 +1,427 @@
+use std::{collections::VecDeque, mem::size_of_val, ptr, slice};
+
+use ash::vk;
+
+use super::{
+    buffer::Buffer,
+    command_buffer::{CommandBuffer, OwnershipTransferResource, QueueOwnershipTransfer},
+    image::{Image, ImageUsage},
+    sync::create_timeline_semaphore,
+    vkcontext::VkContext,
+};
+
+pub const DEFAULT_STAGING_BUFFER_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Identifies the batch an upload was recorded into. The upload is complete once the manager's timeline semaphore
+/// reaches `value`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct UploadTicket {
+    pub value: u64,
+}
+
+/// A semaphore wait that a consuming queue submission must include before it may read uploaded data.
+#[derive(Clone, Copy)]
+pub struct UploadWait {
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+    pub stage_mask: vk::PipelineStageFlags2,
+}
+
+struct PendingAcquire {
+    transfer: QueueOwnershipTransfer,
+    dst_stage: vk::PipelineStageFlags,
+    dst_access: vk::AccessFlags,
+}
+
+struct RecordingBatch<'ctx> {
+    command_buffer: CommandBuffer<'ctx>,
+    ring_end: u64,
+    acquires: Vec<PendingAcquire>,
+}
+
+struct SubmittedBatch<'ctx> {
+    value: u64,
+    ring_end: u64,
+    _command_buffer: CommandBuffer<'ctx>,
+}
+
+pub struct UploadManager<'ctx> {
+    staging_buffer: Buffer<'ctx>,
+    staging_memory: *mut u8,
+    staging_alignment: u64,
+
+    // Ring offsets grow monotonically; the physical offset is the value modulo the staging buffer size.
+    ring_head: u64,
+    ring_tail: u64,
+
+    recording: Option<RecordingBatch<'ctx>>,
+    submitted: VecDeque<SubmittedBatch<'ctx>>,
+    pending_acquires: Vec<PendingAcquire>,
+
+    next_value: u64,
+    last_submitted_value: u64,
+    last_waited_value: u64,
+
+    pub timeline_semaphore: vk::Semaphore,
+    pub command_pool: vk::CommandPool,
+    vkcontext: &'ctx VkContext,
+}
+
+impl<'ctx> UploadManager<'ctx> {
+    pub fn new(vkcontext: &'ctx VkContext, staging_buffer_size: vk::DeviceSize) -> Self {
+        let mut staging_buffer = Buffer::new(
+            vkcontext,
+            staging_buffer_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            true,
+        );
+
+        // The staging buffer stays mapped for the lifetime of the manager.
+        let staging_memory = staging_buffer.lock_memory(0, staging_buffer_size, vk::MemoryMapFlags::default()) as *mut u8;
+
+        let staging_alignment = vkcontext.physical_device_properties.limits.optimal_buffer_copy_offset_alignment.max(16);
+
+        let command_pool = {
+            let create_info = vk::CommandPoolCreateInfo::default()
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                .queue_family_index(vkcontext.queue_family_indices.transfer_index);
+
+            unsafe { vkcontext.device.create_command_pool(&create_info, None).unwrap() }
+        };
+
+        let timeline_semaphore = create_timeline_semaphore(vkcontext, 0);
+
+        Self {
+            staging_buffer,
+            staging_memory,
+            staging_alignment,
+            ring_head: 0,
+            ring_tail: 0,
+            recording: None,
+            submitted: VecDeque::new(),
+            pending_acquires: Vec::new(),
+            next_value: 1,
+            last_submitted_value: 0,
+            last_waited_value: 0,
+            timeline_semaphore,
+            command_pool,
+            vkcontext,
+        }
+    }
+}
+
+impl<'ctx> UploadManager<'ctx> {
+    pub fn upload_buffer<T: Copy>(&mut self, dest: &Buffer, dest_offset: vk::DeviceSize, s: &[T]) -> UploadTicket {
+        let size = size_of_val(s) as vk::DeviceSize;
+        let staging_offset = self.write_staging(s);
+
+        let vkcontext = self.vkcontext;
+        let staging_buffer = self.staging_buffer.handle;
+        let transfer_index = vkcontext.queue_family_indices.transfer_index;
+        let graphics_index = vkcontext.queue_family_indices.graphics_index;
+
+        let batch = self.recording_batch();
+
+        let buffer_copy = vk::BufferCopy::default()
+            .src_offset(staging_offset)
+            .dst_offset(dest_offset)
+            .size(size);
+
+        unsafe {
+            vkcontext.device.cmd_copy_buffer(
+                batch.command_buffer.handle,
+                staging_buffer,
+                dest.handle,
+                slice::from_ref(&buffer_copy),
+            );
+        }
+
+        let transfer = QueueOwnershipTransfer {
+            resource: OwnershipTransferResource::Buffer { buffer: dest.handle, offset: dest_offset, size },
+            src_queue_family_index: transfer_index,
+            dst_queue_family_index: graphics_index,
+        };
+
+        if transfer.is_required() {
+            batch.command_buffer.release_ownership(&transfer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+
+            batch.acquires.push(PendingAcquire {
+                transfer,
+                dst_stage: vk::PipelineStageFlags::VERTEX_INPUT
+                    | vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_access: vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ
+                    | vk::AccessFlags::UNIFORM_READ
+                    | vk::AccessFlags::SHADER_READ,
+            });
+        }
+
+        self.current_ticket()
+    }
+
+    /// Uploads tightly packed texel data into mip 0 of every layer of `dest` and leaves it in `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn upload_image(&mut self, dest: &Image, data: &[u8]) -> UploadTicket {
+        self.upload_image_levels(dest, &[data])
+    }
+
+    /// Uploads one entry of `levels` per mip level, starting at mip 0, each holding every layer of that level tightly
+    /// packed, and leaves `dest` in `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn upload_image_levels(&mut self, dest: &Image, levels: &[&[u8]]) -> UploadTicket {
+        // Every level goes into one staging allocation so that none of them can be retired before the copies record.
+        let mut level_offsets = Vec::with_capacity(levels.len());
+        let mut packed = Vec::new();
+
+        for level in levels {
+            packed.resize(packed.len().next_multiple_of(16), 0);
+            level_offsets.push(packed.len() as vk::DeviceSize);
+            packed.extend_from_slice(level);
+        }
+
+        let staging_offset = self.write_staging(&packed);
+
+        let staging_buffer = self.staging_buffer.handle;
+        let transfer_index = self.vkcontext.queue_family_indices.transfer_index;
+        let graphics_index = self.vkcontext.queue_family_indices.graphics_index;
+
+        let batch = self.recording_batch();
+
+        dest.transition_to(batch.command_buffer.handle, ImageUsage::TransferDst);
+
+        for (mip_level, level_offset) in level_offsets.into_iter().enumerate() {
+            dest.copy_from_buffer(batch.command_buffer.handle, staging_buffer, staging_offset + level_offset, mip_level as u32);
+        }
+
+        let transfer = QueueOwnershipTransfer {
+            resource: OwnershipTransferResource::Image {
+                image: dest.handle,
+                subresource_range: dest.subresource_range(),
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+            src_queue_family_index: transfer_index,
+            dst_queue_family_index: graphics_index,
+        };
+
+        if transfer.is_required() {
+            batch.command_buffer.release_ownership(&transfer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+
+            batch.acquires.push(PendingAcquire {
+                transfer,
+                dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_access: vk::AccessFlags::SHADER_READ,
+            });
+
+            // The ownership transfer performs the layout transition.
+            dest.mark_usage(dest.subresource_range(), ImageUsage::ShaderRead);
+        } else {
+            dest.transition_to(batch.command_buffer.handle, ImageUsage::ShaderRead);
+        }
+
+        self.current_ticket()
+    }
+
+    /// Submits every upload recorded since the last flush as one batch on the transfer queue. Should be called once
+    /// per frame.
+    pub fn flush(&mut self) -> Option<UploadTicket> {
+        let batch = self.recording.take()?;
+
+        batch.command_buffer.end(self.vkcontext);
+
+        let value = self.next_value;
+        self.next_value += 1;
+
+        let command_buffer_info = vk::CommandBufferSubmitInfo::default()
+            .command_buffer(batch.command_buffer.handle);
+
+        let signal_info = vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.timeline_semaphore)
+            .value(value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER);
+
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(slice::from_ref(&command_buffer_info))
+            .signal_semaphore_infos(slice::from_ref(&signal_info));
+
+        unsafe {
+            self.vkcontext.device.queue_submit2(
+                self.vkcontext.transfer_queue,
+                slice::from_ref(&submit_info),
+                vk::Fence::null(),
+            )
+            .unwrap();
+        }
+
+        self.last_submitted_value = value;
+        self.pending_acquires.extend(batch.acquires);
+        self.submitted.push_back(SubmittedBatch {
+            value,
+            ring_end: batch.ring_end,
+            _command_buffer: batch.command_buffer,
+        });
+
+        Some(UploadTicket { value })
+    }
+
+    pub fn is_complete(&self, ticket: UploadTicket) -> bool {
+        ticket.value <= self.completed_value()
+    }
+
+    /// Blocks until the upload identified by `ticket` has finished on the GPU, submitting its batch first if needed.
+    pub fn wait(&mut self, ticket: UploadTicket) {
+        if ticket.value > self.last_submitted_value {
+            self.flush();
+        }
+
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(slice::from_ref(&self.timeline_semaphore))
+            .values(slice::from_ref(&ticket.value));
+
+        unsafe { self.vkcontext.device.wait_semaphores(&wait_info, u64::MAX).unwrap(); }
+
+        self.collect_completed();
+    }
+
+    pub fn wait_idle(&mut self) {
+        self.flush();
+
+        if self.last_submitted_value > 0 {
+            self.wait(UploadTicket { value: self.last_submitted_value });
+        }
+    }
+
+    /// Records the acquire barriers for every batch submitted since the previous call into `command_buffer`, which
+    /// must belong to the graphics queue. Returns the semaphore wait that the graphics submission has to include.
+    pub fn record_pending_acquires(&mut self, command_buffer: &CommandBuffer) -> Option<UploadWait> {
+        if self.last_submitted_value == self.last_waited_value {
+            return None;
+        }
+
+        for acquire in self.pending_acquires.drain(..) {
+            command_buffer.acquire_ownership(&acquire.transfer, acquire.dst_stage, acquire.dst_access);
+        }
+
+        self.last_waited_value = self.last_submitted_value;
+
+        Some(UploadWait {
+            semaphore: self.timeline_semaphore,
+            value: self.last_submitted_value,
+            stage_mask: vk::PipelineStageFlags2::VERTEX_INPUT
+                | vk::PipelineStageFlags2::VERTEX_SHADER
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                | vk::PipelineStageFlags2::COMPUTE_SHADER
+                | vk::PipelineStageFlags2::ALL_TRANSFER,
+        })
+    }
+
+    pub fn completed_value(&self) -> u64 {
+        unsafe { self.vkcontext.device.get_semaphore_counter_value(self.timeline_semaphore).unwrap() }
+    }
+}
+
+impl<'ctx> UploadManager<'ctx> {
+    fn current_ticket(&self) -> UploadTicket {
+        UploadTicket { value: self.next_value }
+    }
+
+    fn recording_batch(&mut self) -> &mut RecordingBatch<'ctx> {
+        let ring_head = self.ring_head;
+
+        let batch = self.recording.get_or_insert_with(|| {
+            let command_buffer = CommandBuffer::new(self.vkcontext, self.command_pool, true);
+            command_buffer.begin(true, false, false);
+
+            RecordingBatch {
+                command_buffer,
+                ring_end: ring_head,
+                acquires: Vec::new(),
+            }
+        });
+
+        batch.ring_end = ring_head;
+
+        batch
+    }
+
+    /// Copies `s` into the staging ring and returns its offset within the staging buffer, waiting for in-flight
+    /// batches to retire when the ring is full.
+    fn write_staging<T: Copy>(&mut self, s: &[T]) -> vk::DeviceSize {
+        let size = size_of_val(s) as u64;
+        let capacity = self.staging_buffer.size;
+
+        assert!(
+            size <= capacity,
+            "Upload of {} bytes does not fit in the {} byte staging buffer.",
+            size,
+            capacity
+        );
+
+        loop {
+            self.collect_completed();
+
+            let mut start = self.ring_head.next_multiple_of(self.staging_alignment);
+
+            // Never split an allocation across the end of the ring.
+            if start % capacity + size > capacity {
+                start = start.next_multiple_of(capacity);
+            }
+
+            if start + size - self.ring_tail <= capacity {
+                self.ring_head = start + size;
+
+                let offset = start % capacity;
+
+                unsafe {
+                    ptr::copy_nonoverlapping(s.as_ptr() as *const u8, self.staging_memory.add(offset as usize), size as usize);
+                }
+
+                return offset;
+            }
+
+            // Out of space: retire the oldest batch, submitting the one being recorded if nothing else is in flight.
+            match self.submitted.front() {
+                Some(oldest) => {
+                    let ticket = UploadTicket { value: oldest.value };
+                    self.wait(ticket);
+                },
+                None => {
+                    let ticket = self.flush().expect("Staging ring is full but holds no uploads.");
+                    self.wait(ticket);
+                },
+            }
+        }
+    }
+
+    fn collect_completed(&mut self) {
+        if self.submitted.is_empty() {
+            return;
+        }
+
+        let completed_value = self.completed_value();
+
+        while let Some(batch) = self.submitted.front() {
+            if batch.value > completed_value {
+                break;
+            }
+
+            self.ring_tail = batch.ring_end;
+            self.submitted.pop_front();
+        }
+    }
+}
+
+impl<'ctx> Drop for UploadManager<'ctx> {
+    fn drop(&mut self) {
+        self.wait_idle();
+
+        self.submitted.clear();
+        self.staging_buffer.unlock_memory();
+
+        unsafe {
+            self.vkcontext.device.destroy_command_pool(self.command_pool, None);
+            self.vkcontext.device.destroy_semaphore(self.timeline_semaphore, None);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use ash::{vk, Device};
 
+use crate::math::vec2::Vec2UI;
+
 pub fn create_image_view(
     device: &Device,
     image: vk::Image,
@@ -37,3 +39,22 @@ pub fn query_memory_type(
         }
     })
 }
+
+pub fn set_viewport_and_scissor(device: &Device, command_buffer: vk::CommandBuffer, offset: Vec2UI, size: Vec2UI) {
+    let viewport = vk::Viewport::default()
+        .x(offset.x as f32)
+        .y(offset.y as f32)
+        .width(size.x as f32)
+        .height(size.y as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::default()
+        .offset(vk::Offset2D { x: offset.x as i32, y: offset.y as i32 })
+        .extent(size.as_vk_extent_2d());
+
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+        device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+    }
+}
@@ -5,11 +5,22 @@ use simple_window::Window;
 use std::ffi::{CStr, CString};
 use super::swapchain::SwapchainSupportDetails;
 use super::debug::*;
+use super::device_features::DeviceFeatures;
+use super::physical_device::{DeviceSelector, PhysicalDeviceReport};
+use super::pipeline_cache::{load_pipeline_cache, pipeline_cache_path, save_pipeline_cache};
 
 pub struct VkContext {
+    config: VkContextBuilder,
+    pub enabled_features: DeviceFeatures,
+    pub enabled_optional_features: DeviceFeatures,
+    pub enabled_extensions: Vec<CString>,
+    pub enabled_optional_extensions: Vec<CString>,
+    pub pipeline_cache: vk::PipelineCache,
     pub queue_family_indices: QueueFamilyIndices,
     pub present_queue: vk::Queue,
     pub graphics_queue: vk::Queue,
+    pub compute_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
     pub device: Device,
     pub physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
@@ -23,8 +34,12 @@ pub struct VkContext {
 
 impl VkContext {
     pub fn new(window: &Window) -> Self {
+        VkContextBuilder::new().build(window)
+    }
+
+    fn from_builder(window: &Window, builder: &VkContextBuilder) -> Self {
         let entry = unsafe { Entry::load().expect("Failed to load ash entry.") };
-        let instance = Self::create_instance(&entry, window);
+        let instance = Self::create_instance(&entry, window, builder);
 
         let surface_instance_loader = surface::Instance::new(&entry, &instance);
 
@@ -42,20 +57,58 @@ impl VkContext {
         let debug_report_callback = setup_debug_messenger(&entry, &instance);
 
         let (physical_device, physical_device_properties, queue_family_indices) =
-            Self::pick_physical_device(&instance, &surface_instance_loader, surface_khr);
+            Self::pick_physical_device(&instance, &surface_instance_loader, surface_khr, builder);
         
         let physical_device_memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
 
-        let (device, graphics_queue, present_queue) = 
-            Self::create_logical_device_with_graphics_queue(&instance, physical_device, queue_family_indices);
+        // Enable every required feature and extension, plus whichever optional ones the device supports.
+        let supported_features = DeviceFeatures::query(&instance, physical_device);
+        let enabled_optional_features = builder.optional_features.intersection(&supported_features);
+        let enabled_features = builder.required_features.union(&enabled_optional_features);
+
+        let supported_extensions = Self::get_supported_device_extensions(&instance, physical_device);
+        let enabled_optional_extensions = builder.optional_extensions.iter()
+            .filter(|ext| supported_extensions.contains(ext) && !builder.required_extensions.contains(ext))
+            .cloned()
+            .collect::<Vec<_>>();
+        let enabled_extensions = builder.required_extensions.iter()
+            .chain(enabled_optional_extensions.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        log::debug!("Enabled optional device features: {:?}", enabled_optional_features.enabled_names());
+        log::debug!("Enabled device extensions: {:?}", enabled_extensions);
+
+        let missing_optional_features = builder.optional_features.missing_from(&supported_features);
+        if !missing_optional_features.is_empty() {
+            log::info!("Unsupported optional device features: {:?}", missing_optional_features);
+        }
+
+        let (device, queues) = Self::create_logical_device_with_queues(
+            &instance,
+            physical_device,
+            queue_family_indices,
+            &enabled_features,
+            &enabled_extensions,
+        );
+
+        let pipeline_cache = load_pipeline_cache(&device, &physical_device_properties, pipeline_cache_path());
 
         let swapchain_instance_loader = swapchain::Instance::new(&entry, &instance);
         let swapchain_device_loader = swapchain::Device::new(&instance, &device);
 
         VkContext {
+            config: builder.clone(),
+            enabled_features,
+            enabled_optional_features,
+            enabled_extensions,
+            enabled_optional_extensions,
+            pipeline_cache,
             queue_family_indices,
-            present_queue,
-            graphics_queue,
+            present_queue: queues.present,
+            graphics_queue: queues.graphics,
+            compute_queue: queues.compute,
+            transfer_queue: queues.transfer,
             device,
             physical_device_memory_properties,
             physical_device_properties,
@@ -78,17 +131,70 @@ impl VkContext {
     pub fn wait_gpu_idle(&self) {
         unsafe { self.device.device_wait_idle().unwrap(); }
     }
+
+    /// Describes every physical device, including the score it was given and why unsuitable ones were rejected.
+    pub fn list_physical_devices(&self) -> Vec<PhysicalDeviceReport> {
+        Self::report_physical_devices(&self.instance, &self.loaders.surface_instance, self.surface_khr, &self.config)
+            .into_iter()
+            .map(|(_, report)| report)
+            .collect()
+    }
+
+    pub fn is_dynamic_rendering_enabled(&self) -> bool {
+        self.enabled_features.vulkan13.dynamic_rendering == vk::TRUE
+    }
+
+    pub fn is_draw_indirect_count_enabled(&self) -> bool {
+        self.enabled_features.vulkan12.draw_indirect_count == vk::TRUE
+            && self.enabled_features.core.draw_indirect_first_instance == vk::TRUE
+    }
+
+    pub fn is_extension_enabled(&self, extension: &CStr) -> bool {
+        self.enabled_extensions.iter().any(|ext| ext.as_c_str() == extension)
+    }
+
+    /// What images of `format` can do with `tiling`.
+    pub fn format_features(&self, format: vk::Format, tiling: vk::ImageTiling) -> vk::FormatFeatureFlags {
+        let properties = unsafe { self.instance.get_physical_device_format_properties(self.physical_device, format) };
+
+        match tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => properties.optimal_tiling_features,
+        }
+    }
+
+    /// Sample counts usable by both colour and depth framebuffer attachments.
+    pub fn supported_sample_counts(&self) -> vk::SampleCountFlags {
+        let limits = &self.physical_device_properties.limits;
+
+        limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts
+    }
+
+    /// The highest supported sample count that does not exceed `requested`. Single sampling is always supported.
+    pub fn clamp_sample_count(&self, requested: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        let supported = self.supported_sample_counts();
+
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|&samples| samples.as_raw() <= requested.as_raw() && supported.contains(samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
 }
 
 impl VkContext {
-    fn create_instance(entry: &Entry, window: &Window) -> Instance {
-        let app_name = CString::new("Industria").unwrap();
-        let engine_name = CString::new("No Engine").unwrap();
+    fn create_instance(entry: &Entry, window: &Window, builder: &VkContextBuilder) -> Instance {
         let app_info = vk::ApplicationInfo::default()
-            .application_name(app_name.as_c_str())
-            .application_version(vk::make_api_version(0, 0, 1, 0))
-            .engine_name(engine_name.as_c_str())
-            .engine_version(vk::make_api_version(0, 0, 1, 0))
+            .application_name(builder.application_name.as_c_str())
+            .application_version(builder.application_version)
+            .engine_name(builder.engine_name.as_c_str())
+            .engine_version(builder.engine_version)
             .api_version(vk::API_VERSION_1_3);
 
         let extension_names =
@@ -119,77 +225,140 @@ impl VkContext {
         instance: &Instance,
         surface_loader: &surface::Instance,
         surface_khr: vk::SurfaceKHR,
+        builder: &VkContextBuilder,
     ) -> (vk::PhysicalDevice, vk::PhysicalDeviceProperties, QueueFamilyIndices) {
-        let devices = unsafe { instance.enumerate_physical_devices().unwrap() };
-        let device = devices
-            .into_iter()
-            .find(|device| Self::is_device_suitable(instance, surface_loader, surface_khr, *device))
-            .expect("No suitable physical devices found.");
+        let devices = Self::report_physical_devices(instance, surface_loader, surface_khr, builder);
+
+        for (_, report) in devices.iter() {
+            log::debug!("Physical device {}", report);
+        }
+
+        let device_selector = match DeviceSelector::from_env() {
+            Some(env_selector) => {
+                log::info!("Physical device selection overridden by environment: {:?}", env_selector);
+                env_selector
+            },
+            None => builder.device_selector.clone(),
+        };
+
+        let reports = devices.iter().map(|(_, report)| report.clone()).collect::<Vec<_>>();
 
+        let selected = match device_selector.select(&reports) {
+            Ok(report) => report,
+            Err(error) => panic!("{}", error),
+        };
+
+        let device = devices[selected.index].0;
         let props = unsafe { instance.get_physical_device_properties(device) };
         
         log::debug!("Selected physical device: {:?}", unsafe {
             CStr::from_ptr(props.device_name.as_ptr())
         });
 
-        let (graphics, present) = Self::find_queue_families(instance, surface_loader, surface_khr, device);
+        let queue_families_indices = selected.queue_family_indices.unwrap();
 
-        let queue_families_indices = QueueFamilyIndices {
-            graphics_index: graphics.unwrap(),
-            present_index: present.unwrap(),
-        };
+        log::debug!(
+            "Queue families: graphics {}, present {}, compute {}{}, transfer {}{}",
+            queue_families_indices.graphics_index,
+            queue_families_indices.present_index,
+            queue_families_indices.compute_index,
+            if queue_families_indices.has_dedicated_compute_queue() { " (dedicated)" } else { "" },
+            queue_families_indices.transfer_index,
+            if queue_families_indices.has_dedicated_transfer_queue() { " (dedicated)" } else { "" },
+        );
 
         (device, props, queue_families_indices)
     }
 
-    fn is_device_suitable(
+    fn report_physical_devices(
+        instance: &Instance,
+        surface_loader: &surface::Instance,
+        surface_khr: vk::SurfaceKHR,
+        builder: &VkContextBuilder,
+    ) -> Vec<(vk::PhysicalDevice, PhysicalDeviceReport)> {
+        let devices = unsafe { instance.enumerate_physical_devices().unwrap() };
+
+        devices.into_iter().enumerate().map(|(index, device)| {
+            let mut report = PhysicalDeviceReport::new(instance, index, device);
+            let supported_features = DeviceFeatures::query(instance, device);
+
+            report.queue_family_indices = Self::find_queue_families(instance, surface_loader, surface_khr, device);
+            report.supported_optional_features = builder.optional_features.intersection(&supported_features).enabled_count();
+            report.rejection_reasons = Self::find_unsuitability_reasons(
+                instance,
+                surface_loader,
+                surface_khr,
+                device,
+                &report,
+                &supported_features,
+                builder,
+            );
+            report.compute_score();
+
+            (device, report)
+        })
+        .collect()
+    }
+
+    fn find_unsuitability_reasons(
         instance: &Instance,
         surface_loader: &surface::Instance,
         surface_khr: vk::SurfaceKHR,
         device: vk::PhysicalDevice,
-    ) -> bool {
-        let (graphics, present) = Self::find_queue_families(instance, surface_loader, surface_khr, device);
-        let extension_support = Self::check_device_extension_support(instance, device);
+        report: &PhysicalDeviceReport,
+        supported_features: &DeviceFeatures,
+        builder: &VkContextBuilder,
+    ) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        if report.api_version < vk::API_VERSION_1_3 {
+            reasons.push(format!(
+                "supports Vulkan {}.{}, 1.3 is required",
+                vk::api_version_major(report.api_version),
+                vk::api_version_minor(report.api_version),
+            ));
+        }
+
+        if report.queue_family_indices.is_none() {
+            reasons.push("no graphics and present capable queue families".to_string());
+        }
+
+        let supported_extensions = Self::get_supported_device_extensions(instance, device);
+        let missing_extensions = builder.required_extensions.iter()
+            .filter(|ext| !supported_extensions.contains(ext))
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
 
-        let is_swapchain_suitable = {
+        if !missing_extensions.is_empty() {
+            reasons.push(format!("missing device extensions: {}", missing_extensions.join(", ")));
+        } else {
+            // Surface support can only be queried once the swapchain extension is known to exist.
             let details = SwapchainSupportDetails::query(instance, device, surface_loader, surface_khr);
-            !details.formats.is_empty() && !details.present_modes.is_empty()
-        };
 
-        let features = unsafe { instance.get_physical_device_features(device) };
+            if details.formats.is_empty() || details.present_modes.is_empty() {
+                reasons.push("no surface formats or present modes for the window surface".to_string());
+            }
+        }
 
-        graphics.is_some()
-            && present.is_some()
-            && extension_support
-            && is_swapchain_suitable
-            && features.sampler_anisotropy == vk::TRUE
-    }
+        let missing_features = builder.required_features.missing_from(supported_features);
 
-    fn check_device_extension_support(instance: &Instance, device: vk::PhysicalDevice) -> bool {
-        let required_extensions = Self::get_required_device_extensions();
+        if !missing_features.is_empty() {
+            reasons.push(format!("missing device features: {}", missing_features.join(", ")));
+        }
+
+        reasons
+    }
 
+    fn get_supported_device_extensions(instance: &Instance, device: vk::PhysicalDevice) -> Vec<CString> {
         let extension_props = unsafe {
             instance
                 .enumerate_device_extension_properties(device)
                 .unwrap()
         };
 
-        for required in required_extensions.iter() {
-            let found = extension_props.iter().any(|ext| {
-                let name = unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) };
-                required == &name
-            });
-
-            if !found {
-                return false;
-            }
-        }
-
-        true
-    }
-
-    fn get_required_device_extensions() -> [&'static CStr; 1] {
-        [swapchain::NAME]
+        extension_props.iter()
+            .map(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) }.to_owned())
+            .collect()
     }
 
     fn find_queue_families(
@@ -197,19 +366,39 @@ impl VkContext {
         surface_loader: &surface::Instance,
         surface_khr: vk::SurfaceKHR,
         device: vk::PhysicalDevice,
-    ) -> (Option<u32>, Option<u32>) {
+    ) -> Option<QueueFamilyIndices> {
         let mut graphics = None;
         let mut present = None;
+        let mut compute = None;
+        let mut transfer = None;
+        let mut compute_transfer = None;
 
         let props = unsafe { instance.get_physical_device_queue_family_properties(device) };
 
-        for (index, family) in props.iter().filter(|f| f.queue_count > 0).enumerate() {
+        for (index, family) in props.iter().enumerate().filter(|(_, f)| f.queue_count > 0) {
             let index = index as u32;
+            let flags = family.queue_flags;
 
-            if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) && graphics.is_none() {
+            if flags.contains(vk::QueueFlags::GRAPHICS) && graphics.is_none() {
                 graphics = Some(index);
             }
 
+            // Dedicated families are those that cannot do graphics work, so submissions to them never compete with
+            // rendering.
+            if !flags.contains(vk::QueueFlags::GRAPHICS) {
+                if flags.contains(vk::QueueFlags::COMPUTE) && compute.is_none() {
+                    compute = Some(index);
+                }
+
+                if flags.contains(vk::QueueFlags::TRANSFER) {
+                    if !flags.contains(vk::QueueFlags::COMPUTE) && transfer.is_none() {
+                        transfer = Some(index);
+                    } else if compute_transfer.is_none() {
+                        compute_transfer = Some(index);
+                    }
+                }
+            }
+
             let present_support = unsafe {
                 surface_loader.
                     get_physical_device_surface_support(device, index, surface_khr)
@@ -219,29 +408,29 @@ impl VkContext {
             if present_support && present.is_none() {
                 present = Some(index);
             }
-
-            if graphics.is_some() && present.is_some() {
-                break;
-            }
         }
 
-        (graphics, present)
+        let graphics = graphics?;
+
+        Some(QueueFamilyIndices {
+            graphics_index: graphics,
+            present_index: present?,
+            compute_index: compute.unwrap_or(graphics),
+            transfer_index: transfer.or(compute_transfer).unwrap_or(graphics),
+        })
     }
 
-    fn create_logical_device_with_graphics_queue(
+    fn create_logical_device_with_queues(
         instance: &Instance,
         device: vk::PhysicalDevice,
         queue_family_indices: QueueFamilyIndices,
-    ) -> (Device, vk::Queue, vk::Queue) {
-        let graphics_family_index = queue_family_indices.graphics_index;
-        let present_family_index = queue_family_indices.present_index;
+        features: &DeviceFeatures,
+        extensions: &[CString],
+    ) -> (Device, Queues) {
         let queue_priorities = [1.0f32];
 
         let queue_create_infos = {
-            let mut indices = vec![graphics_family_index, present_family_index];
-            indices.dedup();
-
-            indices
+            queue_family_indices.unique_indices()
                 .iter()
                 .map(|index| {
                     vk::DeviceQueueCreateInfo::default()
@@ -251,24 +440,22 @@ impl VkContext {
                 .collect::<Vec<_>>()
         };
 
-        let device_extensions = Self::get_required_device_extensions();
-        let device_extensions_ptrs = device_extensions
+        let device_extensions_ptrs = extensions
             .iter()
             .map(|ext| ext.as_ptr())
             .collect::<Vec<_>>();
 
-        let device_features = vk::PhysicalDeviceFeatures::default()
-            .sampler_anisotropy(true);
-
-        let mut vk11_device_features = vk::PhysicalDeviceVulkan11Features::default()
-            .storage_buffer16_bit_access(true)
-            .uniform_and_storage_buffer16_bit_access(true);
+        let mut vk11_device_features = features.vulkan11;
+        let mut vk12_device_features = features.vulkan12;
+        let mut vk13_device_features = features.vulkan13;
 
         let device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
             .enabled_extension_names(&device_extensions_ptrs)
-            .enabled_features(&device_features)
-            .push_next(&mut vk11_device_features);
+            .enabled_features(&features.core)
+            .push_next(&mut vk11_device_features)
+            .push_next(&mut vk12_device_features)
+            .push_next(&mut vk13_device_features);
 
         let device = unsafe {
             instance
@@ -276,16 +463,25 @@ impl VkContext {
                 .expect("Failed to create logical device.")
         };
 
-        let graphics_queue = unsafe { device.get_device_queue(graphics_family_index, 0) };
-        let present_queue = unsafe { device.get_device_queue(present_family_index, 0) };
+        let queues = unsafe {
+            Queues {
+                graphics: device.get_device_queue(queue_family_indices.graphics_index, 0),
+                present: device.get_device_queue(queue_family_indices.present_index, 0),
+                compute: device.get_device_queue(queue_family_indices.compute_index, 0),
+                transfer: device.get_device_queue(queue_family_indices.transfer_index, 0),
+            }
+        };
 
-        (device, graphics_queue, present_queue)
+        (device, queues)
     }
 }
 
 impl Drop for VkContext {
     fn drop(&mut self) {
+        save_pipeline_cache(&self.device, self.pipeline_cache, pipeline_cache_path());
+
         unsafe {
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
             self.device.destroy_device(None);
             self.loaders.surface_instance.destroy_surface(self.surface_khr, None);
             if let Some((utils, messenger)) = self.debug_report_callback.take() {
@@ -296,10 +492,152 @@ impl Drop for VkContext {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Queue families used by the context. `compute_index` and `transfer_index` refer to dedicated (non-graphics)
+/// families when the device exposes them and fall back to `graphics_index` otherwise.
+#[derive(Clone, Copy, Debug)]
 pub struct QueueFamilyIndices {
     pub graphics_index: u32,
     pub present_index: u32,
+    pub compute_index: u32,
+    pub transfer_index: u32,
+}
+
+impl QueueFamilyIndices {
+    pub fn has_dedicated_compute_queue(&self) -> bool {
+        self.compute_index != self.graphics_index
+    }
+
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.transfer_index != self.graphics_index
+    }
+
+    pub fn unique_indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(4);
+
+        for index in [self.graphics_index, self.present_index, self.compute_index, self.transfer_index] {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+
+        indices
+    }
+}
+
+struct Queues {
+    graphics: vk::Queue,
+    present: vk::Queue,
+    compute: vk::Queue,
+    transfer: vk::Queue,
+}
+
+/// Describes the application and the device capabilities it needs. Required features and extensions make devices
+/// without them unsuitable; optional ones are enabled when supported and reported through
+/// `VkContext::enabled_optional_features` and `VkContext::enabled_optional_extensions`.
+#[derive(Clone)]
+pub struct VkContextBuilder {
+    pub application_name: CString,
+    pub application_version: u32,
+    pub engine_name: CString,
+    pub engine_version: u32,
+    pub device_selector: DeviceSelector,
+    pub required_features: DeviceFeatures,
+    pub optional_features: DeviceFeatures,
+    pub required_extensions: Vec<CString>,
+    pub optional_extensions: Vec<CString>,
+}
+
+impl VkContextBuilder {
+    pub fn new() -> Self {
+        // Timeline semaphores and synchronization2 back the renderer's frame and upload synchronization.
+        let required_features = DeviceFeatures {
+            vulkan12: vk::PhysicalDeviceVulkan12Features::default()
+                .timeline_semaphore(true),
+            vulkan13: vk::PhysicalDeviceVulkan13Features::default()
+                .synchronization2(true),
+            ..Default::default()
+        };
+
+        // Indirect draws with a GPU-written count and first instance back the GPU-driven path. Compressed textures
+        // are decoded on the CPU when their format family is missing.
+        let optional_features = DeviceFeatures {
+            core: vk::PhysicalDeviceFeatures::default()
+                .sampler_anisotropy(true)
+                .draw_indirect_first_instance(true)
+                .texture_compression_bc(true)
+                .texture_compression_etc2(true)
+                .texture_compression_astc_ldr(true),
+            vulkan11: vk::PhysicalDeviceVulkan11Features::default()
+                .storage_buffer16_bit_access(true)
+                .uniform_and_storage_buffer16_bit_access(true),
+            vulkan12: vk::PhysicalDeviceVulkan12Features::default()
+                .draw_indirect_count(true),
+            vulkan13: vk::PhysicalDeviceVulkan13Features::default()
+                .dynamic_rendering(true),
+        };
+
+        Self {
+            application_name: CString::new("LiSE Application").unwrap(),
+            application_version: vk::make_api_version(0, 0, 1, 0),
+            engine_name: CString::new("LiSE").unwrap(),
+            engine_version: vk::make_api_version(0, 0, 1, 0),
+            device_selector: DeviceSelector::Auto,
+            required_features,
+            optional_features,
+            required_extensions: vec![swapchain::NAME.to_owned()],
+            optional_extensions: Vec::new(),
+        }
+    }
+
+    pub fn application_name(mut self, name: &str) -> Self {
+        self.application_name = CString::new(name).unwrap();
+        self
+    }
+
+    pub fn application_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.application_version = vk::make_api_version(0, major, minor, patch);
+        self
+    }
+
+    /// Chooses the physical device. The `LISE_GPU` environment variable still takes precedence.
+    pub fn device_selector(mut self, device_selector: DeviceSelector) -> Self {
+        self.device_selector = device_selector;
+        self
+    }
+
+    pub fn require_features(mut self, features: DeviceFeatures) -> Self {
+        self.required_features = self.required_features.union(&features);
+        self
+    }
+
+    pub fn request_features(mut self, features: DeviceFeatures) -> Self {
+        self.optional_features = self.optional_features.union(&features);
+        self
+    }
+
+    pub fn require_extension(mut self, extension: &CStr) -> Self {
+        if !self.required_extensions.iter().any(|ext| ext.as_c_str() == extension) {
+            self.required_extensions.push(extension.to_owned());
+        }
+        self
+    }
+
+    pub fn request_extension(mut self, extension: &CStr) -> Self {
+        if !self.optional_extensions.iter().any(|ext| ext.as_c_str() == extension) {
+            self.optional_extensions.push(extension.to_owned());
+        }
+        self
+    }
+
+    pub fn build(self, window: &Window) -> VkContext {
+        VkContext::from_builder(window, &self)
+    }
+}
+
+impl Default for VkContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct ExtensionLoaders {
@@ -20,17 +20,40 @@ impl Clock {
 }
 
 pub mod fs {
-    use std::{io::Cursor, path::Path};
+    use std::{io::Cursor, path::{Path, PathBuf}, sync::OnceLock};
+
+    /// The first `assets` directory next to the executable or in one of its parents, so loads don't depend on the
+    /// working directory. Falls back to `assets` in the working directory if there is none.
+    pub fn asset_directory() -> &'static Path {
+        static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+        DIRECTORY.get_or_init(|| {
+            std::env::current_exe().ok()
+                .and_then(|executable| {
+                    executable.ancestors().skip(1).map(|directory| directory.join("assets")).find(|directory| directory.is_dir())
+                })
+                .unwrap_or_else(|| PathBuf::from("assets"))
+        })
+    }
+
+    /// Where `path`, relative to the assets directory, is loaded from.
+    pub fn asset_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        asset_directory().join(path)
+    }
 
     pub fn load<P: AsRef<Path>>(path: P) -> Cursor<Vec<u8>> {
+        try_load(path).unwrap()
+    }
+
+    pub fn try_load<P: AsRef<Path>>(path: P) -> std::io::Result<Cursor<Vec<u8>>> {
         use std::fs::File;
         use std::io::Read;
         
         let mut buf = Vec::new();
-        let fullpath = &Path::new("assets").join(&path);
-        let mut file = File::open(&fullpath).unwrap();
-        file.read_to_end(&mut buf).unwrap();
+        let fullpath = &asset_path(&path);
+        let mut file = File::open(&fullpath)?;
+        file.read_to_end(&mut buf)?;
 
-        Cursor::new(buf)
+        Ok(Cursor::new(buf))
     }
 }